@@ -0,0 +1,243 @@
+// Light-weight browsing over an output directory that SnapDown has already
+// downloaded into. Everything here is derived from the filenames that
+// run_downloader() produces, i.e. `<timestamp>_<latitude>_<longitude>.<ext>`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    Image,
+    Video,
+    Other,
+}
+
+impl MediaKind {
+    pub fn from_extension(ext: &str) -> MediaKind {
+        match ext.to_ascii_lowercase().as_str() {
+            "jpg" | "jpeg" | "png" | "gif" | "svg" | "webp" | "heic" => MediaKind::Image,
+            "mp4" | "mov" => MediaKind::Video,
+            _ => MediaKind::Other,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MediaKind::Image => "Image",
+            MediaKind::Video => "Video",
+            MediaKind::Other => "Other",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    pub path: PathBuf,
+    pub file_name: String,
+    // YYYY-MM-DD, or empty if the filename didn't start with a date
+    pub date: String,
+    pub media_kind: MediaKind,
+    // "<latitude>_<longitude>" as it appears in the filename
+    pub location: String,
+    // Download link, for memories that aren't downloaded yet
+    pub url: Option<String>,
+}
+
+impl ArchiveEntry {
+    pub fn from_path(path: &Path) -> Option<ArchiveEntry> {
+        let file_name = path.file_name()?.to_string_lossy().to_string();
+        let stem = path.file_stem()?.to_string_lossy().to_string();
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let date = match stem.get(..10) {
+            Some(prefix) if is_iso_date(prefix) => prefix.to_string(),
+            _ => String::new(),
+        };
+
+        // The coordinates are always the last two underscore-separated parts
+        let parts: Vec<&str> = stem.rsplitn(3, '_').collect();
+        let location = if parts.len() == 3 {
+            format!("{}_{}", parts[1], parts[0])
+        } else {
+            String::new()
+        };
+
+        Some(ArchiveEntry {
+            path: path.to_path_buf(),
+            file_name,
+            date,
+            media_kind: MediaKind::from_extension(&ext),
+            location,
+            url: None,
+        })
+    }
+}
+
+fn is_iso_date(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    bytes.len() == 10
+        && bytes[4] == b'-'
+        && bytes[7] == b'-'
+        && bytes
+            .iter()
+            .enumerate()
+            .all(|(i, b)| i == 4 || i == 7 || b.is_ascii_digit())
+}
+
+// Search criteria from the "Browse archive" tab. Empty strings mean "don't
+// filter on this".
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveQuery {
+    pub date_from: String,
+    pub date_to: String,
+    pub media_kind: Option<MediaKind>,
+    pub place: String,
+}
+
+impl ArchiveQuery {
+    pub fn matches(&self, entry: &ArchiveEntry) -> bool {
+        // ISO dates compare correctly as plain strings
+        let date_from = self.date_from.trim();
+        if !date_from.is_empty() && (entry.date.is_empty() || entry.date.as_str() < date_from) {
+            return false;
+        }
+        let date_to = self.date_to.trim();
+        if !date_to.is_empty() && (entry.date.is_empty() || entry.date.as_str() > date_to) {
+            return false;
+        }
+        if let Some(kind) = self.media_kind
+            && entry.media_kind != kind
+        {
+            return false;
+        }
+        let place = self.place.trim();
+        if !place.is_empty() && !entry.location.contains(place) {
+            return false;
+        }
+        true
+    }
+}
+
+// Scan the top level of the output directory for downloaded files, and the
+// folder of memories with an implausible date, sorted newest first.
+pub fn scan_archive(output_dir: &Path) -> Result<Vec<ArchiveEntry>> {
+    let mut entries = Vec::new();
+    let unknown_date = fs::read_dir(output_dir.join(crate::naming::UNKNOWN_DATE_FOLDER))
+        .into_iter()
+        .flatten();
+    for dir_entry in fs::read_dir(output_dir)?.chain(unknown_date) {
+        let path = dir_entry?.path();
+        // The .json files of --sidecars aren't memories
+        if !path.is_file() || crate::sidecar::is_sidecar(&path) {
+            continue;
+        }
+        if let Some(entry) = ArchiveEntry::from_path(&path) {
+            entries.push(entry);
+        }
+    }
+    entries.sort_by(|a, b| b.file_name.cmp(&a.file_name));
+    Ok(entries)
+}
+
+// Memories in the input file that aren't in the output directory yet, for
+// listing along with the downloaded ones
+pub fn not_downloaded_entries(
+    records: &[csv::StringRecord],
+    output_dir: &Path,
+) -> Vec<ArchiveEntry> {
+    // Files renamed to match their type are under a different name
+    let manifest_entries = crate::manifest::read_entries(output_dir).unwrap_or_default();
+    // The GUI has no other naming
+    let mut entries: Vec<ArchiveEntry> = crate::naming::unique_filenames_and_urls(
+        records,
+        &crate::naming::LegacyNamer,
+        &|filename| manifest_entries.contains_key(filename),
+    )
+    .into_iter()
+    .flatten()
+    .filter(|(filename, _)| {
+        let saved_filename = manifest_entries
+            .get(filename)
+            .map_or(filename.as_str(), |entry| entry.saved_filename());
+        !output_dir.join(saved_filename).exists()
+    })
+    .map(|(filename, url)| (output_dir.join(filename), url))
+    .filter_map(|(path, url)| {
+        Some(ArchiveEntry {
+            url: Some(url.to_string()),
+            ..ArchiveEntry::from_path(&path)?
+        })
+    })
+    .collect();
+    entries.sort_by(|a, b| b.file_name.cmp(&a.file_name));
+    entries
+}
+
+// Open a file or folder with the platform's default handler
+pub fn open_in_file_manager(path: &Path) -> Result<()> {
+    #[cfg(target_os = "windows")]
+    let mut command = std::process::Command::new("explorer");
+    #[cfg(target_os = "macos")]
+    let mut command = std::process::Command::new("open");
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut command = std::process::Command::new("xdg-open");
+
+    command.arg(path).spawn()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_from_html_style_filename() {
+        let entry = ArchiveEntry::from_path(Path::new(
+            "out/2026-01-13_01-55-38_UTC_40.25548_-111.645325.jpg",
+        ))
+        .unwrap();
+        assert_eq!(entry.date, "2026-01-13");
+        assert_eq!(entry.media_kind, MediaKind::Image);
+        assert_eq!(entry.location, "40.25548_-111.645325");
+    }
+
+    #[test]
+    fn test_entry_from_csv_style_filename() {
+        let entry =
+            ArchiveEntry::from_path(Path::new("out/1800-01-18T00-28-45+00-00_40.0_40.0.mp4"))
+                .unwrap();
+        assert_eq!(entry.date, "1800-01-18");
+        assert_eq!(entry.media_kind, MediaKind::Video);
+        assert_eq!(entry.location, "40.0_40.0");
+    }
+
+    #[test]
+    fn test_query_matches() {
+        let entry = ArchiveEntry::from_path(Path::new(
+            "out/2026-01-13_01-55-38_UTC_40.25548_-111.645325.jpg",
+        ))
+        .unwrap();
+
+        assert!(ArchiveQuery::default().matches(&entry));
+        let mut query = ArchiveQuery {
+            date_from: "2026-01-01".to_string(),
+            date_to: "2026-01-31".to_string(),
+            media_kind: Some(MediaKind::Image),
+            place: "-111.6".to_string(),
+        };
+        assert!(query.matches(&entry));
+        query.date_to = "2026-01-12".to_string();
+        assert!(!query.matches(&entry));
+        query.date_to.clear();
+        query.media_kind = Some(MediaKind::Video);
+        assert!(!query.matches(&entry));
+        query.media_kind = None;
+        query.place = "51.5".to_string();
+        assert!(!query.matches(&entry));
+    }
+}
@@ -0,0 +1,275 @@
+// Backs off when the server limits requests. Snapchat's CDN answers too many
+// downloads at once with 429 Too Many Requests (or 503 with a Retry-After),
+// so instead of failing those records, the run waits as long as the server
+// asks, halves how many downloads it runs at a time, and goes back up while
+// downloads succeed again.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use ureq::http::HeaderMap;
+
+use crate::control::RunControl;
+use crate::format;
+
+// How long to wait when the server doesn't say
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(10);
+// A server asking for hours would stall an unattended run, it's asked again
+// after this at most
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+// Successful downloads in a row before doubling the downloads at a time
+const RAMP_UP_AFTER: usize = 20;
+// How often a record is tried again after being rate limited, before it
+// counts as failed
+pub const MAX_RATE_LIMITED_RETRIES: usize = 5;
+// The longest wait between --retries
+const MAX_TRANSIENT_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+// Shared by all of a run's downloads
+pub struct Backoff {
+    // --jobs, the most downloads at a time
+    jobs: usize,
+    state: Mutex<State>,
+}
+
+struct State {
+    // Downloads allowed at a time, at most `jobs`
+    limit: usize,
+    active: usize,
+    // No download starts before this
+    paused_until: Option<Instant>,
+    successes: usize,
+}
+
+// Held by a download while it runs
+pub struct Slot<'a>(&'a Backoff);
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().active -= 1;
+    }
+}
+
+// How slowed down a run is, for the status
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Throttled {
+    pub limit: usize,
+    pub jobs: usize,
+}
+
+impl Throttled {
+    // e.g. "slowed down to 12 at a time by the server"
+    pub fn message(&self) -> String {
+        format!(
+            "slowed down to {} at a time by the server",
+            format::format_count(self.limit)
+        )
+    }
+}
+
+impl Backoff {
+    pub fn new(jobs: usize) -> Backoff {
+        Backoff {
+            jobs: jobs.max(1),
+            state: Mutex::new(State {
+                limit: jobs.max(1),
+                active: 0,
+                paused_until: None,
+                successes: 0,
+            }),
+        }
+    }
+
+    // None while the server's delay isn't over or enough downloads are
+    // running already
+    pub fn try_start(&self) -> Option<Slot<'_>> {
+        let mut state = self.state.lock().unwrap();
+        let paused = state
+            .paused_until
+            .is_some_and(|until| Instant::now() < until);
+        if paused || state.active >= state.limit {
+            return None;
+        }
+        state.active += 1;
+        Some(Slot(self))
+    }
+
+    // Block until a download can start. None if the run was cancelled while
+    // waiting.
+    pub fn start(&self, control: &RunControl) -> Option<Slot<'_>> {
+        loop {
+            if control.is_cancelled() {
+                return None;
+            }
+            if let Some(slot) = self.try_start() {
+                return Some(slot);
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    // Like start(), for the async downloader
+    #[cfg(not(feature = "rayon-downloader"))]
+    pub async fn start_async(&self, control: &RunControl) -> Option<Slot<'_>> {
+        loop {
+            if control.is_cancelled() {
+                return None;
+            }
+            if let Some(slot) = self.try_start() {
+                return Some(slot);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    // The server limited a request, asking to wait `retry_after`. Only the
+    // first of a burst cuts the downloads at a time, the ones already
+    // running when it came get theirs too. `log` is only called then.
+    pub fn rate_limited(&self, retry_after: Option<Duration>, log: impl Fn(String)) {
+        let delay = retry_after
+            .unwrap_or(DEFAULT_RETRY_DELAY)
+            .min(MAX_RETRY_DELAY);
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.successes = 0;
+        let paused = state.paused_until.is_some_and(|until| now < until);
+        if !paused {
+            // Going by what actually ran, which may be a lot less than --jobs
+            state.limit = (state.active.min(state.limit) / 2).max(1);
+            log(format!(
+                "The server is limiting requests, waiting {} and slowing down to {} at a time",
+                format::format_duration(delay),
+                format::format_count(state.limit)
+            ));
+        }
+        state.paused_until = state.paused_until.max(Some(now + delay));
+    }
+
+    // A download got through. `log` is called once it's back to --jobs.
+    pub fn succeeded(&self, log: impl Fn(String)) {
+        let mut state = self.state.lock().unwrap();
+        if state.limit >= self.jobs {
+            return;
+        }
+        state.successes += 1;
+        if state.successes >= RAMP_UP_AFTER {
+            state.successes = 0;
+            state.limit = (state.limit * 2).min(self.jobs);
+            if state.limit == self.jobs {
+                log(format!(
+                    "The server isn't limiting requests anymore, back to {} downloads at a time",
+                    format::format_count(self.jobs)
+                ));
+            }
+        }
+    }
+
+    // None unless slowed down
+    pub fn throttled(&self) -> Option<Throttled> {
+        let state = self.state.lock().unwrap();
+        (state.limit < self.jobs).then_some(Throttled {
+            limit: state.limit,
+            jobs: self.jobs,
+        })
+    }
+}
+
+// The Retry-After header of a response, if it has one
+pub fn retry_after(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(ureq::http::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+}
+
+// Whether a response is the server limiting requests: 429 always, 503 only
+// with a Retry-After, as without one it's just as likely the server being
+// down
+pub fn is_rate_limited(status: u16, retry_after: Option<&str>) -> bool {
+    status == 429 || (status == 503 && retry_after.is_some())
+}
+
+// Server errors that are often gone on the next try, for --retries. Limited
+// requests are waited out as above instead.
+pub fn is_transient_status(status: u16) -> bool {
+    matches!(status, 408 | 500 | 502 | 503 | 504)
+}
+
+// --retries: how long to wait before the `retry`th try again of a request,
+// doubling from a second up to half a minute
+pub fn retry_delay(retry: usize) -> Duration {
+    let exponent = retry.saturating_sub(1).min(5) as u32;
+    (Duration::from_secs(1) * 2u32.pow(exponent)).min(MAX_TRANSIENT_RETRY_DELAY)
+}
+
+// A Retry-After header, in seconds or an HTTP date
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    // A date in the past means right away
+    Some(
+        (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_backoff() {
+        let backoff = Backoff::new(8);
+        let slots: Vec<_> = (0..8).map(|_| backoff.try_start().unwrap()).collect();
+        assert!(backoff.try_start().is_none());
+        assert_eq!(backoff.throttled(), None);
+
+        let logged = RefCell::new(Vec::new());
+        let log = |message| logged.borrow_mut().push(message);
+        backoff.rate_limited(Some(Duration::from_millis(50)), log);
+        // The rest of the burst doesn't cut it further, or shorten the wait
+        backoff.rate_limited(Some(Duration::ZERO), log);
+        assert_eq!(logged.borrow().len(), 1);
+        assert_eq!(backoff.throttled(), Some(Throttled { limit: 4, jobs: 8 }));
+        drop(slots);
+        assert!(backoff.try_start().is_none());
+
+        std::thread::sleep(Duration::from_millis(60));
+        let slots: Vec<_> = (0..4).map(|_| backoff.try_start().unwrap()).collect();
+        assert!(backoff.try_start().is_none());
+        drop(slots);
+
+        for _ in 0..RAMP_UP_AFTER {
+            backoff.succeeded(log);
+        }
+        assert_eq!(backoff.throttled(), None);
+        assert_eq!(logged.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert!(is_transient_status(503) && !is_transient_status(404));
+        let delays: Vec<u64> = (1..=7).map(|retry| retry_delay(retry).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 30, 30]);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        let in_a_minute = (chrono::Utc::now() + chrono::Duration::seconds(60)).to_rfc2822();
+        let delay = parse_retry_after(&in_a_minute).unwrap();
+        assert!(delay > Duration::from_secs(55) && delay <= Duration::from_secs(60));
+        assert_eq!(parse_retry_after("soon"), None);
+        assert!(is_rate_limited(429, None));
+        assert!(!is_rate_limited(503, None));
+        assert!(is_rate_limited(503, Some("30")));
+    }
+}
@@ -0,0 +1,113 @@
+// Bursts: photos taken a second or two apart at the same place, as holding
+// the shutter button makes. An export can have thousands of them in a row,
+// so the {burst_index} placeholder of --naming gives the shots of a burst its
+// number, to keep them together and tell them from the rest. The number is
+// the time of the burst's first shot rather than a count, so a newer export
+// with another burst before it doesn't rename it, and a resumed run finds its
+// files again.
+
+use chrono::{DateTime, Utc};
+
+use crate::record::{record_location, record_timestamp};
+
+// The most seconds between two shots of the same burst
+const MAX_GAP_SECS: i64 = 3;
+// Fewer shots are just a few photos
+const MIN_SHOTS: usize = 3;
+// About 100 m, GPS wanders a bit between shots
+const MAX_DISTANCE_DEGREES: f64 = 0.001;
+
+// The burst of each record, as the time of its first shot. None for records
+// that aren't a photo in a burst.
+pub fn find_bursts(records: &[csv::StringRecord]) -> Vec<Option<DateTime<Utc>>> {
+    let mut photos: Vec<_> = records
+        .iter()
+        .enumerate()
+        .filter(|(_, row)| row.get(1) == Some("Image"))
+        .filter_map(|(index, row)| Some((record_timestamp(row)?, index, record_location(row))))
+        .collect();
+    photos.sort_by_key(|(timestamp, index, _)| (*timestamp, *index));
+
+    let mut bursts = vec![None; records.len()];
+    let mut start = 0;
+    for end in 1..=photos.len() {
+        let continues = end < photos.len() && {
+            let (previous, _, previous_place) = photos[end - 1];
+            let (timestamp, _, place) = photos[end];
+            (timestamp - previous).num_seconds() <= MAX_GAP_SECS
+                && same_place(previous_place, place)
+        };
+        if continues {
+            continue;
+        }
+        if end - start >= MIN_SHOTS {
+            let (first_shot, _, _) = photos[start];
+            for (_, index, _) in &photos[start..end] {
+                bursts[*index] = Some(first_shot);
+            }
+        }
+        start = end;
+    }
+    bursts
+}
+
+fn same_place(a: Option<(f64, f64)>, b: Option<(f64, f64)>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            (a.0 - b.0).abs() <= MAX_DISTANCE_DEGREES && (a.1 - b.1).abs() <= MAX_DISTANCE_DEGREES
+        }
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn photo(time: &str, latitude: &str) -> csv::StringRecord {
+        let timestamp = format!("2023-06-01 {} UTC", time);
+        csv::StringRecord::from(vec![&timestamp, "Image", latitude, "2.0", "https://a/b"])
+    }
+
+    #[test]
+    fn test_find_bursts() {
+        let records = [
+            photo("10:00:00", "48.0"),
+            photo("10:00:01", "48.0"),
+            // Out of order in the input
+            photo("10:00:04", "48.0004"),
+            photo("10:00:02", "48.0"),
+            // Too long after
+            photo("10:00:10", "48.0"),
+            // Quick, but somewhere else each time
+            photo("11:00:00", "10.0"),
+            photo("11:00:01", "20.0"),
+            photo("11:00:02", "30.0"),
+            // A video in the middle of a burst is left out of it
+            photo("12:00:00", "0.0"),
+            csv::StringRecord::from(vec!["2023-06-01 12:00:01 UTC", "Video", "0.0", "0.0", "x"]),
+            photo("12:00:01", "0.0"),
+            photo("12:00:03", "0.0"),
+        ];
+        let one = record_timestamp(&records[0]);
+        let two = record_timestamp(&records[8]);
+        assert_eq!(
+            find_bursts(&records),
+            [
+                one, one, one, one, None, None, None, None, two, None, two, two
+            ]
+        );
+
+        // Another burst before them doesn't change theirs
+        let earlier = [
+            photo("09:00:00", "1.0"),
+            photo("09:00:01", "1.0"),
+            photo("09:00:02", "1.0"),
+        ];
+        let bursts = find_bursts(&[&earlier[..], &records[..]].concat());
+        assert_eq!(bursts[0], record_timestamp(&earlier[0]));
+        assert_eq!(bursts[3], one);
+        assert_eq!(bursts[11], two);
+    }
+}
@@ -0,0 +1,227 @@
+// Chat media: the photos and videos sent in chats, the MEDIA messages of
+// chat_history.json or chat_history.html from the export. They're turned
+// into records like the memories', with the conversation's folder as a sixth
+// field, and downloaded the same way into a folder for each conversation
+// under chats/ (see naming::record_folder()).
+//
+// Messages that aren't media, and media without a download link (older
+// exports only list the media IDs of the files in chat_media/), are left out.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Read};
+
+use anyhow::Result;
+use lol_html::{HtmlRewriter, Settings, element, text};
+use serde::Deserialize;
+
+use crate::export::{decode_entities, strip_html};
+use crate::naming::folder_name;
+use crate::tolerant_html::{download_call_link, feed_rewriter};
+
+// The folder in the output directory the conversations' folders go in
+pub const CHAT_FOLDER: &str = "chats";
+
+const MEDIA_TYPE: &str = "MEDIA";
+
+// A message of chat_history.json, which has the messages of each
+// conversation under the other person's username
+#[derive(Debug, Deserialize)]
+struct ChatMessage {
+    #[serde(rename = "From", default)]
+    from: String,
+    #[serde(rename = "Media Type", default)]
+    media_type: String,
+    #[serde(rename = "Created", default)]
+    created: String,
+    // Group chats have a title, the others are named after the username
+    #[serde(rename = "Conversation Title", default)]
+    conversation_title: Option<String>,
+    // As in memories_history.json
+    #[serde(rename = "Media Download Url", default)]
+    media_download_url: Option<String>,
+    #[serde(rename = "Download Link", default)]
+    download_link: Option<String>,
+    // Some exports only have the link as the message's content
+    #[serde(rename = "Content", default)]
+    content: Option<String>,
+}
+
+impl ChatMessage {
+    fn link(&self) -> Option<&str> {
+        [&self.media_download_url, &self.download_link, &self.content]
+            .into_iter()
+            .flatten()
+            .map(|link| link.trim())
+            .find(|link| link.starts_with("http"))
+    }
+}
+
+// (timestamp, format, latitude, longitude, download_url, folder). Chat media
+// has no location, and its type is only known once it's downloaded.
+fn chat_record(created: &str, link: &str, conversation: &str) -> csv::StringRecord {
+    let folder = format!("{}/{}", CHAT_FOLDER, folder_name(conversation));
+    csv::StringRecord::from(vec![created, "Media", "", "", link, &folder])
+}
+
+pub fn parse_chat_history_json(json: impl Read) -> Result<Vec<csv::StringRecord>> {
+    let conversations: BTreeMap<String, Vec<ChatMessage>> = serde_json::from_reader(json)?;
+    let mut records = Vec::new();
+    for (username, messages) in &conversations {
+        for message in messages {
+            if !message.media_type.eq_ignore_ascii_case(MEDIA_TYPE) {
+                continue;
+            }
+            let Some(link) = message.link() else {
+                continue;
+            };
+            let conversation = message
+                .conversation_title
+                .as_deref()
+                .filter(|title| !title.trim().is_empty())
+                .unwrap_or(username);
+            log::debug!("Chat media from {} in {}", message.from, conversation);
+            records.push(chat_record(&message.created, link, conversation));
+        }
+    }
+    Ok(records)
+}
+
+// chat_history.html has a heading with the name of each conversation, and a
+// table of its messages under it: who sent it, the media type and when,
+// with a link for media
+#[derive(Default)]
+struct ChatPage {
+    conversation: String,
+    heading: Option<String>,
+    cells: Vec<String>,
+    link: Option<String>,
+    records: Vec<csv::StringRecord>,
+}
+
+impl ChatPage {
+    fn finish_row(&mut self) {
+        let cells: Vec<String> = self.cells.drain(..).map(|cell| strip_html(&cell)).collect();
+        let Some(link) = self.link.take() else {
+            return;
+        };
+        let is_media = cells
+            .iter()
+            .any(|cell| cell.eq_ignore_ascii_case(MEDIA_TYPE));
+        let created = cells.iter().find(|cell| cell.ends_with("UTC"));
+        if let Some(created) = created
+            && is_media
+        {
+            self.records
+                .push(chat_record(created, &link, &self.conversation));
+        }
+    }
+
+    fn finish_heading(&mut self) {
+        if let Some(heading) = self.heading.take() {
+            self.conversation = strip_html(&heading);
+        }
+    }
+}
+
+pub fn parse_chat_history_html(html: impl BufRead) -> Result<Vec<csv::StringRecord>> {
+    let page = RefCell::new(ChatPage::default());
+    {
+        let rewriter = HtmlRewriter::new(
+            Settings {
+                element_content_handlers: vec![
+                    element!("h1, h2, h3, h4", |_| {
+                        let mut page = page.borrow_mut();
+                        page.finish_row();
+                        page.heading = Some(String::new());
+                        Ok(())
+                    }),
+                    text!("h1, h2, h3, h4", |chunk| {
+                        if let Some(heading) = &mut page.borrow_mut().heading {
+                            heading.push_str(chunk.as_str());
+                        }
+                        Ok(())
+                    }),
+                    element!("tr", |_| {
+                        let mut page = page.borrow_mut();
+                        page.finish_heading();
+                        page.finish_row();
+                        Ok(())
+                    }),
+                    element!("td", |_| {
+                        page.borrow_mut().cells.push(String::new());
+                        Ok(())
+                    }),
+                    text!("td", |chunk| {
+                        if let Some(cell) = page.borrow_mut().cells.last_mut() {
+                            cell.push_str(chunk.as_str());
+                        }
+                        Ok(())
+                    }),
+                    element!("tr [onclick]", |element| {
+                        let onclick = element.get_attribute("onclick").unwrap_or_default();
+                        if let Some(link) = download_call_link(&decode_entities(&onclick)) {
+                            page.borrow_mut().link.get_or_insert(link);
+                        }
+                        Ok(())
+                    }),
+                    element!("tr a[href^='https']", |element| {
+                        if let Some(href) = element.get_attribute("href") {
+                            page.borrow_mut().link.get_or_insert(decode_entities(&href));
+                        }
+                        Ok(())
+                    }),
+                ],
+                ..Settings::new()
+            },
+            |_: &[u8]| {},
+        );
+        feed_rewriter(html, rewriter)?;
+    }
+    let mut page = page.into_inner();
+    page.finish_row();
+    Ok(page.records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chat_history() {
+        let json = r#"{
+            "friend1": [
+                {"From": "friend1", "Media Type": "TEXT", "Created": "2024-01-01 10:00:00 UTC", "Content": "hi"},
+                {"From": "me", "Media Type": "MEDIA", "Created": "2024-01-01 10:01:00 UTC",
+                 "Content": "", "Media Download Url": "https://a.example.com/1"},
+                {"From": "friend1", "Media Type": "MEDIA", "Created": "2024-01-01 10:02:00 UTC",
+                 "Media IDs": "b~abc"}
+            ],
+            "group": [
+                {"From": "friend2", "Media Type": "MEDIA", "Created": "2024-01-02 09:00:00 UTC",
+                 "Conversation Title": "Trip", "Content": "https://a.example.com/2"}
+            ]
+        }"#;
+        let expected = [
+            chat_record(
+                "2024-01-01 10:01:00 UTC",
+                "https://a.example.com/1",
+                "friend1",
+            ),
+            chat_record("2024-01-02 09:00:00 UTC", "https://a.example.com/2", "Trip"),
+        ];
+        assert_eq!(parse_chat_history_json(json.as_bytes()).unwrap(), expected);
+
+        let html = "<h1>Chat History</h1>\
+            <h3>friend1</h3><table>\
+            <tr><th>From<th>Media Type<th>Created<th>\
+            <tr><td>friend1<td>TEXT<td>2024-01-01 10:00:00 UTC<td>hi\
+            <tr><td>me<td><b>MEDIA</b><td>2024-01-01 10:01:00 UTC\
+            <td><a href='https://a.example.com/1'>Download</a>\
+            </table><h3>Trip</h3><table>\
+            <tr><td>friend2</td><td>MEDIA</td><td>2024-01-02 09:00:00 UTC</td>\
+            <td><a href=# onclick=\"downloadMemories('https://a.example.com/2', this)\">Download</a></td></tr>\
+            </table>";
+        assert_eq!(parse_chat_history_html(html.as_bytes()).unwrap(), expected);
+    }
+}
@@ -0,0 +1,884 @@
+// Command line arguments. `snapdown` on its own opens the GUI, everything
+// else is a subcommand. The old `snapdown --cli -i ... -o ...` form still
+// works, it's turned into `snapdown download -i ... -o ...` before parsing.
+
+use std::ffi::OsString;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::dedup::DedupMode;
+use crate::naming::Naming;
+use crate::schedule::Schedule;
+use crate::sections::Section;
+use crate::space_check::SpaceCheck;
+use crate::upload::UploadTarget;
+use crate::views::{LinkKind, ViewKind};
+use crate::webdav::WEBDAV_SCHEME;
+use crate::{DEFAULT_NUM_JOBS, input, notify, throttle};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "snapdown",
+    version,
+    about = "Quickly download all your Snapchat memories",
+    after_help = "Run without a command to open the GUI. See --help-all for the full reference."
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[arg(
+        long,
+        help = "Show the full reference (input files, output, exit codes...)"
+    )]
+    pub help_all: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    #[command(about = "Download every memory in an export")]
+    Download(Box<DownloadArgs>),
+    #[command(
+        about = "Convert an export's list of memories to a snap_export.csv or JSON, without downloading"
+    )]
+    Parse(ParseArgs),
+    #[command(
+        about = "Check the files in an output directory against its manifest, and optionally download broken ones again"
+    )]
+    Verify(VerifyArgs),
+    #[command(about = "Compare two exports, listing the memories added and removed between them")]
+    Diff(DiffArgs),
+    #[command(about = "Open the GUI (the default)")]
+    Gui,
+}
+
+#[derive(Debug, Args)]
+pub struct DownloadArgs {
+    #[arg(
+        long,
+        value_name = "NAME|FILE.toml",
+        help = "Use the settings of this preset from snapdown_presets.json, or of a file from --print-config, for the options not given"
+    )]
+    pub preset: Option<String>,
+    #[arg(
+        short = 'i',
+        value_name = "INPUT",
+        value_parser = input::expand_path_arg,
+        help = "Export zip, memories_history.html/.json or snap_export.csv (asked for if missing)"
+    )]
+    pub input: Option<String>,
+    #[arg(
+        long,
+        value_name = "memories,chats,stories,spotlight",
+        value_delimiter = ',',
+        value_parser = Section::parse,
+        help = "Which parts of an export zip to download (default: memories)"
+    )]
+    pub sections: Vec<Section>,
+    #[arg(
+        short = 'o',
+        value_name = "OUTPUT_DIR",
+        value_parser = input::expand_path_arg,
+        help = "Where to download to (asked for if missing), or a webdav:// folder like --webdav"
+    )]
+    pub output_dir: Option<String>,
+    #[arg(
+        short = 'j',
+        value_name = "JOBS",
+        default_value_t = DEFAULT_NUM_JOBS,
+        help = "Number of downloads at the same time"
+    )]
+    pub jobs: usize,
+    #[arg(
+        long,
+        value_name = "CSV",
+        value_parser = input::expand_path_arg,
+        help = "Write the records that failed to download to this CSV file"
+    )]
+    pub export_failures: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        value_parser = input::expand_path_arg,
+        help = "Write the JSON report of the run here (default: <OUTPUT_DIR>/snapdown_report.json)"
+    )]
+    pub report: Option<String>,
+    #[arg(
+        long,
+        value_name = "PORT",
+        help = "Serve the progress of the run as JSON on this port, e.g. 8123"
+    )]
+    pub status_port: Option<u16>,
+    #[arg(long, help = "Write details of failed requests to a log file")]
+    pub debug_http: bool,
+    #[arg(
+        long,
+        help = "Keep the signed parts of download links in the logs and report"
+    )]
+    pub log_full_urls: bool,
+    #[arg(
+        long,
+        help = "Stop at the first failed download and show everything about it"
+    )]
+    pub fail_fast: bool,
+    #[arg(
+        long,
+        help = "Request a fresh download link for each file first, for exports older than a few days"
+    )]
+    pub resolve_links: bool,
+    #[arg(
+        long,
+        help = "Don't write the capture date and location into downloaded photos"
+    )]
+    pub no_exif: bool,
+    #[arg(
+        long,
+        help = "Don't set the files' modified times to when the memories were taken"
+    )]
+    pub no_touch: bool,
+    #[arg(
+        long,
+        help = "Draw captions and stickers onto photos instead of saving them separately"
+    )]
+    pub composite_overlays: bool,
+    #[arg(
+        long,
+        help = "Turn sideways photos upright instead of relying on their EXIF orientation"
+    )]
+    pub auto_rotate: bool,
+    #[arg(
+        long,
+        help = "Write a Google Takeout style <file>.json with the date and coordinates of each file"
+    )]
+    pub sidecars: bool,
+    #[arg(
+        long,
+        value_name = "delete|hardlink",
+        value_parser = DedupMode::parse,
+        help = "Delete duplicate files, or replace them with hard links to the first copy"
+    )]
+    pub dedup: Option<DedupMode>,
+    #[arg(
+        long,
+        value_name = "by-type,by-year,by-place",
+        value_delimiter = ',',
+        value_parser = ViewKind::parse,
+        help = "Also link the files into folders by type, year or place"
+    )]
+    pub views: Vec<ViewKind>,
+    #[arg(
+        long,
+        value_name = "hardlink|symlink",
+        value_parser = LinkKind::parse,
+        requires = "views",
+        help = "How --views links the files (default: hardlink)"
+    )]
+    pub view_links: Option<LinkKind>,
+    #[arg(
+        long,
+        help = "Once everything is downloaded, make the files read-only and seal the archive"
+    )]
+    pub freeze: bool,
+    #[arg(
+        long,
+        help = "Download every file again, even ones that are already downloaded"
+    )]
+    pub force: bool,
+    #[arg(
+        long,
+        value_name = "RATE",
+        value_parser = throttle::parse_rate,
+        help = "Limit the total download speed, in bytes per second (e.g. 500K, 5M)"
+    )]
+    pub limit_rate: Option<u64>,
+    #[arg(
+        long,
+        value_name = "HH:MM-HH:MM",
+        value_parser = Schedule::parse,
+        help = "Only download between these times (e.g. 01:00-07:00), pausing the rest of the day"
+    )]
+    pub schedule: Option<Schedule>,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = parse_seconds,
+        help = "Give up connecting to a server after this long"
+    )]
+    pub connect_timeout: Option<Duration>,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = parse_seconds,
+        help = "Give up waiting for a server to start answering after this long"
+    )]
+    pub response_timeout: Option<Duration>,
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        help = "Try a download that failed with a connection error, timeout or server error again up to N times"
+    )]
+    pub retries: usize,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Keep up to this many connections to each server open between downloads (default: --jobs)"
+    )]
+    pub pool_size: Option<usize>,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = parse_seconds,
+        help = "Close a connection kept open after it's unused this long (default: 30)"
+    )]
+    pub pool_idle_timeout: Option<Duration>,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = parse_seconds,
+        help = "Log what the downloads are doing when nothing was downloaded for this long (default: 900)"
+    )]
+    pub stall_timeout: Option<Duration>,
+    #[arg(
+        long,
+        help = "Stop a stalled run and start it again, continuing the partial files"
+    )]
+    pub restart_stalled: bool,
+    #[arg(
+        long,
+        value_name = "REPORT",
+        help = "Play back the snapdown_report.json of an earlier run of the same input, instead of downloading"
+    )]
+    pub replay: Option<String>,
+    #[arg(
+        long,
+        value_name = "FACTOR",
+        default_value_t = 1.0,
+        value_parser = parse_speed,
+        help = "Play back --replay this many times as fast"
+    )]
+    pub replay_speed: f64,
+    #[arg(
+        long,
+        value_name = "legacy|hash|index|TEMPLATE",
+        value_parser = Naming::parse,
+        help = "How to name the files, e.g. {date}_{place}_{type} (default: legacy)"
+    )]
+    pub naming: Option<Naming>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = input::expand_path_arg,
+        requires = "naming",
+        help = "Look {place} up in this GeoNames cities file instead of the built-in cities"
+    )]
+    pub places: Option<String>,
+    #[arg(
+        long,
+        value_name = "URL",
+        value_parser = parse_proxy,
+        help = "Download through this proxy, e.g. socks5://localhost:1080 (default: $HTTPS_PROXY)"
+    )]
+    pub proxy: Option<ureq::Proxy>,
+    #[arg(
+        long,
+        value_name = "CMD",
+        help = "Run a command on each downloaded file, with {path} replaced by its path"
+    )]
+    pub post_process_cmd: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        requires_all = ["server", "api_key"],
+        help = "Upload each downloaded file to this server, with its date and place"
+    )]
+    pub upload: Option<UploadTarget>,
+    #[arg(
+        long,
+        value_name = "URL",
+        requires = "upload",
+        help = "The address of the --upload server, e.g. https://photos.example.com"
+    )]
+    pub server: Option<String>,
+    #[arg(
+        long,
+        value_name = "KEY",
+        requires = "upload",
+        help = "An API key for the --upload server"
+    )]
+    pub api_key: Option<String>,
+    #[arg(
+        long,
+        requires = "upload",
+        conflicts_with_all = ["post_process_cmd", "views", "dedup", "sidecars", "freeze"],
+        help = "Delete each file once it's uploaded, keeping none of them here"
+    )]
+    pub upload_only: bool,
+    #[arg(
+        long,
+        value_name = "BUCKET",
+        conflicts_with_all = [
+            "post_process_cmd",
+            "views",
+            "dedup",
+            "sidecars",
+            "freeze",
+            "upload",
+            "composite_overlays",
+            "auto_rotate",
+            "dry_run"
+        ],
+        help = "Store the files in this S3 bucket instead of the output directory"
+    )]
+    pub s3_bucket: Option<String>,
+    #[arg(
+        long,
+        value_name = "URL",
+        requires = "s3_bucket",
+        help = "The S3-compatible service, e.g. http://localhost:9000 (default: AWS)"
+    )]
+    pub s3_endpoint: Option<String>,
+    #[arg(
+        long,
+        value_name = "REGION",
+        default_value = "us-east-1",
+        help = "The region of the --s3-bucket"
+    )]
+    pub s3_region: String,
+    #[arg(
+        long,
+        value_name = "PREFIX",
+        requires = "s3_bucket",
+        help = "Put the files under this prefix in the bucket, e.g. snapchat"
+    )]
+    pub s3_prefix: Option<String>,
+    #[arg(
+        long,
+        value_name = "URL",
+        conflicts_with_all = [
+            "post_process_cmd",
+            "views",
+            "dedup",
+            "sidecars",
+            "freeze",
+            "upload",
+            "composite_overlays",
+            "auto_rotate",
+            "dry_run",
+            "s3_bucket"
+        ],
+        help = "Store the files in this WebDAV folder instead, e.g. webdav://me@cloud.example.com/remote.php/dav/files/me/Snapchat"
+    )]
+    pub webdav: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "sample",
+        help = "Check there's space for the files first, by the sizes of some or all of them"
+    )]
+    pub space_check: SpaceCheck,
+    #[arg(
+        long,
+        help = "Only show how many files would be downloaded or skipped, without downloading"
+    )]
+    pub dry_run: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["input", "sections", "dry_run", "replay"],
+        help = "Only try the downloads that failed again, as the manifest in the output directory has them, without the input"
+    )]
+    pub retry_failed: bool,
+    #[arg(
+        long,
+        value_name = "URL",
+        requires = "email_to",
+        help = "Email a summary when the run is done, through this server (e.g. smtps://me@smtp.example.com)"
+    )]
+    pub smtp: Option<String>,
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        requires = "smtp",
+        help = "Who to email the summary to"
+    )]
+    pub email_to: Option<String>,
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        requires = "smtp",
+        help = "Who the summary email is from (default: --email-to)"
+    )]
+    pub email_from: Option<String>,
+    #[arg(
+        long,
+        help = "Show a desktop notification when the run is over, or when many downloads fail"
+    )]
+    pub notify: bool,
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value_t = notify::DEFAULT_ERROR_RATE,
+        value_parser = parse_percent,
+        requires = "notify",
+        help = "Notify when more than this percent of the downloads so far failed"
+    )]
+    pub notify_error_rate: f64,
+    #[arg(
+        long,
+        help = "Download into the output directory even if it has another account's export"
+    )]
+    pub allow_mixed_archives: bool,
+    #[arg(
+        long,
+        value_name = "ZIP",
+        value_parser = input::expand_path_arg,
+        help = "After the run, zip the logs, report and settings to attach to a bug report"
+    )]
+    pub support_bundle: Option<String>,
+    #[arg(
+        long,
+        help = "Print the settings the download would run with as TOML, and exit"
+    )]
+    pub print_config: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ParseArgs {
+    #[arg(
+        short = 'i',
+        value_name = "INPUT",
+        value_parser = input::expand_path_arg,
+        help = "Export zip, memories_history.html/.json or snap_export.csv"
+    )]
+    pub input: String,
+    #[arg(
+        short = 'o',
+        value_name = "FILE",
+        value_parser = input::expand_path_arg,
+        help = "The file to write, or - for the standard output (default: snap_export.csv or memories.json)"
+    )]
+    pub output: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        help = "What to write (default: json if FILE ends in .json, otherwise csv)"
+    )]
+    pub format: Option<ParseFormat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ParseFormat {
+    Csv,
+    Json,
+}
+
+impl ParseArgs {
+    // The format and file to write, filling in whichever wasn't given
+    pub fn output(&self) -> (ParseFormat, &str) {
+        let format = self.format.unwrap_or(match &self.output {
+            Some(output) if output.to_ascii_lowercase().ends_with(".json") => ParseFormat::Json,
+            _ => ParseFormat::Csv,
+        });
+        let output = self.output.as_deref().unwrap_or(match format {
+            ParseFormat::Csv => "snap_export.csv",
+            ParseFormat::Json => "memories.json",
+        });
+        (format, output)
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    #[arg(
+        short = 'a',
+        value_name = "OLD",
+        value_parser = input::expand_path_arg,
+        help = "The older export (zip, memories_history.html/.json or snap_export.csv)"
+    )]
+    pub old: String,
+    #[arg(
+        short = 'b',
+        value_name = "NEW",
+        value_parser = input::expand_path_arg,
+        help = "The newer export"
+    )]
+    pub new: String,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    #[arg(
+        short = 'o',
+        value_name = "OUTPUT_DIR",
+        value_parser = input::expand_path_arg,
+        help = "The output directory of earlier runs"
+    )]
+    pub output_dir: String,
+    #[arg(
+        long,
+        requires = "input",
+        help = "Download missing, empty and changed files again (needs -i)"
+    )]
+    pub repair: bool,
+    #[arg(
+        short = 'i',
+        value_name = "INPUT",
+        value_parser = input::expand_path_arg,
+        requires = "repair",
+        help = "The export the files were downloaded from, for --repair"
+    )]
+    pub input: Option<String>,
+    #[arg(
+        long,
+        requires = "repair",
+        help = "Request a fresh download link for each file first, see download --help"
+    )]
+    pub resolve_links: bool,
+    #[arg(
+        long,
+        value_name = "legacy|hash|index|TEMPLATE",
+        value_parser = Naming::parse,
+        requires = "repair",
+        help = "The --naming the files were downloaded with, for --repair"
+    )]
+    pub naming: Option<Naming>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = input::expand_path_arg,
+        requires = "naming",
+        help = "The --places the files were downloaded with, for --repair"
+    )]
+    pub places: Option<String>,
+}
+
+pub fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    match seconds.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Ok(Duration::from_secs_f64(seconds)),
+        _ => Err(format!("expected a number of seconds, got {:?}", seconds)),
+    }
+}
+
+fn parse_speed(speed: &str) -> Result<f64, String> {
+    match speed.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!("expected a number above 0, got {:?}", speed)),
+    }
+}
+
+fn parse_percent(percent: &str) -> Result<f64, String> {
+    match percent.trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(percent),
+        _ => Err(format!(
+            "expected a percent from 0 to 100, got {:?}",
+            percent
+        )),
+    }
+}
+
+fn parse_proxy(proxy: &str) -> Result<ureq::Proxy, ureq::Error> {
+    ureq::Proxy::new(proxy)
+}
+
+// Arguments in the old form are options without a command. With --cli they
+// meant a download; without it they were ignored and the GUI opened, which
+// still happens.
+pub fn upgrade_legacy_args(mut args: Vec<OsString>) -> Vec<OsString> {
+    let Some(first) = args.get(1).and_then(|arg| arg.to_str()) else {
+        return args;
+    };
+    if !first.starts_with('-') || ["-h", "--help", "--help-all", "-V", "--version"].contains(&first)
+    {
+        return args;
+    }
+    // Only a --cli of its own, not the value of an option before it
+    let download = DownloadArgs::augment_args(clap::Command::new("download"));
+    let is_flag: Vec<bool> = (0..args.len())
+        .map(|i| {
+            i > 0
+                && args[i] == "--cli"
+                && !args[i - 1]
+                    .to_str()
+                    .is_some_and(|previous| takes_value(&download, previous))
+        })
+        .collect();
+    if is_flag.contains(&true) {
+        let mut is_flag = is_flag.into_iter();
+        args.retain(|_| !is_flag.next().unwrap());
+        args.insert(1, OsString::from("download"));
+    } else {
+        log::warn!("Options without --cli are ignored, opening the GUI");
+        args.truncate(1);
+        args.push(OsString::from("gui"));
+    }
+    args
+}
+
+// -o webdav://... stores the files in that WebDAV folder, the same as
+// --webdav, which it's turned into before parsing so clap checks what it
+// can't be used with. The manifest, report and logs then go in the default
+// output directory.
+pub fn webdav_output_args(mut args: Vec<OsString>) -> Vec<OsString> {
+    let download = DownloadArgs::augment_args(clap::Command::new("download"));
+    let is_webdav = |value: &str| value.starts_with(WEBDAV_SCHEME);
+    let mut i = 1;
+    while let Some(arg) = args.get(i).and_then(|arg| arg.to_str()) {
+        if arg == "--" {
+            break;
+        }
+        if arg == "-o" {
+            if args
+                .get(i + 1)
+                .and_then(|value| value.to_str())
+                .is_some_and(is_webdav)
+            {
+                args[i] = OsString::from("--webdav");
+            }
+            i += 2;
+            continue;
+        }
+        // -owebdav://... and -o=webdav://...
+        if let Some(value) = arg.strip_prefix("-o") {
+            let value = value.strip_prefix('=').unwrap_or(value);
+            if is_webdav(value) {
+                let value = OsString::from(value);
+                args.splice(i..=i, [OsString::from("--webdav"), value]);
+                i += 2;
+                continue;
+            }
+        }
+        i += if takes_value(&download, arg) { 2 } else { 1 };
+    }
+    args
+}
+
+// Whether `arg` is one of `command`'s options with the next argument as its
+// value
+fn takes_value(command: &clap::Command, arg: &str) -> bool {
+    let option = if let Some(long) = arg.strip_prefix("--") {
+        command
+            .get_arguments()
+            .find(|option| option.get_long() == Some(long))
+    } else if let Some(short) = arg.strip_prefix('-')
+        && short.chars().count() == 1
+    {
+        command
+            .get_arguments()
+            .find(|option| option.get_short() == short.chars().next())
+    } else {
+        None
+    };
+    option.is_some_and(|option| option.get_action().takes_values())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(webdav_output_args(upgrade_legacy_args(
+            args.iter().map(OsString::from).collect(),
+        )))
+    }
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_legacy_args() {
+        let cli = parse(&["snapdown", "--cli", "-i", "in.csv", "-o", "out", "-j", "5"]).unwrap();
+        let Some(Command::Download(args)) = cli.command else {
+            panic!("expected a download: {:?}", cli.command);
+        };
+        assert_eq!(args.input.as_deref(), Some("in.csv"));
+        assert_eq!(args.output_dir.as_deref(), Some("out"));
+        assert_eq!(args.jobs, 5);
+
+        // A value that happens to be --cli stays
+        let args = upgrade_legacy_args(
+            ["snapdown", "--cli", "-o", "--cli", "-j", "5", "--cli"]
+                .iter()
+                .map(OsString::from)
+                .collect(),
+        );
+        assert_eq!(args, ["snapdown", "download", "-o", "--cli", "-j", "5"]);
+        let args = upgrade_legacy_args(
+            ["snapdown", "--naming", "--cli"]
+                .iter()
+                .map(OsString::from)
+                .collect(),
+        );
+        assert_eq!(args, ["snapdown", "gui"]);
+
+        let cli = parse(&["snapdown", "-i", "in.csv"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Gui)));
+        assert!(parse(&["snapdown"]).unwrap().command.is_none());
+        assert!(parse(&["snapdown", "--help-all"]).unwrap().help_all);
+    }
+
+    #[test]
+    fn test_webdav_output_args() {
+        let webdav = "webdav://me@cloud.example.com/dav";
+        for output in [
+            vec!["-o", webdav],
+            vec!["-owebdav://me@cloud.example.com/dav"],
+            vec!["-o=webdav://me@cloud.example.com/dav"],
+        ] {
+            let args = [&["snapdown", "download", "-i", "in.zip"][..], &output].concat();
+            let Some(Command::Download(args)) = parse(&args).unwrap().command else {
+                panic!("expected a download");
+            };
+            assert_eq!(args.webdav.as_deref(), Some(webdav));
+            assert_eq!(args.output_dir, None);
+        }
+        let cli = parse(&["snapdown", "download", "-o", "out"]).unwrap();
+        let Some(Command::Download(args)) = cli.command else {
+            panic!("expected a download");
+        };
+        assert_eq!(args.output_dir.as_deref(), Some("out"));
+        // It can't be used with what --webdav can't
+        assert!(parse(&["snapdown", "download", "-o", webdav, "--dedup"]).is_err());
+    }
+
+    #[test]
+    fn test_download_args() {
+        let cli = parse(&[
+            "snapdown",
+            "download",
+            "--views",
+            "by-year,by-type",
+            "--dedup",
+            "hardlink",
+            "--limit-rate",
+            "5M",
+            "--connect-timeout",
+            "2.5",
+            "--pool-size",
+            "20",
+            "--naming",
+            "index",
+            "--space-check",
+            "full",
+            "--sections",
+            "chats,memories",
+            "--schedule",
+            "22:00-06:00",
+            "--retries",
+            "3",
+        ])
+        .unwrap();
+        let Some(Command::Download(args)) = cli.command else {
+            panic!("expected a download: {:?}", cli.command);
+        };
+        assert_eq!(args.views, [ViewKind::Year, ViewKind::Type]);
+        assert_eq!(args.dedup, Some(DedupMode::HardLink));
+        assert_eq!(args.limit_rate, Some(5 * 1024 * 1024));
+        assert_eq!(args.connect_timeout, Some(Duration::from_millis(2500)));
+        assert_eq!(args.retries, 3);
+        assert_eq!(args.pool_size, Some(20));
+        assert_eq!(args.naming, Some(Naming::Index));
+        assert_eq!(args.jobs, DEFAULT_NUM_JOBS);
+        assert_eq!(args.space_check, SpaceCheck::Full);
+        assert_eq!(args.sections, [Section::Chats, Section::Memories]);
+        assert!(parse(&["snapdown", "download", "--sections", "snaps"]).is_err());
+        assert_eq!(args.schedule.unwrap().to_string(), "22:00-06:00");
+        assert!(parse(&["snapdown", "download", "--schedule", "22:00"]).is_err());
+
+        assert!(parse(&["snapdown", "download", "--dedup", "copy"]).is_err());
+        let Some(Command::Download(args)) = parse(&["snapdown", "download", "--preset", "NAS"])
+            .unwrap()
+            .command
+        else {
+            panic!("expected a download");
+        };
+        assert_eq!(args.preset.as_deref(), Some("NAS"));
+        assert!(parse(&["snapdown", "download", "--view-links", "symlink"]).is_err());
+        assert!(parse(&["snapdown", "download", "--smtp", "smtps://example.com"]).is_err());
+        assert!(parse(&["snapdown", "download", "--connect-timeout", "0"]).is_err());
+        assert!(parse(&["snapdown", "download", "--naming", "{city}"]).is_err());
+        assert!(parse(&["snapdown", "download", "--upload", "immich"]).is_err());
+        let upload = [
+            "snapdown",
+            "download",
+            "--upload",
+            "immich",
+            "--server",
+            "https://photos.example.com",
+            "--api-key",
+            "key",
+            "--upload-only",
+        ];
+        let Some(Command::Download(args)) = parse(&upload).unwrap().command else {
+            panic!("expected a download");
+        };
+        assert_eq!(args.upload, Some(UploadTarget::Immich));
+        assert!(args.upload_only);
+        assert!(parse(&[&upload[..], &["--views", "by-year"]].concat()).is_err());
+        assert!(parse(&["snapdown", "download", "--s3-prefix", "snapchat"]).is_err());
+        let s3 = ["snapdown", "download", "--s3-bucket", "memories"];
+        let Some(Command::Download(args)) = parse(&s3).unwrap().command else {
+            panic!("expected a download");
+        };
+        assert_eq!(args.s3_bucket.as_deref(), Some("memories"));
+        assert_eq!(args.s3_region, "us-east-1");
+        assert!(parse(&[&s3[..], &["--sidecars"]].concat()).is_err());
+        assert!(parse(&[&upload[..], &s3[2..]].concat()).is_err());
+        assert!(
+            parse(&[&s3[..], &["--webdav", "webdav://me@cloud.example.com/dav"]].concat()).is_err()
+        );
+        let Some(Command::Download(args)) = parse(&["snapdown", "download", "--retry-failed"])
+            .unwrap()
+            .command
+        else {
+            panic!("expected a download");
+        };
+        assert!(args.retry_failed && args.input.is_none());
+        assert!(parse(&["snapdown", "download", "--retry-failed", "-i", "in.csv"]).is_err());
+        let notify = [
+            "snapdown",
+            "download",
+            "--notify",
+            "--notify-error-rate",
+            "25%",
+        ];
+        let Some(Command::Download(args)) = parse(&notify).unwrap().command else {
+            panic!("expected a download");
+        };
+        assert!(args.notify);
+        assert_eq!(args.notify_error_rate, 25.0);
+        assert!(parse(&["snapdown", "download", "--notify-error-rate", "25"]).is_err());
+        assert!(parse(&[&notify[..4], &["150"]].concat()).is_err());
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let output = |args: &[&str]| {
+            let Some(Command::Parse(args)) = parse(args).unwrap().command else {
+                panic!("expected parse");
+            };
+            let (format, output) = args.output();
+            (format, output.to_string())
+        };
+        assert_eq!(
+            output(&["snapdown", "parse", "-i", "a.zip"]),
+            (ParseFormat::Csv, "snap_export.csv".to_string())
+        );
+        assert_eq!(
+            output(&["snapdown", "parse", "-i", "a.zip", "-o", "out.JSON"]),
+            (ParseFormat::Json, "out.JSON".to_string())
+        );
+        assert_eq!(
+            output(&["snapdown", "parse", "-i", "a.zip", "--format", "json"]),
+            (ParseFormat::Json, "memories.json".to_string())
+        );
+        assert_eq!(
+            output(&[
+                "snapdown", "parse", "-i", "a.zip", "-o", "-", "--format", "csv"
+            ]),
+            (ParseFormat::Csv, "-".to_string())
+        );
+    }
+}
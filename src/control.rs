@@ -0,0 +1,181 @@
+// Pause/resume/cancel for a run in progress. The GUI buttons and the CLI's
+// Ctrl+C handler flip these flags, and the download workers check them
+// between (and during) downloads.
+
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+
+// Why a run stopped before going through all the records
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    // Cancel button, or Ctrl+C
+    UserCancelled,
+    // Writing a file failed because the output directory's disk is full.
+    // Every other download would fail the same way, so there's no point
+    // going on.
+    DiskFull,
+    // Nothing downloaded for --stall-timeout, with --restart-stalled. The
+    // CLI starts the run again, a few times, see watchdog.rs.
+    Stalled,
+    // A download failed, with --fail-fast
+    FailFast,
+}
+
+impl StopReason {
+    // Exit code of a CLI run that stopped for this reason, see EXIT STATUS in
+    // help.rs
+    pub fn exit_code(self) -> i32 {
+        match self {
+            StopReason::UserCancelled => 130,
+            StopReason::DiskFull => 2,
+            StopReason::Stalled => 3,
+            StopReason::FailFast => 4,
+        }
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::UserCancelled => write!(f, "cancelled by the user"),
+            StopReason::DiskFull => write!(f, "the output disk is full"),
+            StopReason::Stalled => write!(f, "the downloads stalled"),
+            StopReason::FailFast => write!(f, "a download failed, with --fail-fast"),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RunControl {
+    cancelled: AtomicBool,
+    paused: AtomicBool,
+    // Only the first reason is kept, e.g. a Ctrl+C while stopping because the
+    // disk is full doesn't change why the run stopped
+    stop_reason: Mutex<Option<StopReason>>,
+}
+
+impl RunControl {
+    pub fn cancel(&self) {
+        self.stop(StopReason::UserCancelled);
+    }
+
+    pub fn stop(&self, reason: StopReason) {
+        self.stop_reason.lock().unwrap().get_or_insert(reason);
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    // Undo a stop, to run again with the same control
+    pub fn restart(&self) {
+        *self.stop_reason.lock().unwrap() = None;
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        *self.stop_reason.lock().unwrap()
+    }
+
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    // Sleep for `duration`, or until the run is cancelled. Returns false if
+    // it was.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let until = std::time::Instant::now() + duration;
+        while !self.is_cancelled() {
+            let left = until.saturating_duration_since(std::time::Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            std::thread::sleep(left.min(Duration::from_millis(100)));
+        }
+        !self.is_cancelled()
+    }
+
+    // Block while paused. Returns false if the run was cancelled, either
+    // before or while waiting.
+    pub fn wait_while_paused(&self) -> bool {
+        while self.is_paused() && !self.is_cancelled() {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        !self.is_cancelled()
+    }
+}
+
+// Reader wrapper so a cancel also stops downloads that are midway through
+// their response body. Pausing also holds the body mid-stream, so large
+// videos don't keep the connection saturated.
+pub struct CancellableReader<'a, R> {
+    inner: R,
+    control: &'a RunControl,
+}
+
+impl<'a, R> CancellableReader<'a, R> {
+    pub fn new(inner: R, control: &'a RunControl) -> Self {
+        CancellableReader { inner, control }
+    }
+}
+
+impl<R: Read> Read for CancellableReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.control.wait_while_paused() {
+            // Not ErrorKind::Interrupted, since io::copy() retries those
+            return Err(io::Error::other("download cancelled"));
+        }
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancellable_reader_stops_after_cancel() {
+        let control = RunControl::default();
+        let data = [1u8; 16];
+        let mut reader = CancellableReader::new(&data[..], &control);
+        let mut buf = [0u8; 8];
+        assert_eq!(reader.read(&mut buf).unwrap(), 8);
+
+        control.cancel();
+        assert!(reader.read(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_first_stop_reason_is_kept() {
+        let control = RunControl::default();
+        assert_eq!(control.stop_reason(), None);
+        control.stop(StopReason::DiskFull);
+        control.cancel();
+        assert!(control.is_cancelled());
+        assert_eq!(control.stop_reason(), Some(StopReason::DiskFull));
+    }
+
+    #[test]
+    fn test_wait_while_paused_returns_on_cancel() {
+        let control = RunControl::default();
+        assert!(control.wait_while_paused());
+        control.pause();
+        control.cancel();
+        assert!(!control.wait_while_paused());
+    }
+}
@@ -0,0 +1,191 @@
+// Duplicate detection (--dedup). Some exports have the same photo or video
+// under several timestamps. Downloads are hashed while they're written anyway,
+// so each new file's hash is looked up among the files seen so far, and
+// duplicates are deleted or replaced by a hard link to the first copy.
+//
+// The hashes are kept in their own index next to the manifest, because the
+// manifest's checksums are taken after the EXIF tags are written, and those
+// differ between copies of a photo with different timestamps.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::hashing::FileHash;
+
+pub const DEDUP_INDEX_FILE: &str = "snapdown_dedup_index.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupMode {
+    Delete,
+    // Keeps every filename, but the data is only stored once. Needs a file
+    // system with hard links (not FAT32/exFAT).
+    HardLink,
+}
+
+impl DedupMode {
+    pub fn parse(mode: &str) -> Result<DedupMode> {
+        match mode.trim().to_ascii_lowercase().as_str() {
+            "delete" => Ok(DedupMode::Delete),
+            "hardlink" | "hard-link" | "link" => Ok(DedupMode::HardLink),
+            _ => Err(anyhow::anyhow!(
+                "unknown mode {:?}, expected delete or hardlink",
+                mode
+            )),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DedupMode::Delete => "Delete",
+            DedupMode::HardLink => "Hard link",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexEntry {
+    // BLAKE3 hash of the file as downloaded
+    hash: String,
+    // The first file seen with that hash, relative to the output directory
+    filename: String,
+}
+
+// Shared by the download workers, like the manifest
+pub struct DedupIndex {
+    mode: DedupMode,
+    output_dir: PathBuf,
+    seen: Mutex<HashMap<String, String>>,
+    journal: Mutex<File>,
+    duplicates: AtomicUsize,
+    bytes_saved: AtomicU64,
+}
+
+impl DedupIndex {
+    pub fn open(output_dir: &Path, mode: DedupMode) -> Result<DedupIndex> {
+        let path = output_dir.join(DEDUP_INDEX_FILE);
+        let mut seen = HashMap::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(&path)?).lines() {
+                match serde_json::from_str::<IndexEntry>(&line?) {
+                    Ok(entry) => {
+                        seen.insert(entry.hash, entry.filename);
+                    }
+                    Err(e) => log::warn!("Skipping bad line in {:?}: {}", path, e),
+                }
+            }
+        }
+        let journal = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(DedupIndex {
+            mode,
+            output_dir: output_dir.to_path_buf(),
+            seen: Mutex::new(seen),
+            journal: Mutex::new(journal),
+            duplicates: AtomicUsize::new(0),
+            bytes_saved: AtomicU64::new(0),
+        })
+    }
+
+    pub fn mode(&self) -> DedupMode {
+        self.mode
+    }
+
+    // Look up a file that was just downloaded. New content is added to the
+    // index and None returned. A duplicate is deleted or hard linked, and the
+    // file it duplicates returned.
+    pub fn dedup(&self, filename: &str, file_hash: &FileHash) -> Result<Option<String>> {
+        let original = {
+            let mut seen = self.seen.lock().unwrap();
+            match seen.get(&file_hash.hash) {
+                // The first copy may have been deleted by hand since
+                Some(original)
+                    if original != filename && self.output_dir.join(original).exists() =>
+                {
+                    original.clone()
+                }
+                _ => {
+                    seen.insert(file_hash.hash.clone(), filename.to_string());
+                    let entry = IndexEntry {
+                        hash: file_hash.hash.clone(),
+                        filename: filename.to_string(),
+                    };
+                    let mut journal = self.journal.lock().unwrap();
+                    writeln!(journal, "{}", serde_json::to_string(&entry)?)?;
+                    return Ok(None);
+                }
+            }
+        };
+
+        let path = self.output_dir.join(filename);
+        match self.mode {
+            DedupMode::Delete => fs::remove_file(&path)?,
+            DedupMode::HardLink => {
+                // Through a temporary name, so the file is never missing
+                let temp_path = path.with_extension("dedup.tmp");
+                let _ = fs::remove_file(&temp_path);
+                fs::hard_link(self.output_dir.join(&original), &temp_path)?;
+                fs::rename(&temp_path, &path)?;
+            }
+        }
+        self.duplicates.fetch_add(1, Ordering::Relaxed);
+        self.bytes_saved
+            .fetch_add(file_hash.size, Ordering::Relaxed);
+        Ok(Some(original))
+    }
+
+    pub fn duplicates(&self) -> usize {
+        self.duplicates.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_saved(&self) -> u64 {
+        self.bytes_saved.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::hash_file;
+
+    #[test]
+    fn test_dedup() {
+        let dir = std::env::temp_dir().join("snapdown_test_dedup");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in [("a.jpg", "same"), ("b.jpg", "same"), ("c.jpg", "other")] {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        let hash = |name: &str| hash_file(&dir.join(name)).unwrap();
+
+        let index = DedupIndex::open(&dir, DedupMode::Delete).unwrap();
+        assert_eq!(index.dedup("a.jpg", &hash("a.jpg")).unwrap(), None);
+        assert_eq!(index.dedup("c.jpg", &hash("c.jpg")).unwrap(), None);
+        assert_eq!(
+            index.dedup("b.jpg", &hash("b.jpg")).unwrap(),
+            Some("a.jpg".to_string())
+        );
+        assert!(!dir.join("b.jpg").exists());
+        assert_eq!((index.duplicates(), index.bytes_saved()), (1, 4));
+        drop(index);
+
+        // The index is kept between runs
+        fs::write(dir.join("d.jpg"), "same").unwrap();
+        let index = DedupIndex::open(&dir, DedupMode::HardLink).unwrap();
+        assert_eq!(
+            index.dedup("d.jpg", &hash("d.jpg")).unwrap(),
+            Some("a.jpg".to_string())
+        );
+        assert_eq!(fs::read_to_string(dir.join("d.jpg")).unwrap(), "same");
+        // Downloading the first copy again isn't a duplicate
+        assert_eq!(index.dedup("a.jpg", &hash("a.jpg")).unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
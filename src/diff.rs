@@ -0,0 +1,168 @@
+// `snapdown diff`: the memories in one export and not the other, to check
+// nothing disappeared from an account between two exports. The links change
+// with every export, so memories are matched by the ID in their link (see
+// naming::memory_id()) where both have one, and the rest by when they were
+// taken and their media type.
+
+use std::collections::HashMap;
+
+use crate::export::snap_export_row;
+use crate::naming;
+
+#[derive(Debug, Default)]
+pub struct ExportDiff {
+    // In the old export only, in its order
+    pub removed: Vec<csv::StringRecord>,
+    // In the new export only, in its order
+    pub added: Vec<csv::StringRecord>,
+    pub unchanged: usize,
+    // Rows of either export that don't look like a memory
+    pub invalid: usize,
+}
+
+// When a memory was taken and its media type, e.g. "2023-06-01 10:00:00
+// UTC, Image"
+pub fn describe(row: &csv::StringRecord) -> String {
+    match snap_export_row(row) {
+        Some(row) => format!("{}, {}", row[0], row[1]),
+        None => String::new(),
+    }
+}
+
+pub fn diff_exports(old: &[csv::StringRecord], new: &[csv::StringRecord]) -> ExportDiff {
+    let mut diff = ExportDiff::default();
+    let valid = |records: &[csv::StringRecord]| -> Vec<usize> {
+        (0..records.len())
+            .filter(|&index| snap_export_row(&records[index]).is_some())
+            .collect()
+    };
+    let old_left = valid(old);
+    let new_left = valid(new);
+    let old_valid = old_left.len();
+    diff.invalid = old.len() - old_valid + new.len() - new_left.len();
+
+    let (old_left, new_left) = match_by(old, new, old_left, new_left, naming::memory_id);
+    let by_time = |row: &csv::StringRecord| Some(describe(row));
+    let (old_left, new_left) = match_by(old, new, old_left, new_left, by_time);
+
+    diff.unchanged = old_valid - old_left.len();
+    diff.removed = old_left
+        .into_iter()
+        .map(|index| old[index].clone())
+        .collect();
+    diff.added = new_left
+        .into_iter()
+        .map(|index| new[index].clone())
+        .collect();
+    diff
+}
+
+// Pair up the records left in each export that have the same key, each
+// with at most one. Returns the ones still left, and those without a key.
+fn match_by(
+    old: &[csv::StringRecord],
+    new: &[csv::StringRecord],
+    old_left: Vec<usize>,
+    new_left: Vec<usize>,
+    key: impl Fn(&csv::StringRecord) -> Option<String>,
+) -> (Vec<usize>, Vec<usize>) {
+    let mut new_by_key: HashMap<String, Vec<usize>> = HashMap::new();
+    for &index in new_left.iter().rev() {
+        if let Some(key) = key(&new[index]) {
+            new_by_key.entry(key).or_default().push(index);
+        }
+    }
+    let mut matched = vec![false; new.len()];
+    let old_left = old_left
+        .into_iter()
+        .filter(|&index| {
+            let Some(key) = key(&old[index]) else {
+                return true;
+            };
+            // The first one left with the key, they were pushed last first
+            match new_by_key.get_mut(&key).and_then(Vec::pop) {
+                Some(new_index) => {
+                    matched[new_index] = true;
+                    false
+                }
+                None => true,
+            }
+        })
+        .collect();
+    let new_left = new_left
+        .into_iter()
+        .filter(|&index| !matched[index])
+        .collect();
+    (old_left, new_left)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(timestamp: &str, media_type: &str, url: &str) -> csv::StringRecord {
+        csv::StringRecord::from(vec![timestamp, media_type, "0.0", "0.0", url])
+    }
+
+    #[test]
+    fn test_diff_exports() {
+        let old = [
+            row(
+                "2023-06-01 10:00:00 UTC",
+                "Image",
+                "https://a.example.com/dmd?mid=1&sig=a",
+            ),
+            row(
+                "2023-06-01 11:00:00 UTC",
+                "Video",
+                "https://a.example.com/dmd?mid=2&sig=a",
+            ),
+            // No memory ID, matched by its time
+            row(
+                "2023-06-02 09:00:00 UTC",
+                "Image",
+                "https://b.example.com/old",
+            ),
+            row(
+                "2023-06-03 09:00:00 UTC",
+                "Image",
+                "https://a.example.com/dmd?mid=3&sig=a",
+            ),
+            csv::StringRecord::from(vec!["not a memory"]),
+        ];
+        let new = [
+            // Signed again, the same memories
+            row(
+                "2023-06-01 10:00:00 UTC",
+                "Image",
+                "https://a.example.com/dmd?mid=1&sig=b",
+            ),
+            row(
+                "2023-06-02 09:00:00 UTC",
+                "Image",
+                "https://b.example.com/new",
+            ),
+            row(
+                "2023-06-03 09:00:00 UTC",
+                "Image",
+                "https://a.example.com/dmd?mid=3&sig=b",
+            ),
+            row(
+                "2024-01-01 00:00:00 UTC",
+                "Video",
+                "https://a.example.com/dmd?mid=4&sig=b",
+            ),
+        ];
+        let diff = diff_exports(&old, &new);
+        assert_eq!(diff.unchanged, 3);
+        assert_eq!(diff.invalid, 1);
+        assert_eq!(diff.removed, [old[1].clone()]);
+        assert_eq!(diff.added, [new[3].clone()]);
+        assert_eq!(describe(&diff.removed[0]), "2023-06-01 11:00:00 UTC, Video");
+
+        // Two memories taken in the same second only match once each
+        let twice = [old[2].clone(), old[2].clone()];
+        let diff = diff_exports(&twice, &old[2..3]);
+        assert_eq!((diff.unchanged, diff.removed.len()), (1, 1));
+    }
+}
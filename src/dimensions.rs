@@ -0,0 +1,182 @@
+// Image sizes from just the start of a file. The browse tab shows each
+// photo's size and whether it's portrait or landscape, and for memories that
+// aren't downloaded yet it can get that with a range request for the first
+// few KB instead of downloading the whole photo.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::exif_tags;
+
+// JPEG and PNG headers are well within this, unless there's a big EXIF
+// thumbnail in front of the size
+pub const SAMPLE_SIZE: u64 = 32 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageSize {
+    pub width: u32,
+    pub height: u32,
+    // EXIF orientation, 1 if there isn't one
+    pub orientation: u16,
+}
+
+impl ImageSize {
+    // Width and height as the photo is shown, i.e. after the EXIF rotation
+    pub fn displayed(&self) -> (u32, u32) {
+        if (5..=8).contains(&self.orientation) {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        }
+    }
+
+    pub fn label(&self) -> String {
+        let (width, height) = self.displayed();
+        let shape = if width > height {
+            "landscape"
+        } else if height > width {
+            "portrait"
+        } else {
+            "square"
+        };
+        format!("{}x{} {}", width, height, shape)
+    }
+}
+
+// The size of a JPEG or PNG from its first bytes. None for other files, or if
+// the size isn't in `data`.
+pub fn from_header(data: &[u8]) -> Option<ImageSize> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        png_size(data)
+    } else if data.starts_with(&[0xFF, 0xD8]) {
+        jpeg_size(data)
+    } else {
+        None
+    }
+}
+
+// The IHDR chunk always comes first
+fn png_size(data: &[u8]) -> Option<ImageSize> {
+    if data.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    Some(ImageSize {
+        width: u32::from_be_bytes(data.get(16..20)?.try_into().ok()?),
+        height: u32::from_be_bytes(data.get(20..24)?.try_into().ok()?),
+        orientation: 1,
+    })
+}
+
+// Walk the segments up to the start of frame, picking up the orientation from
+// the EXIF segment on the way
+fn jpeg_size(data: &[u8]) -> Option<ImageSize> {
+    let mut orientation = 1;
+    let mut i = 2;
+    loop {
+        if *data.get(i)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(i + 1)?;
+        match marker {
+            // Fill byte
+            0xFF => {
+                i += 1;
+                continue;
+            }
+            // Markers without a length
+            0xD0..=0xD8 | 0x01 => {
+                i += 2;
+                continue;
+            }
+            // End of image, or start of scan before any frame
+            0xD9 | 0xDA => return None,
+            _ => {}
+        }
+        let length = u16::from_be_bytes([*data.get(i + 2)?, *data.get(i + 3)?]) as usize;
+        let segment = data.get(i + 4..i + 2 + length)?;
+        match marker {
+            0xE1 if segment.starts_with(b"Exif\0\0") => {
+                orientation = exif_tags::orientation(Some(&segment[6..])) as u16;
+            }
+            // Start of frame, except DHT, JPG and DAC which share the range
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                return Some(ImageSize {
+                    height: u16::from_be_bytes([*segment.get(1)?, *segment.get(2)?]) as u32,
+                    width: u16::from_be_bytes([*segment.get(3)?, *segment.get(4)?]) as u32,
+                    orientation,
+                });
+            }
+            _ => {}
+        }
+        i += 2 + length;
+    }
+}
+
+// Size of a downloaded file
+pub fn read_local(path: &Path) -> Result<Option<ImageSize>> {
+    let mut data = Vec::new();
+    File::open(path)?.take(SAMPLE_SIZE).read_to_end(&mut data)?;
+    Ok(from_header(&data))
+}
+
+// Size of a file that isn't downloaded yet, from the first SAMPLE_SIZE bytes.
+// Servers that ignore the Range header send the whole file, but only the
+// start of it is read before the connection is dropped.
+pub fn sample_remote(agent: &ureq::Agent, download_url: &str) -> Result<Option<ImageSize>> {
+    let mut response = agent
+        .get(download_url)
+        .header("Range", format!("bytes=0-{}", SAMPLE_SIZE - 1))
+        .call()?;
+    let mut data = Vec::new();
+    response
+        .body_mut()
+        .as_reader()
+        .take(SAMPLE_SIZE)
+        .read_to_end(&mut data)?;
+    Ok(from_header(&data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn encode(width: u32, height: u32, format: image::ImageFormat) -> Vec<u8> {
+        let mut data = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut data), format)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_from_header() {
+        let jpeg = encode(30, 20, image::ImageFormat::Jpeg);
+        assert_eq!(
+            from_header(&jpeg),
+            Some(ImageSize {
+                width: 30,
+                height: 20,
+                orientation: 1
+            })
+        );
+        let png = encode(7, 9, image::ImageFormat::Png);
+        assert_eq!(from_header(&png[..24]).unwrap().label(), "7x9 portrait");
+        assert_eq!(from_header(&jpeg[..10]), None);
+        assert_eq!(from_header(b"PK\x03\x04 a zip"), None);
+    }
+
+    #[test]
+    fn test_rotated_size() {
+        let size = ImageSize {
+            width: 1920,
+            height: 1080,
+            orientation: 6,
+        };
+        assert_eq!(size.displayed(), (1080, 1920));
+        assert_eq!(size.label(), "1080x1920 portrait");
+    }
+}
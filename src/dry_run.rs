@@ -0,0 +1,175 @@
+// --dry-run: go through the input like a real run would, working out every
+// file name and checking what's already in the output directory, but without
+// any requests and without writing anything. Handy for checking a huge export
+// before spending hours downloading it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use crate::format::format_count;
+use crate::manifest::{self, DownloadPlan, ManifestEntry};
+use crate::naming::{self, Namer};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DryRunReport {
+    // Not in the output directory yet
+    pub to_download: usize,
+    // Partially downloaded before, would be continued
+    pub to_resume: usize,
+    // Already downloaded completely
+    pub to_skip: usize,
+    // Rows that don't look like a memory
+    pub invalid: usize,
+    // Files to download or resume, by media type (Image, Video, ...)
+    pub by_media_type: BTreeMap<String, usize>,
+}
+
+impl DryRunReport {
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            "Dry run, nothing was downloaded. A real run would:".to_string(),
+            format!("  - Download: {} files", format_count(self.to_download)),
+            format!(
+                "  - Continue partial downloads: {} files",
+                format_count(self.to_resume)
+            ),
+            format!(
+                "  - Skip: {} files (already downloaded)",
+                format_count(self.to_skip)
+            ),
+        ];
+        if self.invalid > 0 {
+            lines.push(format!(
+                "  - Fail: {} rows (unexpected number of columns)",
+                format_count(self.invalid)
+            ));
+        }
+        if !self.by_media_type.is_empty() {
+            lines.push("Files to download by media type:".to_string());
+            for (media_type, count) in &self.by_media_type {
+                lines.push(format!("  - {}: {}", media_type, format_count(*count)));
+            }
+        }
+        lines
+    }
+}
+
+// With `force`, everything is downloaded again, as with --force
+pub fn plan_run(
+    records: &[csv::StringRecord],
+    output_dir: &Path,
+    namer: &dyn Namer,
+    force: bool,
+) -> DryRunReport {
+    let manifest_entries = manifest::read_entries(output_dir).unwrap_or_else(|e| {
+        log::error!("Error reading {}: {}", manifest::MANIFEST_FILE, e);
+        HashMap::new()
+    });
+    plan_records(records, output_dir, namer, &manifest_entries, force)
+}
+
+// Files without a manifest entry are counted as skipped, though the real run
+// checks their size with the server first and may download them again
+fn plan_records(
+    records: &[csv::StringRecord],
+    output_dir: &Path,
+    namer: &dyn Namer,
+    manifest_entries: &HashMap<String, ManifestEntry>,
+    force: bool,
+) -> DryRunReport {
+    let mut report = DryRunReport::default();
+    for (row, filename_and_url) in records.iter().zip(naming::unique_filenames_and_urls(
+        records,
+        namer,
+        &|filename| manifest_entries.contains_key(filename),
+    )) {
+        let Some((filename, _)) = filename_and_url else {
+            report.invalid += 1;
+            continue;
+        };
+        let entry = manifest_entries.get(&filename);
+        let saved_filename = entry.map_or(filename.as_str(), ManifestEntry::saved_filename);
+        let existing_size = fs::metadata(output_dir.join(saved_filename))
+            .map(|metadata| metadata.len())
+            .ok();
+        let plan = if force {
+            DownloadPlan::Fresh
+        } else {
+            manifest::plan_download(entry, existing_size)
+        };
+        match plan {
+            DownloadPlan::Skip | DownloadPlan::AdoptExisting { .. } => {
+                report.to_skip += 1;
+                continue;
+            }
+            DownloadPlan::Resume { .. } => report.to_resume += 1,
+            DownloadPlan::Fresh => report.to_download += 1,
+        }
+        *report.by_media_type.entry(row[1].to_string()).or_default() += 1;
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::EntryStatus;
+    use crate::naming::LegacyNamer;
+
+    #[test]
+    fn test_plan_records() {
+        let dir = std::env::temp_dir().join("snapdown_test_dry_run");
+        fs::create_dir_all(&dir).unwrap();
+        let record = |timestamp: &str, media_type: &str| {
+            csv::StringRecord::from(vec![
+                timestamp,
+                media_type,
+                "40.0",
+                "-111.0",
+                "https://example.com/a",
+            ])
+        };
+        let records = [
+            record("2026-01-01 00:00:00 UTC", "Image"),
+            record("2026-01-02 00:00:00 UTC", "Video"),
+            record("2026-01-03 00:00:00 UTC", "Video"),
+            record("2026-01-04 00:00:00 UTC", "Image"),
+            csv::StringRecord::from(vec!["bad row"]),
+        ];
+        let done = crate::record_filename_and_url(&records[0]).unwrap().0;
+        let partial = crate::record_filename_and_url(&records[1]).unwrap().0;
+        fs::write(dir.join(&done), b"done").unwrap();
+        fs::write(dir.join(&partial), b"pa").unwrap();
+        let entry = |filename: &String, status| ManifestEntry {
+            url: "https://example.com/a".to_string(),
+            filename: filename.clone(),
+            status,
+            bytes_written: 4,
+            checksum: None,
+            views: Vec::new(),
+            mime_type: None,
+            saved_as: None,
+            uploaded: None,
+            md5: None,
+            record: None,
+        };
+        let manifest_entries = HashMap::from([
+            (done.clone(), entry(&done, EntryStatus::Completed)),
+            (partial.clone(), entry(&partial, EntryStatus::Downloading)),
+        ]);
+
+        let report = plan_records(&records, &dir, &LegacyNamer, &manifest_entries, false);
+        assert_eq!(
+            report,
+            DryRunReport {
+                to_download: 2,
+                to_resume: 1,
+                to_skip: 1,
+                invalid: 1,
+                by_media_type: BTreeMap::from([("Image".to_string(), 1), ("Video".to_string(), 2)]),
+            }
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
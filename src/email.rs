@@ -0,0 +1,107 @@
+// Summary email at the end of a CLI run (--smtp and --email-to), for runs
+// started by a scheduled task that nobody watches. The failed memories are
+// attached as a snap_export.csv, which can be fed straight back in to retry
+// them.
+
+use anyhow::Result;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::{Message, SmtpTransport, Transport};
+use url::Url;
+
+// So the password doesn't have to be in the --smtp URL, where it would end
+// up in shell history and scheduled task settings
+pub const SMTP_PASSWORD_ENV: &str = "SNAPDOWN_SMTP_PASSWORD";
+
+#[derive(Debug, Clone)]
+pub struct EmailSettings {
+    // e.g. smtps://me%40gmail.com@smtp.gmail.com, see lettre's
+    // SmtpTransport::from_url() for the format
+    smtp_url: Url,
+    from: Mailbox,
+    to: Mailbox,
+}
+
+impl EmailSettings {
+    // Checked when parsing the arguments, so a typo shows up right away and
+    // not after hours of downloading
+    pub fn new(smtp_url: &str, to: &str, from: Option<&str>) -> Result<EmailSettings> {
+        let mut smtp_url =
+            Url::parse(smtp_url).map_err(|e| anyhow::anyhow!("invalid SMTP URL: {}", e))?;
+        if smtp_url.password().is_none()
+            && let Ok(password) = std::env::var(SMTP_PASSWORD_ENV)
+        {
+            smtp_url
+                .set_password(Some(&password))
+                .map_err(|_| anyhow::anyhow!("SMTP URL can't have a password"))?;
+        }
+        // Fails for unknown schemes and missing hosts
+        SmtpTransport::from_url(smtp_url.as_str())?;
+        let to: Mailbox = to
+            .parse()
+            .map_err(|e| anyhow::anyhow!("invalid address {:?}: {}", to, e))?;
+        let from = match from {
+            Some(from) => from
+                .parse()
+                .map_err(|e| anyhow::anyhow!("invalid address {:?}: {}", from, e))?,
+            None => to.clone(),
+        };
+        Ok(EmailSettings { smtp_url, from, to })
+    }
+
+    // For --print-config, without the password
+    pub fn smtp_server(&self) -> String {
+        let mut smtp_url = self.smtp_url.clone();
+        let _ = smtp_url.set_password(None);
+        smtp_url.to_string()
+    }
+
+    pub fn to(&self) -> String {
+        self.to.to_string()
+    }
+
+    pub fn from(&self) -> String {
+        self.from.to_string()
+    }
+
+    pub fn send(
+        &self,
+        subject: &str,
+        body: String,
+        attachment: Option<(&str, Vec<u8>)>,
+    ) -> Result<()> {
+        let builder = Message::builder()
+            .from(self.from.clone())
+            .to(self.to.clone())
+            .subject(subject);
+        let text = SinglePart::plain(body);
+        let message = match attachment {
+            Some((filename, data)) => builder.multipart(
+                MultiPart::mixed().singlepart(text).singlepart(
+                    Attachment::new(filename.to_string())
+                        .body(data, ContentType::parse("text/csv").unwrap()),
+                ),
+            )?,
+            None => builder.singlepart(text)?,
+        };
+        SmtpTransport::from_url(self.smtp_url.as_str())?
+            .build()
+            .send(&message)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_email_settings() {
+        let settings =
+            EmailSettings::new("smtps://me@smtp.example.com", "me@example.com", None).unwrap();
+        assert_eq!(settings.from, settings.to);
+        assert!(EmailSettings::new("smtp.example.com", "me@example.com", None).is_err());
+        assert!(EmailSettings::new("ftp://example.com", "me@example.com", None).is_err());
+        assert!(EmailSettings::new("smtps://smtp.example.com", "not an address", None).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+// Input files opened and saved again in Excel or Notepad can start with a
+// byte order mark, or be UTF-16 ("Unicode" in their Save dialogs). The
+// parsers only understand UTF-8, and a BOM ends up in the first header
+// cell, so Utf8Reader turns such files into plain UTF-8 while they're read.
+
+use std::io::{self, Read};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+// Wraps the reader of an input file so it reads as UTF-8 without a BOM
+pub struct Utf8Reader<R> {
+    inner: R,
+    encoding: Encoding,
+    // Read from `inner`, but not returned yet: the first bytes when they
+    // weren't a BOM, or UTF-16 transcoded to UTF-8
+    pending: Vec<u8>,
+    pending_start: usize,
+    // UTF-16 bytes that don't make a whole character yet
+    partial: Vec<u8>,
+}
+
+impl<R: Read> Utf8Reader<R> {
+    // Reads the first few bytes to find out the encoding
+    pub fn new(mut inner: R) -> io::Result<Utf8Reader<R>> {
+        let mut start = Vec::with_capacity(4);
+        (&mut inner).take(4).read_to_end(&mut start)?;
+        let (encoding, bom_len) = detect(&start);
+        if encoding != Encoding::Utf8 {
+            log::info!("Input file is {:?}, converting it to UTF-8", encoding);
+        }
+        let mut reader = Utf8Reader {
+            inner,
+            encoding,
+            pending: Vec::new(),
+            pending_start: 0,
+            partial: Vec::new(),
+        };
+        let start = &start[bom_len..];
+        match encoding {
+            Encoding::Utf8 => reader.pending.extend_from_slice(start),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                reader.partial.extend_from_slice(start);
+                reader.transcode(false);
+            }
+        }
+        Ok(reader)
+    }
+
+    // Move the whole characters in `partial` to `pending` as UTF-8. At the
+    // end of the file, whatever is left over becomes U+FFFD.
+    fn transcode(&mut self, at_end: bool) {
+        let mut units: Vec<u16> = self
+            .partial
+            .chunks_exact(2)
+            .map(|pair| match self.encoding {
+                Encoding::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+                _ => u16::from_le_bytes([pair[0], pair[1]]),
+            })
+            .collect();
+        let mut kept = self.partial.len() % 2;
+        // A high surrogate needs the unit after it
+        if !at_end
+            && units
+                .last()
+                .is_some_and(|unit| (0xD800..0xDC00).contains(unit))
+        {
+            units.pop();
+            kept += 2;
+        }
+        for c in char::decode_utf16(units) {
+            let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+            self.pending
+                .extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+        self.partial.drain(..self.partial.len() - kept);
+        if at_end && !self.partial.is_empty() {
+            self.partial.clear();
+            self.pending
+                .extend_from_slice(char::REPLACEMENT_CHARACTER.to_string().as_bytes());
+        }
+    }
+}
+
+impl<R: Read> Read for Utf8Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.pending_start < self.pending.len() {
+                let pending = &self.pending[self.pending_start..];
+                let mut len = pending.len().min(buf.len());
+                buf[..len].copy_from_slice(&pending[..len]);
+                self.pending_start += len;
+                if self.pending_start == self.pending.len() {
+                    self.pending.clear();
+                    self.pending_start = 0;
+                    // So the first read isn't just the few bytes that were
+                    // looked at, the HTML parser looks at the start too
+                    if self.encoding == Encoding::Utf8 {
+                        len += self.inner.read(&mut buf[len..])?;
+                    }
+                }
+                return Ok(len);
+            }
+            if self.encoding == Encoding::Utf8 {
+                return self.inner.read(buf);
+            }
+            let mut raw = [0u8; 8192];
+            let read = self.inner.read(&mut raw)?;
+            self.partial.extend_from_slice(&raw[..read]);
+            self.transcode(read == 0);
+            if read == 0 && self.pending.is_empty() {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+// The encoding of a file starting with `start`, and the length of its BOM.
+// UTF-16 without a BOM is recognized by the zero bytes next to the ASCII
+// characters every input file starts with.
+fn detect(start: &[u8]) -> (Encoding, usize) {
+    match start {
+        [0xEF, 0xBB, 0xBF, ..] => (Encoding::Utf8, 3),
+        [0xFF, 0xFE, ..] => (Encoding::Utf16Le, 2),
+        [0xFE, 0xFF, ..] => (Encoding::Utf16Be, 2),
+        [a, 0, b, 0] if *a != 0 && *b != 0 => (Encoding::Utf16Le, 0),
+        [0, a, 0, b] if *a != 0 && *b != 0 => (Encoding::Utf16Be, 0),
+        _ => (Encoding::Utf8, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(data: &[u8]) -> (Encoding, String) {
+        let mut text = String::new();
+        Utf8Reader::new(data)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        (detect(&data[..data.len().min(4)]).0, text)
+    }
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| {
+                if big_endian {
+                    unit.to_be_bytes()
+                } else {
+                    unit.to_le_bytes()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_utf8_reader() {
+        let text = "timestamp_utc,format\n2026-01-13 01:55:38 UTC,Zoë 📷\n";
+        assert_eq!(
+            read_all(text.as_bytes()),
+            (Encoding::Utf8, text.to_string())
+        );
+        assert_eq!(
+            read_all(&[b"\xEF\xBB\xBF", text.as_bytes()].concat()),
+            (Encoding::Utf8, text.to_string())
+        );
+        assert_eq!(
+            read_all(&[&b"\xFF\xFE"[..], &utf16(text, false)].concat()),
+            (Encoding::Utf16Le, text.to_string())
+        );
+        assert_eq!(
+            read_all(&[&b"\xFE\xFF"[..], &utf16(text, true)].concat()),
+            (Encoding::Utf16Be, text.to_string())
+        );
+        assert_eq!(
+            read_all(&utf16(text, false)),
+            (Encoding::Utf16Le, text.to_string())
+        );
+        // Bigger than a read, so characters are split between reads
+        let long = text.repeat(1000);
+        assert_eq!(read_all(&utf16(&long, true)).1, long);
+        assert_eq!(read_all(b"ab"), (Encoding::Utf8, "ab".to_string()));
+        assert_eq!(
+            read_all(b"\xFF\xFEa"),
+            (Encoding::Utf16Le, "\u{FFFD}".to_string())
+        );
+    }
+}
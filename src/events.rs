@@ -0,0 +1,259 @@
+// What a run tells whoever started it while it goes: the records it read,
+// each file as it starts, downloads and ends, the counts of the whole run
+// every so often and at the end, and the console's log lines.
+// run_downloader() sends them all to one EventSink, which the GUI and the CLI
+// each have their own of, so neither follows a run by reading the log.
+//
+// Sending never fails from the run's side. A sink that can't pass an event on
+// (a GUI that was closed) logs that and drops it.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::error;
+
+use crate::file_list::{FileRow, FileStatus};
+use crate::gui_channel;
+use crate::tray::TrayTarget;
+use crate::{LogEntry, SnapdownStatus};
+
+// Files are referred to by their position in the input
+pub enum DownloadEvent {
+    // Sent once the input is read, with every record in input order
+    Parsed {
+        rows: Vec<FileRow>,
+    },
+    Started {
+        id: usize,
+    },
+    // The bytes of the file written so far, partial file included, every
+    // PROGRESS_INTERVAL while its body is downloaded
+    Progress {
+        id: usize,
+        bytes: u64,
+    },
+    // Downloaded, skipped or not a memory, with the size on disk if there is
+    // a file
+    Finished {
+        id: usize,
+        status: FileStatus,
+        size: Option<u64>,
+    },
+    Failed {
+        id: usize,
+        error: String,
+    },
+    // The counts of the run so far, when it starts and then every
+    // STATUS_INTERVAL
+    Status(Box<SnapdownStatus>),
+    // The last status, once the run is over
+    Summary(Box<SnapdownStatus>),
+    // A line for the GUI's console. It's in the log already.
+    Log(LogEntry),
+}
+
+impl DownloadEvent {
+    // The file it's about, if it's about one
+    fn file_id(&self) -> Option<usize> {
+        match self {
+            DownloadEvent::Started { id }
+            | DownloadEvent::Progress { id, .. }
+            | DownloadEvent::Finished { id, .. }
+            | DownloadEvent::Failed { id, .. } => Some(*id),
+            _ => None,
+        }
+    }
+}
+
+pub trait EventSink: Sync {
+    fn send(&self, event: DownloadEvent);
+}
+
+// Often enough for a progress bar, not a send per read of every download
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+// Sends the Progress of file `id` as its body is read, starting from `bytes`
+// (the part already there when continuing a partial file)
+pub struct FileProgress<'a> {
+    events: &'a dyn EventSink,
+    id: usize,
+    bytes: u64,
+    last_sent: Instant,
+}
+
+impl<'a> FileProgress<'a> {
+    pub fn new(events: &'a dyn EventSink, id: usize, bytes: u64) -> Self {
+        FileProgress {
+            events,
+            id,
+            bytes,
+            last_sent: Instant::now(),
+        }
+    }
+
+    pub fn add(&mut self, read: usize) {
+        self.bytes += read as u64;
+        if self.last_sent.elapsed() >= PROGRESS_INTERVAL {
+            self.last_sent = Instant::now();
+            self.events.send(DownloadEvent::Progress {
+                id: self.id,
+                bytes: self.bytes,
+            });
+        }
+    }
+}
+
+// The file events the GUI hasn't read yet. Only the last of each file's is
+// kept, as that's all the file list shows, so a window hidden to the tray for
+// a run of hours holds one for each record at most rather than every one.
+#[derive(Default)]
+pub struct FileEvents {
+    pending: Mutex<PendingFileEvents>,
+}
+
+#[derive(Default)]
+struct PendingFileEvents {
+    parsed: Option<Vec<FileRow>>,
+    by_id: BTreeMap<usize, DownloadEvent>,
+}
+
+impl FileEvents {
+    pub fn push(&self, event: DownloadEvent) {
+        let mut pending = self.pending.lock().unwrap();
+        match (event.file_id(), event) {
+            (Some(id), event) => {
+                pending.by_id.insert(id, event);
+            }
+            // A new run, what's left of the last one is for its rows
+            (None, DownloadEvent::Parsed { rows }) => {
+                pending.parsed = Some(rows);
+                pending.by_id.clear();
+            }
+            _ => {}
+        }
+    }
+
+    // Everything since the last time, the rows first
+    pub fn take(&self) -> Vec<DownloadEvent> {
+        let mut pending = self.pending.lock().unwrap();
+        let parsed = pending
+            .parsed
+            .take()
+            .map(|rows| DownloadEvent::Parsed { rows });
+        parsed
+            .into_iter()
+            .chain(std::mem::take(&mut pending.by_id).into_values())
+            .collect()
+    }
+}
+
+// The GUI's channels for the console, the file list and the status area,
+// and the tray icon if there is one
+pub struct GuiSink {
+    pub logs: gui_channel::Sender<LogEntry>,
+    pub files: Arc<FileEvents>,
+    pub statuses: gui_channel::Sender<SnapdownStatus>,
+    pub tray: Option<Arc<TrayTarget>>,
+}
+
+impl EventSink for GuiSink {
+    fn send(&self, event: DownloadEvent) {
+        match event {
+            DownloadEvent::Status(status) | DownloadEvent::Summary(status) => {
+                // The tray first, the GUI doesn't read the statuses while
+                // it's hidden, see tray.rs
+                if let Some(tray) = &self.tray {
+                    let (state, tooltip) = status.tray_status();
+                    tray.set_status(state, tooltip);
+                }
+                if let Err(e) = self.statuses.send(*status) {
+                    error!("Error sending status to GUI: {}", e);
+                }
+            }
+            DownloadEvent::Log(entry) => {
+                if let Err(e) = self.logs.send(entry) {
+                    error!("Error sending message to GUI console: {}", e);
+                }
+            }
+            event => self.files.push(event),
+        }
+    }
+}
+
+// The CLI only shows the statuses, in its progress bar. Its log lines are
+// printed by the logger.
+pub struct CliSink {
+    pub statuses: gui_channel::Sender<SnapdownStatus>,
+}
+
+impl EventSink for CliSink {
+    fn send(&self, event: DownloadEvent) {
+        if let DownloadEvent::Status(status) | DownloadEvent::Summary(status) = event
+            && let Err(e) = self.statuses.send(*status)
+        {
+            error!("Error sending status to the progress bar: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gui_sink() {
+        let (logs, recv_logs) = gui_channel::bounded(gui_channel::LOG_CAPACITY);
+        let files = Arc::new(FileEvents::default());
+        let (statuses, recv_statuses) = gui_channel::bounded(gui_channel::STATUS_CAPACITY);
+        let sink = GuiSink {
+            logs,
+            files: files.clone(),
+            statuses,
+            tray: None,
+        };
+        sink.send(DownloadEvent::Status(Box::new(SnapdownStatus::new(3))));
+        sink.send(DownloadEvent::Started { id: 1 });
+        sink.send(DownloadEvent::Log(LogEntry {
+            level: log::Level::Info,
+            message: "Downloading 3 files:".to_string(),
+            record: None,
+        }));
+        sink.send(DownloadEvent::Failed {
+            id: 1,
+            error: "http status: 404".to_string(),
+        });
+
+        let statuses: Vec<_> = recv_statuses.try_iter().collect();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].total_count, 3);
+        let messages: Vec<_> = recv_logs.try_iter().map(|entry| entry.message).collect();
+        assert_eq!(messages, ["Downloading 3 files:"]);
+        // Only the last of the file's
+        assert!(matches!(
+            files.take()[..],
+            [DownloadEvent::Failed { id: 1, .. }]
+        ));
+        assert!(files.take().is_empty());
+    }
+
+    #[test]
+    fn test_file_events() {
+        let files = FileEvents::default();
+        files.push(DownloadEvent::Started { id: 4 });
+        // A new run
+        files.push(DownloadEvent::Parsed { rows: Vec::new() });
+        files.push(DownloadEvent::Started { id: 2 });
+        files.push(DownloadEvent::Progress { id: 2, bytes: 10 });
+        files.push(DownloadEvent::Started { id: 1 });
+        files.push(DownloadEvent::Progress { id: 2, bytes: 20 });
+        assert!(matches!(
+            files.take()[..],
+            [
+                DownloadEvent::Parsed { .. },
+                DownloadEvent::Started { id: 1 },
+                DownloadEvent::Progress { id: 2, bytes: 20 }
+            ]
+        ));
+    }
+}
@@ -0,0 +1,303 @@
+// Embed a memory's capture time and location in downloaded JPEGs as EXIF
+// tags, so photo managers sort them by when they were taken rather than when
+// they were downloaded. Other kinds of media are left alone.
+//
+// Also turns photos upright (--auto-rotate), for viewers that ignore the EXIF
+// orientation and show phone photos sideways.
+
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use exif::experimental::Writer;
+use exif::{Field, In, Rational, Tag, Value};
+use image::codecs::jpeg::JpegEncoder;
+use image::metadata::Orientation;
+use img_parts::jpeg::Jpeg;
+use img_parts::{Bytes, ImageEXIF};
+
+fn ascii(tag: Tag, value: &str) -> Field {
+    Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value: Value::Ascii(vec![value.as_bytes().to_vec()]),
+    }
+}
+
+// Degrees, minutes and seconds, as EXIF stores GPS coordinates
+fn degrees_minutes_seconds(value: f64) -> Vec<Rational> {
+    let value = value.abs();
+    let degrees = value.trunc();
+    let minutes = ((value - degrees) * 60.0).trunc();
+    let seconds = (value - degrees - minutes / 60.0) * 3600.0;
+    vec![
+        Rational::from((degrees as u32, 1)),
+        Rational::from((minutes as u32, 1)),
+        Rational::from(((seconds * 10_000.0).round() as u32, 10_000)),
+    ]
+}
+
+fn capture_fields(timestamp: DateTime<Utc>, location: Option<(f64, f64)>) -> Vec<Field> {
+    let mut fields = vec![
+        ascii(
+            Tag::DateTimeOriginal,
+            &timestamp.format("%Y:%m:%d %H:%M:%S").to_string(),
+        ),
+        ascii(Tag::OffsetTimeOriginal, "+00:00"),
+    ];
+    if let Some((latitude, longitude)) = location {
+        fields.extend([
+            Field {
+                tag: Tag::GPSVersionID,
+                ifd_num: In::PRIMARY,
+                value: Value::Byte(vec![2, 3, 0, 0]),
+            },
+            ascii(Tag::GPSLatitudeRef, if latitude < 0.0 { "S" } else { "N" }),
+            Field {
+                tag: Tag::GPSLatitude,
+                ifd_num: In::PRIMARY,
+                value: Value::Rational(degrees_minutes_seconds(latitude)),
+            },
+            ascii(
+                Tag::GPSLongitudeRef,
+                if longitude < 0.0 { "W" } else { "E" },
+            ),
+            Field {
+                tag: Tag::GPSLongitude,
+                ifd_num: In::PRIMARY,
+                value: Value::Rational(degrees_minutes_seconds(longitude)),
+            },
+            ascii(Tag::GPSMapDatum, "WGS-84"),
+        ]);
+    }
+    fields
+}
+
+// The new EXIF block: the image's existing main tags (if it has any EXIF
+// data), with the capture tags added or replaced
+fn build_exif(existing: Option<&[u8]>, capture: &[Field]) -> Result<Vec<u8>> {
+    let existing_fields: Vec<Field> = existing
+        .and_then(|data| exif::Reader::new().read_raw(data.to_vec()).ok())
+        .map(|existing| {
+            existing
+                .fields()
+                // The thumbnail IFD isn't kept, it needs its image data too
+                .filter(|field| field.ifd_num == In::PRIMARY)
+                .filter(|field| capture.iter().all(|new| new.tag != field.tag))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut writer = Writer::new();
+    for field in existing_fields.iter().chain(capture) {
+        writer.push_field(field);
+    }
+    let mut buffer = Cursor::new(Vec::new());
+    writer.write(&mut buffer, false)?;
+    Ok(buffer.into_inner())
+}
+
+// Add the capture time (and location, if there is one) to the JPEG at path.
+// Returns false without touching the file if it isn't a JPEG.
+pub fn write_capture_exif(
+    path: &Path,
+    timestamp: DateTime<Utc>,
+    location: Option<(f64, f64)>,
+) -> Result<bool> {
+    let data = fs::read(path)?;
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return Ok(false);
+    }
+    let mut jpeg = Jpeg::from_bytes(Bytes::from(data))?;
+    // set_exif() puts the EXIF segment after the first 3, which any real
+    // JPEG has (SOI is not counted)
+    if jpeg.segments().len() < 3 {
+        return Ok(false);
+    }
+
+    let existing = jpeg.exif();
+    let exif_data = build_exif(existing.as_deref(), &capture_fields(timestamp, location))?;
+    jpeg.set_exif(Some(Bytes::from(exif_data)));
+
+    // Write to a temporary file first, so the download isn't lost if
+    // SnapDown is stopped halfway through writing
+    let temp_path = path.with_extension("exif.tmp");
+    jpeg.encoder().write_to(fs::File::create(&temp_path)?)?;
+    fs::rename(&temp_path, path)?;
+    Ok(true)
+}
+
+// The EXIF orientation of a JPEG, 1 (upright) if it doesn't have one
+pub fn orientation(exif_data: Option<&[u8]>) -> u32 {
+    exif_data
+        .and_then(|data| exif::Reader::new().read_raw(data.to_vec()).ok())
+        .and_then(|exif| {
+            exif.get_field(Tag::Orientation, In::PRIMARY)?
+                .value
+                .get_uint(0)
+        })
+        .unwrap_or(1)
+}
+
+// Rotate/flip the JPEG at path so it's upright without its EXIF orientation,
+// and set that to 1. The other EXIF tags are kept. Returns false without
+// touching the file if it isn't a JPEG or is upright already.
+pub fn rotate_upright(path: &Path) -> Result<bool> {
+    let data = fs::read(path)?;
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return Ok(false);
+    }
+    let existing = Jpeg::from_bytes(Bytes::from(data.clone()))?.exif();
+    let Some(rotation) = u8::try_from(orientation(existing.as_deref()))
+        .ok()
+        .and_then(Orientation::from_exif)
+        .filter(|rotation| *rotation != Orientation::NoTransforms)
+    else {
+        return Ok(false);
+    };
+
+    let mut image = image::load_from_memory_with_format(&data, image::ImageFormat::Jpeg)?;
+    image.apply_orientation(rotation);
+    // Rotating means encoding the photo again, so keep the quality high
+    let mut rotated = Vec::new();
+    image
+        .to_rgb8()
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut rotated, 95))?;
+
+    let mut jpeg = Jpeg::from_bytes(Bytes::from(rotated))?;
+    let upright = Field {
+        tag: Tag::Orientation,
+        ifd_num: In::PRIMARY,
+        value: Value::Short(vec![1]),
+    };
+    jpeg.set_exif(Some(Bytes::from(build_exif(
+        existing.as_deref(),
+        &[upright],
+    )?)));
+
+    let temp_path = path.with_extension("exif.tmp");
+    jpeg.encoder().write_to(fs::File::create(&temp_path)?)?;
+    fs::rename(&temp_path, path)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // The smallest valid JPEG: a 1x1 grey pixel
+    const TINY_JPEG: &[u8] = &[
+        0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x4A, 0x46, 0x49, 0x46, 0x00, 0x01, 0x01, 0x00, 0x00,
+        0x01, 0x00, 0x01, 0x00, 0x00, 0xFF, 0xDB, 0x00, 0x43, 0x00, 0x08, 0x06, 0x06, 0x07, 0x06,
+        0x05, 0x08, 0x07, 0x07, 0x07, 0x09, 0x09, 0x08, 0x0A, 0x0C, 0x14, 0x0D, 0x0C, 0x0B, 0x0B,
+        0x0C, 0x19, 0x12, 0x13, 0x0F, 0x14, 0x1D, 0x1A, 0x1F, 0x1E, 0x1D, 0x1A, 0x1C, 0x1C, 0x20,
+        0x24, 0x2E, 0x27, 0x20, 0x22, 0x2C, 0x23, 0x1C, 0x1C, 0x28, 0x37, 0x29, 0x2C, 0x30, 0x31,
+        0x34, 0x34, 0x34, 0x1F, 0x27, 0x39, 0x3D, 0x38, 0x32, 0x3C, 0x2E, 0x33, 0x34, 0x32, 0xFF,
+        0xC0, 0x00, 0x0B, 0x08, 0x00, 0x01, 0x00, 0x01, 0x01, 0x01, 0x11, 0x00, 0xFF, 0xC4, 0x00,
+        0x14, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x09, 0xFF, 0xC4, 0x00, 0x14, 0x10, 0x01, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xDA, 0x00,
+        0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00, 0x2A, 0x9F, 0xFF, 0xD9,
+    ];
+
+    #[test]
+    fn test_write_capture_exif() {
+        let dir = std::env::temp_dir().join("snapdown_test_write_capture_exif");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("photo.jpg");
+        fs::write(&path, TINY_JPEG).unwrap();
+
+        let timestamp = Utc.with_ymd_and_hms(2026, 1, 13, 1, 55, 38).unwrap();
+        assert!(write_capture_exif(&path, timestamp, Some((40.25548, -111.645325))).unwrap());
+
+        let exif = exif::Reader::new()
+            .read_from_container(&mut std::io::BufReader::new(fs::File::open(&path).unwrap()))
+            .unwrap();
+        let field = |tag| exif.get_field(tag, In::PRIMARY).unwrap();
+        assert_eq!(
+            field(Tag::DateTimeOriginal).display_value().to_string(),
+            "2026-01-13 01:55:38"
+        );
+        assert_eq!(field(Tag::GPSLongitudeRef).display_value().to_string(), "W");
+        let Value::Rational(latitude) = &field(Tag::GPSLatitude).value else {
+            panic!("GPSLatitude isn't rational");
+        };
+        let latitude =
+            latitude[0].to_f64() + latitude[1].to_f64() / 60.0 + latitude[2].to_f64() / 3600.0;
+        assert!((latitude - 40.25548).abs() < 0.000001);
+
+        // Writing again replaces the tags rather than adding more
+        let later = Utc.with_ymd_and_hms(2026, 2, 1, 0, 0, 0).unwrap();
+        assert!(write_capture_exif(&path, later, None).unwrap());
+        let exif = exif::Reader::new()
+            .read_from_container(&mut std::io::BufReader::new(fs::File::open(&path).unwrap()))
+            .unwrap();
+        assert_eq!(
+            exif.get_field(Tag::DateTimeOriginal, In::PRIMARY)
+                .unwrap()
+                .display_value()
+                .to_string(),
+            "2026-02-01 00:00:00"
+        );
+        // The location from the first write is kept
+        assert!(exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_some());
+
+        // Not a JPEG
+        let video = dir.join("video.mp4");
+        fs::write(&video, b"\x00\x00\x00\x18ftypmp42").unwrap();
+        assert!(!write_capture_exif(&video, timestamp, None).unwrap());
+        assert_eq!(fs::read(&video).unwrap(), b"\x00\x00\x00\x18ftypmp42");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_upright() {
+        let dir = std::env::temp_dir().join("snapdown_test_rotate_upright");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("photo.jpg");
+
+        // A 4x2 photo stored sideways (orientation 6: rotate 90° clockwise)
+        let mut data = Vec::new();
+        image::RgbImage::new(4, 2)
+            .write_with_encoder(JpegEncoder::new(&mut data))
+            .unwrap();
+        let mut jpeg = Jpeg::from_bytes(Bytes::from(data)).unwrap();
+        let sideways = Field {
+            tag: Tag::Orientation,
+            ifd_num: In::PRIMARY,
+            value: Value::Short(vec![6]),
+        };
+        let capture = ascii(Tag::DateTimeOriginal, "2026:01:13 01:55:38");
+        jpeg.set_exif(Some(Bytes::from(
+            build_exif(None, &[sideways, capture]).unwrap(),
+        )));
+        jpeg.encoder()
+            .write_to(fs::File::create(&path).unwrap())
+            .unwrap();
+
+        assert!(rotate_upright(&path).unwrap());
+        assert_eq!(image::open(&path).unwrap().to_rgb8().dimensions(), (2, 4));
+        let exif = exif::Reader::new()
+            .read_from_container(&mut std::io::BufReader::new(fs::File::open(&path).unwrap()))
+            .unwrap();
+        assert_eq!(
+            exif.get_field(Tag::Orientation, In::PRIMARY)
+                .unwrap()
+                .value
+                .get_uint(0),
+            Some(1)
+        );
+        assert!(exif.get_field(Tag::DateTimeOriginal, In::PRIMARY).is_some());
+
+        // Upright already
+        assert!(!rotate_upright(&path).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,317 @@
+// Writing records back out in the same snap_export.csv layout that
+// extract_download_links.js produces, so the file can be fed straight back
+// into SnapDown as a new input. `snapdown parse` can also write them as JSON,
+// for other tools.
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Serialize;
+
+pub const SNAP_EXPORT_HEADER: [&str; 5] = [
+    "timestamp_utc",
+    "format",
+    "latitude",
+    "longitude",
+    "download_url",
+];
+
+// After the other columns, for chats, stories and Spotlight. Memories leave it
+// empty, and a file with only memories doesn't have it.
+pub const FOLDER_COLUMN: &str = "folder";
+
+// Normalize a record from either input format into the 5 snap_export.csv
+// columns (timestamp_utc, format, latitude, longitude, download_url).
+// Returns None for rows that don't have the shape of a downloadable record.
+pub fn snap_export_row(row: &csv::StringRecord) -> Option<[String; 5]> {
+    match row.len() {
+        // Chats, stories and Spotlight have a folder after the link, see
+        // sections.rs
+        5 | 6 => Some([
+            strip_html(&row[0]),
+            strip_html(&row[1]),
+            strip_html(&row[2]),
+            strip_html(&row[3]),
+            row[4].to_string(),
+        ]),
+        4 => {
+            // memories_history.html has "Latitude, Longitude: <lat>, <long>"
+            let lat_long = strip_html(&row[2]).replace("Latitude, Longitude: ", "");
+            let (latitude, longitude) = match lat_long.split_once(',') {
+                Some((lat, long)) => (lat.trim().to_string(), long.trim().to_string()),
+                None => (lat_long.trim().to_string(), String::new()),
+            };
+            Some([
+                strip_html(&row[0]),
+                strip_html(&row[1]),
+                latitude,
+                longitude,
+                row[3].to_string(),
+            ])
+        }
+        _ => None,
+    }
+}
+
+// Text of a table cell from memories_history.html, without tags like <b>
+// and with the usual entities decoded. Download URLs are left alone, they
+// come from a script and are used as is.
+pub fn strip_html(field: &str) -> String {
+    let mut text = String::with_capacity(field.len());
+    let mut in_tag = false;
+    for c in field.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    decode_entities(&text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// The entities browsers write when saving a page, "&amp;" last so "&amp;lt;"
+// stays "&lt;"
+pub fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+// The folder of a chat, story or Spotlight record, as it was read, see
+// sections.rs. Without it the media would be downloaded again into the output
+// directory under other names when the file is read back.
+fn record_folder(row: &csv::StringRecord) -> Option<&str> {
+    row.get(5).filter(|folder| !folder.is_empty())
+}
+
+// A record in `snapdown parse`'s JSON output. The coordinates are numbers,
+// or null if the memory has none.
+#[derive(Debug, Serialize)]
+struct JsonRow {
+    timestamp_utc: String,
+    format: String,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    download_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    folder: Option<String>,
+}
+
+impl JsonRow {
+    fn new(record: &csv::StringRecord) -> Option<JsonRow> {
+        let [timestamp_utc, format, latitude, longitude, download_url] = snap_export_row(record)?;
+        Some(JsonRow {
+            timestamp_utc,
+            format,
+            latitude: latitude.parse().ok(),
+            longitude: longitude.parse().ok(),
+            download_url,
+            folder: record_folder(record).map(str::to_string),
+        })
+    }
+}
+
+// Write the given records as a JSON array. Returns how many were written.
+pub fn write_snap_export_json<W: Write>(writer: W, records: &[csv::StringRecord]) -> Result<usize> {
+    let rows: Vec<JsonRow> = records.iter().filter_map(JsonRow::new).collect();
+    let mut writer = std::io::BufWriter::new(writer);
+    serde_json::to_writer_pretty(&mut writer, &rows)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(rows.len())
+}
+
+// Write the given records as a snap_export.csv file. Returns how many rows
+// were written (rows that can't be normalized are left out).
+pub fn write_snap_export_csv(path: &Path, records: &[csv::StringRecord]) -> Result<usize> {
+    write_rows(&mut csv::Writer::from_path(path)?, records)
+}
+
+// The same, to any writer (stdout, for `snapdown parse -o -`)
+pub fn write_snap_export_csv_to<W: Write>(
+    writer: W,
+    records: &[csv::StringRecord],
+) -> Result<usize> {
+    write_rows(&mut csv::Writer::from_writer(writer), records)
+}
+
+// The same, in memory, e.g. for an email attachment
+pub fn snap_export_csv_bytes(records: &[csv::StringRecord]) -> Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    write_rows(&mut writer, records)?;
+    Ok(writer.into_inner().map_err(|e| e.into_error())?)
+}
+
+fn write_rows<W: Write>(
+    writer: &mut csv::Writer<W>,
+    records: &[csv::StringRecord],
+) -> Result<usize> {
+    let with_folders = records
+        .iter()
+        .any(|record| snap_export_row(record).is_some() && record_folder(record).is_some());
+    if with_folders {
+        writer.write_record(SNAP_EXPORT_HEADER.iter().chain([&FOLDER_COLUMN]))?;
+    } else {
+        writer.write_record(SNAP_EXPORT_HEADER)?;
+    }
+    let mut written = 0;
+    for record in records {
+        if let Some(row) = snap_export_row(record) {
+            if with_folders {
+                let folder = record_folder(record).unwrap_or_default();
+                writer.write_record(row.iter().map(String::as_str).chain([folder]))?;
+            } else {
+                writer.write_record(&row)?;
+            }
+            written += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snap_export_row_from_html_record() {
+        let record = csv::StringRecord::from(vec![
+            "2026-01-13 01:55:38 UTC",
+            "Image",
+            "Latitude, Longitude: 40.25548, -111.645325",
+            "https://example.com/a",
+        ]);
+        let row = snap_export_row(&record).unwrap();
+        assert_eq!(row[0], "2026-01-13 01:55:38 UTC");
+        assert_eq!(row[1], "Image");
+        assert_eq!(row[2], "40.25548");
+        assert_eq!(row[3], "-111.645325");
+        assert_eq!(row[4], "https://example.com/a");
+    }
+
+    #[test]
+    fn test_strip_html() {
+        let record = csv::StringRecord::from(vec![
+            "<b>2026-01-13 01:55:38 UTC</b>",
+            " <span>Image</span>",
+            "Latitude, Longitude: <i>40.25548</i>,&nbsp;-111.645325",
+            "https://example.com/a?x=1&amp;y=2",
+        ]);
+        let row = snap_export_row(&record).unwrap();
+        assert_eq!(row[0], "2026-01-13 01:55:38 UTC");
+        assert_eq!(row[1], "Image");
+        assert_eq!(row[2], "40.25548");
+        assert_eq!(row[3], "-111.645325");
+        assert_eq!(row[4], "https://example.com/a?x=1&amp;y=2");
+        assert_eq!(strip_html("Tom &amp; Jerry &lt;3"), "Tom & Jerry <3");
+    }
+
+    #[test]
+    fn test_snap_export_json() {
+        let records = vec![
+            csv::StringRecord::from(vec![
+                "2026-01-13 01:55:38 UTC",
+                "Image",
+                "Latitude, Longitude: 40.25548, -111.645325",
+                "https://example.com/a",
+            ]),
+            csv::StringRecord::from(vec![
+                "2026-01-14 01:55:38 UTC",
+                "Video",
+                "",
+                "",
+                "https://example.com/b",
+            ]),
+            csv::StringRecord::from(vec!["bad row"]),
+        ];
+        let mut json = Vec::new();
+        assert_eq!(write_snap_export_json(&mut json, &records).unwrap(), 2);
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json[0]["latitude"], 40.25548);
+        assert_eq!(json[0]["longitude"], -111.645325);
+        assert_eq!(json[1]["format"], "Video");
+        assert!(json[1]["latitude"].is_null());
+    }
+
+    #[test]
+    fn test_snap_export_round_trip() {
+        let records = vec![
+            csv::StringRecord::from(vec![
+                "1800-01-18T00:28:45+00:00",
+                "SVG",
+                "40.0",
+                "40.0",
+                "https://example.com/a?x=1,2",
+            ]),
+            csv::StringRecord::from(vec!["bad row"]),
+        ];
+        let path = std::env::temp_dir().join("snapdown_test_export_round_trip_snap_export.csv");
+        assert_eq!(write_snap_export_csv(&path, &records).unwrap(), 1);
+
+        // Read it back the same way run_downloader() does
+        let mut rdr = csv::Reader::from_path(&path).unwrap();
+        let read_back: Vec<csv::StringRecord> = rdr.records().map(|r| r.unwrap()).collect();
+        assert_eq!(read_back.len(), 1);
+        assert_eq!(read_back[0], records[0]);
+        assert_eq!(
+            snap_export_csv_bytes(&records).unwrap(),
+            std::fs::read(&path).unwrap()
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_snap_export_folders() {
+        let chat = csv::StringRecord::from(vec![
+            "2026-01-13 01:55:38 UTC",
+            "Image",
+            "",
+            "",
+            "https://example.com/chat",
+            "chats/alice",
+        ]);
+        let memory = csv::StringRecord::from(vec![
+            "2026-01-14 01:55:38 UTC",
+            "Video",
+            "Latitude, Longitude: 40.0, 40.0",
+            "https://example.com/memory",
+        ]);
+        let records = vec![chat.clone(), memory];
+        let csv = String::from_utf8(snap_export_csv_bytes(&records).unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "timestamp_utc,format,latitude,longitude,download_url,folder\n\
+             2026-01-13 01:55:38 UTC,Image,,,https://example.com/chat,chats/alice\n\
+             2026-01-14 01:55:38 UTC,Video,40.0,40.0,https://example.com/memory,\n"
+        );
+        let read_back: Vec<csv::StringRecord> = csv::Reader::from_reader(csv.as_bytes())
+            .records()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(read_back[0], chat);
+        assert_eq!(
+            crate::naming::record_folder(&read_back[0]).unwrap(),
+            "chats/alice"
+        );
+        assert_eq!(crate::naming::record_folder(&read_back[1]), None);
+
+        let mut json = Vec::new();
+        write_snap_export_json(&mut json, &records).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json[0]["folder"], "chats/alice");
+        assert!(json[1].get("folder").is_none());
+
+        // Only memories, the same columns as before
+        let csv = snap_export_csv_bytes(&records[1..]).unwrap();
+        assert!(csv.starts_with(b"timestamp_utc,format,latitude,longitude,download_url\n"));
+    }
+}
@@ -0,0 +1,240 @@
+// How the engines on threads make their requests: the rayon-downloader one,
+// the one for --s3-bucket and --webdav, a retry from the GUI, and the space
+// check before a run. The ureq agent sends them, and in tests a MockFetch
+// answers them. It's the blocking twin of async_download's HttpFetch, and a
+// run uses DownloadOptions::http for both instead of clients of its own if
+// it's set.
+
+use ureq::http::Response;
+#[cfg(test)]
+use ureq::http::{HeaderMap, StatusCode};
+
+pub trait BlockingFetch: Send + Sync {
+    // A GET, with a Range header if `range` is given, e.g. "bytes=100-".
+    // With `keep_bad_status` (for --debug-http), a bad status is a response
+    // with its headers and body rather than an error.
+    fn get(
+        &self,
+        url: &str,
+        range: Option<&str>,
+        keep_bad_status: bool,
+    ) -> Result<Response<ureq::Body>, ureq::Error>;
+
+    // A POST of a form encoded body
+    fn post_form(&self, url: &str, body: &str) -> Result<Response<ureq::Body>, ureq::Error>;
+}
+
+impl BlockingFetch for ureq::Agent {
+    fn get(
+        &self,
+        url: &str,
+        range: Option<&str>,
+        keep_bad_status: bool,
+    ) -> Result<Response<ureq::Body>, ureq::Error> {
+        let mut request = ureq::Agent::get(self, url);
+        if let Some(range) = range {
+            request = request.header("Range", range);
+        }
+        if keep_bad_status {
+            // Keep the redirects too, for the debug log
+            request
+                .config()
+                .save_redirect_history(true)
+                .http_status_as_error(false)
+                .build()
+                .call()
+        } else {
+            request.call()
+        }
+    }
+
+    fn post_form(&self, url: &str, body: &str) -> Result<Response<ureq::Body>, ureq::Error> {
+        ureq::Agent::post(self, url)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .send(body)
+    }
+}
+
+// Both kinds of client, for DownloadOptions::http. The rayon engine only has
+// the blocking one.
+#[cfg(not(feature = "rayon-downloader"))]
+pub trait Fetch: BlockingFetch + crate::async_download::HttpFetch {}
+
+#[cfg(not(feature = "rayon-downloader"))]
+impl<T: BlockingFetch + crate::async_download::HttpFetch> Fetch for T {}
+
+#[cfg(feature = "rayon-downloader")]
+pub trait Fetch: BlockingFetch {}
+
+#[cfg(feature = "rayon-downloader")]
+impl<T: BlockingFetch> Fetch for T {}
+
+// Answers requests with what it was given for each URL, and keeps a line for
+// each request it got. A URL it wasn't given anything for is a 404. Like most
+// servers it answers a Range request for a file with that part of it.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockFetch {
+    // In order for the requests to come, the last one for any after that
+    answers: std::sync::Mutex<std::collections::HashMap<String, Vec<MockAnswer>>>,
+    requests: std::sync::Mutex<Vec<String>>,
+}
+
+// A status and body, or None for a server that doesn't answer
+#[cfg(test)]
+type MockAnswer = Option<(u16, Vec<u8>)>;
+
+#[cfg(test)]
+pub struct MockResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+#[cfg(test)]
+impl MockFetch {
+    pub fn answer(&self, url: &str, status: u16, body: &[u8]) {
+        self.push(url, Some((status, body.to_vec())));
+    }
+
+    // Like a server that's down, the request times out
+    pub fn fail(&self, url: &str) {
+        self.push(url, None);
+    }
+
+    fn push(&self, url: &str, answer: MockAnswer) {
+        let mut answers = self.answers.lock().unwrap();
+        answers.entry(url.to_string()).or_default().push(answer);
+    }
+
+    // E.g. "GET https://example.com/a.jpg bytes=0-0"
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    // `request` is its line for requests(). None if the server doesn't
+    // answer.
+    pub fn respond(&self, request: String, url: &str, range: Option<&str>) -> Option<MockResponse> {
+        self.requests.lock().unwrap().push(request);
+        let answer = {
+            let mut answers = self.answers.lock().unwrap();
+            match answers.get_mut(url) {
+                Some(queue) if queue.len() > 1 => queue.remove(0),
+                Some(queue) => queue[0].clone(),
+                None => Some((404, Vec::new())),
+            }
+        };
+        let (status, mut body) = answer?;
+        let mut status = StatusCode::from_u16(status).unwrap();
+        let mut headers = HeaderMap::new();
+        let range = range
+            .and_then(|range| range.strip_prefix("bytes="))
+            .and_then(|range| range.split_once('-'))
+            .and_then(|(start, end)| {
+                let start: usize = start.parse().ok()?;
+                let end = end.parse().map_or(body.len(), |end: usize| end + 1);
+                (start < end && end <= body.len()).then_some((start, end))
+            });
+        if status == StatusCode::OK
+            && let Some((start, end)) = range
+        {
+            let content_range = format!("bytes {}-{}/{}", start, end - 1, body.len());
+            headers.insert(
+                ureq::http::header::CONTENT_RANGE,
+                content_range.parse().unwrap(),
+            );
+            body = body[start..end].to_vec();
+            status = StatusCode::PARTIAL_CONTENT;
+        }
+        headers.insert(ureq::http::header::CONTENT_LENGTH, body.len().into());
+        Some(MockResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    // As ureq would have it
+    fn respond_blocking(
+        &self,
+        request: String,
+        url: &str,
+        range: Option<&str>,
+        keep_bad_status: bool,
+    ) -> Result<Response<ureq::Body>, ureq::Error> {
+        let response = self
+            .respond(request, url, range)
+            .ok_or(ureq::Error::Timeout(ureq::Timeout::Global))?;
+        if !keep_bad_status
+            && (response.status.is_client_error() || response.status.is_server_error())
+        {
+            return Err(ureq::Error::StatusCode(response.status.as_u16()));
+        }
+        let mut builder = Response::builder().status(response.status);
+        for (name, value) in &response.headers {
+            builder = builder.header(name, value);
+        }
+        Ok(builder
+            .body(ureq::Body::builder().data(response.body))
+            .unwrap())
+    }
+}
+
+#[cfg(test)]
+impl BlockingFetch for MockFetch {
+    fn get(
+        &self,
+        url: &str,
+        range: Option<&str>,
+        keep_bad_status: bool,
+    ) -> Result<Response<ureq::Body>, ureq::Error> {
+        let request = match range {
+            Some(range) => format!("GET {} {}", url, range),
+            None => format!("GET {}", url),
+        };
+        self.respond_blocking(request, url, range, keep_bad_status)
+    }
+
+    fn post_form(&self, url: &str, body: &str) -> Result<Response<ureq::Body>, ureq::Error> {
+        self.respond_blocking(format!("POST {} {}", url, body), url, None, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_fetch() {
+        let http = MockFetch::default();
+        http.answer("https://example.com/a.jpg", 200, b"jpeg");
+        http.fail("https://example.com/b.jpg");
+
+        let mut response = http
+            .get("https://example.com/a.jpg", Some("bytes=1-"), false)
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()["content-range"], "bytes 1-3/4");
+        assert_eq!(response.body_mut().read_to_string().unwrap(), "peg");
+        assert!(matches!(
+            http.get("https://example.com/b.jpg", None, false),
+            Err(ureq::Error::Timeout(_))
+        ));
+        assert!(matches!(
+            http.get("https://example.com/c.jpg", None, false),
+            Err(ureq::Error::StatusCode(404))
+        ));
+        // For --debug-http
+        let response = http.get("https://example.com/c.jpg", None, true).unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            http.requests(),
+            [
+                "GET https://example.com/a.jpg bytes=1-",
+                "GET https://example.com/b.jpg",
+                "GET https://example.com/c.jpg",
+                "GET https://example.com/c.jpg",
+            ]
+        );
+    }
+}
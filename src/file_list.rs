@@ -0,0 +1,300 @@
+// The GUI's list of files in the current run, one row per record with its
+// status, kept up to date by the run's file events, see events.rs.
+
+use std::cmp::Ordering;
+
+use crate::events::DownloadEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FileStatus {
+    Queued,
+    Downloading,
+    Done,
+    Skipped,
+    Failed,
+    // Not a memory, see DownloadOutcome::Invalid
+    Invalid,
+}
+
+impl FileStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FileStatus::Queued => "Queued",
+            FileStatus::Downloading => "Downloading",
+            FileStatus::Done => "Done",
+            FileStatus::Skipped => "Skipped",
+            FileStatus::Failed => "Failed",
+            FileStatus::Invalid => "Not a memory",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileRow {
+    pub timestamp: String,
+    pub media_type: String,
+    // Empty if the record doesn't have the shape of one
+    pub filename: String,
+    pub status: FileStatus,
+    // Size on disk once done or skipped, or written so far while downloading
+    pub size: Option<u64>,
+    // Why the last attempt failed, if it did
+    pub error: Option<String>,
+    // Times the file was downloaded or tried to, retries included
+    pub attempts: u32,
+    // For the row's context menu (copy URL, retry)
+    pub record: csv::StringRecord,
+}
+
+impl FileRow {
+    // With the record's filename and URL as the run has them
+    pub fn queued(
+        record: &csv::StringRecord,
+        filename_and_url: &Option<(String, &str)>,
+    ) -> FileRow {
+        FileRow {
+            timestamp: record.get(0).unwrap_or_default().to_string(),
+            media_type: record.get(1).unwrap_or_default().to_string(),
+            filename: filename_and_url
+                .as_ref()
+                .map(|(filename, _)| filename.clone())
+                .unwrap_or_default(),
+            status: FileStatus::Queued,
+            size: None,
+            error: None,
+            attempts: 0,
+            record: record.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileColumn {
+    Timestamp,
+    MediaType,
+    Filename,
+    Status,
+    Size,
+}
+
+pub struct FileList {
+    rows: Vec<FileRow>,
+    sort_column: FileColumn,
+    ascending: bool,
+    // Row indices in display order, None when it needs sorting again
+    order: Option<Vec<usize>>,
+}
+
+impl Default for FileList {
+    fn default() -> Self {
+        FileList {
+            rows: Vec::new(),
+            sort_column: FileColumn::Timestamp,
+            ascending: true,
+            order: None,
+        }
+    }
+}
+
+impl FileList {
+    // Rows are the records' positions in the input
+    pub fn apply(&mut self, event: DownloadEvent) {
+        match event {
+            DownloadEvent::Parsed { rows } => self.rows = rows,
+            DownloadEvent::Started { id } => {
+                if let Some(row) = self.rows.get_mut(id) {
+                    row.status = FileStatus::Downloading;
+                }
+            }
+            // May be the first the GUI sees of the file, see FileEvents
+            DownloadEvent::Progress { id, bytes } => {
+                if let Some(row) = self.rows.get_mut(id) {
+                    row.status = FileStatus::Downloading;
+                    row.size = Some(bytes);
+                }
+            }
+            DownloadEvent::Finished { id, status, size } => {
+                if let Some(row) = self.rows.get_mut(id) {
+                    if status == FileStatus::Done {
+                        row.attempts += 1;
+                    }
+                    row.status = status;
+                    row.size = size;
+                    row.error = (status == FileStatus::Invalid)
+                        .then(|| crate::INVALID_RECORD_ERROR.to_string());
+                }
+            }
+            DownloadEvent::Failed { id, error } => {
+                if let Some(row) = self.rows.get_mut(id) {
+                    row.attempts += 1;
+                    row.status = FileStatus::Failed;
+                    row.size = None;
+                    row.error = Some(error);
+                }
+            }
+            _ => return,
+        }
+        self.order = None;
+    }
+
+    // A file retried from a context menu or the error list. Retries aren't
+    // part of a run, so the row is found by its record.
+    pub fn retried(&mut self, record: &csv::StringRecord, result: Result<(), String>) {
+        if let Some(row) = self.rows.iter_mut().find(|row| row.record == *record) {
+            row.attempts += 1;
+            (row.status, row.error) = match result {
+                Ok(()) => (FileStatus::Done, None),
+                Err(error) => (FileStatus::Failed, Some(error)),
+            };
+            self.order = None;
+        }
+    }
+
+    // The filename of a record's row, empty if it has none
+    pub fn filename(&self, record: &csv::StringRecord) -> Option<&str> {
+        self.rows
+            .iter()
+            .find(|row| row.record == *record)
+            .map(|row| row.filename.as_str())
+    }
+
+    // The files that failed, in input order
+    pub fn failed_rows(&self) -> impl Iterator<Item = &FileRow> {
+        self.rows
+            .iter()
+            .filter(|row| row.status == FileStatus::Failed)
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn sort_column(&self) -> (FileColumn, bool) {
+        (self.sort_column, self.ascending)
+    }
+
+    // Sort by `column`, or reverse the order if already sorted by it
+    pub fn sort_by(&mut self, column: FileColumn) {
+        if self.sort_column == column {
+            self.ascending = !self.ascending;
+        } else {
+            self.sort_column = column;
+            self.ascending = true;
+        }
+        self.order = None;
+    }
+
+    // The row at `position` in the sorted list
+    pub fn sorted_row(&mut self, position: usize) -> Option<&FileRow> {
+        let rows = &self.rows;
+        let (column, ascending) = (self.sort_column, self.ascending);
+        let order = self.order.get_or_insert_with(|| {
+            let mut order: Vec<usize> = (0..rows.len()).collect();
+            // Stable, so rows that compare equal stay in input order
+            order.sort_by(|&a, &b| {
+                let ordering = compare(&rows[a], &rows[b], column);
+                if ascending {
+                    ordering
+                } else {
+                    ordering.reverse()
+                }
+            });
+            order
+        });
+        order.get(position).map(|&index| &rows[index])
+    }
+}
+
+fn compare(a: &FileRow, b: &FileRow, column: FileColumn) -> Ordering {
+    match column {
+        FileColumn::Timestamp => a.timestamp.cmp(&b.timestamp),
+        FileColumn::MediaType => a.media_type.cmp(&b.media_type),
+        FileColumn::Filename => a.filename.cmp(&b.filename),
+        FileColumn::Status => a.status.cmp(&b.status),
+        FileColumn::Size => a.size.cmp(&b.size),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_list() {
+        let record = |timestamp: &str, media_type: &str| {
+            csv::StringRecord::from(vec![
+                timestamp,
+                media_type,
+                "40.0",
+                "-111.0",
+                "https://example.com/a",
+            ])
+        };
+        let records = [
+            record("2026-01-02 00:00:00 UTC", "Video"),
+            record("2026-01-01 00:00:00 UTC", "Image"),
+            record("2026-01-03 00:00:00 UTC", "Image"),
+        ];
+        let mut list = FileList::default();
+        list.apply(DownloadEvent::Parsed {
+            rows: records
+                .iter()
+                .map(|record| FileRow::queued(record, &crate::record_filename_and_url(record)))
+                .collect(),
+        });
+        list.apply(DownloadEvent::Progress { id: 0, bytes: 512 });
+        list.apply(DownloadEvent::Finished {
+            id: 2,
+            status: FileStatus::Done,
+            size: Some(1234),
+        });
+        list.apply(DownloadEvent::Failed {
+            id: 1,
+            error: "Error downloading from a: timed out".to_string(),
+        });
+        list.retried(
+            &records[1],
+            Err("Error downloading from a: 404".to_string()),
+        );
+
+        let timestamps = |list: &mut FileList| {
+            (0..list.row_count())
+                .map(|position| list.sorted_row(position).unwrap().timestamp[..10].to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            timestamps(&mut list),
+            ["2026-01-01", "2026-01-02", "2026-01-03"]
+        );
+        list.sort_by(FileColumn::Timestamp);
+        assert_eq!(
+            timestamps(&mut list),
+            ["2026-01-03", "2026-01-02", "2026-01-01"]
+        );
+        list.sort_by(FileColumn::Status);
+        let statuses: Vec<FileStatus> = (0..3)
+            .map(|position| list.sorted_row(position).unwrap().status)
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                FileStatus::Downloading,
+                FileStatus::Done,
+                FileStatus::Failed
+            ]
+        );
+        assert_eq!(list.sorted_row(0).unwrap().size, Some(512));
+        assert_eq!(list.sorted_row(1).unwrap().size, Some(1234));
+        assert!(list.sorted_row(3).is_none());
+
+        let failed: Vec<_> = list.failed_rows().collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].attempts, 2);
+        assert_eq!(
+            failed[0].error.as_deref(),
+            Some("Error downloading from a: 404")
+        );
+        list.retried(&records[1], Ok(()));
+        assert_eq!(list.failed_rows().count(), 0);
+    }
+}
@@ -0,0 +1,202 @@
+// Small formatting helpers shared by the GUI and the CLI summaries. Numbers
+// are written the way the user's locale does, e.g. 12,345 files and 3.4 GB
+// in English, but 12.345 files and 3,4 GB in German.
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+// Separators for writing numbers in a locale
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumberStyle {
+    decimal: char,
+    group: char,
+    // Whether there's a space before %, e.g. "42 %"
+    percent_space: bool,
+}
+
+// Languages writing 12.345,6
+const DOT_GROUP_LANGUAGES: [&str; 14] = [
+    "da", "de", "el", "es", "hr", "id", "it", "nl", "pt", "ro", "sl", "sr", "tr", "vi",
+];
+// Languages writing 12 345,6
+const SPACE_GROUP_LANGUAGES: [&str; 16] = [
+    "bg", "cs", "et", "fi", "fr", "hu", "lt", "lv", "nb", "nn", "no", "pl", "ru", "sk", "sv", "uk",
+];
+// Of those, the ones that put a space before %
+const PERCENT_SPACE_LANGUAGES: [&str; 11] = [
+    "cs", "da", "de", "es", "fi", "fr", "nb", "no", "sk", "sv", "ru",
+];
+
+impl NumberStyle {
+    pub const ENGLISH: NumberStyle = NumberStyle {
+        decimal: '.',
+        group: ',',
+        percent_space: false,
+    };
+
+    // From a locale name like "de_DE.UTF-8", "fr-CA" or "C". Anything
+    // unknown is written the English way.
+    pub fn from_locale(locale: &str) -> NumberStyle {
+        let locale = locale.split(['.', '@']).next().unwrap_or_default();
+        let mut parts = locale.split(['_', '-']);
+        let language = parts.next().unwrap_or_default().to_ascii_lowercase();
+        let region = parts.next().unwrap_or_default().to_ascii_uppercase();
+        let percent_space = PERCENT_SPACE_LANGUAGES.contains(&language.as_str());
+        match (language.as_str(), region.as_str()) {
+            // Switzerland writes 12'345.6, Spanish in the Americas mostly
+            // writes numbers the English way
+            ("de" | "it", "CH") => NumberStyle {
+                decimal: '.',
+                group: '\'',
+                percent_space,
+            },
+            ("es", "MX" | "US" | "419") => NumberStyle::ENGLISH,
+            (language, _) if DOT_GROUP_LANGUAGES.contains(&language) => NumberStyle {
+                decimal: ',',
+                group: '.',
+                percent_space,
+            },
+            // A no-break space, so numbers aren't split across lines
+            (language, _) if SPACE_GROUP_LANGUAGES.contains(&language) => NumberStyle {
+                decimal: ',',
+                group: '\u{a0}',
+                percent_space,
+            },
+            _ => NumberStyle::ENGLISH,
+        }
+    }
+
+    // The user's, going by the first of SNAPDOWN_LOCALE, LC_ALL, LC_NUMERIC
+    // and LANG that's set. Windows doesn't set the last three, so there it's
+    // English unless SNAPDOWN_LOCALE is set.
+    pub fn current() -> NumberStyle {
+        static CURRENT: OnceLock<NumberStyle> = OnceLock::new();
+        *CURRENT.get_or_init(|| {
+            ["SNAPDOWN_LOCALE", "LC_ALL", "LC_NUMERIC", "LANG"]
+                .into_iter()
+                .filter_map(|name| std::env::var(name).ok())
+                .find(|value| !value.is_empty())
+                .map(|locale| NumberStyle::from_locale(&locale))
+                .unwrap_or(NumberStyle::ENGLISH)
+        })
+    }
+
+    // e.g. "12,345"
+    pub fn count(&self, count: u64) -> String {
+        let digits = count.to_string();
+        let mut grouped = String::with_capacity(digits.len() * 4 / 3);
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index).is_multiple_of(3) {
+                grouped.push(self.group);
+            }
+            grouped.push(digit);
+        }
+        grouped
+    }
+
+    // e.g. "3.4 GB"
+    pub fn bytes(&self, bytes: u64) -> String {
+        const UNITS: [&str; 5] = ["KB", "MB", "GB", "TB", "PB"];
+        if bytes < 1000 {
+            return format!("{} B", bytes);
+        }
+        let mut value = bytes as f64;
+        let mut unit = "B";
+        for next_unit in UNITS {
+            if value < 1000.0 {
+                break;
+            }
+            value /= 1000.0;
+            unit = next_unit;
+        }
+        let value = format!("{:.1}", value).replace('.', &self.decimal.to_string());
+        format!("{} {}", value, unit)
+    }
+
+    // e.g. "42%" for 0.42
+    pub fn percent(&self, fraction: f64) -> String {
+        let percent = (fraction * 100.0).round() as u64;
+        if self.percent_space {
+            format!("{}\u{a0}%", percent)
+        } else {
+            format!("{}%", percent)
+        }
+    }
+}
+
+// Number of files, records etc. in the user's locale, e.g. "12,345"
+pub fn format_count(count: usize) -> String {
+    NumberStyle::current().count(count as u64)
+}
+
+// Human readable byte count in the user's locale, e.g. "3.4 GB". Uses
+// decimal units, like file managers and disk vendors do.
+pub fn format_bytes(bytes: u64) -> String {
+    NumberStyle::current().bytes(bytes)
+}
+
+// A fraction as a percentage in the user's locale, e.g. "42%"
+pub fn format_percent(fraction: f64) -> String {
+    NumberStyle::current().percent(fraction)
+}
+
+// Rough duration for progress displays, e.g. "1h 05m", "3m 20s" or "42s"
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60)
+    } else if seconds >= 60 {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        let english = NumberStyle::ENGLISH;
+        assert_eq!(english.bytes(0), "0 B");
+        assert_eq!(english.bytes(999), "999 B");
+        assert_eq!(english.bytes(1_000), "1.0 KB");
+        assert_eq!(english.bytes(3_400_000_000), "3.4 GB");
+        assert_eq!(english.bytes(48_000_000_000_000), "48.0 TB");
+        assert_eq!(
+            NumberStyle::from_locale("de_DE").bytes(3_400_000_000),
+            "3,4 GB"
+        );
+    }
+
+    #[test]
+    fn test_number_styles() {
+        let english = NumberStyle::from_locale("en_US.UTF-8");
+        assert_eq!(english, NumberStyle::ENGLISH);
+        assert_eq!(english.count(0), "0");
+        assert_eq!(english.count(999), "999");
+        assert_eq!(english.count(12_345), "12,345");
+        assert_eq!(english.count(1_234_567), "1,234,567");
+        assert_eq!(english.percent(0.424), "42%");
+
+        let german = NumberStyle::from_locale("de_DE.UTF-8");
+        assert_eq!(german.count(12_345), "12.345");
+        assert_eq!(german.percent(1.0), "100\u{a0}%");
+        assert_eq!(
+            NumberStyle::from_locale("fr-CA").count(12_345),
+            "12\u{a0}345"
+        );
+        assert_eq!(NumberStyle::from_locale("de_CH").count(12_345), "12'345");
+        assert_eq!(NumberStyle::from_locale("es_MX").count(12_345), "12,345");
+        assert_eq!(NumberStyle::from_locale("C"), NumberStyle::ENGLISH);
+        assert_eq!(NumberStyle::from_locale(""), NumberStyle::ENGLISH);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(42_900)), "42s");
+        assert_eq!(format_duration(Duration::from_secs(200)), "3m 20s");
+        assert_eq!(format_duration(Duration::from_secs(3900)), "1h 05m");
+    }
+}
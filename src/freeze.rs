@@ -0,0 +1,171 @@
+// --freeze: once a run has downloaded everything, make the files read-only
+// and seal the archive. The sealed manifest lists every file with its
+// checksum, plus one hash over all of them, so it's easy to tell later
+// whether anything in the archive changed.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::hashing;
+use crate::manifest::{EntryStatus, Manifest};
+
+pub const SEALED_MANIFEST_FILE: &str = "snapdown_manifest.sealed.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SealedFile {
+    pub filename: String,
+    pub size: u64,
+    // BLAKE3
+    pub checksum: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SealedManifest {
+    // RFC 3339
+    pub sealed_at: String,
+    pub total_bytes: u64,
+    // BLAKE3 of the "<filename>\t<checksum>\n" lines of all files, sorted by
+    // filename
+    pub archive_hash: String,
+    pub files: Vec<SealedFile>,
+}
+
+// Seal the completed downloads in `manifest`. Files whose size no longer
+// matches the manifest (changed by --post-process-cmd, for one) and files
+// from before the manifest had checksums are hashed again first.
+pub fn freeze(output_dir: &Path, manifest: &Manifest) -> Result<SealedManifest> {
+    let mut entries: Vec<_> = manifest
+        .entries()
+        .into_iter()
+        .filter(|entry| entry.status == EntryStatus::Completed)
+        .collect();
+    entries.sort_by(|a, b| a.saved_filename().cmp(b.saved_filename()));
+
+    let mut files = Vec::with_capacity(entries.len());
+    let mut to_hash: Vec<(usize, PathBuf)> = Vec::new();
+    for entry in entries {
+        let filename = entry.saved_filename().to_string();
+        let path = output_dir.join(&filename);
+        let size = fs::metadata(&path)?.len();
+        match entry.checksum {
+            Some(checksum) if size == entry.bytes_written => files.push(SealedFile {
+                filename,
+                size,
+                checksum,
+            }),
+            _ => {
+                to_hash.push((files.len(), path));
+                files.push(SealedFile {
+                    filename,
+                    size,
+                    checksum: String::new(),
+                });
+            }
+        }
+    }
+    let paths: Vec<PathBuf> = to_hash.iter().map(|(_, path)| path.clone()).collect();
+    for ((index, _), result) in to_hash.iter().zip(hashing::hash_files(&paths, None)) {
+        let file_hash = result?;
+        files[*index].size = file_hash.size;
+        files[*index].checksum = file_hash.hash;
+    }
+
+    let mut hasher = blake3::Hasher::new();
+    for file in &files {
+        hasher.update(format!("{}\t{}\n", file.filename, file.checksum).as_bytes());
+    }
+    let sealed = SealedManifest {
+        sealed_at: chrono::Utc::now().to_rfc3339(),
+        total_bytes: files.iter().map(|file| file.size).sum(),
+        archive_hash: hasher.finalize().to_hex().to_string(),
+        files,
+    };
+
+    for file in &sealed.files {
+        let path = output_dir.join(&file.filename);
+        let mut permissions = fs::metadata(&path)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions)?;
+    }
+    let path = output_dir.join(SEALED_MANIFEST_FILE);
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, serde_json::to_string_pretty(&sealed)?)?;
+    fs::rename(&temp_path, &path)?;
+    Ok(sealed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::ManifestEntry;
+
+    #[test]
+    fn test_freeze() {
+        let dir = std::env::temp_dir().join("snapdown_test_freeze");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let manifest = Manifest::open(&dir).unwrap();
+        for (filename, status, checksum) in [
+            ("b.jpg", EntryStatus::Completed, None),
+            (
+                "a.jpg",
+                EntryStatus::Completed,
+                Some("not checked".to_string()),
+            ),
+            ("c.jpg", EntryStatus::Failed, None),
+        ] {
+            fs::write(dir.join(filename), "data").unwrap();
+            manifest.record(ManifestEntry {
+                url: "https://example.com/a".to_string(),
+                filename: filename.to_string(),
+                status,
+                bytes_written: 4,
+                checksum,
+                views: Vec::new(),
+                mime_type: None,
+                saved_as: None,
+                uploaded: None,
+                md5: None,
+                record: None,
+            });
+        }
+
+        let sealed = freeze(&dir, &manifest).unwrap();
+        let filenames: Vec<&str> = sealed.files.iter().map(|f| f.filename.as_str()).collect();
+        assert_eq!(filenames, ["a.jpg", "b.jpg"]);
+        assert_eq!(sealed.total_bytes, 8);
+        // Taken from the manifest when the size still matches
+        assert_eq!(sealed.files[0].checksum, "not checked");
+        assert_eq!(
+            sealed.files[1].checksum,
+            blake3::hash(b"data").to_hex().to_string()
+        );
+        assert!(
+            fs::metadata(dir.join("a.jpg"))
+                .unwrap()
+                .permissions()
+                .readonly()
+        );
+        assert!(
+            !fs::metadata(dir.join("c.jpg"))
+                .unwrap()
+                .permissions()
+                .readonly()
+        );
+        let written: SealedManifest =
+            serde_json::from_str(&fs::read_to_string(dir.join(SEALED_MANIFEST_FILE)).unwrap())
+                .unwrap();
+        assert_eq!(written.archive_hash, sealed.archive_hash);
+
+        for filename in ["a.jpg", "b.jpg"] {
+            let mut permissions = fs::metadata(dir.join(filename)).unwrap().permissions();
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            fs::set_permissions(dir.join(filename), permissions).unwrap();
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+// Place names for coordinates, for {place} in --naming, without any network
+// requests. The place is the nearest city of a list built into SnapDown (the
+// capitals and largest cities, a few hundred), or with --places, of a GeoNames
+// cities file (cities500.txt, cities1000.txt... from
+// https://download.geonames.org/export/dump/), which has every town.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Result, bail};
+
+const BUILTIN_CITIES: &str = include_str!("geocode/cities.tsv");
+// ISO 3166 codes, which GeoNames has instead of names
+const COUNTRIES: &str = include_str!("geocode/countries.tsv");
+// A memory further than this from every city has no place, rather than one
+// of a city hours away
+const MAX_DISTANCE_KM: f64 = 50.0;
+const EARTH_RADIUS_KM: f64 = 6371.0;
+const KM_PER_DEGREE: f64 = 111.2;
+
+struct City {
+    name: String,
+    country: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+pub struct Geocoder {
+    // By the whole degrees of their coordinates, so a lookup only goes
+    // through the cities around
+    cells: HashMap<(i32, i32), Vec<City>>,
+}
+
+impl fmt::Debug for Geocoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cities: usize = self.cells.values().map(Vec::len).sum();
+        write!(f, "Geocoder({} cities)", cities)
+    }
+}
+
+// The cell of a coordinate, with longitudes wrapping around
+fn cell(latitude: f64, longitude: f64) -> (i32, i32) {
+    (
+        latitude.floor() as i32,
+        (longitude.floor() as i32).rem_euclid(360),
+    )
+}
+
+// Great-circle distance
+fn distance_km(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (latitude_a, latitude_b) = (a.0.to_radians(), b.0.to_radians());
+    let half_latitude = (latitude_b - latitude_a) / 2.0;
+    let half_longitude = (b.1 - a.1).to_radians() / 2.0;
+    let h = half_latitude.sin().powi(2)
+        + latitude_a.cos() * latitude_b.cos() * half_longitude.sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * h.sqrt().min(1.0).asin()
+}
+
+impl Geocoder {
+    pub fn builtin() -> Geocoder {
+        Geocoder::parse(BUILTIN_CITIES, 0, 1, 2, 3).expect("the built-in cities are valid")
+    }
+
+    // A GeoNames cities file, tab separated with the name in the 2nd column,
+    // latitude and longitude in the 5th and 6th, and the country in the 9th
+    pub fn open(path: &Path) -> Result<Geocoder> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("can't read {}: {}", path.display(), e))?;
+        let geocoder = Geocoder::parse(&text, 1, 8, 4, 5).map_err(|e| {
+            anyhow::anyhow!("{} isn't a GeoNames cities file: {}", path.display(), e)
+        })?;
+        if geocoder.cells.is_empty() {
+            bail!("{} has no cities", path.display());
+        }
+        Ok(geocoder)
+    }
+
+    // One city per line, with its fields in the given columns
+    fn parse(
+        text: &str,
+        name_column: usize,
+        country_column: usize,
+        latitude_column: usize,
+        longitude_column: usize,
+    ) -> Result<Geocoder> {
+        let countries: HashMap<&str, &str> = COUNTRIES
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .collect();
+        let mut cells: HashMap<(i32, i32), Vec<City>> = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let field = |column: usize| fields.get(column).copied().unwrap_or_default();
+            let (Ok(latitude), Ok(longitude)) = (
+                field(latitude_column).parse::<f64>(),
+                field(longitude_column).parse::<f64>(),
+            ) else {
+                bail!("line {} has no coordinates", number + 1);
+            };
+            let code = field(country_column);
+            cells
+                .entry(cell(latitude, longitude))
+                .or_default()
+                .push(City {
+                    name: field(name_column).to_string(),
+                    country: countries.get(code).unwrap_or(&code).to_string(),
+                    latitude,
+                    longitude,
+                });
+        }
+        Ok(Geocoder { cells })
+    }
+
+    // City_Country of the nearest city, e.g. Paris_France, with any spaces
+    // in the names replaced by - so the _ only separates the two. None for
+    // 0, 0, which Snapchat has for memories without a location.
+    pub fn place(&self, latitude: f64, longitude: f64) -> Option<String> {
+        if (latitude, longitude) == (0.0, 0.0) {
+            return None;
+        }
+        // Degrees of longitude get shorter towards the poles, so more cells
+        // around are within the distance
+        let latitude_cells = (MAX_DISTANCE_KM / KM_PER_DEGREE).ceil() as i32;
+        let longitude_km = KM_PER_DEGREE * latitude.to_radians().cos();
+        let longitude_cells = (MAX_DISTANCE_KM / longitude_km.max(1.0)).ceil().min(180.0) as i32;
+        let (row, column) = cell(latitude, longitude);
+        let nearest = (-latitude_cells..=latitude_cells)
+            .flat_map(|dy| {
+                (-longitude_cells..=longitude_cells)
+                    .map(move |dx| (row + dy, (column + dx).rem_euclid(360)))
+            })
+            .filter_map(|key| self.cells.get(&key))
+            .flatten()
+            .map(|city| {
+                let distance = distance_km((latitude, longitude), (city.latitude, city.longitude));
+                (distance, city)
+            })
+            .filter(|(distance, _)| *distance <= MAX_DISTANCE_KM)
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))?;
+        let city = nearest.1;
+        Some(format!(
+            "{}_{}",
+            city.name.replace(' ', "-"),
+            city.country.replace(' ', "-")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_places() {
+        let geocoder = Geocoder::builtin();
+        assert_eq!(
+            geocoder.place(48.8584, 2.2945).as_deref(),
+            Some("Paris_France")
+        );
+        assert_eq!(
+            geocoder.place(40.25548, -111.645325).as_deref(),
+            Some("Provo_United-States")
+        );
+        // Across the date line, and in the middle of the Pacific
+        assert_eq!(geocoder.place(-17.75, 177.45).as_deref(), Some("Nadi_Fiji"));
+        assert_eq!(geocoder.place(-30.0, -140.0), None);
+        assert_eq!(geocoder.place(0.0, 0.0), None);
+    }
+
+    #[test]
+    fn test_geonames_places() {
+        let dir = std::env::temp_dir().join("snapdown_test_geonames");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cities1000.txt");
+        fs::write(
+            &path,
+            "2988507\tParis\tParis\t\t48.85341\t2.3488\tP\tPPLC\tFR\t\n\
+             5780026\tOrem\tOrem\t\t40.2969\t-111.69465\tP\tPPL\tUS\t\n\
+             1\tSomewhere\tSomewhere\t\t10.0\t10.0\tP\tPPL\tZZ\t\n",
+        )
+        .unwrap();
+        let geocoder = Geocoder::open(&path).unwrap();
+        assert_eq!(
+            geocoder.place(40.29, -111.7).as_deref(),
+            Some("Orem_United-States")
+        );
+        // Countries that aren't known keep their code
+        assert_eq!(geocoder.place(10.1, 10.1).as_deref(), Some("Somewhere_ZZ"));
+
+        fs::write(&path, "not a cities file").unwrap();
+        assert!(Geocoder::open(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+// Bounded channels for the log lines and statuses the downloader sends the
+// GUI. A minimized window doesn't read them until it's shown again, which
+// with std's unbounded channels meant memory growing for as long as a run of
+// hours lasts. Sending never blocks the downloader: once a channel is full
+// the oldest message is dropped to make room, and the GUI is told how many
+// it missed. The console only shows the last lines anyway, and each status
+// replaces the one before. The file list needs every file's last event, so
+// those are kept by file instead, see events::FileEvents.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{RecvError, SendError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex};
+
+// More than the console shows, so a short hiccup loses nothing
+pub const LOG_CAPACITY: usize = 4096;
+// Only the last one matters
+pub const STATUS_CAPACITY: usize = 16;
+
+struct Shared<T> {
+    queue: Mutex<VecDeque<T>>,
+    // For recv(), when something is sent or the last sender is gone
+    changed: Condvar,
+    capacity: usize,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+    // Since the receiver last asked
+    dropped: AtomicUsize,
+}
+
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(VecDeque::with_capacity(capacity)),
+        changed: Condvar::new(),
+        capacity: capacity.max(1),
+        senders: AtomicUsize::new(1),
+        receiver_alive: AtomicBool::new(true),
+        dropped: AtomicUsize::new(0),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    // Like mpsc::Sender::send(), an error only if the receiver is gone
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        if !self.shared.receiver_alive.load(Ordering::Relaxed) {
+            return Err(SendError(value));
+        }
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.len() >= self.shared.capacity {
+            queue.pop_front();
+            self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        queue.push_back(value);
+        drop(queue);
+        self.shared.changed.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // Under the lock, so a recv() that just saw a sender left can't
+            // miss this
+            let _queue = self.shared.queue.lock().unwrap();
+            self.shared.changed.notify_all();
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        match queue.pop_front() {
+            Some(value) => Ok(value),
+            None if self.shared.senders.load(Ordering::Acquire) == 0 => {
+                Err(TryRecvError::Disconnected)
+            }
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    // What's there now, without waiting
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.try_recv().ok())
+    }
+
+    // Wait for the next message, an error once all the senders are gone and
+    // everything they sent was received
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        loop {
+            if let Some(value) = queue.pop_front() {
+                return Ok(value);
+            }
+            if self.shared.senders.load(Ordering::Acquire) == 0 {
+                return Err(RecvError);
+            }
+            queue = self.shared.changed.wait(queue).unwrap();
+        }
+    }
+
+    // How many messages were dropped to make room since the last call
+    pub fn take_dropped(&self) -> usize {
+        self.shared.dropped.swap(0, Ordering::Relaxed)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver_alive.store(false, Ordering::Relaxed);
+    }
+}
+
+// Blocks for each message like recv(), until the senders are gone
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { receiver: self }
+    }
+}
+
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drops_oldest() {
+        let (sender, receiver) = bounded(3);
+        for i in 0..5 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(receiver.take_dropped(), 2);
+        assert_eq!(receiver.take_dropped(), 0);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [2, 3, 4]);
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+        drop(sender);
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn test_into_iter_ends_with_senders() {
+        let (sender, receiver) = bounded(100);
+        let other = sender.clone();
+        let thread = std::thread::spawn(move || {
+            for i in 0..10 {
+                other.send(i).unwrap();
+            }
+        });
+        sender.send(10).unwrap();
+        drop(sender);
+        let mut received: Vec<i32> = receiver.into_iter().collect();
+        thread.join().unwrap();
+        received.sort();
+        assert_eq!(received, (0..=10).collect::<Vec<_>>());
+
+        let (sender, receiver) = bounded(1);
+        drop(receiver);
+        assert!(sender.send(1).is_err());
+    }
+}
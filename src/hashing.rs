@@ -0,0 +1,310 @@
+// Content hashing for verifying downloaded files. New downloads are hashed
+// while they're written (HashingWriter). Checking a whole archive can mean
+// 100+ GB, so existing files are hashed in parallel on their own rayon pool
+// (separate from the download pool) with large read buffers, and progress is
+// reported as files complete.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+use rayon::prelude::*;
+
+use crate::md5::Md5;
+
+// Large enough that hashing isn't bottlenecked on syscalls, small enough that
+// a thread per core doesn't use much memory
+pub const HASH_BUFFER_SIZE: usize = 1024 * 1024;
+
+// Hex encoded BLAKE3 hash and size of a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileHash {
+    pub hash: String,
+    pub size: u64,
+    // Only from a HashingWriter that was asked for it, see with_md5()
+    pub md5: Option<[u8; 16]>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HashProgress {
+    pub files_done: usize,
+    pub files_total: usize,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+}
+
+// Returns the number of bytes read
+fn update_from_reader(hasher: &mut blake3::Hasher, mut reader: impl Read) -> io::Result<u64> {
+    let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+    let mut size = 0;
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(size),
+            Ok(n) => {
+                hasher.update(&buffer[..n]);
+                size += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+pub fn hash_reader(reader: impl Read) -> io::Result<FileHash> {
+    let mut hasher = blake3::Hasher::new();
+    let size = update_from_reader(&mut hasher, reader)?;
+    Ok(FileHash {
+        hash: hasher.finalize().to_hex().to_string(),
+        size,
+        md5: None,
+    })
+}
+
+pub fn hash_file(path: &Path) -> io::Result<FileHash> {
+    hash_reader(File::open(path)?)
+}
+
+// Hashes everything written through it, so a download's checksum is ready as
+// soon as the body has been copied, without reading the file back
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: blake3::Hasher,
+    size: u64,
+    md5: Option<Md5>,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        HashingWriter {
+            inner,
+            hasher: blake3::Hasher::new(),
+            size: 0,
+            md5: None,
+        }
+    }
+
+    // Also work out the MD5, to check a download against the server's. Of
+    // what's written only, update_from() doesn't add to it.
+    pub fn with_md5(mut self) -> Self {
+        self.md5 = Some(Md5::new());
+        self
+    }
+
+    // Include data that's already in the file, when appending to a partial
+    // download
+    pub fn update_from(&mut self, reader: impl Read) -> io::Result<()> {
+        self.size += update_from_reader(&mut self.hasher, reader)?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<FileHash> {
+        self.inner.flush()?;
+        Ok(FileHash {
+            hash: self.hasher.finalize().to_hex().to_string(),
+            size: self.size,
+            md5: self.md5.map(Md5::finalize),
+        })
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        if let Some(md5) = &mut self.md5 {
+            md5.update(&buf[..n]);
+        }
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// The same for a body that's read rather than written, like one streamed
+// into a bucket with --s3-bucket
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: blake3::Hasher,
+    size: u64,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        HashingReader {
+            inner,
+            hasher: blake3::Hasher::new(),
+            size: 0,
+        }
+    }
+
+    // Of what was read so far
+    pub fn finish(self) -> FileHash {
+        FileHash {
+            hash: self.hasher.finalize().to_hex().to_string(),
+            size: self.size,
+            md5: None,
+        }
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+}
+
+// Hash all the files in parallel. Results are in the same order as `paths`.
+// A progress event is sent after each file.
+pub fn hash_files(
+    paths: &[PathBuf],
+    progress: Option<&mpsc::Sender<HashProgress>>,
+) -> Vec<io::Result<FileHash>> {
+    // Sizes up front so progress can be shown in bytes, which is what matters
+    // when an archive mixes small photos with multi-GB videos
+    let bytes_total = paths
+        .iter()
+        .filter_map(|path| path.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    let files_done = AtomicUsize::new(0);
+    let bytes_done = AtomicU64::new(0);
+
+    let hash_one = |path: &PathBuf| {
+        let result = hash_file(path);
+        let files_done = files_done.fetch_add(1, Ordering::Relaxed) + 1;
+        let size = result.as_ref().map(|file_hash| file_hash.size).unwrap_or(0);
+        let bytes_done = bytes_done.fetch_add(size, Ordering::Relaxed) + size;
+        if let Some(progress) = progress {
+            let _ = progress.send(HashProgress {
+                files_done,
+                files_total: paths.len(),
+                bytes_done,
+                bytes_total,
+            });
+        }
+        result
+    };
+
+    match rayon::ThreadPoolBuilder::new().build() {
+        Ok(pool) => pool.install(|| paths.par_iter().map(hash_one).collect()),
+        // Hashing on the current thread is slow, but still correct
+        Err(e) => {
+            log::error!("Error creating hashing thread pool: {}", e);
+            paths.iter().map(hash_one).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Instant;
+
+    fn write_test_files(dir: &Path, count: usize, size: usize) -> Vec<PathBuf> {
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        (0..count)
+            .map(|i| {
+                let path = dir.join(format!("file_{}.bin", i));
+                let data: Vec<u8> = (0..size).map(|j| ((i + j) % 251) as u8).collect();
+                fs::write(&path, data).unwrap();
+                path
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hashing_writer() {
+        let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        let expected = hash_reader(&data[..]).unwrap();
+
+        let mut output = Vec::new();
+        let mut writer = HashingWriter::new(&mut output);
+        io::copy(&mut &data[..], &mut writer).unwrap();
+        assert_eq!(writer.finish().unwrap(), expected);
+        assert_eq!(output, data);
+
+        // Continuing a partial file
+        let mut writer = HashingWriter::new(Vec::new());
+        writer.update_from(&data[..40_000]).unwrap();
+        writer.write_all(&data[40_000..]).unwrap();
+        assert_eq!(writer.finish().unwrap(), expected);
+
+        let mut writer = HashingWriter::new(io::sink()).with_md5();
+        writer.write_all(b"abc").unwrap();
+        assert_eq!(
+            writer
+                .finish()
+                .unwrap()
+                .md5
+                .map(crate::md5::to_hex)
+                .as_deref(),
+            Some("900150983cd24fb0d6963f7d28e17f72")
+        );
+
+        let mut reader = HashingReader::new(&data[..]);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(reader.finish(), expected);
+    }
+
+    #[test]
+    fn test_hash_files() {
+        let dir = std::env::temp_dir().join("snapdown_test_hash_files");
+        let mut paths = write_test_files(&dir, 8, HASH_BUFFER_SIZE + 123);
+        paths.push(dir.join("missing.bin"));
+
+        let (sender, receiver) = mpsc::channel();
+        let results = hash_files(&paths, Some(&sender));
+        drop(sender);
+
+        assert_eq!(results.len(), 9);
+        for (path, result) in paths.iter().zip(&results).take(8) {
+            let expected = blake3::hash(&fs::read(path).unwrap()).to_hex().to_string();
+            let file_hash = result.as_ref().unwrap();
+            assert_eq!(file_hash.hash, expected);
+            assert_eq!(file_hash.size, (HASH_BUFFER_SIZE + 123) as u64);
+        }
+        assert!(results[8].is_err());
+
+        let events: Vec<HashProgress> = receiver.iter().collect();
+        assert_eq!(events.len(), 9);
+        let last = events.iter().max_by_key(|event| event.files_done).unwrap();
+        assert_eq!(last.files_done, 9);
+        assert_eq!(last.bytes_done, last.bytes_total);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Throughput check, run with:
+    //   cargo test --release hash_files_benchmark -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn hash_files_benchmark() {
+        let dir = std::env::temp_dir().join("snapdown_bench_hash_files");
+        let paths = write_test_files(&dir, 64, 32 * 1024 * 1024);
+
+        let start = Instant::now();
+        let results = hash_files(&paths, None);
+        let elapsed = start.elapsed();
+
+        assert!(results.iter().all(|result| result.is_ok()));
+        let total: u64 = results.iter().map(|r| r.as_ref().unwrap().size).sum();
+        println!(
+            "Hashed {} in {:.2} s ({}/s)",
+            crate::format::format_bytes(total),
+            elapsed.as_secs_f64(),
+            crate::format::format_bytes((total as f64 / elapsed.as_secs_f64()) as u64)
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,602 @@
+// Long-form help printed by --help-all, a complete reference for people who
+// only have the binary (no repo or internet connection at hand)
+
+use crate::freeze::SEALED_MANIFEST_FILE;
+use crate::http_debug::HTTP_DEBUG_LOG_FILE;
+use crate::identity::ARCHIVE_IDENTITY_FILE;
+use crate::install::PORTABLE_MARKER_FILE;
+use crate::manifest::MANIFEST_FILE;
+use crate::presets::PRESETS_FILE;
+use crate::quarantine::QUARANTINE_DIR;
+use crate::replay::REPLAY_ENV;
+use crate::report::REPORT_FILE;
+use crate::usage::USAGE_FILE;
+use crate::{DEFAULT_NUM_JOBS, DEFAULT_OUTPUT_DIR};
+
+pub fn long_help(program_name: &str) -> String {
+    format!(
+        "\
+NAME
+    snapdown - quickly download all your Snapchat memories
+
+SYNOPSIS
+    {program_name} [gui]
+    {program_name} download [-i <input>] [-o <output_dir>] [-j <jobs>] [options]
+    {program_name} parse -i <input> [-o <file>] [--format csv|json]
+    {program_name} verify -o <output_dir> [--repair -i <input> [--resolve-links] [--naming <naming> [--places <file>]]]
+    {program_name} diff -a <old_input> -b <new_input>
+
+DESCRIPTION
+    Without a command, SnapDown opens its GUI. The input file can be
+    dropped onto its window, as can the folder of an extracted export.
+    While it downloads, Hide to tray hides the window and leaves an icon
+    in the system tray, coloured by how the run is going, with Pause,
+    Open output folder and Quit in its menu. Clicking the icon shows the
+    window again.
+
+    download
+        Downloads every memory listed in the input file into the output
+        directory, many at a time, and prints a summary at the end. If -i
+        or -o is missing and SnapDown is running in a terminal, it asks for
+        them (Tab completes file names). The options below are for this
+        command. The older form, {program_name} --cli [options], still works
+        and means the same.
+    parse
+        Reads the memories list from any input file (see INPUT FILES) and
+        writes it to <file>, without downloading anything. Coordinates are
+        split into latitude and longitude, and HTML tags and entities are
+        removed. With --format csv (the default, unless <file> ends in
+        .json) it's a snap_export.csv, which can be the input of a download
+        later. With --format json it's an array of objects with the same
+        fields, and the coordinates as numbers (null for memories without a
+        location). <file> defaults to snap_export.csv or memories.json; -
+        writes to the standard output.
+    verify
+        Checks every downloaded file in <output_dir> against its
+        {MANIFEST_FILE}: that it's still there, with the same size and
+        checksum. Lists the files that aren't, and changes nothing. Files
+        downloaded before the manifest had checksums only get their size
+        checked, and files that aren't in the manifest at all are checked
+        for being empty (as a crash can leave them).
+
+        With --repair, the broken files are moved into
+        <output_dir>/{QUARANTINE_DIR}/ and downloaded again from <input>,
+        which should be the export they came from, and then checked again.
+        From an export zip, that's the parts of it the broken files are
+        from, chats or stories as well as memories. Everything else is
+        skipped, as in any resumed run. Broken files that couldn't be
+        downloaded again are put back afterwards, the others stay in
+        quarantine to be looked at or deleted, and the report lists both.
+        --resolve-links works the same as for download, and --naming and
+        --places have to be the ones the files were downloaded with.
+    diff
+        Compares two exports of the same account, e.g. last year's and
+        this year's, and lists the memories (date and media type) that are
+        only in one of them, to check none disappeared in between. Both
+        can be any input file. The links change with every export, so
+        memories are matched by the memory ID in their links, or if there
+        isn't one by their date and media type.
+
+    Each command has a short summary of its options with -h, e.g.
+    {program_name} download -h.
+
+INPUT FILES
+    mydata~*.zip
+        The export Snapchat emails you a link to. It doesn't need to be
+        extracted first; the memories list is read from inside it.
+    memories_history.html (or a .html, .htm, .mhtml or .mht file of it)
+        html/memories_history.html from the export. It can also be the page
+        saved again from a browser with Save Page As, as a complete page,
+        HTML only or a single file, whatever it's named: a page is taken for
+        it if it has the memories' download links in it. If its table isn't
+        written the way SnapDown expects, it's read again with a slower
+        parser that takes any table rows with a download link.
+    memories_history.json (or a renamed copy)
+        json/memories_history.json from the export. A .json file named
+        otherwise is read if it has the list's \"Saved Media\" in it.
+    chat_history.html or chat_history.json
+        The chat history from the export. The photos and videos sent in
+        chats are downloaded instead of memories, into a folder for each
+        conversation in chats/ of <output_dir>. Media without a download
+        link (older exports only have the files in chat_media/) is skipped.
+    shared_story.json, story_history.json or spotlight.json (or .html)
+        The stories and Spotlight files from the export. Their media goes
+        into stories/ or spotlight/ of <output_dir>. Only the entries with
+        a download link are downloaded, many exports only list the snaps.
+    snap_export.csv
+        A CSV with the columns timestamp_utc, format, latitude, longitude
+        and download_url, with a header row. Made by
+        javascript/extract_download_links.js, or by --export-failures. A
+        folder column after them keeps chats, stories and Spotlight in
+        their folders.
+
+    Files saved again in Excel or Notepad are read too, whether they were
+    saved as UTF-8 with a byte order mark or as UTF-16 (Unicode).
+
+OPTIONS
+    -i <input>
+        The input file, see INPUT FILES.
+    --sections <memories,chats,stories,spotlight>
+        Which parts of an export zip to download (default: memories).
+        memories go at the top of <output_dir> as always, and the others
+        into folders of their own: chats/<conversation>/, stories/ and
+        spotlight/, as when their files are the input. Parts the export
+        doesn't have are skipped. Other input files are one part already.
+    -o <output_dir>
+        Where to download to. Created if it doesn't exist. The GUI uses
+        {DEFAULT_OUTPUT_DIR} by default. A webdav:// address is the same as
+        --webdav with that address, with the manifest, report and logs in
+        {DEFAULT_OUTPUT_DIR}.
+    -j <jobs>
+        Number of downloads to run at the same time (default:
+        {DEFAULT_NUM_JOBS}). When the server limits requests (HTTP 429, or 503
+        with a Retry-After), SnapDown waits as long as it asks, runs half
+        as many downloads at a time, and goes back up to <jobs> as
+        downloads succeed again. A record limited 5 times in a row fails.
+    --preset <name|file.toml>
+        Use the settings of a preset saved in {PRESETS_FILE} (see FILES)
+        for the options that aren't given: the output directory, --naming,
+        --views, --view-links, --dedup, --sections, --sidecars, --no-exif,
+        --no-touch, --composite-overlays, --auto-rotate and --freeze. A
+        preset can turn these flags on but not off again. Names aren't case
+        sensitive. A .toml file saved from --print-config works as a preset
+        too, with those of its settings.
+    --export-failures <csv>
+        After the run, write the records that failed to download to this
+        file, as a snap_export.csv that can be used as the input of another
+        run to retry just those. Name it snap_export.csv (or something
+        ending in it) so it's recognized as an input.
+    --report <path>
+        Where to write the JSON report of the run (default:
+        <output_dir>/{REPORT_FILE}). It lists what happened to every
+        record, with its URL, file name, status, error, size and how long it
+        took, and the totals and timings of the whole run. Attach it to a
+        support request rather than copying bits of the log, but keep in
+        mind that the download links in it work for anyone until they
+        expire.
+    --status-port <port>
+        While the run goes, serve its progress as JSON on this port, to
+        check on a long run on a NAS or another computer without a screen
+        from a browser or phone: open http://<its address>:<port>/. The
+        page has the phase of the run (reading_input, downloading,
+        finishing, finished or stopped), the counts, the download speed in
+        bytes per second and the estimated time left. It's reachable from
+        the whole network, but has no file names or links in it.
+        /metrics on the same port has Prometheus metrics to scrape:
+        snapdown_records, snapdown_downloads_total by outcome,
+        snapdown_errors_total by kind (invalid, download_link, connection,
+        http_status, file), snapdown_bytes_total and the
+        snapdown_download_duration_seconds histogram.
+    --debug-http
+        Append the status, headers, timing and redirects of every failed
+        request to {HTTP_DEBUG_LOG_FILE}, to attach to bug reports.
+    --log-full-urls
+        Keep the download links whole in snapdown.log, {HTTP_DEBUG_LOG_FILE}
+        and the report. By default the values of their uid, sid and sig
+        parameters (and the signatures of other CDNs) are replaced with
+        <redacted>: anyone with a whole link can download the memory, and
+        logs get posted in public issues. Only for debugging links
+        yourself, don't share logs written with it.
+    --fail-fast
+        Stop at the first download that fails, instead of going on with the
+        others, and show its record, link, error, and what the server
+        answered: the status, headers and the start of the body. For
+        finding out why a whole export fails, rather than reading the same
+        error thousands of times. Turns on --debug-http.
+    --resolve-links
+        Request a freshly signed download link for each memory first, the
+        way the Snapchat web page does. Use this if downloads fail with
+        expired link errors, which happens with exports older than a few
+        days.
+    --no-exif
+        Don't write the capture date and location into downloaded photos
+        (see OUTPUT).
+    --no-touch
+        Don't set the files' modified times to when the memories were taken
+        (see OUTPUT).
+    --composite-overlays
+        Draw the captions, drawings and stickers of photos onto them,
+        instead of saving them as separate files (see OUTPUT).
+    --auto-rotate
+        Turn photos taken sideways upright, for viewers that ignore the
+        orientation in the photo's EXIF tags (see OUTPUT).
+    --sidecars
+        Write a <file>.json next to each downloaded file, with the capture
+        date, location (coordinates), download link (without its
+        signature), when it was downloaded and its BLAKE3 checksum (see
+        OUTPUT).
+    --dedup <delete|hardlink>
+        Some exports have the same photo or video under several timestamps.
+        With this, every download is compared (by BLAKE3 hash) with the
+        files downloaded before, and copies are deleted, or replaced with a
+        hard link to the first one (keeping every filename while only
+        storing the data once). The summary shows how much space that saved.
+        The hashes are kept in snapdown_dedup_index.jsonl in the output
+        directory.
+    --views <by-type,by-year,by-place>
+        After the run, also link every downloaded file into folders of the
+        output directory: by-type/<Image|Video|...>/, by-year/<year>/ and
+        by-place/<latitude>_<longitude>/ (rounded to about 10 km, unknown
+        for memories without a location). The links don't take up space,
+        and the files stay where they are. Which links a file has is kept
+        in {MANIFEST_FILE}, so later runs only link new files.
+    --view-links <hardlink|symlink>
+        How --views links the files. Hard links (the default) work
+        everywhere, but only on one disk. Symbolic links are relative, and
+        need developer mode or admin rights on Windows.
+    --freeze
+        If the run ends with every memory downloaded (no failures, not
+        stopped), make the files read-only and write a sealed manifest,
+        {SEALED_MANIFEST_FILE}, with the size and BLAKE3 checksum of each
+        file and one hash over the whole archive. This marks the archive as
+        complete and guards it against accidental changes. A run with
+        failures isn't frozen; run again to retry them first.
+    --force
+        Download every memory again, even the ones that are already
+        downloaded, overwriting them. See RESUMING for what's skipped
+        otherwise.
+    --limit-rate <rate>
+        Limit the total download speed of all downloads together, so the
+        rest of the network stays usable. In bytes per second, optionally
+        followed by K, M or G (powers of 1024, like curl), e.g. 500K or 5M.
+    --schedule <HH:MM-HH:MM>
+        Only download between these two times of day, in local time, e.g.
+        01:00-07:00 for a network with a nighttime allowance, or 22:00-06:00
+        across midnight. The run pauses when the window closes and goes on
+        when it opens again, however many days that takes. Downloads in
+        progress at the pause are held; the ones whose connection doesn't
+        survive it fail, to be retried by the next run (see RESUMING).
+        Pausing or resuming by hand (in the GUI) holds until the window
+        next opens or closes. The progress is saved at each pause, so the
+        computer can be switched off in between and a new run continues
+        where this one stopped.
+    --connect-timeout <seconds>
+        Give up connecting to a server after this many seconds. No limit by
+        default.
+    --response-timeout <seconds>
+        Give up waiting for a server to start answering a request after
+        this many seconds. No limit by default. This doesn't limit how long
+        the download itself takes.
+    --retries <n>
+        When a download fails to connect, times out, or the server answers
+        with an error that may be gone on the next try (HTTP 408, 500, 502,
+        503 or 504), try it again up to <n> times, waiting 1, 2, 4...
+        seconds in between (at most 30). 0 by default, leaving failed
+        downloads to the next run. Only failed connections count towards
+        deciding the network is down; a slow server doesn't.
+    --pool-size <n>
+        Keep up to this many connections to each server open between
+        downloads, so the next ones don't connect and do a TLS handshake
+        again. As many as --jobs by default.
+    --pool-idle-timeout <seconds>
+        Close a connection kept open once it's been unused this many
+        seconds. 30 by default.
+    --stall-timeout <seconds>
+        When nothing was downloaded for this many seconds, log what each
+        download in progress is doing: how long ago it started and how much
+        of it is written. 900 (15 minutes) by default. A paused run doesn't
+        count as stalled.
+    --restart-stalled
+        When the run stalls, also stop it and start it again, continuing the
+        partial files, up to 3 times. If it still stalls, SnapDown exits
+        with status 3.
+    --replay <report>
+        Don't download anything, play back the {REPORT_FILE} of an
+        earlier run of the same input file instead: every record starts and
+        finishes when it did in that run, with the same outcome, and the
+        progress, summary, statistics and report of this run come from
+        that. For trying things out without a network connection, into a
+        scratch output directory. The GUI does the same with the report in
+        the {REPLAY_ENV} environment variable.
+    --replay-speed <factor>
+        Play back --replay this many times as fast, e.g. 10. 1 by default.
+    --naming <naming>
+        How to name the files (see OUTPUT): legacy (the default), hash,
+        index, or a template of placeholders.
+    --places <file>
+        Look {{place}} up in a GeoNames cities file, e.g. cities1000.txt
+        (unzipped) from https://download.geonames.org/export/dump/, which
+        has every town with at least 1,000 people, instead of the few
+        hundred largest cities built into SnapDown. Nothing is looked up
+        online either way. Only for a --naming template with {{place}}.
+    --proxy <url>
+        Download through a proxy, e.g. http://proxy.example.com:8080 or
+        socks5://localhost:1080. Without this, the proxy in the ALL_PROXY,
+        HTTPS_PROXY or HTTP_PROXY environment variable is used, if set.
+    --post-process-cmd <cmd>
+        Run a command on each file once it's downloaded, e.g. to tag,
+        rename or upload it. It runs through the shell (sh, or cmd on
+        Windows), with {{path}} replaced by the file's path. Without {{path}},
+        the path is added at the end. What the command prints goes into the
+        log, and a failing command doesn't fail the download. At most one
+        command per CPU runs at a time. Files the command moves or renames
+        are downloaded again by the next run.
+    --upload immich --server <url> --api-key <key>
+        Upload each file to an Immich server once it's downloaded, e.g.
+        --server https://photos.example.com with an API key made under
+        Account Settings, API Keys (it needs to read the user and upload
+        and update assets). The date and place of the memory are set on the
+        upload, videos included. The server and key are checked before
+        anything is downloaded. Files that were already downloaded, or
+        failed to upload before, are uploaded at the end of the run, and
+        ones the server already has aren't uploaded twice. Uploads are
+        counted apart from the downloads: a file that couldn't be uploaded
+        is still downloaded. Not used by the GUI.
+    --upload-only
+        With --upload, delete each file once it's uploaded instead of also
+        keeping it in the output directory. The manifest remembers them,
+        so they aren't downloaded again. Can't be used with
+        --post-process-cmd, --views, --dedup, --sidecars or --freeze, which
+        all need the files.
+    --s3-bucket <bucket>
+        Store the files in a bucket of AWS S3, or of an S3-compatible
+        service like MinIO or Backblaze B2, instead of the output directory.
+        Each download is streamed straight into the bucket, nothing is
+        written to disk but the manifest, report and logs, which still go
+        in the output directory. The files are laid out by date, as
+        <prefix>/<year>/<month>/<filename>. The credentials are read from
+        AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY (and AWS_SESSION_TOKEN,
+        if set), and checked before anything is downloaded. Files already in
+        the bucket are skipped, but partial downloads start over, and no
+        EXIF tags or file times are set. Each download takes a thread, so
+        there are at most 4 per CPU at a time, whatever -j is. Can't be
+        used with the options
+        that change or need the files: --post-process-cmd, --upload,
+        --views, --dedup, --sidecars, --freeze, --composite-overlays,
+        --auto-rotate and --dry-run. Not used by the GUI.
+    --s3-endpoint <url>
+        The S3-compatible service to use, e.g. http://localhost:9000 for
+        MinIO or https://s3.us-west-004.backblazeb2.com. AWS's endpoint of
+        --s3-region if not set. Requests use path-style addresses.
+    --s3-region <region>
+        The bucket's region, us-east-1 if not set. Services other than AWS
+        mostly accept any region.
+    --s3-prefix <prefix>
+        Put the files under a prefix in the bucket, e.g. snapchat for
+        snapchat/2023/06/...
+    --webdav <url>
+        Store the files in a folder of a WebDAV server, like Nextcloud or
+        ownCloud, instead of the output directory. webdav://<user>@<host>/<path>
+        is https, http:// and https:// addresses work too. For Nextcloud
+        the path is /remote.php/dav/files/<user>/<folder>, and the password
+        is best an app password, made under Settings, Security. It's read
+        from SNAPDOWN_WEBDAV_PASSWORD, or from the address if it's in it.
+        As with --s3-bucket, each download is streamed straight to the
+        server, at most 4 per CPU at a time, and only the manifest, report
+        and logs go in the output directory ({DEFAULT_OUTPUT_DIR} if there's no
+        -o). The folder is created if it's missing, files already in it
+        are skipped, and in Nextcloud big files are uploaded in chunks of
+        10 MB. -o webdav://... does the same. Can't be used with the same
+        options as --s3-bucket, or with it. Not used by the GUI.
+    --space-check <off|sample|full>
+        Before downloading, ask the server the size of some (sample, the
+        default: 30 spread over the files left to download) or all (full)
+        of the files, and compare what they need with the free space in
+        the output directory. The estimate is logged and shown in the GUI,
+        and a run that probably won't fit gets a warning. With full, a run
+        whose every size is known doesn't start without the space for it.
+        Files stored with --s3-bucket or --webdav aren't checked. Before
+        a run, the GUI's Plan can show a sampled estimate like this one,
+        with how long the download takes at the speed of the last run,
+        once Estimate size and time is clicked.
+    --dry-run
+        Read the input and check the output directory like a real run, but
+        don't download or write anything. Prints how many files would be
+        downloaded, continued and skipped, and how many of each media type.
+    --retry-failed
+        Only try the downloads that failed in the output directory again,
+        without -i: the manifest keeps the record of each one that fails,
+        and the name it got. Nothing else is read or checked again, so a
+        retry of a few files out of thousands is quick. The GUI's Retry
+        failed button after a run does the same.
+    --smtp <url>, --email-to <address>, --email-from <address>
+        Email a summary to <address> when the run is done, for scheduled
+        runs nobody watches. Memories that failed are attached as a
+        snap_export.csv that can be run again. <url> is the mail server,
+        e.g. smtps://me%40example.com@smtp.example.com (TLS, port 465) or
+        smtp://me%40example.com@smtp.example.com:587?tls=required
+        (STARTTLS). The password can be in the URL, but is better set in
+        SNAPDOWN_SMTP_PASSWORD. The email is sent from --email-from, or
+        from <address> itself if not given. Not used by the GUI.
+    --notify, --notify-error-rate <percent>
+        Show a desktop notification when the run is over, and the first
+        time more than <percent> (default 10) of the downloads so far
+        failed, once at least 20 are done. Without a desktop to show it
+        on, the notification is only logged. The GUI's Notify me setting
+        does the same.
+    --allow-mixed-archives
+        Download into the output directory even if it already has memories
+        from a different Snapchat account. Without this, the new export
+        goes into an export_<date> subfolder instead.
+    --support-bundle <zip>
+        After the run, or if it couldn't start, write a zip to attach to a
+        bug report: snapdown.log, {HTTP_DEBUG_LOG_FILE}, the report, the settings
+        of the run and the version and OS. The signatures of the download
+        links in it are cut off, so it's safe to post. The GUI has a Create
+        support bundle button for the same.
+    --print-config
+        Print every setting the download would run with, as TOML, and exit
+        without downloading anything: the defaults, the --preset and the
+        options given put together, and the proxy from the environment if
+        there's no --proxy. The keys are the options' names, e.g. jobs = 500
+        and no_exif = false; settings that are off are left out, and so
+        are passwords and API keys. Saved as a .toml file, it can be given
+        to --preset. The GUI shows the same under Advanced settings, Show
+        effective settings.
+    -h, --help
+        Short usage summary, of SnapDown or of a command.
+    -V, --version
+        SnapDown's version.
+    --help-all
+        This reference.
+
+OUTPUT
+    All files are saved directly in the output directory. By default
+    (--naming legacy) they're named after the memory's capture time (UTC)
+    and location:
+
+        <date>_<time>_UTC_<latitude>_<longitude>.<ext>
+        e.g. 2026-01-13_01-55-38_UTC_40.25548_-111.645325.jpg
+
+    --naming hash names them after a hash of the memory's ID instead, which
+    stays the same in later exports (e.g. 3f9a0c2be41d7785.jpg), and
+    --naming index after their position in the input file (00001.jpg,
+    00002.mp4...). Anything else is a template, with these placeholders:
+
+        {{timestamp}}                   the capture time as in the input
+        {{date}} {{time}}                 2026-01-13 and 01-55-38 (UTC)
+        {{year}} {{month}} {{day}}          2026, 01 and 13
+        {{latitude}} {{longitude}}        empty without a location
+        {{place}}                       the nearest city within 50 km, e.g.
+                                      Paris_France (see --places), or
+                                      unknown
+        {{type}}                        image, video...
+        {{index}} {{hash}}                as for --naming index and hash
+        {{burst_index}}                 e.g. 20260113-015538 for the photos of
+                                      each burst (3 or more taken at most 3
+                                      seconds apart at the same place), the
+                                      time of its first photo; empty, along
+                                      with a separator next to it, for
+                                      other files
+
+    e.g. --naming '{{date}}_{{time}}_{{type}}' gives 2026-01-13_01-55-38_image.jpg,
+    and --naming '{{date}}_{{burst_index}}_{{time}}' keeps each burst together
+    as 2026-01-13_20260113-015538_01-55-38.jpg and so on.
+    Characters that can't be in a filename, like / and :, become -. Files
+    are found again by their names, so a resumed run needs the same
+    --naming, or it downloads everything again under the new names.
+
+    Memories that would get the same name, like ones taken in the same
+    second at the same place, are told apart: the first keeps the name,
+    and the others, in the order of the input file, get _1, _2 and so on
+    before the extension.
+
+    Some very old memories have a capture time that can't be right, like
+    1970-01-01 or any other date before Snapchat existed, one in the
+    future, or none at all. Whatever the naming, those go in unknown-date/
+    named after their position among the memories (unknown-date/00042.jpg),
+    or among the files of their folder with --sections, instead of sorting
+    before everything else for good. One that was downloaded under its
+    usual name before keeps that name. Each is logged as a warning and
+    marked in the report, and doesn't get that date as its file date, in
+    its EXIF tags or in its sidecar.
+
+    The extension comes from the media type: Image is .jpg, Video is .mp4,
+    PNG is .png, SVG is .svg, and anything else is .bin. Once downloaded,
+    a file that turns out to be something else (a PNG, HEIC or WebP photo,
+    or a MOV video) is renamed to the right extension. Its type, and the
+    name it was saved under, are kept in {MANIFEST_FILE}.
+
+    Unless --no-exif is given, the capture date (DateTimeOriginal) and
+    location (GPS tags) are written into each downloaded JPEG, so photo
+    managers sort them by when they were taken. Other media is left as is.
+    Memories with a caption, drawing or sticker come with the overlay as a
+    separate image, saved next to the memory as <name>_overlay.png. With
+    --composite-overlays, the overlay is drawn onto photos instead (videos
+    still get the separate file).
+
+    Phone photos are often stored sideways, with an EXIF tag saying how to
+    turn them. With --auto-rotate, such photos are turned upright and the
+    tag is reset, so they show the right way up everywhere. This saves the
+    photo again, at high quality.
+
+    With --sidecars, each file gets a <file>.json, e.g.
+    2026-01-13_01-55-38_UTC_40.25548_-111.645325.jpg.json, laid out like
+    the ones of Google Takeout: the date is in photoTakenTime and the
+    location in geoData. Immich (through immich-go), PhotoPrism and other
+    photo managers read those when importing, which matters most for
+    videos, as they don't get EXIF tags. Files already downloaded by an
+    earlier run get theirs when it's run again with --sidecars.
+
+    Unless --no-touch is given, the modified time (and on Windows, the
+    created time) of every downloaded file is set to the capture time too.
+
+RESUMING
+    Runs can be interrupted and started again with the same input and
+    output directory. Files that were downloaded completely are skipped,
+    failed ones are retried, and partially downloaded files are continued
+    where they stopped. Files the manifest doesn't know about (from before
+    it existed, or from a run that crashed) are compared with the size on
+    the server first, and continued or downloaded again if they differ;
+    empty ones are always downloaded again. Pressing Ctrl+C once stops the
+    run after the downloads in progress; pressing it again exits
+    immediately. A run also
+    stops by itself when the output disk is full. If the network connection
+    drops, downloads wait for it to come back and then carry on.
+
+    When the server gives a file's MD5, as a Content-MD5 header or an ETag
+    that is one, the downloaded file is checked against it and downloaded
+    again (up to twice more) if it doesn't match. The manifest keeps the
+    MD5 of the files that were checked.
+
+FILES
+    snapdown.log
+        Full log of every run.
+    {HTTP_DEBUG_LOG_FILE}
+        Details of failed requests, with --debug-http.
+    <output_dir>/{MANIFEST_FILE}
+        Status, size, type and BLAKE3 checksum of every download (and the
+        MD5 the server gave, when it gave one), used to resume.
+    <output_dir>/{ARCHIVE_IDENTITY_FILE}
+        Which account and export the directory was first downloaded from.
+    <output_dir>/{SEALED_MANIFEST_FILE}
+        Checksums of every file and of the whole archive, with --freeze.
+    <output_dir>/{REPORT_FILE}
+        What happened to every record in the last run, see --report.
+    <output_dir>/{QUARANTINE_DIR}/
+        The broken files verify --repair downloaded again, as they were.
+    {PRESETS_FILE}
+        Presets saved from the GUI, for --preset. A JSON object of presets
+        by name, each with any of output_dir, naming, views (a list),
+        view_links, dedup (delete, hardlink or keep), sidecars, write_exif,
+        touch, composite_overlays, auto_rotate and freeze (true or false),
+        taking the same values as the options.
+    {USAGE_FILE}
+        How much SnapDown downloaded, by month, for metered connections.
+        Each run adds to it, and the summary (and the GUI) shows this
+        month's total and the total since the first month.
+
+    snapdown.log, {HTTP_DEBUG_LOG_FILE}, {PRESETS_FILE} and {USAGE_FILE}
+    are in the current directory, unless SnapDown was installed (into
+    Program Files, or as an MSIX package). Installed copies keep them, and
+    the GUI settings, in %LOCALAPPDATA%\\SnapDown instead. A {PORTABLE_MARKER_FILE}
+    file next to the executable forces the portable behavior.
+
+ENVIRONMENT
+    ALL_PROXY, HTTPS_PROXY, HTTP_PROXY, NO_PROXY
+        Proxy to download through (and hosts to reach without it), when
+        --proxy isn't given.
+    SNAPDOWN_SMTP_PASSWORD
+        Password for the --smtp server, if the URL doesn't have one.
+    SNAPDOWN_LOCALE, LC_ALL, LC_NUMERIC, LANG
+        The first of these that's set decides how numbers are written in
+        summaries and the GUI, e.g. SNAPDOWN_LOCALE=de_DE for 12.345 files
+        and 3,4 GB. Windows doesn't set the others, so use SNAPDOWN_LOCALE
+        there. The default is English (12,345 files, 3.4 GB).
+    SNAPDOWN_LOG
+        Log filter for snapdown.log, in env_logger syntax (default:
+        error,snapdown=info). E.g. SNAPDOWN_LOG=snapdown=debug also logs
+        every downloaded and skipped file.
+
+    Paths given to -i, -o and --export-failures, or typed in at the
+    prompts, can use environment variables as ${{NAME}}, $NAME or %NAME%,
+    and ~ for the home directory, e.g. -o %USERPROFILE%\\Pictures\\Snapchat.
+    This makes scripts work for everyone, even when started without a
+    shell that would expand them. A variable that isn't set is an error.
+
+EXIT STATUS
+    0    The run finished. Individual downloads may still have failed, see
+         the summary or use --export-failures. For verify, every file is
+         fine (or was repaired). For diff, no memory was removed.
+    1    Bad arguments, or the run couldn't start (unreadable input file,
+         output directory can't be created, ...). For verify, some files
+         are missing or changed. For diff, memories of <old_input> are
+         missing from <new_input>.
+    2    The run stopped early because the output disk is full.
+    3    The downloads stalled with --restart-stalled, and kept stalling
+         after starting again.
+    4    A download failed with --fail-fast.
+    130  The run was cancelled with Ctrl+C.
+"
+    )
+}
@@ -0,0 +1,245 @@
+// With --debug-http, details about every failed request are appended to
+// http_debug.log, so a user can attach something actionable to a bug report
+// instead of just "it says error".
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Result;
+use ureq::ResponseExt;
+use ureq::http::{HeaderMap, StatusCode};
+
+use crate::redact;
+
+pub const HTTP_DEBUG_LOG_FILE: &str = "http_debug.log";
+// How much of the body of a bad response is written out, enough for the
+// error page or message of the server
+pub const BODY_SNIPPET_LEN: usize = 512;
+
+// Only these response headers are written out, the rest is noise for
+// debugging download failures
+const HEADERS_OF_INTEREST: [&str; 11] = [
+    "content-type",
+    "content-length",
+    "location",
+    "retry-after",
+    "server",
+    "date",
+    "cache-control",
+    "etag",
+    "x-cache",
+    "via",
+    "x-amz-cf-pop",
+];
+
+pub struct HttpDebugLog {
+    file: Mutex<File>,
+    // Keep the signed parts of the links, see redact.rs
+    full_urls: bool,
+    // The last entry of each URL, also kept for --fail-fast, which shows
+    // the failed record's when stopping
+    entries: Mutex<HashMap<String, String>>,
+    // The links --resolve-links got, by the record's link
+    resolved: Mutex<HashMap<String, String>>,
+}
+
+impl HttpDebugLog {
+    pub fn open(path: &Path, full_urls: bool) -> Result<HttpDebugLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(HttpDebugLog {
+            file: Mutex::new(file),
+            full_urls,
+            entries: Mutex::new(HashMap::new()),
+            resolved: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // A request that never got a response (DNS, connect, TLS, timeout...)
+    pub fn record_transport_error(&self, url: &str, elapsed: Duration, error: &str) {
+        let mut entry = entry_header(url, elapsed, error);
+        entry.push_str("  status: <no response>\n");
+        self.write_entry(url, &entry);
+    }
+
+    // A request that got a response, but still failed (bad status, or the
+    // body couldn't be read/written)
+    pub fn record_response(
+        &self,
+        url: &str,
+        response: &ureq::http::Response<ureq::Body>,
+        elapsed: Duration,
+        error: &str,
+        body: &[u8],
+    ) {
+        let redirects: Vec<String> = response
+            .get_redirect_history()
+            .unwrap_or_default()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        self.record_status(
+            url,
+            response.status(),
+            response.headers(),
+            &redirects,
+            elapsed,
+            error,
+            body,
+        );
+    }
+
+    // Like record_response(), for responses that aren't ureq's. `redirects`
+    // has every URL the request went to, the first and last included.
+    // `body` is the start of the response's body, if it was read.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_status(
+        &self,
+        url: &str,
+        status: StatusCode,
+        headers: &HeaderMap,
+        redirects: &[String],
+        elapsed: Duration,
+        error: &str,
+        body: &[u8],
+    ) {
+        let mut entry = entry_header(url, elapsed, error);
+        entry.push_str(&format!("  status: {}\n", status));
+        if redirects.len() > 1 {
+            entry.push_str("  redirects:\n");
+            for uri in redirects {
+                entry.push_str(&format!("    -> {}\n", uri));
+            }
+        }
+        entry.push_str("  headers:\n");
+        for name in HEADERS_OF_INTEREST {
+            if let Some(value) = headers.get(name) {
+                entry.push_str(&format!(
+                    "    {}: {}\n",
+                    name,
+                    value.to_str().unwrap_or("<non-ascii>")
+                ));
+            }
+        }
+        if !body.is_empty() {
+            // On one line, whatever is in it
+            entry.push_str(&format!(
+                "  body: {}\n",
+                String::from_utf8_lossy(body).escape_debug()
+            ));
+        }
+        self.write_entry(url, &entry);
+    }
+
+    // With --resolve-links, a record's requests are for the link it got
+    pub fn record_resolved(&self, url: &str, resolved: &str) {
+        self.resolved
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), resolved.to_string());
+    }
+
+    // The last entry written for the record with link `url`
+    pub fn entry(&self, url: &str) -> Option<String> {
+        let resolved = self.resolved.lock().unwrap().get(url).cloned();
+        let entries = self.entries.lock().unwrap();
+        resolved
+            .and_then(|resolved| entries.get(&resolved))
+            .or_else(|| entries.get(url))
+            .cloned()
+    }
+
+    fn write_entry(&self, url: &str, entry: &str) {
+        // The redirects and Location header have links too
+        let entry = if self.full_urls {
+            entry.to_string()
+        } else {
+            redact::redact_tokens(entry)
+        };
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), entry.clone());
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = writeln!(file, "{}", entry) {
+            log::error!("Error writing to {}: {}", HTTP_DEBUG_LOG_FILE, e);
+        }
+    }
+}
+
+fn entry_header(url: &str, elapsed: Duration, error: &str) -> String {
+    format!(
+        "[{}] GET {}\n  error: {}\n  elapsed: {} ms\n",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        url,
+        error,
+        elapsed.as_millis()
+    )
+}
+
+// The start of a bad response's body, for record_status()
+pub fn body_snippet(reader: impl Read) -> Vec<u8> {
+    let mut body = Vec::new();
+    // A body that breaks off is still worth showing, as far as it got
+    let _ = reader.take(BODY_SNIPPET_LEN as u64).read_to_end(&mut body);
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_status() {
+        let dir = std::env::temp_dir().join("snapdown_test_http_debug");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(HTTP_DEBUG_LOG_FILE);
+        let log = HttpDebugLog::open(&path, false).unwrap();
+        assert_eq!(log.entry("https://example.com/a"), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("server", "AmazonS3".parse().unwrap());
+        headers.insert("x-not-interesting", "1".parse().unwrap());
+        let body = body_snippet(&b"<Error>\n<Code>AccessDenied</Code>"[..]);
+        log.record_status(
+            "https://example.com/a",
+            StatusCode::FORBIDDEN,
+            &headers,
+            &[],
+            Duration::from_millis(120),
+            "http status: 403",
+            &body,
+        );
+        log.record_transport_error(
+            "https://example.com/b?uid=1&sig=2",
+            Duration::ZERO,
+            "timed out",
+        );
+
+        let first = log.entry("https://example.com/a").unwrap();
+        assert!(first.contains("GET https://example.com/a\n"), "{}", first);
+        assert!(first.contains("  status: 403 Forbidden\n"));
+        assert!(first.contains("    server: AmazonS3\n"));
+        assert!(!first.contains("x-not-interesting"));
+        assert!(first.contains("  body: <Error>\\n<Code>AccessDenied</Code>\n"));
+        let url = "https://example.com/b?uid=1&sig=2";
+        assert!(log.entry(url).unwrap().contains("  error: timed out\n"));
+        // A record's request with the link --resolve-links got for it
+        log.record_transport_error("https://example.com/c", Duration::ZERO, "refused");
+        assert_eq!(log.entry("https://example.com/d"), None);
+        log.record_resolved("https://example.com/d", "https://example.com/c");
+        assert!(
+            log.entry("https://example.com/d")
+                .unwrap()
+                .contains("refused")
+        );
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.contains("GET https://example.com/b?uid=<redacted>&sig=<redacted>\n"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(body_snippet(&[0; 2000][..]).len(), BODY_SNIPPET_LEN);
+    }
+}
@@ -0,0 +1,146 @@
+// Which Snapchat account/export an output directory was downloaded from.
+// Mixing exports of different accounts in one folder can make files with the
+// same timestamp and location clash, so a run checks this before starting.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::input;
+
+pub const ARCHIVE_IDENTITY_FILE: &str = "snapdown_archive.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportIdentity {
+    // Snapchat user id, taken from the `uid=` parameter of the download links
+    pub account_id: Option<String>,
+    // YYYY-MM-DD the export file was created (its modification time)
+    pub export_date: String,
+}
+
+impl ExportIdentity {
+    pub fn from_input_file(input_file: &Path) -> Result<ExportIdentity> {
+        // The links inside a zip are compressed, so look in the memories file
+        let data =
+            if input::InputFormat::from_path(input_file) == Some(input::InputFormat::ExportZip) {
+                input::read_memories_file_from_zip(input_file)?
+            } else {
+                fs::read(input_file)?
+            };
+        let modified: chrono::DateTime<chrono::Local> =
+            fs::metadata(input_file)?.modified()?.into();
+        Ok(ExportIdentity {
+            account_id: find_account_id(&data),
+            export_date: modified.format("%Y-%m-%d").to_string(),
+        })
+    }
+}
+
+// The first `uid=<id>` query parameter in the input. Every download link of
+// an export has the same one.
+pub fn find_account_id(data: &[u8]) -> Option<String> {
+    const KEY: &[u8] = b"uid=";
+    let start = data.windows(KEY.len()).position(|w| w == KEY)? + KEY.len();
+    let id: Vec<u8> = data[start..]
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric() || **b == b'-')
+        .copied()
+        .collect();
+    if id.is_empty() {
+        None
+    } else {
+        Some(String::from_utf8_lossy(&id).to_string())
+    }
+}
+
+pub fn read_archive_identity(output_dir: &Path) -> Option<ExportIdentity> {
+    let data = fs::read(output_dir.join(ARCHIVE_IDENTITY_FILE)).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+// Record the identity, unless the folder already has one
+pub fn write_archive_identity_if_missing(
+    output_dir: &Path,
+    identity: &ExportIdentity,
+) -> Result<()> {
+    let path = output_dir.join(ARCHIVE_IDENTITY_FILE);
+    if !path.exists() {
+        fs::write(path, serde_json::to_string_pretty(identity)?)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ArchiveCheck {
+    // Nothing downloaded here yet, or from the same account
+    Ok,
+    DifferentAccount { existing: ExportIdentity },
+}
+
+pub fn check_archive(output_dir: &Path, identity: &ExportIdentity) -> ArchiveCheck {
+    match read_archive_identity(output_dir) {
+        Some(existing) => match (&existing.account_id, &identity.account_id) {
+            (Some(existing_id), Some(new_id)) if existing_id != new_id => {
+                ArchiveCheck::DifferentAccount { existing }
+            }
+            // If either account is unknown there's nothing to compare
+            _ => ArchiveCheck::Ok,
+        },
+        None => ArchiveCheck::Ok,
+    }
+}
+
+// Subfolder to keep an export separate from the rest of the output directory
+pub fn export_subfolder(output_dir: &Path, identity: &ExportIdentity) -> PathBuf {
+    output_dir.join(format!("export_{}", identity.export_date))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_account_id() {
+        let html = b"downloadMemories('https://us-east1-aws.api.snapchat.com/dmd/mm?uid=d3658ad1-6f2d&sid=x', this)";
+        assert_eq!(find_account_id(html), Some("d3658ad1-6f2d".to_string()));
+        assert_eq!(find_account_id(b"no links here"), None);
+    }
+
+    #[test]
+    fn test_check_archive() {
+        let output_dir = std::env::temp_dir().join("snapdown_test_check_archive");
+        let _ = fs::remove_dir_all(&output_dir);
+        fs::create_dir_all(&output_dir).unwrap();
+
+        let first = ExportIdentity {
+            account_id: Some("account-1".to_string()),
+            export_date: "2026-01-13".to_string(),
+        };
+        assert_eq!(check_archive(&output_dir, &first), ArchiveCheck::Ok);
+        write_archive_identity_if_missing(&output_dir, &first).unwrap();
+
+        // A later export of the same account is fine
+        let same_account = ExportIdentity {
+            export_date: "2026-02-01".to_string(),
+            ..first.clone()
+        };
+        assert_eq!(check_archive(&output_dir, &same_account), ArchiveCheck::Ok);
+
+        let other_account = ExportIdentity {
+            account_id: Some("account-2".to_string()),
+            export_date: "2026-02-01".to_string(),
+        };
+        assert_eq!(
+            check_archive(&output_dir, &other_account),
+            ArchiveCheck::DifferentAccount { existing: first }
+        );
+        assert_eq!(
+            export_subfolder(&output_dir, &other_account),
+            output_dir.join("export_2026-02-01")
+        );
+
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+}
@@ -0,0 +1,626 @@
+// The kinds of input files SnapDown can read, including the mydata~*.zip that
+// Snapchat emails a link to. The memories list is read straight out of the
+// zip, so users don't have to extract it first.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use zip::ZipArchive;
+
+use crate::encoding::Utf8Reader;
+use crate::sections::Section;
+
+// How much of a file detect() looks into, enough to get past the styles and
+// scripts at the top of a page to its first memory
+const SNIFF_LIMIT: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    MemoriesHtml,
+    MemoriesJson,
+    // The media sent in chats, see chat.rs
+    ChatHistoryHtml,
+    ChatHistoryJson,
+    // Stories and Spotlight, see sections.rs
+    SectionHtml(Section),
+    SectionJson(Section),
+    SnapExportCsv,
+    ExportZip,
+}
+
+impl InputFormat {
+    // Going by the file name only, case insensitively, so a directory named
+    // e.g. "memories.zip files" or a trailing slash doesn't confuse it. JSON
+    // and HTML files only if they're named after a part of the export, see
+    // detect() for the others.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<InputFormat> {
+        let name = path.as_ref().file_name()?.to_string_lossy().to_lowercase();
+        let (is_html, is_json) = html_or_json(&name);
+        let other_section = Section::of_other_file(&name);
+        if name.contains("chat_history") && is_html {
+            Some(InputFormat::ChatHistoryHtml)
+        } else if name.contains("chat_history") && is_json {
+            Some(InputFormat::ChatHistoryJson)
+        } else if let Some(section) = other_section
+            && is_html
+        {
+            Some(InputFormat::SectionHtml(section))
+        } else if let Some(section) = other_section
+            && is_json
+        {
+            Some(InputFormat::SectionJson(section))
+        } else if name.contains("memories_history") && is_html {
+            Some(InputFormat::MemoriesHtml)
+        } else if name.contains("memories_history") && is_json {
+            Some(InputFormat::MemoriesJson)
+        } else if name.ends_with("snap_export.csv") {
+            Some(InputFormat::SnapExportCsv)
+        } else if name.ends_with(".zip") {
+            Some(InputFormat::ExportZip)
+        } else {
+            None
+        }
+    }
+
+    // Like from_path(), but a JSON or HTML file that isn't named after a
+    // part of the export is looked into, as a page saved again from a
+    // browser is named after its title, and a copy may have been renamed.
+    // It's the memories list if the list's "Saved Media" or download links
+    // are in it, so SnapDown's own snapdown_report.json or another page of
+    // the export isn't taken for one.
+    pub fn detect(path: impl AsRef<Path>) -> Option<InputFormat> {
+        let path = path.as_ref();
+        if let Some(format) = InputFormat::from_path(path) {
+            return Some(format);
+        }
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        let (is_html, is_json) = html_or_json(&name);
+        if !is_html && !is_json {
+            return None;
+        }
+        let mut start = Vec::new();
+        Utf8Reader::new(File::open(path).ok()?)
+            .ok()?
+            .take(SNIFF_LIMIT)
+            .read_to_end(&mut start)
+            .ok()?;
+        let start = String::from_utf8_lossy(&start);
+        if is_json && start.contains("\"Saved Media\"") {
+            Some(InputFormat::MemoriesJson)
+        } else if is_html
+            && (start.contains("downloadMemories(")
+                || start.to_lowercase().contains("<title>memories</title>"))
+        {
+            Some(InputFormat::MemoriesHtml)
+        } else {
+            None
+        }
+    }
+
+    // What the GUI calls it once picked
+    pub fn description(&self) -> String {
+        match self {
+            InputFormat::MemoriesHtml => "memories list, HTML".to_string(),
+            InputFormat::MemoriesJson => "memories list, JSON".to_string(),
+            InputFormat::ChatHistoryHtml => "chat media, HTML".to_string(),
+            InputFormat::ChatHistoryJson => "chat media, JSON".to_string(),
+            InputFormat::SectionHtml(section) => format!("{}, HTML", section.name()),
+            InputFormat::SectionJson(section) => format!("{}, JSON", section.name()),
+            InputFormat::SnapExportCsv => "SnapDown CSV".to_string(),
+            InputFormat::ExportZip => "Snapchat export zip".to_string(),
+        }
+    }
+}
+
+// Clean up a path given on the command line or typed in: surrounding
+// whitespace and quotes (drag and drop, Windows' "Copy as path"), the stray
+// quote cmd.exe leaves when a quoted path ends in a backslash ("C:\Memories\"
+// arrives as C:\Memories"), and trailing slashes, except for roots like / and
+// C:\
+pub fn clean_path_arg(arg: &str) -> String {
+    let mut path = arg.trim();
+    for quote in ['"', '\''] {
+        if let Some(unquoted) = path
+            .strip_prefix(quote)
+            .and_then(|rest| rest.strip_suffix(quote))
+        {
+            path = unquoted.trim();
+            break;
+        }
+    }
+    if let Some(unquoted) = path.strip_suffix('"')
+        && !unquoted.contains('"')
+    {
+        path = unquoted;
+    }
+    let trimmed = path.trim_end_matches(['/', '\\']);
+    if !trimmed.is_empty() && !trimmed.ends_with(':') {
+        path = trimmed;
+    }
+    path.to_string()
+}
+
+// clean_path_arg(), then expand_env_vars(), for paths that didn't come
+// through a shell or came from a script shared between machines
+pub fn expand_path_arg(arg: &str) -> Result<String> {
+    expand_env_vars(&clean_path_arg(arg), |name| std::env::var(name).ok())
+}
+
+// Expand environment variables the way both kinds of shell write them,
+// ${NAME}, $NAME and %NAME%, and a leading ~ to the home directory. Anything
+// that isn't a variable name (C$ shares, "100%") is left as is, but a
+// variable that isn't set is an error rather than a folder named after it.
+fn expand_env_vars(path: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let is_name = |name: &str| {
+        name.chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    let var = |name: &str| {
+        lookup(name).ok_or_else(|| anyhow::anyhow!("environment variable {} isn't set", name))
+    };
+
+    let mut expanded = String::new();
+    let mut rest = path;
+    if let Some(after) = rest.strip_prefix('~')
+        && (after.is_empty() || after.starts_with(['/', '\\']))
+    {
+        let home = lookup("HOME")
+            .or_else(|| lookup("USERPROFILE"))
+            .ok_or_else(|| anyhow::anyhow!("can't expand ~, HOME isn't set"))?;
+        expanded.push_str(&home);
+        rest = after;
+    }
+    while let Some(index) = rest.find(['$', '%']) {
+        expanded.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        let (value, consumed) = if rest[index..].starts_with('%') {
+            match after.split_once('%') {
+                Some((name, _)) if is_name(name) => (var(name)?, name.len() + 2),
+                _ => ("%".to_string(), 1),
+            }
+        } else if let Some(braced) = after.strip_prefix('{') {
+            match braced.split_once('}') {
+                Some((name, _)) if is_name(name) => (var(name)?, name.len() + 3),
+                _ => ("$".to_string(), 1),
+            }
+        } else {
+            let name_len = after
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(after.len());
+            match &after[..name_len] {
+                name if is_name(name) => (var(name)?, name.len() + 1),
+                _ => ("$".to_string(), 1),
+            }
+        };
+        expanded.push_str(&value);
+        rest = &rest[index + consumed..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+// Whether a lowercase file name is of an HTML (or MHTML) file, and whether
+// it's of a JSON file
+fn html_or_json(name: &str) -> (bool, bool) {
+    let is_html = [".html", ".htm", ".mhtml", ".mht"]
+        .iter()
+        .any(|extension| name.ends_with(extension));
+    (is_html, name.ends_with(".json"))
+}
+
+// Check that the input file is there, readable and of a known kind before
+// starting a run, so the error names the path that was checked instead of
+// coming from somewhere deep in the parsing
+pub fn check_input_file(path: &Path) -> Result<()> {
+    // Relative paths are relative to wherever SnapDown was started from,
+    // which isn't always obvious
+    let shown = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let shown = shown.display();
+    let metadata = fs::metadata(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => anyhow::anyhow!("{} doesn't exist", shown),
+        _ => anyhow::anyhow!("Can't read {}: {}", shown, e),
+    })?;
+    if metadata.is_dir() {
+        return Err(anyhow::anyhow!("{} is a folder, not a file", shown));
+    }
+    if InputFormat::detect(path).is_none() {
+        return Err(anyhow::anyhow!(
+            "{} isn't a file SnapDown can read. Use Snapchat's mydata~*.zip export, \
+             memories_history.html/.json, chat_history.html/.json, shared_story.json, \
+             spotlight.json or a snap_export.csv",
+            shown
+        ));
+    }
+    File::open(path).map_err(|e| anyhow::anyhow!("Can't read {}: {}", shown, e))?;
+    Ok(())
+}
+
+// Of the files dropped onto the GUI, the one to read: the first SnapDown can
+// read, so dropping everything picked in a folder of the extracted export
+// works too. A dropped folder, like the extracted export itself, is looked
+// into for its memories list. If none of them will do, the error is the
+// first one's.
+pub fn pick_dropped_file(paths: &[PathBuf]) -> Result<PathBuf> {
+    let mut first_error = None;
+    for path in paths {
+        let path = if path.is_dir() {
+            find_memories_file_in_dir(path).unwrap_or_else(|| path.clone())
+        } else {
+            path.clone()
+        };
+        match check_input_file(&path) {
+            Ok(()) => return Ok(path),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    Err(first_error.unwrap_or_else(|| anyhow::anyhow!("Nothing was dropped")))
+}
+
+// As find_memories_file() finds it in the zip
+fn find_memories_file_in_dir(dir: &Path) -> Option<PathBuf> {
+    [
+        "html/memories_history.html",
+        "memories_history.html",
+        "json/memories_history.json",
+        "memories_history.json",
+    ]
+    .into_iter()
+    .map(|name| dir.join(name))
+    .find(|path| path.is_file())
+}
+
+pub fn open_export_zip(path: &Path) -> Result<ZipArchive<BufReader<File>>> {
+    Ok(ZipArchive::new(BufReader::new(File::open(path)?))?)
+}
+
+// Name and format of the memories list inside an export zip. Normally
+// html/memories_history.html, with json/memories_history.json as a fallback
+// for exports that only have the JSON.
+pub fn find_memories_file<R: Read + Seek>(
+    archive: &ZipArchive<R>,
+) -> Option<(String, InputFormat)> {
+    let find = |suffix: &str| {
+        archive
+            .file_names()
+            .find(|name| name.ends_with(suffix))
+            .map(|name| name.to_string())
+    };
+    if let Some(name) = find("memories_history.html") {
+        Some((name, InputFormat::MemoriesHtml))
+    } else {
+        find("memories_history.json").map(|name| (name, InputFormat::MemoriesJson))
+    }
+}
+
+// The files of a section inside an export zip, and their formats. The
+// memories as find_memories_file() finds them. The other sections can have
+// each of their files both in json/ and html/, and then the JSON is read.
+pub fn find_section_files<R: Read + Seek>(
+    archive: &ZipArchive<R>,
+    section: Section,
+) -> Vec<(String, InputFormat)> {
+    if section == Section::Memories {
+        return find_memories_file(archive).into_iter().collect();
+    }
+    let mut files = BTreeMap::new();
+    for name in archive.file_names().filter(|name| section.has_file(name)) {
+        let (Some(format), Some(stem)) =
+            (InputFormat::from_path(name), Path::new(name).file_stem())
+        else {
+            continue;
+        };
+        let is_json = matches!(
+            format,
+            InputFormat::ChatHistoryJson | InputFormat::SectionJson(_)
+        );
+        let stem = stem.to_string_lossy().to_lowercase();
+        if is_json || !files.contains_key(&stem) {
+            files.insert(stem, (name.to_string(), format));
+        }
+    }
+    files.into_values().collect()
+}
+
+// The memories list out of an export zip, as bytes
+pub fn read_memories_file_from_zip(path: &Path) -> Result<Vec<u8>> {
+    let mut archive = open_export_zip(path)?;
+    let (name, _) = find_memories_file(&archive).ok_or_else(|| no_memories_file_error(path))?;
+    let mut data = Vec::new();
+    archive.by_name(&name)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+pub fn no_memories_file_error(path: &Path) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Couldn't find memories_history.html or memories_history.json in {}",
+        path.display()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    fn make_zip(files: &[(&str, &str)]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            writer
+                .start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_input_format_from_path() {
+        assert_eq!(
+            InputFormat::from_path("export/html/memories_history.html"),
+            Some(InputFormat::MemoriesHtml)
+        );
+        assert_eq!(
+            InputFormat::from_path("memories_history.json"),
+            Some(InputFormat::MemoriesJson)
+        );
+        assert_eq!(
+            InputFormat::from_path("json/chat_history.json"),
+            Some(InputFormat::ChatHistoryJson)
+        );
+        assert_eq!(
+            InputFormat::from_path("Chat_History.HTML"),
+            Some(InputFormat::ChatHistoryHtml)
+        );
+        assert_eq!(
+            InputFormat::from_path("json/shared_story.json"),
+            Some(InputFormat::SectionJson(Section::Stories))
+        );
+        assert_eq!(
+            InputFormat::from_path("html/spotlight.html"),
+            Some(InputFormat::SectionHtml(Section::Spotlight))
+        );
+        assert_eq!(
+            InputFormat::from_path("snap_export.csv"),
+            Some(InputFormat::SnapExportCsv)
+        );
+        assert_eq!(
+            InputFormat::from_path("mydata~1768335041137.ZIP"),
+            Some(InputFormat::ExportZip)
+        );
+        // Could be anything, see detect()
+        assert_eq!(InputFormat::from_path("Snapchat Memories.mhtml"), None);
+        assert_eq!(InputFormat::from_path("snapdown_report.json"), None);
+        assert_eq!(InputFormat::from_path("photo.jpg"), None);
+    }
+
+    #[test]
+    fn test_input_format_detect() {
+        let dir = std::env::temp_dir().join("snapdown_test_input_format_detect");
+        fs::create_dir_all(&dir).unwrap();
+        let test_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test");
+        // Saved again from a browser, or renamed
+        for (name, format) in [
+            ("test_chrome.mhtml", InputFormat::MemoriesHtml),
+            ("test_firefox.html", InputFormat::MemoriesHtml),
+            ("test.html", InputFormat::MemoriesHtml),
+            ("test.json", InputFormat::MemoriesJson),
+        ] {
+            assert_eq!(
+                InputFormat::detect(test_dir.join(name)),
+                Some(format),
+                "{}",
+                name
+            );
+        }
+        for (name, contents) in [
+            (
+                "snapdown_report.json",
+                "{\"run\": {\"input_file\": \"a.zip\"}}",
+            ),
+            ("snapdown_presets.json", "{\"presets\": []}"),
+            (
+                "friends.html",
+                "<title>Friends</title><a href=memories.html>Memories</a>",
+            ),
+        ] {
+            let path = dir.join(name);
+            fs::write(&path, contents).unwrap();
+            assert_eq!(InputFormat::detect(&path), None, "{}", name);
+            assert!(check_input_file(&path).is_err(), "{}", name);
+        }
+        // Named after a part of the export, nothing is read
+        assert_eq!(
+            InputFormat::detect(dir.join("missing/memories_history.json")),
+            Some(InputFormat::MemoriesJson)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_input_format_from_unusual_paths() {
+        assert_eq!(
+            InputFormat::from_path("C:\\Users\\Zoë\\Mes Documents\\Memories_History.HTML"),
+            Some(InputFormat::MemoriesHtml)
+        );
+        assert_eq!(
+            InputFormat::from_path("/home/zoë/スナップ/mydata~1.zip/"),
+            Some(InputFormat::ExportZip)
+        );
+        assert_eq!(
+            InputFormat::from_path("/home/me/old.zip exports/photo.jpg"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_check_input_file() {
+        let dir = std::env::temp_dir().join("snapdown_test_check_input_file");
+        fs::create_dir_all(&dir).unwrap();
+        let csv_path = dir.join("snap_export.csv");
+        fs::write(
+            &csv_path,
+            "timestamp_utc,format,latitude,longitude,download_url\n",
+        )
+        .unwrap();
+        let other_path = dir.join("notes.txt");
+        fs::write(&other_path, "").unwrap();
+
+        assert!(check_input_file(&csv_path).is_ok());
+        let missing = check_input_file(&dir.join("memories_history.html")).unwrap_err();
+        assert!(
+            missing
+                .to_string()
+                .contains("memories_history.html doesn't exist")
+        );
+        let folder = check_input_file(&dir).unwrap_err();
+        assert!(folder.to_string().contains("is a folder"));
+        let unknown = check_input_file(&other_path).unwrap_err();
+        assert!(
+            unknown
+                .to_string()
+                .contains("notes.txt isn't a file SnapDown can read")
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pick_dropped_file() {
+        let dir = std::env::temp_dir().join("snapdown_test_pick_dropped_file");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("export/json")).unwrap();
+        let memories = dir.join("export/json/memories_history.json");
+        fs::write(&memories, "{}").unwrap();
+        let photo = dir.join("photo.jpg");
+        fs::write(&photo, "").unwrap();
+
+        // The first file it can read, wherever it is in the selection
+        assert_eq!(
+            pick_dropped_file(&[photo.clone(), memories.clone()]).unwrap(),
+            memories
+        );
+        // The extracted export
+        assert_eq!(pick_dropped_file(&[dir.join("export")]).unwrap(), memories);
+        let error = pick_dropped_file(&[photo, dir.clone()]).unwrap_err();
+        assert!(error.to_string().contains("photo.jpg isn't a file"));
+        assert!(pick_dropped_file(&[]).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_expand_env_vars() {
+        let lookup = |name: &str| match name {
+            "HOME" => Some("/home/me".to_string()),
+            "USERPROFILE" => Some("C:\\Users\\me".to_string()),
+            "SNAPS" => Some("memories".to_string()),
+            _ => None,
+        };
+        let expand = |path| expand_env_vars(path, lookup);
+        assert_eq!(expand("${HOME}/snaps").unwrap(), "/home/me/snaps");
+        assert_eq!(expand("$HOME/$SNAPS.zip").unwrap(), "/home/me/memories.zip");
+        assert_eq!(
+            expand("%USERPROFILE%\\Downloads").unwrap(),
+            "C:\\Users\\me\\Downloads"
+        );
+        assert_eq!(expand("~/snaps").unwrap(), "/home/me/snaps");
+        assert_eq!(expand("~").unwrap(), "/home/me");
+        // Not variables
+        assert_eq!(
+            expand("\\\\server\\C$\\snaps").unwrap(),
+            "\\\\server\\C$\\snaps"
+        );
+        assert_eq!(expand("100% done").unwrap(), "100% done");
+        assert_eq!(expand("~snaps").unwrap(), "~snaps");
+        assert_eq!(expand("${}").unwrap(), "${}");
+        assert!(expand("$NOPE/snaps").is_err());
+        assert!(expand("%NOPE%").is_err());
+    }
+
+    #[test]
+    fn test_clean_path_arg() {
+        assert_eq!(clean_path_arg("  snap_export.csv \n"), "snap_export.csv");
+        assert_eq!(
+            clean_path_arg("\"C:\\Users\\me\\Downloads\\mydata~1.zip\""),
+            "C:\\Users\\me\\Downloads\\mydata~1.zip"
+        );
+        assert_eq!(
+            clean_path_arg("'/home/me/my memories/snap_export.csv' "),
+            "/home/me/my memories/snap_export.csv"
+        );
+        assert_eq!(clean_path_arg("C:\\My Memories\""), "C:\\My Memories");
+        assert_eq!(clean_path_arg("out/"), "out");
+        assert_eq!(clean_path_arg("C:\\Snaps\\"), "C:\\Snaps");
+        assert_eq!(clean_path_arg("/"), "/");
+        assert_eq!(clean_path_arg("C:\\"), "C:\\");
+        assert_eq!(clean_path_arg("Mémoires été"), "Mémoires été");
+    }
+
+    #[test]
+    fn test_find_memories_file() {
+        let archive = make_zip(&[
+            ("index.html", "<html></html>"),
+            ("json/memories_history.json", "{}"),
+            ("html/memories_history.html", "<table></table>"),
+        ]);
+        assert_eq!(
+            find_memories_file(&archive),
+            Some((
+                "html/memories_history.html".to_string(),
+                InputFormat::MemoriesHtml
+            ))
+        );
+
+        let archive = make_zip(&[("json/memories_history.json", "{}")]);
+        assert_eq!(
+            find_memories_file(&archive),
+            Some((
+                "json/memories_history.json".to_string(),
+                InputFormat::MemoriesJson
+            ))
+        );
+
+        let archive = make_zip(&[("index.html", "<html></html>")]);
+        assert_eq!(find_memories_file(&archive), None);
+    }
+
+    #[test]
+    fn test_find_section_files() {
+        let archive = make_zip(&[
+            ("html/memories_history.html", "<table></table>"),
+            ("html/chat_history.html", "<table></table>"),
+            ("json/chat_history.json", "{}"),
+            ("html/shared_story.html", "<table></table>"),
+            ("json/story_history.json", "{}"),
+        ]);
+        assert_eq!(
+            find_section_files(&archive, Section::Chats),
+            [(
+                "json/chat_history.json".to_string(),
+                InputFormat::ChatHistoryJson
+            )]
+        );
+        assert_eq!(
+            find_section_files(&archive, Section::Stories),
+            [
+                (
+                    "html/shared_story.html".to_string(),
+                    InputFormat::SectionHtml(Section::Stories)
+                ),
+                (
+                    "json/story_history.json".to_string(),
+                    InputFormat::SectionJson(Section::Stories)
+                ),
+            ]
+        );
+        assert_eq!(find_section_files(&archive, Section::Spotlight), []);
+        assert_eq!(find_section_files(&archive, Section::Memories).len(), 1);
+    }
+}
@@ -0,0 +1,146 @@
+// Where SnapDown keeps its own files (snapdown.log, http_debug.log, the
+// presets and the GUI settings). A portable copy, unzipped anywhere and run
+// from there, keeps them in the current directory like it always has. An
+// installed copy (MSI into Program Files, or MSIX into WindowsApps) can't
+// write next to itself, so it uses %LOCALAPPDATA%\SnapDown instead.
+//
+// The download manifest and archive identity always stay in the output
+// directory, since they describe that directory's files.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+// Put this file next to the executable to force portable mode, e.g. for a
+// portable copy kept in a Program Files folder
+pub const PORTABLE_MARKER_FILE: &str = "snapdown.portable";
+
+// Name of the directory in %LOCALAPPDATA% for installed copies
+const APP_DIR_NAME: &str = "SnapDown";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InstallMode {
+    Portable,
+    Installed,
+}
+
+impl InstallMode {
+    // Detected once, from where the running executable is
+    pub fn current() -> InstallMode {
+        static MODE: OnceLock<InstallMode> = OnceLock::new();
+        *MODE.get_or_init(|| match std::env::current_exe() {
+            Ok(exe) => detect(&exe, &install_roots()),
+            Err(_) => InstallMode::Portable,
+        })
+    }
+}
+
+// Directories installers put programs in. Empty outside Windows, where
+// SnapDown is only distributed as a portable binary.
+fn install_roots() -> Vec<PathBuf> {
+    if !cfg!(windows) {
+        return Vec::new();
+    }
+    let mut roots: Vec<PathBuf> = ["ProgramFiles", "ProgramFiles(x86)", "ProgramW6432"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .map(PathBuf::from)
+        .collect();
+    // Per-user installs
+    if let Some(local_app_data) = std::env::var_os("LOCALAPPDATA") {
+        roots.push(PathBuf::from(local_app_data).join("Programs"));
+    }
+    roots
+}
+
+fn detect(exe: &Path, install_roots: &[PathBuf]) -> InstallMode {
+    let exe_dir = exe.parent().unwrap_or(Path::new(""));
+    if exe_dir.join(PORTABLE_MARKER_FILE).exists() {
+        return InstallMode::Portable;
+    }
+    // Windows paths aren't case sensitive
+    let exe_path = exe.to_string_lossy().to_lowercase();
+    let in_install_root = install_roots.iter().any(|root| {
+        let root = root.to_string_lossy().to_lowercase();
+        !root.is_empty() && Path::new(&exe_path).starts_with(&root)
+    });
+    // MSIX packages are always installed into a WindowsApps folder
+    let in_msix_package = exe
+        .components()
+        .any(|component| component.as_os_str().eq_ignore_ascii_case("WindowsApps"));
+    if in_install_root || in_msix_package {
+        InstallMode::Installed
+    } else {
+        InstallMode::Portable
+    }
+}
+
+// Directory for SnapDown's own files. Relative (the current directory) in
+// portable mode.
+pub fn data_dir() -> PathBuf {
+    match InstallMode::current() {
+        InstallMode::Portable => PathBuf::new(),
+        InstallMode::Installed => {
+            let dir = std::env::var_os("LOCALAPPDATA")
+                .map(PathBuf::from)
+                .unwrap_or_else(std::env::temp_dir)
+                .join(APP_DIR_NAME);
+            if let Err(e) = std::fs::create_dir_all(&dir) {
+                // Logging isn't set up yet when this is first called
+                eprintln!("Error creating {}: {}", dir.display(), e);
+            }
+            dir
+        }
+    }
+}
+
+// Path of one of SnapDown's own files, e.g. data_file("snapdown.log")
+pub fn data_file(name: &str) -> PathBuf {
+    data_dir().join(name)
+}
+
+// File eframe saves the GUI settings to. None keeps eframe's default, which
+// portable copies have always used.
+pub fn gui_settings_file() -> Option<PathBuf> {
+    match InstallMode::current() {
+        InstallMode::Portable => None,
+        InstallMode::Installed => Some(data_file("gui_settings.ron")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_install_mode() {
+        let roots = [
+            PathBuf::from("C:\\Program Files"),
+            PathBuf::from("/opt/programs"),
+        ];
+        assert_eq!(
+            detect(Path::new("/opt/programs/SnapDown/snapdown"), &roots),
+            InstallMode::Installed
+        );
+        assert_eq!(
+            detect(Path::new("/home/me/Downloads/snapdown/snapdown"), &roots),
+            InstallMode::Portable
+        );
+        assert_eq!(
+            detect(
+                Path::new("/x/WindowsApps/SnapDown_1.0_x64/snapdown.exe"),
+                &[]
+            ),
+            InstallMode::Installed
+        );
+    }
+
+    #[test]
+    fn test_portable_marker_wins() {
+        let dir = std::env::temp_dir().join("snapdown_test_portable_marker");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(PORTABLE_MARKER_FILE), "").unwrap();
+        let exe = dir.join("snapdown");
+        assert_eq!(detect(&exe, &[std::env::temp_dir()]), InstallMode::Portable);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
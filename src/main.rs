@@ -1,28 +1,407 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader, copy};
-use std::path::Path;
-use std::sync::mpsc;
+use std::io::{BufRead, BufReader, Read, copy};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64};
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use chrono;
 use circular_buffer::CircularBuffer;
-use csv::Reader;
+use clap::{CommandFactory, Parser};
 use eframe::egui;
 use egui::{Color32, FontId, TextStyle};
+use egui_extras::{Column, TableBuilder};
 use env_logger::{Builder, Env};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+#[cfg(feature = "rayon-downloader")]
 use rayon::prelude::*;
 use std::fs::OpenOptions;
 use std::io::Write;
-use ureq;
+
+mod archive;
+#[cfg(not(feature = "rayon-downloader"))]
+mod async_download;
+mod backoff;
+mod burst;
+mod chat;
+mod cli;
+mod config;
+mod control;
+mod dedup;
+mod diff;
+mod dimensions;
+mod dry_run;
+mod email;
+mod encoding;
+mod events;
+mod exif_tags;
+mod export;
+mod fetch;
+mod file_list;
+mod format;
+mod freeze;
+mod geocode;
+mod gui_channel;
+mod hashing;
+mod help;
+mod http_debug;
+mod identity;
+mod input;
+mod install;
+mod manifest;
+mod md5;
+mod media_type;
+mod metrics;
+mod naming;
+mod network;
+mod notify;
+mod overlay;
+mod planner;
+mod post_process;
+mod presets;
+mod prompt;
+mod quarantine;
+mod record;
+mod redact;
+mod replay;
+mod report;
+mod s3;
+mod saved_page;
+mod schedule;
+mod sections;
+mod sidecar;
+mod snapshot;
+mod space_check;
+mod stats;
+mod status_page;
+mod storage;
+mod support_bundle;
+#[cfg(test)]
+mod test_server;
+mod throttle;
+mod throughput;
+mod tolerant_html;
+mod tray;
+mod upload;
+mod usage;
+mod verify;
+mod views;
+mod watchdog;
+mod webdav;
+
+use archive::{ArchiveEntry, ArchiveQuery, MediaKind};
+use backoff::{Backoff, Throttled};
+use config::EffectiveConfig;
+use control::{CancellableReader, RunControl, StopReason};
+use dedup::{DedupIndex, DedupMode};
+use dimensions::ImageSize;
+use dry_run::DryRunReport;
+use email::EmailSettings;
+use encoding::Utf8Reader;
+use events::{CliSink, DownloadEvent, EventSink, FileEvents, FileProgress, GuiSink};
+use fetch::{BlockingFetch, Fetch};
+use file_list::{FileColumn, FileList, FileRow, FileStatus};
+use hashing::{FileHash, HashingReader, HashingWriter};
+use http_debug::{HTTP_DEBUG_LOG_FILE, HttpDebugLog};
+use identity::{ArchiveCheck, ExportIdentity};
+use input::InputFormat;
+use manifest::{DownloadPlan, EntryStatus, Manifest, ManifestEntry};
+use md5::{EtagHosts, ServerMd5};
+use metrics::Metrics;
+use naming::{LegacyNamer, Namer, Naming};
+use network::NetworkMonitor;
+use notify::Notifier;
+use planner::{Plan, SizeSample};
+use post_process::PostProcessor;
+use presets::Preset;
+use quarantine::QuarantinedFile;
+use replay::Replay;
+use report::{HostReport, RecordReport, RecordStatus, RunReport};
+use s3::{S3Bucket, S3Settings};
+use schedule::Schedule;
+use sections::Section;
+use snapshot::{ProgressSnapshot, SnapshotConfig};
+use space_check::{SpaceCheck, SpaceEstimate};
+use stats::{HostStats, HostStatsCollector};
+use status_page::{Phase, StatusPage, StatusServer};
+use storage::{LocalDir, StorageBackend, is_local_dir};
+use throttle::{RateLimiter, ThrottledReader};
+use throughput::{CountingReader, Throughput};
+use tray::{Tray, TrayState, TrayTarget};
+use upload::{ImmichUploader, UploadSettings, Uploaded};
+use usage::Usage;
+use views::{LinkKind, ViewKind, ViewSettings};
+use watchdog::Watchdog;
+use webdav::{WebDavFolder, WebDavSettings};
+
+// A console message, at the level it was logged at. Messages about a
+// specific record keep a copy of it, so the GUI can offer actions like
+// copying its URL or retrying just that file.
+struct LogEntry {
+    level: log::Level,
+    message: String,
+    record: Option<csv::StringRecord>,
+}
+
+impl LogEntry {
+    // For the console's filter and search box. The search ignores case.
+    fn matches(&self, errors_only: bool, search: &str) -> bool {
+        if errors_only && self.level != log::Level::Error {
+            return false;
+        }
+        let search = search.trim();
+        search.is_empty() || self.message.to_lowercase().contains(&search.to_lowercase())
+    }
+}
 
 struct SnapdownStatus {
     finished: bool,
+    // Number of records in the input, known once it's parsed
+    total_count: usize,
     error_count: usize,
     success_count: usize,
     skip_count: usize,
+    // Rows that aren't memories, like a wrong number of columns. Not counted
+    // as errors, downloading again won't change them.
+    invalid_count: usize,
+    // Bytes downloaded in this run so far, downloads in progress included
+    // (not counting the parts of resumed files downloaded before)
+    bytes_downloaded: u64,
+    // Size of the skipped files, going by the manifest
+    bytes_skipped: u64,
+    // Average download speed since the run started, in bytes per second
+    throughput: f64,
+    // The speed over the last few seconds, and of each second of the last
+    // minute for the sparkline, see throughput.rs
+    current_throughput: f64,
+    throughput_history: Vec<f64>,
+    // Time since the run started
+    elapsed: Duration,
+    // Why the run stopped early, if it did
+    stop_reason: Option<StopReason>,
+    // Records whose download failed, only filled in once finished
+    failed_records: Vec<csv::StringRecord>,
+    // Success/error/latency per CDN host, only filled in once finished
+    host_stats: Vec<(String, HostStats)>,
+    // What a --dry-run found, instead of downloading
+    dry_run_report: Option<DryRunReport>,
+    // Duplicates found by --dedup and the space that saved, only filled in
+    // once finished
+    duplicate_count: usize,
+    dedup_bytes_saved: u64,
+    // Set while the server limits requests, see backoff.rs
+    throttled: Option<Throttled>,
+    // With --upload, files sent to the server (including ones it already
+    // had) and ones that couldn't be. Not in the counts above, a file that
+    // failed to upload is still downloaded.
+    upload_count: usize,
+    upload_error_count: usize,
+    // What --space-check found before the downloads started
+    space_estimate: Option<SpaceEstimate>,
+    // When the --schedule window opens again, e.g. "01:00", while it has the
+    // run paused
+    paused_until: Option<String>,
+    // What SnapDown downloaded on this computer by month, this run included,
+    // see usage.rs. Only filled in once finished.
+    usage: Option<Usage>,
+}
+
+impl SnapdownStatus {
+    fn new(total_count: usize) -> Self {
+        SnapdownStatus {
+            finished: false,
+            total_count,
+            error_count: 0,
+            success_count: 0,
+            skip_count: 0,
+            invalid_count: 0,
+            bytes_downloaded: 0,
+            bytes_skipped: 0,
+            throughput: 0.0,
+            current_throughput: 0.0,
+            throughput_history: Vec::new(),
+            elapsed: Duration::ZERO,
+            stop_reason: None,
+            failed_records: Vec::new(),
+            host_stats: Vec::new(),
+            dry_run_report: None,
+            duplicate_count: 0,
+            dedup_bytes_saved: 0,
+            throttled: None,
+            upload_count: 0,
+            upload_error_count: 0,
+            space_estimate: None,
+            paused_until: None,
+            usage: None,
+        }
+    }
+
+    fn processed_count(&self) -> usize {
+        self.success_count + self.error_count + self.skip_count + self.invalid_count
+    }
+
+    // Estimated time left, going by how fast records were processed so far
+    fn eta(&self) -> Option<Duration> {
+        let processed = self.processed_count();
+        if processed == 0 || self.finished {
+            return None;
+        }
+        let remaining = self.total_count.saturating_sub(processed);
+        Some(self.elapsed.mul_f64(remaining as f64 / processed as f64))
+    }
+
+    // What skipping the files that were already there saved, e.g. "1.2 GB
+    // already downloaded, about 10m saved". None if nothing was skipped.
+    fn skip_savings(&self) -> Option<String> {
+        skip_savings(self.bytes_skipped, self.throughput)
+    }
+
+    // e.g. "2.1 MB/s (1.8 MB/s average), 350 MB, 3m 20s left"
+    fn progress_message(&self) -> String {
+        let speed = if self.finished {
+            format!("{}/s", format::format_bytes(self.throughput.round() as u64))
+        } else {
+            format!(
+                "{}/s ({}/s average), {}",
+                format::format_bytes(self.current_throughput.round() as u64),
+                format::format_bytes(self.throughput.round() as u64),
+                format::format_bytes(self.bytes_downloaded)
+            )
+        };
+        let mut message = match self.eta() {
+            Some(eta) => format!("{}, {} left", speed, format::format_duration(eta)),
+            None => speed,
+        };
+        if self.upload_count > 0 {
+            message = format!(
+                "{}, {} uploaded",
+                message,
+                format::format_count(self.upload_count)
+            );
+        }
+        if self.upload_error_count > 0 {
+            message = format!(
+                "{}, {} failed to upload",
+                message,
+                format::format_count(self.upload_error_count)
+            );
+        }
+        if let Some(throttled) = self.throttled {
+            message = format!("{}, {}", message, throttled.message());
+        }
+        match &self.paused_until {
+            Some(time) => format!("{}, paused until {} (--schedule)", message, time),
+            None => message,
+        }
+    }
+
+    // With --notify, see notify.rs. Not for dry runs, which are over in
+    // seconds, or runs the user cancelled themselves.
+    // The notification's thread, see notify::send()
+    fn notify_finished(&self) -> Option<std::thread::JoinHandle<()>> {
+        if self.dry_run_report.is_some() || self.stop_reason == Some(StopReason::UserCancelled) {
+            return None;
+        }
+        let summary = match self.stop_reason {
+            Some(reason) => format!("SnapDown stopped: {}", reason),
+            None => "SnapDown finished".to_string(),
+        };
+        Some(notify::send(
+            summary,
+            format!(
+                "{} downloaded, {} failed, {} skipped in {}",
+                format::format_count(self.success_count),
+                format::format_count(self.error_count),
+                format::format_count(self.skip_count),
+                format::format_duration(self.elapsed)
+            ),
+        ))
+    }
+
+    // The tray icon's state and tooltip, see tray.rs
+    fn tray_status(&self) -> (TrayState, String) {
+        if !self.finished {
+            let tooltip = format!(
+                "SnapDown: {} of {} files, {} failed",
+                format::format_count(self.processed_count()),
+                format::format_count(self.total_count),
+                format::format_count(self.error_count)
+            );
+            return (TrayState::Downloading, tooltip);
+        }
+        let state = if self.error_count > 0 || self.stop_reason.is_some() {
+            TrayState::Failed
+        } else {
+            TrayState::Completed
+        };
+        let mut tooltip = format!(
+            "SnapDown: {} downloaded, {} failed, {} skipped",
+            format::format_count(self.success_count),
+            format::format_count(self.error_count),
+            format::format_count(self.skip_count)
+        );
+        if let Some(reason) = self.stop_reason {
+            tooltip = format!("{}. Stopped: {}", tooltip, reason);
+        }
+        (state, tooltip)
+    }
+
+    // With --notify, once too many downloads failed
+    fn notify_error_rate(&self, notifier: &mut Notifier) {
+        let downloads = self.success_count + self.error_count;
+        if let Some(body) = notifier.check_error_rate(downloads, self.error_count) {
+            notify::send("SnapDown: many downloads are failing".to_string(), body);
+        }
+    }
+
+    // For --status-port
+    fn status_page(&self) -> StatusPage {
+        let phase = if self.stop_reason.is_some() {
+            Phase::Stopped
+        } else if self.finished {
+            Phase::Finished
+        } else if self.processed_count() >= self.total_count && self.total_count > 0 {
+            Phase::Finishing
+        } else {
+            Phase::Downloading
+        };
+        StatusPage {
+            phase,
+            total: self.total_count,
+            processed: self.processed_count(),
+            downloaded: self.success_count,
+            skipped: self.skip_count,
+            failed: self.error_count,
+            invalid: self.invalid_count,
+            uploaded: self.upload_count,
+            upload_failed: self.upload_error_count,
+            bytes_downloaded: self.bytes_downloaded,
+            rate: self.throughput,
+            elapsed_secs: self.elapsed.as_secs(),
+            eta_secs: self.eta().map(|eta| eta.as_secs()),
+            stop_reason: self.stop_reason,
+        }
+    }
+}
+
+// Time saved is estimated from this run's download speed, so there's no
+// estimate for a run that didn't download anything
+fn skip_savings(bytes_skipped: u64, throughput: f64) -> Option<String> {
+    if bytes_skipped == 0 {
+        return None;
+    }
+    let bytes = format::format_bytes(bytes_skipped);
+    if throughput < 1.0 {
+        return Some(format!("{} already downloaded", bytes));
+    }
+    let time_saved = Duration::from_secs_f64(bytes_skipped as f64 / throughput);
+    Some(format!(
+        "{} already downloaded, about {} saved",
+        bytes,
+        format::format_duration(time_saved)
+    ))
 }
 
 enum SnapdownState {
@@ -33,56 +412,234 @@ enum SnapdownState {
     // Error,
 }
 
+// The output folder already has an export from a different account
+struct ArchiveConflict {
+    existing: ExportIdentity,
+    subfolder: PathBuf,
+}
+
+#[derive(PartialEq)]
+enum SnapdownTab {
+    Download,
+    BrowseArchive,
+}
+
+// The settings a plan was worked out for, see show_planner()
+#[derive(Clone, PartialEq)]
+struct PlanKey {
+    input: String,
+    output_dir: String,
+    naming: String,
+    force: bool,
+    resolve_links: bool,
+    sections: Vec<Section>,
+    // As typed, the sizes are asked for through it
+    proxy: String,
+    // Whether to ask the server for the sizes of some of the files
+    estimate: bool,
+}
+
+type PlanResult = (PlanKey, Result<(SizeSample, Plan), String>);
+
+// What the Download tab shows under the controls
+#[derive(PartialEq)]
+enum DownloadView {
+    Files,
+    Console,
+}
+
 struct SnapdownEframeApp {
     picked_path: Option<String>,
+    // What's in it, see InputFormat::detect()
+    picked_format: Option<InputFormat>,
     state: SnapdownState,
     recv_from_filepicker: mpsc::Receiver<String>,
     send_from_filepicker: mpsc::Sender<String>,
-    recv_logs_from_downloader: mpsc::Receiver<String>,
-    send_logs_from_downloader: mpsc::Sender<String>,
-    recv_status_from_downloader: mpsc::Receiver<SnapdownStatus>,
-    send_status_from_downloader: mpsc::Sender<SnapdownStatus>,
+    recv_output_dir_from_picker: mpsc::Receiver<String>,
+    send_output_dir_from_picker: mpsc::Sender<String>,
+    // Where downloads go, and the free space there when last checked
+    output_dir: String,
+    output_dir_free_space: Option<u64>,
+    // What SnapDown downloaded on this computer, by month, as of the last
+    // run. None if snapdown_usage.json can't be read.
+    usage: Option<Usage>,
+    // Where snapdown.log is, found once rather than every frame
+    log_path: PathBuf,
+    recv_logs_from_downloader: gui_channel::Receiver<LogEntry>,
+    send_logs_from_downloader: gui_channel::Sender<LogEntry>,
+    file_events: Arc<FileEvents>,
+    // (record, error if it failed again) for files retried from the console's
+    // context menu or the error list
+    recv_retry_results: mpsc::Receiver<(csv::StringRecord, Result<(), String>)>,
+    send_retry_results: mpsc::Sender<(csv::StringRecord, Result<(), String>)>,
+    recv_status_from_downloader: gui_channel::Receiver<SnapdownStatus>,
+    send_status_from_downloader: gui_channel::Sender<SnapdownStatus>,
     success_count: usize,
     error_count: usize,
     skip_count: usize,
+    invalid_count: usize,
+    // Progress of the current (or last) run, see SnapdownStatus
+    total_count: usize,
+    bytes_downloaded: u64,
+    throughput_history: Vec<f64>,
+    // See SnapdownStatus::skip_savings(), only set once finished
+    skip_savings: Option<String>,
+    progress_message: String,
+    run_elapsed: Duration,
+    stop_reason: Option<StopReason>,
+    failed_records: Vec<csv::StringRecord>,
+    host_stats: Vec<(String, HostStats)>,
+    duplicate_count: usize,
+    dedup_bytes_saved: u64,
+    space_estimate: Option<SpaceEstimate>,
+    // Pause/cancel flags of the current (or last) run
+    run_control: Arc<RunControl>,
+    // Number of errors of each kind in the current run, for progress snapshots
+    error_breakdown: std::collections::BTreeMap<String, usize>,
+    // Waiting for the user to decide how to handle mixing archives
+    pending_archive_conflict: Option<ArchiveConflict>,
+    // Shown in a dialog when a run couldn't be started
+    start_error: Option<String>,
+    // The same when a file dropped onto the window can't be read
+    drop_error: Option<String>,
     // This will act as a circular buffer to limit memory usage
-    messages_console: CircularBuffer<1024, String>,
+    messages_console: CircularBuffer<1024, LogEntry>,
+    // Only show the console's errors, and the ones with this text in them
+    console_errors_only: bool,
+    console_search: String,
+    // Every file of the current (or last) run and how it's going
+    file_list: FileList,
+    download_view: DownloadView,
     // Flag to ensure style is only on the first update, then saved to context
     style_applied: bool,
+    debug_http: bool,
+    resolve_links: bool,
+    write_exif: bool,
+    touch: bool,
+    composite_overlays: bool,
+    auto_rotate: bool,
+    sidecars: bool,
+    dedup: Option<DedupMode>,
+    // "Notify me" and its error rate in percent, and the notifier of the
+    // current run with it, see notify.rs
+    notify: bool,
+    notify_error_rate: f64,
+    notifier: Option<Notifier>,
+    // The system tray icon and what it acts on, None without a system tray
+    tray: Option<Tray>,
+    tray_target: Option<Arc<TrayTarget>>,
+    // The Advanced settings: downloads at the same time, --retries, and the
+    // text of the timeout fields in seconds (empty for no limit)
+    jobs: usize,
+    retries: usize,
+    connect_timeout: String,
+    response_timeout: String,
+    // The window with the settings a run would get, see config.rs
+    show_effective_settings: bool,
+    // Views to link the files into after a run, none for no views
+    views: Vec<ViewKind>,
+    view_links: LinkKind,
+    freeze: bool,
+    force: bool,
+    // Text of the speed limit field, e.g. "5M". Empty for no limit.
+    limit_rate: String,
+    // Text of the download hours field, e.g. "01:00-07:00". Empty for any
+    // time.
+    schedule: String,
+    // Text of the proxy field, e.g. "socks5://localhost:1080". Empty to use
+    // the proxy environment variables, if any.
+    proxy: String,
+    // Text of the post-processing command field. Empty for none.
+    post_process_cmd: String,
+    // Text of the file naming field, as for --naming. Empty for legacy.
+    naming: String,
+    // The parts of an export zip to download
+    sections: Vec<Section>,
+    // Saved presets by name, see presets.rs, and the name in the preset field
+    presets: std::collections::BTreeMap<String, Preset>,
+    preset_name: String,
+    // Why the presets couldn't be read or saved
+    preset_error: Option<String>,
+    dry_run: bool,
+    // Result of the last run, if it was a dry run
+    dry_run_report: Option<DryRunReport>,
+    // The planner shown before a run (see planner.rs), and the settings it's
+    // for. Worked out again on a background thread once they change.
+    plan: Option<Result<Plan, String>>,
+    plan_key: Option<PlanKey>,
+    planning: bool,
+    // Sizes of a sample of the input's files (with or without fresh links),
+    // only asked for again for a different input
+    size_sample: Option<((String, bool), SizeSample)>,
+    // The input the user asked for an estimate of the size and time for.
+    // Until they do, the planner makes no requests.
+    estimate_input: Option<String>,
+    recv_plans: mpsc::Receiver<PlanResult>,
+    send_plans: mpsc::Sender<PlanResult>,
+    tab: SnapdownTab,
+    archive_query: ArchiveQuery,
+    // Files found the last time the output directory was scanned
+    archive_entries: Vec<ArchiveEntry>,
+    archive_error: Option<String>,
+    // Counts the scans, so the memories not downloaded yet of an older one
+    // aren't added to a newer one's files
+    archive_scan: usize,
+    // Read from the input on a background thread, see
+    // list_not_downloaded_entries()
+    listing_not_downloaded: bool,
+    recv_not_downloaded: mpsc::Receiver<(usize, Vec<ArchiveEntry>)>,
+    send_not_downloaded: mpsc::Sender<(usize, Vec<ArchiveEntry>)>,
+    // Also list the memories that aren't downloaded yet, and get their image
+    // sizes with range requests (an extra request each)
+    sample_image_sizes: bool,
+    // Sizes of the images listed so far. None while a sample is in progress,
+    // or if the size couldn't be read.
+    image_sizes: std::collections::HashMap<PathBuf, Option<ImageSize>>,
+    recv_image_sizes: mpsc::Receiver<(PathBuf, Option<ImageSize>)>,
+    send_image_sizes: mpsc::Sender<(PathBuf, Option<ImageSize>)>,
+    // Fraction of the Download tab's height given to the controls/status
+    // area, the rest goes to the console
+    status_panel_ratio: f32,
 }
 
+const STATUS_PANEL_RATIO_KEY: &str = "status_panel_ratio";
+const PROXY_KEY: &str = "proxy";
+const OUTPUT_DIR_KEY: &str = "output_dir";
+const POST_PROCESS_CMD_KEY: &str = "post_process_cmd";
+const DEFAULT_STATUS_PANEL_RATIO: f32 = 0.5;
+const MIN_STATUS_PANEL_RATIO: f32 = 0.1;
+const MAX_STATUS_PANEL_RATIO: f32 = 0.9;
+
 impl eframe::App for SnapdownEframeApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, STATUS_PANEL_RATIO_KEY, &self.status_panel_ratio);
+        eframe::set_value(storage, PROXY_KEY, &self.proxy);
+        eframe::set_value(storage, OUTPUT_DIR_KEY, &self.output_dir);
+        eframe::set_value(storage, POST_PROCESS_CMD_KEY, &self.post_process_cmd);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Set up custom styling (do this only once)
+        // Set up custom styling (do this only once). Both a light and a dark
+        // style are registered, and egui picks between them based on the OS
+        // theme unless the user overrides it with the theme buttons.
         if !self.style_applied {
-            let mut style = (*ctx.style()).clone();
-
-            style.visuals.window_fill = Color32::YELLOW;
-            style.visuals.panel_fill = Color32::YELLOW;
-            style.visuals.extreme_bg_color = Color32::WHITE;
-            // style.visuals.override_text_color = Some(Color32::BLACK);
-
-            style.visuals.window_corner_radius = egui::CornerRadius::same(6);
-            style.visuals.widgets.inactive.corner_radius = egui::CornerRadius::same(6);
-            style.visuals.widgets.hovered.corner_radius = egui::CornerRadius::same(6);
-            style.visuals.widgets.active.corner_radius = egui::CornerRadius::same(6);
-
-            style.spacing.button_padding = egui::vec2(12.0, 8.0);
-            style.spacing.item_spacing = egui::vec2(10.0, 10.0);
-
-            style
-                .text_styles
-                .insert(TextStyle::Heading, FontId::proportional(24.0));
-            style
-                .text_styles
-                .insert(TextStyle::Body, FontId::proportional(16.0));
-            style
-                .text_styles
-                .insert(TextStyle::Button, FontId::proportional(16.0));
-
-            ctx.set_style(style);
+            ctx.set_style_of(
+                egui::Theme::Light,
+                snapdown_style(&ctx.style_of(egui::Theme::Light), egui::Theme::Light),
+            );
+            ctx.set_style_of(
+                egui::Theme::Dark,
+                snapdown_style(&ctx.style_of(egui::Theme::Dark), egui::Theme::Dark),
+            );
+            ctx.set_theme(egui::ThemePreference::System);
+            info!("Detected system theme: {:?}", ctx.system_theme());
             self.style_applied = true;
         }
+        self.handle_dropped_files(ctx);
+        self.show_effective_settings_window(ctx);
+        if let Some(tray_target) = &self.tray_target {
+            tray_target.set_output_dir(&self.output_dir);
+        }
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ////////////////////////////////////////////////////////////////////
@@ -90,390 +647,3688 @@ impl eframe::App for SnapdownEframeApp {
             ////////////////////////////////////////////////////////////////////
             ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
                 ui.heading("SnapDown: Download SnapChat files quickly!");
-
-                if ui
-                    .button("Open memories_history.html or snap_export.csv file...")
-                    .clicked()
-                {
-                    // Open file dialog in separate thread to avoid blocking UI
-                    // Clone the sender for use in the thread
-                    let send_from_filepicker_clone = self.send_from_filepicker.clone();
-                    std::thread::spawn(move || {
-                        match rfd::FileDialog::new().pick_file() {
-                            Some(path) => {
-                                // Once file is picked, send it back to the UI thread
-                                match send_from_filepicker_clone.send(path.display().to_string()) {
-                                    Err(e) => {
-                                        error!(
-                                            "Error sending picked file path to UI thread: {}",
-                                            e
-                                        );
-                                    }
-                                    _ => {}
-                                }
-                            }
-                            _ => {}
-                        }
-                    });
-                    self.state = SnapdownState::SelectingFile;
-                }
-            });
-
-            self.recv_from_filepicker
-                .try_iter()
-                .for_each(|picked_path| {
-                    info!(
-                        "Picked file and received it from picker thread: {}",
-                        picked_path
+                egui::widgets::global_theme_preference_buttons(ui);
+                self.show_output_dir_header(ui);
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.tab, SnapdownTab::Download, "Download");
+                    ui.selectable_value(
+                        &mut self.tab,
+                        SnapdownTab::BrowseArchive,
+                        "Browse archive",
                     );
-                    self.picked_path = Some(picked_path);
-                    self.state = SnapdownState::Idle;
-                });
-
-            match &self.picked_path {
-                Some(picked_path) => {
-                    ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
-                        ui.label("Picked file:");
-                        ui.monospace(picked_path);
-
-                        if ui.button("Run SnapDown").clicked() {
-                            let picked_path = picked_path.clone();
-                            let send_logs_from_downloader_clone =
-                                self.send_logs_from_downloader.clone();
-                            let send_status_from_downloader_clone =
-                                self.send_status_from_downloader.clone();
-                            std::thread::spawn(move || {
-                                match run_downloader(
-                                    &picked_path,
-                                    "snapdown_output",
-                                    DEFAULT_NUM_JOBS,
-                                    Some(&send_logs_from_downloader_clone),
-                                    Some(&send_status_from_downloader_clone),
-                                ) {
-                                    Ok(_) => log_message(
-                                        Some(&send_logs_from_downloader_clone),
-                                        "SnapDown completed successfully.".to_string(),
-                                    ),
-                                    Err(e) => log_error(
-                                        Some(&send_logs_from_downloader_clone),
-                                        format!("Error running SnapDown: {}", e),
-                                    ),
-                                }
-                            });
-                            self.state = SnapdownState::Downloading;
-                        }
-                    });
-                }
-                None => {}
-            }
-
-            self.recv_status_from_downloader
-                .try_iter()
-                .for_each(|status| {
-                    if status.finished {
-                        self.state = SnapdownState::Completed;
-                    } else {
-                        self.state = SnapdownState::Downloading;
-                    }
-                    self.success_count = status.success_count;
-                    self.error_count = status.error_count;
-                    self.skip_count = status.skip_count;
                 });
-
-            ui.separator();
-            ui.heading("Status");
-            ui.separator();
-            match self.state {
-                SnapdownState::Idle => {
-                    ui.label("Idle. Ready to start downloading.");
-                }
-                SnapdownState::SelectingFile => {
-                    ui.label("Selecting file...");
-                }
-                SnapdownState::Downloading => {
-                    ui.label("Downloading files...");
-                    ui.label(format!("Successful downloads: {}", self.success_count));
-                    ui.label(format!("Errors: {}", self.error_count));
-                    ui.label(format!("Skipped: {}", self.skip_count));
-                }
-                SnapdownState::Completed => {
-                    ui.label("Download completed!");
-                    ui.label(format!("Successful downloads: {}", self.success_count));
-                    ui.label(format!("Errors: {}", self.error_count));
-                    ui.label(format!("Skipped: {}", self.skip_count));
-                }
-            }
-            ui.heading("Console Log (last 1024 messages only; see snapdown.log for full log)");
-            ui.separator();
-            ////////////////////////////////////////////////////////////////////
-            // Console Log Section
-            ////////////////////////////////////////////////////////////////////
-            self.recv_logs_from_downloader.try_iter().for_each(|msg| {
-                self.messages_console.push_back(msg);
             });
+            ui.separator();
 
-            // Capture remaining space
-            let available = ui.available_size();
-
-            // ----- scrollable content -----
-            egui::ScrollArea::vertical()
-                .auto_shrink([false, false])
-                .show(ui, |ui| {
-                    ui.set_min_size(available);
-
-                    for message in &self.messages_console {
-                        ui.monospace(message);
-                    }
-                });
+            match self.tab {
+                SnapdownTab::Download => self.show_download_tab(ui),
+                SnapdownTab::BrowseArchive => self.show_browse_archive_tab(ui),
+            }
         });
     }
 }
 
-const DEFAULT_NUM_JOBS: usize = 500;
-
-fn print_usage(program_name: &str) {
-    eprintln!(
-        "Usage: {} [--cli -i <input_csv> -o <output_dir> -j <jobs>]",
-        program_name
-    );
-    eprintln!("\nOptions:");
-    eprintln!("  --cli     Use the command line interface instead of the GUI, with options below:");
-    eprintln!("  -i <input_csv>   Path to the input CSV file");
-    eprintln!("  -o <output_dir>  Path to the output directory");
-    eprintln!(
-        "  -j <jobs>     Number of parallel downloads (default: {})",
-        DEFAULT_NUM_JOBS
-    );
-    eprintln!("  -h, --help    Show this help message");
-}
-
-struct Args {
-    input_csv: String,
-    output_dir: String,
-    jobs: usize,
-    cli: bool,
+// Why the last Run click didn't start a run (e.g. the input file is gone), or
+// the like, until OK is clicked
+fn show_error_modal(ui: &mut egui::Ui, id: &str, heading: &str, message: &mut Option<String>) {
+    let Some(text) = message else {
+        return;
+    };
+    let mut closed = false;
+    egui::Modal::new(egui::Id::new(id)).show(ui.ctx(), |ui| {
+        ui.heading(heading);
+        ui.label(text.as_str());
+        if ui.button("OK").clicked() {
+            closed = true;
+        }
+    });
+    if closed {
+        *message = None;
+    }
 }
 
-fn parse_args() -> Result<Args> {
-    let args: Vec<String> = std::env::args().collect();
-
-    // Check for help flag
-    if args.len() > 1 && (args[1] == "-h" || args[1] == "--help") {
-        print_usage(&args[0]);
-        std::process::exit(0);
-    }
+// The SnapDown look: yellow panels in light mode, and a dark gray with yellow
+// accents in dark mode so it doesn't glare on a dark desktop
+fn snapdown_style(base: &egui::Style, theme: egui::Theme) -> egui::Style {
+    let mut style = base.clone();
 
-    let mut input_csv = None;
-    let mut output_dir = None;
-    let mut jobs = DEFAULT_NUM_JOBS;
-    let mut cli = false;
-
-    let mut i = 1;
-    while i < args.len() {
-        match args[i].as_str() {
-            "-i" => {
-                if i + 1 >= args.len() {
-                    eprintln!("Error: -i flag requires a value\n");
-                    print_usage(&args[0]);
-                    std::process::exit(1);
-                }
-                input_csv = Some(args[i + 1].clone());
-                i += 2;
-            }
-            "-o" => {
-                if i + 1 >= args.len() {
-                    eprintln!("Error: -o flag requires a value\n");
-                    print_usage(&args[0]);
-                    std::process::exit(1);
-                }
-                output_dir = Some(args[i + 1].clone());
-                i += 2;
-            }
-            "-j" => {
-                if i + 1 >= args.len() {
-                    eprintln!("Error: -j flag requires a value\n");
-                    print_usage(&args[0]);
-                    std::process::exit(1);
-                }
-                jobs = args[i + 1].parse().unwrap_or_else(|_| {
-                    eprintln!("Error: Invalid value for -j flag: {}\n", args[i + 1]);
-                    print_usage(&args[0]);
-                    std::process::exit(1);
-                });
-                i += 2;
-            }
-            "--cli" => {
-                cli = true;
-                i += 1;
-            }
-            _ => {
-                eprintln!("Error: Unknown argument: {}\n", args[i]);
-                print_usage(&args[0]);
-                std::process::exit(1);
-            }
+    match theme {
+        egui::Theme::Light => {
+            style.visuals.window_fill = Color32::YELLOW;
+            style.visuals.panel_fill = Color32::YELLOW;
+            style.visuals.extreme_bg_color = Color32::WHITE;
+            // style.visuals.override_text_color = Some(Color32::BLACK);
+        }
+        egui::Theme::Dark => {
+            style.visuals.window_fill = Color32::from_gray(32);
+            style.visuals.panel_fill = Color32::from_gray(32);
+            style.visuals.extreme_bg_color = Color32::from_gray(16);
+            style.visuals.selection.bg_fill = Color32::from_rgb(128, 112, 0);
+            style.visuals.hyperlink_color = Color32::YELLOW;
         }
     }
 
-    // Only require -i and -o if CLI mode is enabled
-    if cli {
-        let input_csv = input_csv.ok_or_else(|| {
-            eprintln!("Error: Missing required argument -i <input_csv>\n");
-            print_usage(&args[0]);
-            std::process::exit(1);
-        })?;
-
-        let output_dir = output_dir.ok_or_else(|| {
-            eprintln!("Error: Missing required argument -o <output_dir>\n");
-            print_usage(&args[0]);
-            std::process::exit(1);
-        })?;
+    style.visuals.window_corner_radius = egui::CornerRadius::same(6);
+    style.visuals.widgets.inactive.corner_radius = egui::CornerRadius::same(6);
+    style.visuals.widgets.hovered.corner_radius = egui::CornerRadius::same(6);
+    style.visuals.widgets.active.corner_radius = egui::CornerRadius::same(6);
 
-        Ok(Args {
-            input_csv,
-            output_dir,
-            jobs,
-            cli,
-        })
-    } else {
-        Ok(Args {
-            input_csv: input_csv.unwrap_or_default(),
-            output_dir: output_dir.unwrap_or_default(),
-            jobs,
-            cli,
-        })
-    }
-}
+    style.spacing.button_padding = egui::vec2(12.0, 8.0);
+    style.spacing.item_spacing = egui::vec2(10.0, 10.0);
 
-fn init_logging() {
-    let file = match OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("snapdown.log")
-    {
-        Ok(f) => f,
-        Err(e) => {
-            eprintln!("Error opening log file snapdown.log: {}", e);
-            std::process::exit(1);
-        }
-    };
+    style
+        .text_styles
+        .insert(TextStyle::Heading, FontId::proportional(24.0));
+    style
+        .text_styles
+        .insert(TextStyle::Body, FontId::proportional(16.0));
+    style
+        .text_styles
+        .insert(TextStyle::Button, FontId::proportional(16.0));
 
-    // Set all dependencies to log at error, and all snapdown logs to info
-    // Pipe the output to the log file
-    Builder::from_env(Env::new().filter_or("SNAPDOWN_LOG", "error,snapdown=info"))
-        .target(env_logger::Target::Pipe(Box::new(file)))
-        .format(move |buf, record| {
-            writeln!(
-                buf,
-                "[{}][{}] {}",
-                record.level(),
-                record.target(),
-                record.args()
-            )
-        })
-        .init();
+    style
 }
 
-fn main() -> Result<()> {
-    let args = parse_args()?;
-
-    init_logging();
+impl SnapdownEframeApp {
+    // Always show where the files will end up. Clicking it (or the button
+    // next to it) lets the user pick a different folder.
+    fn show_output_dir_header(&mut self, ui: &mut egui::Ui) {
+        self.recv_output_dir_from_picker
+            .try_iter()
+            .for_each(|output_dir| {
+                info!("Picked output folder: {}", output_dir);
+                self.output_dir_free_space = output_dir_free_space(Path::new(&output_dir));
+                self.output_dir = output_dir;
+            });
 
-    if args.cli {
-        info!(
-            "[{}] Starting SnapDown (CLI mode)...",
-            chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
-        );
-        info!("Input CSV: {}", args.input_csv);
-        info!("Output directory: {}", args.output_dir);
-        info!("Parallel jobs: {}", args.jobs);
-        return run_downloader(&args.input_csv, &args.output_dir, args.jobs, None, None);
-    } else {
-        info!(
-            "[{}] Starting SnapDown (GUI mode)...",
-            chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
-        );
-        return run_gui();
+        let free_space = match self.output_dir_free_space {
+            Some(bytes) => format!(" ({} free)", format::format_bytes(bytes)),
+            None => String::new(),
+        };
+        ui.horizontal(|ui| {
+            ui.label("Output folder:");
+            if ui
+                .link(format!(
+                    "{}{}",
+                    resolve_output_dir(&self.output_dir).display(),
+                    free_space
+                ))
+                .on_hover_text("Click to choose a different output folder")
+                .clicked()
+                || ui.button("Choose output folder...").clicked()
+            {
+                self.pick_output_dir();
+            }
+        });
     }
-}
-
-fn run_gui() -> Result<()> {
-    let (send_from_filepicker, recv_from_filepicker) = mpsc::channel::<String>();
-    let (send_logs_from_downloader, recv_logs_from_downloader) = mpsc::channel::<String>();
-    let (send_status_from_downloader, recv_status_from_downloader) =
-        mpsc::channel::<SnapdownStatus>();
-    let snapdown_app = SnapdownEframeApp {
-        picked_path: None,
-        state: SnapdownState::Idle,
-        send_from_filepicker: send_from_filepicker,
-        recv_from_filepicker: recv_from_filepicker,
-        send_logs_from_downloader: send_logs_from_downloader,
-        recv_logs_from_downloader: recv_logs_from_downloader,
-        send_status_from_downloader: send_status_from_downloader,
-        recv_status_from_downloader: recv_status_from_downloader,
-        success_count: 0,
-        error_count: 0,
-        skip_count: 0,
-        messages_console: CircularBuffer::<1024, String>::new(),
-        style_applied: false,
-    };
 
-    // Have the GUI take care of getting args from the user
-    let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default().with_inner_size([640.0, 240.0]),
-        ..Default::default()
-    };
-    eframe::run_native(
-        "SnapDown GUI",
-        options,
-        Box::new(|_cc| Ok(Box::new(snapdown_app))),
-    )
-    .map_err(|e| anyhow::anyhow!("Failed to run GUI: {}", e))
-}
+    fn pick_output_dir(&self) {
+        // Open folder dialog in separate thread to avoid blocking UI
+        let send_output_dir_from_picker_clone = self.send_output_dir_from_picker.clone();
+        let current_dir = resolve_output_dir(&self.output_dir);
+        std::thread::spawn(move || {
+            if let Some(path) = rfd::FileDialog::new()
+                .set_directory(current_dir)
+                .pick_folder()
+                && let Err(e) = send_output_dir_from_picker_clone.send(path.display().to_string())
+            {
+                error!("Error sending picked output folder to UI thread: {}", e);
+            }
+        });
+    }
 
-fn log_message(gui_console: Option<&mpsc::Sender<String>>, message: String) {
-    info!("{}", &message);
-    match gui_console {
-        Some(sender) => {
-            sender.send(message).unwrap_or_else(|e| {
-                error!("Error sending message to GUI console: {}", e);
+    fn show_download_tab(&mut self, ui: &mut egui::Ui) {
+        // The controls/status area and the console share the tab, split by a
+        // draggable divider. The split is stored as a ratio so it still makes
+        // sense after the window is resized.
+        let total_height = ui.available_height();
+        let status_height = total_height * self.status_panel_ratio;
+        egui::ScrollArea::vertical()
+            .id_salt("status_area")
+            .max_height(status_height)
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                self.show_controls_and_status(ui);
             });
+
+        let (divider_rect, divider_response) =
+            ui.allocate_exact_size(egui::vec2(ui.available_width(), 8.0), egui::Sense::drag());
+        let divider_stroke = if divider_response.hovered() || divider_response.dragged() {
+            ui.visuals().widgets.active.fg_stroke
+        } else {
+            ui.visuals().widgets.noninteractive.bg_stroke
+        };
+        ui.painter().hline(
+            divider_rect.x_range(),
+            divider_rect.center().y,
+            divider_stroke,
+        );
+        if divider_response.hovered() || divider_response.dragged() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::ResizeVertical);
+        }
+        if divider_response.dragged() && total_height > 0.0 {
+            self.status_panel_ratio = ((status_height + divider_response.drag_delta().y)
+                / total_height)
+                .clamp(MIN_STATUS_PANEL_RATIO, MAX_STATUS_PANEL_RATIO);
         }
-        None => {}
-    }
-}
 
-fn log_error(gui_console: Option<&mpsc::Sender<String>>, message: String) {
-    error!("{}", &message);
-    match gui_console {
-        Some(sender) => {
-            sender.send(message).unwrap_or_else(|e| {
-                error!("Error sending message to GUI console: {}", e);
-            });
+        // Received whichever view is shown, the error breakdown in progress
+        // snapshots comes from the console messages
+        self.receive_from_downloader();
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.download_view, DownloadView::Files, "Files");
+            ui.selectable_value(&mut self.download_view, DownloadView::Console, "Console");
+        });
+        match self.download_view {
+            DownloadView::Files => self.show_file_list(ui),
+            DownloadView::Console => self.show_console(ui),
         }
-        None => {}
     }
-}
 
-// // Helper function to find a pattern in bytes, returns position if found
-// fn find_pattern(haystack: &[u8], needle: &[u8]) -> Option<usize> {
-//     if needle.is_empty() || haystack.len() < needle.len() {
-//         return None;
-//     }
+    fn show_controls_and_status(&mut self, ui: &mut egui::Ui) {
+        ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
+            if ui
+                .button(
+                    "Open Snapchat export zip, memories_history.html/.json or snap_export.csv...",
+                )
+                .clicked()
+            {
+                // Open file dialog in separate thread to avoid blocking UI
+                // Clone the sender for use in the thread
+                let send_from_filepicker_clone = self.send_from_filepicker.clone();
+                std::thread::spawn(move || {
+                    if let Some(path) = rfd::FileDialog::new().pick_file() {
+                        // Once file is picked, send it back to the UI thread
+                        if let Err(e) = send_from_filepicker_clone.send(path.display().to_string())
+                        {
+                            error!("Error sending picked file path to UI thread: {}", e);
+                        }
+                    }
+                });
+                self.state = SnapdownState::SelectingFile;
+            }
+            if self.picked_path.is_none() {
+                ui.weak("...or drop it onto this window");
+            }
+        });
 
-//     for i in 0..=(haystack.len() - needle.len()) {
-//         if &haystack[i..i + needle.len()] == needle {
-//             return Some(i);
-//         }
-//     }
-//     None
-// }
+        self.recv_from_filepicker
+            .try_iter()
+            .for_each(|picked_path| {
+                info!(
+                    "Picked file and received it from picker thread: {}",
+                    picked_path
+                );
+                self.picked_format = InputFormat::detect(&picked_path);
+                self.picked_path = Some(picked_path);
+                self.state = SnapdownState::Idle;
+            });
 
-// // Extract latitude and longitude from location string
-// fn extract_coordinates(location: &str) -> (Option<String>, Option<String>) {
-//     // Look for pattern like "Latitude, Longitude: 40.25548, -111.645325"
-//     if let Some(colon_pos) = location.find(':') {
-//         let coords_part = &location[colon_pos + 1..].trim();
-//         let parts: Vec<&str> = coords_part.split(',').collect();
+        let mut run_clicked = false;
+        if let Some(picked_path) = self.picked_path.clone() {
+            ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
+                match self.picked_format {
+                    Some(format) => ui.label(format!("Picked file ({}):", format.description())),
+                    None => ui.label("Picked file:"),
+                };
+                ui.monospace(&picked_path);
+                self.show_presets(ui);
+                if InputFormat::from_path(&picked_path) == Some(InputFormat::ExportZip) {
+                    ui.horizontal(|ui| {
+                        ui.label("Download from the export:");
+                        for section in Section::ALL {
+                            let mut checked = self.sections.contains(&section);
+                            if ui.checkbox(&mut checked, section.name()).changed() {
+                                if checked {
+                                    self.sections.push(section);
+                                    self.sections.sort();
+                                } else {
+                                    self.sections.retain(|other| *other != section);
+                                }
+                            }
+                        }
+                    });
+                }
+                ui.checkbox(
+                    &mut self.debug_http,
+                    format!(
+                        "Write details of failed requests to {}",
+                        HTTP_DEBUG_LOG_FILE
+                    ),
+                );
+                ui.checkbox(
+                    &mut self.resolve_links,
+                    "Request fresh download links (for exports older than a few days)",
+                );
+                ui.checkbox(
+                    &mut self.write_exif,
+                    "Write capture date and location into photos (EXIF)",
+                );
+                ui.checkbox(
+                    &mut self.touch,
+                    "Set file dates to when the memories were taken",
+                );
+                ui.checkbox(
+                    &mut self.composite_overlays,
+                    "Draw captions and stickers onto photos (instead of separate _overlay.png files)",
+                );
+                ui.checkbox(
+                    &mut self.auto_rotate,
+                    "Turn sideways photos upright (for viewers that ignore the EXIF orientation)",
+                );
+                ui.checkbox(
+                    &mut self.sidecars,
+                    "Write a .json file with the date and coordinates next to each file (Google Takeout style)",
+                );
+                ui.horizontal(|ui| {
+                    ui.checkbox(
+                        &mut self.notify,
+                        "Notify me when the download is done, or when more than",
+                    );
+                    ui.add_enabled(
+                        self.notify,
+                        egui::DragValue::new(&mut self.notify_error_rate)
+                            .range(0.0..=100.0)
+                            .suffix("%"),
+                    );
+                    ui.label("of the files fail");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Duplicate files:");
+                    egui::ComboBox::from_id_salt("dedup_mode")
+                        .selected_text(self.dedup.map_or("Keep all", |mode| mode.label()))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.dedup, None, "Keep all");
+                            for mode in [DedupMode::Delete, DedupMode::HardLink] {
+                                ui.selectable_value(&mut self.dedup, Some(mode), mode.label());
+                            }
+                        });
+                });
+                self.show_advanced_settings(ui);
+                ui.horizontal(|ui| {
+                    ui.label("Download speed limit (e.g. 5M, empty for none):");
+                    ui.add(egui::TextEdit::singleline(&mut self.limit_rate).desired_width(80.0));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Only download between (e.g. 01:00-07:00, empty for any time):");
+                    ui.add(egui::TextEdit::singleline(&mut self.schedule).desired_width(100.0));
+                });
+                ui.checkbox(
+                    &mut self.freeze,
+                    "Make the files read-only once everything is downloaded",
+                );
+                ui.checkbox(
+                    &mut self.force,
+                    "Download everything again, even files that are already there",
+                );
+                ui.checkbox(
+                    &mut self.dry_run,
+                    "Dry run (only show what would be downloaded)",
+                );
+                ui.horizontal(|ui| {
+                    ui.label("Proxy (e.g. socks5://localhost:1080):");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.proxy)
+                            .hint_text("none")
+                            .desired_width(200.0),
+                    );
+                    if let Err(e) = self.parsed_proxy() {
+                        ui.colored_label(ui.visuals().error_fg_color, e);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Run after each download:");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.post_process_cmd)
+                            .hint_text("e.g. exiftool -overwrite_original ... {path}")
+                            .desired_width(300.0),
+                    );
+                });
+
+                // Last chance to notice the files are going somewhere else
+                // than intended
+                ui.label(format!(
+                    "Files will be saved in {}",
+                    resolve_output_dir(&self.output_dir).display()
+                ));
+                self.show_planner(ui);
+                if ui.button("Run SnapDown").clicked() {
+                    run_clicked = true;
+                }
+            });
+        }
+        if run_clicked {
+            self.request_run();
+        }
+        self.show_archive_conflict_modal(ui);
+        show_error_modal(
+            ui,
+            "start_error_modal",
+            "Can't start downloading",
+            &mut self.start_error,
+        );
+        show_error_modal(
+            ui,
+            "drop_error_modal",
+            "Can't open the dropped file",
+            &mut self.drop_error,
+        );
+
+        self.recv_status_from_downloader
+            .try_iter()
+            .for_each(|status| {
+                if status.finished {
+                    self.state = SnapdownState::Completed;
+                } else {
+                    self.state = SnapdownState::Downloading;
+                }
+                if let Some(notifier) = &mut self.notifier {
+                    status.notify_error_rate(notifier);
+                    if status.finished {
+                        status.notify_finished();
+                    }
+                }
+                self.success_count = status.success_count;
+                self.error_count = status.error_count;
+                self.skip_count = status.skip_count;
+                self.invalid_count = status.invalid_count;
+                self.total_count = status.total_count;
+                self.bytes_downloaded = status.bytes_downloaded;
+                self.progress_message = status.progress_message();
+                self.run_elapsed = status.elapsed;
+                self.stop_reason = status.stop_reason;
+                self.space_estimate = status.space_estimate;
+                if status.finished {
+                    self.skip_savings = status.skip_savings();
+                    self.dry_run_report = status.dry_run_report;
+                    self.failed_records = status.failed_records;
+                    self.host_stats = status.host_stats;
+                    self.duplicate_count = status.duplicate_count;
+                    self.dedup_bytes_saved = status.dedup_bytes_saved;
+                    if status.usage.is_some() {
+                        self.usage = status.usage;
+                    }
+                    self.output_dir_free_space = output_dir_free_space(Path::new(&self.output_dir));
+                    // What's left to download changed
+                    self.plan_key = None;
+                }
+                self.throughput_history = status.throughput_history;
+            });
+
+        self.recv_retry_results
+            .try_iter()
+            .for_each(|(record, result)| {
+                let succeeded = result.is_ok();
+                self.file_list.retried(&record, result);
+                if succeeded
+                    && let Some(index) = self.failed_records.iter().position(|r| *r == record)
+                {
+                    self.failed_records.remove(index);
+                    self.error_count = self.error_count.saturating_sub(1);
+                    self.success_count += 1;
+                }
+            });
+
+        ui.separator();
+        ui.heading("Status");
+        ui.separator();
+        match self.state {
+            SnapdownState::Idle => {
+                ui.label("Idle. Ready to start downloading.");
+                if let Some(usage) = self.usage.as_ref().filter(|usage| usage.total() > 0) {
+                    ui.label(format!(
+                        "Data used: {}",
+                        usage.summary(&usage::current_month())
+                    ));
+                }
+            }
+            SnapdownState::SelectingFile => {
+                ui.label("Selecting file...");
+            }
+            SnapdownState::Downloading => {
+                ui.horizontal(|ui| {
+                    if let Some(reason) = self.run_control.stop_reason() {
+                        ui.label(format!(
+                            "Stopping ({}), waiting for downloads in progress to stop...",
+                            reason
+                        ));
+                        return;
+                    }
+                    if self.run_control.is_paused() {
+                        ui.label("Paused.");
+                        if ui.button("Resume").clicked() {
+                            self.run_control.resume();
+                        }
+                    } else {
+                        ui.label("Downloading files...");
+                        if ui.button("Pause").clicked() {
+                            self.run_control.pause();
+                        }
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.run_control.cancel();
+                    }
+                    if self.tray.is_some()
+                        && ui
+                            .button("Hide to tray")
+                            .on_hover_text("Keep downloading with the window hidden")
+                            .clicked()
+                    {
+                        ui.ctx()
+                            .send_viewport_cmd(egui::ViewportCommand::Visible(false));
+                    }
+                });
+                if self.total_count > 0 {
+                    let processed = self.success_count
+                        + self.error_count
+                        + self.skip_count
+                        + self.invalid_count;
+                    let fraction = processed as f32 / self.total_count as f32;
+                    ui.add(
+                        egui::ProgressBar::new(fraction)
+                            .text(format::format_percent(fraction as f64)),
+                    );
+                    ui.label(format!(
+                        "{} of {} files, {}",
+                        format::format_count(processed),
+                        format::format_count(self.total_count),
+                        self.progress_message
+                    ));
+                    show_sparkline(ui, &self.throughput_history);
+                }
+                if let Some(estimate) = &self.space_estimate {
+                    ui.label(format!("Space: {}", estimate.message()));
+                }
+                ui.label(format!(
+                    "Successful downloads: {}",
+                    format::format_count(self.success_count)
+                ));
+                self.show_error_list(ui);
+                ui.label(format!(
+                    "Skipped: {}",
+                    format::format_count(self.skip_count)
+                ));
+                self.show_invalid_count(ui);
+                self.show_report_buttons(ui);
+            }
+            SnapdownState::Completed if self.dry_run_report.is_some() => {
+                if let Some(report) = &self.dry_run_report {
+                    for line in report.lines() {
+                        ui.label(line);
+                    }
+                }
+            }
+            SnapdownState::Completed => {
+                if let Some(reason) = self.stop_reason {
+                    ui.label(format!("Stopped: {}.", reason));
+                } else {
+                    ui.label("Download completed!");
+                }
+                ui.label(format!(
+                    "Successful downloads: {}",
+                    format::format_count(self.success_count)
+                ));
+                self.show_error_list(ui);
+                match &self.skip_savings {
+                    Some(savings) => ui.label(format!(
+                        "Skipped: {} ({})",
+                        format::format_count(self.skip_count),
+                        savings
+                    )),
+                    None => ui.label(format!(
+                        "Skipped: {}",
+                        format::format_count(self.skip_count)
+                    )),
+                };
+                self.show_invalid_count(ui);
+                ui.label(format!(
+                    "Downloaded {} in {}",
+                    format::format_bytes(self.bytes_downloaded),
+                    format::format_duration(self.run_elapsed)
+                ));
+                if let Some(usage) = &self.usage {
+                    ui.label(format!(
+                        "Data used: {}",
+                        usage.summary(&usage::current_month())
+                    ));
+                }
+                if self.duplicate_count > 0 {
+                    ui.label(format!(
+                        "Duplicates: {} ({} saved)",
+                        format::format_count(self.duplicate_count),
+                        format::format_bytes(self.dedup_bytes_saved)
+                    ));
+                }
+                if !self.host_stats.is_empty() {
+                    egui::CollapsingHeader::new("Per-host statistics").show(ui, |ui| {
+                        for (host, stats) in &self.host_stats {
+                            let line = stats::format_host_stats(host, stats);
+                            ui.add(
+                                egui::Label::new(egui::RichText::new(&line).monospace())
+                                    .sense(egui::Sense::click()),
+                            )
+                            .context_menu(|ui| {
+                                if ui.button("Copy line").clicked() {
+                                    ui.ctx().copy_text(line.clone());
+                                    ui.close();
+                                }
+                            });
+                        }
+                    });
+                }
+                if self.dry_run_report.is_none()
+                    && is_local_dir(&self.output_dir)
+                    && ui
+                        .button("Open output folder")
+                        .on_hover_text(resolve_output_dir(&self.output_dir).display().to_string())
+                        .clicked()
+                {
+                    self.open_output_folder();
+                }
+                if !self.failed_records.is_empty()
+                    && ui
+                        .button(format!(
+                            "Retry failed ({})",
+                            format::format_count(self.failed_records.len())
+                        ))
+                        .on_hover_text("Download only the files that failed again")
+                        .clicked()
+                {
+                    self.start_run(true);
+                }
+                if !self.failed_records.is_empty()
+                    && ui
+                        .button(format!(
+                            "Export failures ({})...",
+                            self.failed_records.len()
+                        ))
+                        .clicked()
+                {
+                    // Save dialog in a separate thread to avoid blocking UI
+                    let failed_records = self.failed_records.clone();
+                    let events = self.event_sink();
+                    std::thread::spawn(move || {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .set_file_name("snap_export.csv")
+                            .save_file()
+                        {
+                            export_failures(&path, &failed_records, Some(&events));
+                        }
+                    });
+                }
+                self.show_report_buttons(ui);
+            }
+        }
+    }
+
+    // Only shown when the input had rows that aren't memories
+    fn show_invalid_count(&self, ui: &mut egui::Ui) {
+        if self.invalid_count > 0 {
+            ui.label(format!(
+                "Not memories: {} rows (see the log)",
+                format::format_count(self.invalid_count)
+            ));
+        }
+    }
+
+    // The error count, which opens into a list of the files that failed and
+    // why, each with buttons to copy its URL or retry it
+    fn show_error_list(&mut self, ui: &mut egui::Ui) {
+        let label = format!("Errors: {}", format::format_count(self.error_count));
+        if self.file_list.failed_rows().next().is_none() {
+            ui.label(label);
+            return;
+        }
+        let mut record_to_retry = None;
+        egui::CollapsingHeader::new(label)
+            .id_salt("error_list")
+            .show(ui, |ui| {
+                egui::ScrollArea::vertical()
+                    .id_salt("error_list_scroll")
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("error_list_grid")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for heading in ["File", "URL", "Error", "Attempts", ""] {
+                                    ui.strong(heading);
+                                }
+                                ui.end_row();
+                                for row in self.file_list.failed_rows() {
+                                    let url = record_filename_and_url(&row.record)
+                                        .map(|(_, url)| url.to_string());
+                                    let error = row.error.as_deref().unwrap_or_default();
+                                    ui.label(&row.filename);
+                                    // Signed links are long, the full one is
+                                    // only copied
+                                    ui.label(redact::redact_urls(
+                                        url.as_deref().unwrap_or_default(),
+                                    ));
+                                    ui.label(snapshot::error_kind(error)).on_hover_text(error);
+                                    ui.label(format::format_count(row.attempts as usize));
+                                    // Rows that aren't memories have nothing
+                                    // to copy or retry
+                                    ui.horizontal(|ui| {
+                                        let Some(url) = url else {
+                                            return;
+                                        };
+                                        if ui.small_button("Copy URL").clicked() {
+                                            ui.ctx().copy_text(url);
+                                        }
+                                        if ui.small_button("Retry").clicked() {
+                                            record_to_retry = Some(row.record.clone());
+                                        }
+                                    });
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+        if let Some(record) = record_to_retry {
+            self.retry_record(record);
+        }
+    }
+
+    // For asking for help, during a run or after it
+    fn show_report_buttons(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            self.show_export_snapshot_button(ui);
+            self.show_support_bundle_button(ui);
+        });
+    }
+
+    fn show_support_bundle_button(&self, ui: &mut egui::Ui) {
+        if !ui.button("Create support bundle...").clicked() {
+            return;
+        }
+        let snapshot = self.progress_snapshot();
+        let files = support_bundle_files(&self.output_dir, None);
+        let events = self.event_sink();
+        std::thread::spawn(move || {
+            if let Some(path) = rfd::FileDialog::new()
+                .set_file_name(support_bundle::SUPPORT_BUNDLE_FILE)
+                .save_file()
+            {
+                write_support_bundle(&path, &snapshot, &files, Some(&events));
+            }
+        });
+    }
+
+    fn show_export_snapshot_button(&self, ui: &mut egui::Ui) {
+        if !ui.button("Export progress snapshot...").clicked() {
+            return;
+        }
+        let snapshot = self.progress_snapshot();
+        let events = self.event_sink();
+        std::thread::spawn(move || {
+            if let Some(path) = rfd::FileDialog::new()
+                .set_file_name("snapdown_snapshot.json")
+                .save_file()
+            {
+                let events: Option<&dyn EventSink> = Some(&events);
+                match snapshot.write(&path) {
+                    Ok(()) => log_message(
+                        events,
+                        format!("Wrote progress snapshot to {}", path.display()),
+                    ),
+                    Err(e) => log_error(
+                        events,
+                        format!(
+                            "Error writing progress snapshot to {}: {}",
+                            path.display(),
+                            e
+                        ),
+                    ),
+                }
+            }
+        });
+    }
+
+    fn progress_snapshot(&self) -> ProgressSnapshot {
+        let state = match self.state {
+            SnapdownState::Completed if self.stop_reason.is_some() => "stopped",
+            SnapdownState::Completed => "completed",
+            _ if self.run_control.is_cancelled() => "stopping",
+            _ if self.run_control.is_paused() => "paused",
+            _ => "downloading",
+        };
+        let recent_log = self
+            .messages_console
+            .iter()
+            .rev()
+            .take(snapshot::SNAPSHOT_LOG_LINES)
+            .rev()
+            .map(|entry| redact::redact_urls(&entry.message))
+            .collect();
+        ProgressSnapshot {
+            created: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            snapdown_version: env!("CARGO_PKG_VERSION"),
+            state: state.to_string(),
+            success_count: self.success_count,
+            error_count: self.error_count,
+            skip_count: self.skip_count,
+            invalid_count: self.invalid_count,
+            error_breakdown: self.error_breakdown.clone(),
+            stop_reason: self.stop_reason.or(self.run_control.stop_reason()),
+            config: SnapshotConfig {
+                input_file: self.picked_path.as_ref().and_then(|path| {
+                    Path::new(path)
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                }),
+                output_dir: self.output_dir.clone(),
+                jobs: self.jobs,
+                retries: self.retries,
+                debug_http: self.debug_http,
+                resolve_links: self.resolve_links,
+                write_exif: self.write_exif,
+                touch: self.touch,
+                composite_overlays: self.composite_overlays,
+                auto_rotate: self.auto_rotate,
+                sidecars: self.sidecars,
+                dedup: self.dedup,
+                views: self.views.iter().map(|kind| kind.dir_name()).collect(),
+                sections: self.sections.iter().map(Section::name).collect(),
+                freeze: self.freeze,
+                force: self.force,
+                limit_rate: throttle::parse_rate(&self.limit_rate).ok(),
+                schedule: Schedule::parse(&self.schedule)
+                    .ok()
+                    .map(|schedule| schedule.to_string()),
+                proxy: !self.proxy.trim().is_empty(),
+                post_process_cmd: !self.post_process_cmd.trim().is_empty(),
+                upload: None,
+            },
+            recent_log,
+        }
+    }
+
+    // Check the output folder doesn't already hold another account's memories
+    // before starting. If it does, ask the user what to do first.
+    fn request_run(&mut self) {
+        let Some(picked_path) = &self.picked_path else {
+            return;
+        };
+        if let Err(e) = input::check_input_file(Path::new(picked_path)) {
+            error!("{}", e);
+            self.start_error = Some(e.to_string());
+            return;
+        }
+        match ExportIdentity::from_input_file(Path::new(picked_path)) {
+            Ok(identity) => {
+                let output_dir = Path::new(&self.output_dir);
+                if let ArchiveCheck::DifferentAccount { existing } =
+                    identity::check_archive(output_dir, &identity)
+                {
+                    self.pending_archive_conflict = Some(ArchiveConflict {
+                        existing,
+                        subfolder: identity::export_subfolder(output_dir, &identity),
+                    });
+                    return;
+                }
+            }
+            Err(e) => error!("Error identifying export {}: {}", picked_path, e),
+        }
+        self.start_run(false);
+    }
+
+    // Picking a preset sets everything it has a setting for. Saving one
+    // saves the settings the presets have, as they are now.
+    fn show_presets(&mut self, ui: &mut egui::Ui) {
+        let mut picked = None;
+        ui.horizontal(|ui| {
+            ui.label("Preset:");
+            egui::ComboBox::from_id_salt("preset")
+                .selected_text("Apply...")
+                .show_ui(ui, |ui| {
+                    if self.presets.is_empty() {
+                        ui.label("No presets saved yet");
+                    }
+                    for name in self.presets.keys() {
+                        if ui.selectable_label(false, name).clicked() {
+                            picked = Some(name.clone());
+                        }
+                    }
+                });
+            ui.add(
+                egui::TextEdit::singleline(&mut self.preset_name)
+                    .hint_text("e.g. Videos to NAS")
+                    .desired_width(160.0),
+            );
+            if ui
+                .add_enabled(
+                    !self.preset_name.trim().is_empty(),
+                    egui::Button::new("Save as preset"),
+                )
+                .on_hover_text(format!("Save these settings in {}", presets::PRESETS_FILE))
+                .clicked()
+            {
+                self.save_preset();
+            }
+        });
+        if let Some(error) = &self.preset_error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        if let Some(name) = picked {
+            self.preset_error = self.apply_preset(&name).err().map(|e| format!("{:#}", e));
+            self.preset_name = name;
+        }
+    }
+
+    fn plan_key(&self) -> Option<PlanKey> {
+        Some(PlanKey {
+            input: self.picked_path.clone()?,
+            output_dir: self.output_dir.clone(),
+            naming: self.naming.trim().to_string(),
+            force: self.force,
+            resolve_links: self.resolve_links,
+            sections: self.sections.clone(),
+            proxy: self.proxy.trim().to_string(),
+            estimate: self.estimate_input == self.picked_path,
+        })
+    }
+
+    // What a run with these settings would download, how big and how long,
+    // above the Run button. A new plan isn't started while one is being
+    // worked out, so typing a file name template doesn't start one per key;
+    // the next one picks up whatever changed in the meantime.
+    fn show_planner(&mut self, ui: &mut egui::Ui) {
+        for (key, result) in self.recv_plans.try_iter() {
+            self.planning = false;
+            self.plan = Some(result.map(|(sample, plan)| {
+                if !sample.is_empty() {
+                    self.size_sample = Some(((key.input.clone(), key.resolve_links), sample));
+                }
+                plan
+            }));
+        }
+        let key = self.plan_key();
+        if !self.planning
+            && !matches!(self.state, SnapdownState::Downloading)
+            && key != self.plan_key
+            && let Some(key) = key
+        {
+            self.start_planning(key, ui.ctx().clone());
+        }
+
+        egui::CollapsingHeader::new("Plan")
+            .default_open(true)
+            .show(ui, |ui| match &self.plan {
+                None => {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Checking the files...");
+                    });
+                }
+                Some(Ok(plan)) => {
+                    let not_enough_space = plan.space().is_some_and(|space| !space.is_enough());
+                    for line in plan.lines() {
+                        if not_enough_space && line.starts_with("Probably not enough") {
+                            ui.colored_label(ui.visuals().warn_fg_color, line);
+                        } else {
+                            ui.label(line);
+                        }
+                    }
+                    if self.planning {
+                        ui.spinner();
+                    } else if !plan.sampled
+                        && plan.files() > 0
+                        && ui
+                            .button("Estimate size and time")
+                            .on_hover_text("Asks the server for the sizes of some of the files")
+                            .clicked()
+                    {
+                        self.estimate_input = self.picked_path.clone();
+                    }
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                }
+            });
+    }
+
+    fn start_planning(&mut self, key: PlanKey, ctx: egui::Context) {
+        self.plan_key = Some(key.clone());
+        let naming = match key.naming.as_str() {
+            "" => Naming::default(),
+            naming => match Naming::parse(naming) {
+                Ok(naming) => naming,
+                Err(e) => {
+                    self.plan = Some(Err(format!("File names: {}", e)));
+                    return;
+                }
+            },
+        };
+        let sample = self
+            .size_sample
+            .as_ref()
+            .filter(|((input, resolve_links), _)| {
+                *input == key.input && *resolve_links == key.resolve_links
+            })
+            .map(|(_, sample)| sample.clone());
+        // Not even without an estimate, a run couldn't start with it
+        let proxy = match parse_proxy(&key.proxy) {
+            Ok(proxy) => proxy,
+            Err(e) => {
+                self.plan = Some(Err(e));
+                return;
+            }
+        };
+        self.planning = true;
+        let agent = DownloadOptions {
+            proxy,
+            ..Default::default()
+        }
+        .http_agent();
+        let send_plans_clone = self.send_plans.clone();
+        std::thread::spawn(move || {
+            let output_dir = resolve_output_dir(&key.output_dir);
+            let result = read_input_sections(&key.input, &key.sections, None)
+                .map(|records| {
+                    let sample = sample.or_else(|| {
+                        key.estimate
+                            .then(|| sample_sizes(&agent, key.resolve_links, &records))
+                    });
+                    let report =
+                        dry_run::plan_run(&records, &output_dir, naming.namer(), key.force);
+                    let plan = Plan::new(
+                        records.len(),
+                        report,
+                        sample.as_ref(),
+                        planner::last_throughput(&output_dir),
+                        output_dir_free_space(&output_dir),
+                    );
+                    (sample.unwrap_or_default(), plan)
+                })
+                .map_err(|e| format!("Error reading {}: {:#}", key.input, e));
+            if send_plans_clone.send((key, result)).is_ok() {
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    fn apply_preset(&mut self, name: &str) -> Result<()> {
+        let preset = presets::find(&self.presets, name)?.clone();
+        info!("Applying preset {:?}", name);
+        if let Some(output_dir) = &preset.output_dir {
+            self.output_dir_free_space = output_dir_free_space(Path::new(output_dir));
+            self.output_dir = output_dir.clone();
+        }
+        if let Some(naming) = &preset.naming {
+            self.naming = naming.clone();
+        }
+        if let Some(views) = preset.views()? {
+            self.views = views;
+        }
+        if let Some(view_links) = preset.view_links()? {
+            self.view_links = view_links;
+        }
+        if let Some(dedup) = preset.dedup()? {
+            self.dedup = dedup;
+        }
+        if let Some(sections) = preset.sections()? {
+            self.sections = sections;
+        }
+        let flags = [
+            (&mut self.sidecars, preset.sidecars),
+            (&mut self.write_exif, preset.write_exif),
+            (&mut self.touch, preset.touch),
+            (&mut self.composite_overlays, preset.composite_overlays),
+            (&mut self.auto_rotate, preset.auto_rotate),
+            (&mut self.freeze, preset.freeze),
+        ];
+        for (flag, value) in flags {
+            if let Some(value) = value {
+                *flag = value;
+            }
+        }
+        Ok(())
+    }
+
+    fn save_preset(&mut self) {
+        let name = self.preset_name.trim().to_string();
+        let preset = Preset {
+            output_dir: Some(self.output_dir.clone()),
+            naming: Some(self.naming.trim().to_string()).filter(|naming| !naming.is_empty()),
+            views: Some(
+                self.views
+                    .iter()
+                    .map(|view| view.dir_name().to_string())
+                    .collect(),
+            ),
+            view_links: Some(presets::link_name(self.view_links).to_string()),
+            dedup: Some(presets::dedup_name(self.dedup).to_string()),
+            sections: Some(
+                self.sections
+                    .iter()
+                    .map(|section| section.name().to_string())
+                    .collect(),
+            ),
+            sidecars: Some(self.sidecars),
+            write_exif: Some(self.write_exif),
+            touch: Some(self.touch),
+            composite_overlays: Some(self.composite_overlays),
+            auto_rotate: Some(self.auto_rotate),
+            freeze: Some(self.freeze),
+        };
+        // Checked like the ones in the file, a bad naming template would
+        // otherwise only turn up when the preset is used
+        if let Some(naming) = &preset.naming
+            && let Err(e) = Naming::parse(naming)
+        {
+            self.preset_error = Some(format!("File names: {}", e));
+            return;
+        }
+        // Saved over one with the same name, whatever its case
+        self.presets
+            .retain(|existing, _| !existing.eq_ignore_ascii_case(&name));
+        self.presets.insert(name.clone(), preset);
+        self.preset_error = match presets::save(&presets::presets_file(), &self.presets) {
+            Ok(()) => {
+                info!("Saved preset {:?}", name);
+                None
+            }
+            Err(e) => Some(format!("Error saving the preset: {:#}", e)),
+        };
+    }
+
+    // Dropping the input file anywhere on the window picks it, as the Open
+    // button does, and goes to the Download tab to start from there. While a
+    // file is dragged over the window, it says where to drop it.
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        if ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+            let painter = ctx.layer_painter(egui::LayerId::new(
+                egui::Order::Foreground,
+                egui::Id::new("file_drop_target"),
+            ));
+            let rect = ctx.viewport_rect();
+            painter.rect_filled(rect, 0.0, Color32::from_black_alpha(192));
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "Drop the export zip, memories_history.html/.json or snap_export.csv here",
+                TextStyle::Heading.resolve(&ctx.style()),
+                Color32::WHITE,
+            );
+        }
+        let dropped: Vec<PathBuf> = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .filter_map(|file| file.path.clone())
+                .collect()
+        });
+        if dropped.is_empty() {
+            return;
+        }
+        self.tab = SnapdownTab::Download;
+        if matches!(self.state, SnapdownState::Downloading) {
+            self.drop_error =
+                Some("Wait for the run to finish before picking another file".to_string());
+            return;
+        }
+        match input::pick_dropped_file(&dropped) {
+            Ok(path) => {
+                info!("Picked dropped file: {}", path.display());
+                self.picked_format = InputFormat::detect(&path);
+                self.picked_path = Some(path.display().to_string());
+                self.state = SnapdownState::Idle;
+            }
+            Err(e) => {
+                error!("Error picking dropped file: {}", e);
+                self.drop_error = Some(e.to_string());
+            }
+        }
+    }
+
+    fn show_archive_conflict_modal(&mut self, ui: &mut egui::Ui) {
+        let Some(conflict) = &self.pending_archive_conflict else {
+            return;
+        };
+        let subfolder = conflict.subfolder.display().to_string();
+        let existing_export_date = conflict.existing.export_date.clone();
+        let mut choice = None;
+        let mut cancelled = false;
+        egui::Modal::new(egui::Id::new("archive_conflict_modal")).show(ui.ctx(), |ui| {
+            ui.heading("Output folder has another account's memories");
+            ui.label(format!(
+                "{} already contains an export from a different Snapchat account (exported {}). \
+                 Mixing archives can cause files with the same name to clash.",
+                self.output_dir, existing_export_date
+            ));
+            if ui.button(format!("Use subfolder {}", subfolder)).clicked() {
+                choice = Some(Some(subfolder.clone()));
+            }
+            if ui.button("Download into the same folder anyway").clicked() {
+                choice = Some(None);
+            }
+            if ui.button("Cancel").clicked() {
+                cancelled = true;
+            }
+        });
+        if cancelled {
+            self.pending_archive_conflict = None;
+        }
+        if let Some(subfolder) = choice {
+            self.pending_archive_conflict = None;
+            if let Some(subfolder) = subfolder {
+                self.output_dir_free_space = output_dir_free_space(Path::new(&subfolder));
+                self.output_dir = subfolder;
+            }
+            self.start_run(false);
+        }
+    }
+
+    // Settings most runs don't need to touch, folded away under the others
+    fn show_advanced_settings(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Advanced settings").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Downloads at the same time:");
+                ui.add(egui::Slider::new(&mut self.jobs, 1..=1000).logarithmic(true));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Try failed downloads again (connection and server errors):");
+                ui.add(
+                    egui::DragValue::new(&mut self.retries)
+                        .range(0..=20)
+                        .suffix(" times"),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("Give up connecting after (seconds):");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.connect_timeout)
+                        .hint_text("no limit")
+                        .desired_width(60.0),
+                );
+                ui.label("Give up waiting for an answer after (seconds):");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.response_timeout)
+                        .hint_text("no limit")
+                        .desired_width(60.0),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("Also link files into:");
+                for kind in ViewKind::ALL {
+                    let mut checked = self.views.contains(&kind);
+                    if ui.checkbox(&mut checked, kind.dir_name()).changed() {
+                        if checked {
+                            self.views.push(kind);
+                        } else {
+                            self.views.retain(|view| *view != kind);
+                        }
+                    }
+                }
+                egui::ComboBox::from_id_salt("view_links")
+                    .selected_text(self.view_links.label())
+                    .show_ui(ui, |ui| {
+                        for kind in [LinkKind::HardLink, LinkKind::Symlink] {
+                            ui.selectable_value(&mut self.view_links, kind, kind.label());
+                        }
+                    });
+            });
+            ui.horizontal(|ui| {
+                ui.label("File names (legacy, hash, index or e.g. {date}_{type}):");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.naming)
+                        .hint_text("legacy")
+                        .desired_width(200.0),
+                );
+            });
+            if ui.button("Show effective settings").clicked() {
+                self.show_effective_settings = true;
+            }
+        });
+    }
+
+    // The settings as --print-config would print them for the same run, or
+    // what's wrong with them that a run wouldn't start
+    fn effective_config(&self) -> Result<EffectiveConfig, String> {
+        let input = self.picked_path.clone().unwrap_or_default();
+        let output_dir = resolve_output_dir(&self.output_dir).display().to_string();
+        let options = self.download_options(&input, false)?;
+        Ok(EffectiveConfig {
+            notify: self.notify,
+            notify_error_rate: self.notify.then_some(self.notify_error_rate),
+            ..options_effective_config(&input, &output_dir, &options)
+        })
+    }
+
+    fn show_effective_settings_window(&mut self, ctx: &egui::Context) {
+        if !self.show_effective_settings {
+            return;
+        }
+        let toml = self
+            .effective_config()
+            .and_then(|config| config.to_toml().map_err(|e| e.to_string()));
+        egui::Window::new("Effective settings")
+            .open(&mut self.show_effective_settings)
+            .default_height(400.0)
+            .show(ctx, |ui| {
+                ui.label("What a run started now would use, as --print-config prints it:");
+                let toml = match &toml {
+                    Ok(toml) => toml,
+                    Err(e) => {
+                        ui.colored_label(ui.visuals().error_fg_color, e);
+                        return;
+                    }
+                };
+                if ui.button("Copy").clicked() {
+                    ui.ctx().copy_text(toml.clone());
+                }
+                ui.separator();
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.add(
+                        egui::Label::new(egui::RichText::new(toml).monospace()).selectable(true),
+                    );
+                });
+            });
+    }
+
+    // The proxy in the settings, None if there isn't one. Everything that
+    // makes requests from the GUI uses this, so a mistyped proxy is an error
+    // rather than a direct connection without it.
+    fn parsed_proxy(&self) -> Result<Option<ureq::Proxy>, String> {
+        parse_proxy(&self.proxy)
+    }
+
+    // Errors go to the console, the click shouldn't just do nothing
+    fn open_output_folder(&self) {
+        if let Err(e) = archive::open_in_file_manager(Path::new(&self.output_dir)) {
+            log_error(
+                Some(&self.event_sink()),
+                format!("Error opening {}: {}", self.output_dir, e),
+            );
+        }
+    }
+
+    // Where a run or a task on another thread sends the GUI what it has to
+    // show, see events.rs
+    fn event_sink(&self) -> GuiSink {
+        GuiSink {
+            logs: self.send_logs_from_downloader.clone(),
+            files: self.file_events.clone(),
+            statuses: self.send_status_from_downloader.clone(),
+            tray: self.tray_target.clone(),
+        }
+    }
+
+    // The options of a run of `picked_path` with the settings, or what's
+    // wrong with them
+    fn download_options(
+        &self,
+        picked_path: &str,
+        retry_failed: bool,
+    ) -> Result<DownloadOptions, String> {
+        let limit_rate = match self.limit_rate.trim() {
+            "" => None,
+            rate => Some(
+                throttle::parse_rate(rate).map_err(|e| format!("Download speed limit: {}", e))?,
+            ),
+        };
+        let schedule = match self.schedule.trim() {
+            "" => None,
+            schedule => {
+                Some(Schedule::parse(schedule).map_err(|e| format!("Download hours: {}", e))?)
+            }
+        };
+        let naming = match self.naming.trim() {
+            "" => Naming::default(),
+            naming => Naming::parse(naming).map_err(|e| format!("File names: {}", e))?,
+        };
+        let proxy = self.parsed_proxy()?;
+        let timeout = |text: &str| match text.trim() {
+            "" => Ok(None),
+            seconds => cli::parse_seconds(seconds).map(Some),
+        };
+        let connect_timeout =
+            timeout(&self.connect_timeout).map_err(|e| format!("Connect timeout: {}", e))?;
+        let response_timeout =
+            timeout(&self.response_timeout).map_err(|e| format!("Response timeout: {}", e))?;
+        if self.sections.is_empty()
+            && !retry_failed
+            && InputFormat::from_path(picked_path) == Some(InputFormat::ExportZip)
+        {
+            return Err("Pick at least one part of the export to download".to_string());
+        }
+        Ok(DownloadOptions {
+            jobs: self.jobs,
+            retries: self.retries,
+            connect_timeout,
+            response_timeout,
+            debug_http: self.debug_http,
+            resolve_links: self.resolve_links,
+            write_exif: self.write_exif,
+            touch: self.touch,
+            composite_overlays: self.composite_overlays,
+            auto_rotate: self.auto_rotate,
+            sidecars: self.sidecars,
+            dedup: self.dedup,
+            views: Some(ViewSettings {
+                kinds: self.views.clone(),
+                link: self.view_links,
+            })
+            .filter(|views| !views.kinds.is_empty()),
+            freeze: self.freeze,
+            force: self.force,
+            limit_rate,
+            schedule,
+            proxy,
+            post_process_cmd: Some(self.post_process_cmd.trim().to_string())
+                .filter(|cmd| !cmd.is_empty()),
+            dry_run: self.dry_run && !retry_failed,
+            replay: std::env::var_os(replay::REPLAY_ENV)
+                .map(PathBuf::from)
+                .filter(|_| !retry_failed),
+            naming,
+            sections: self.sections.clone(),
+            retry_failed,
+            usage_path: Some(usage::usage_file()),
+            ..Default::default()
+        })
+    }
+
+    // With `retry_failed`, only the downloads that failed, see
+    // DownloadOptions
+    fn start_run(&mut self, retry_failed: bool) {
+        let Some(picked_path) = self.picked_path.clone() else {
+            return;
+        };
+        let output_dir = self.output_dir.clone();
+        self.run_control = Arc::new(RunControl::default());
+        self.error_breakdown.clear();
+        self.total_count = 0;
+        self.dry_run_report = None;
+        self.skip_savings = None;
+        self.duplicate_count = 0;
+        self.dedup_bytes_saved = 0;
+        self.space_estimate = None;
+        self.notifier = self.notify.then(|| Notifier::new(self.notify_error_rate));
+        let run_control = self.run_control.clone();
+        let options = match self.download_options(&picked_path, retry_failed) {
+            Ok(options) => options,
+            Err(e) => {
+                self.start_error = Some(e);
+                return;
+            }
+        };
+        if let Some(tray_target) = &self.tray_target {
+            tray_target.set_run_control(run_control.clone());
+        }
+        let events = self.event_sink();
+        std::thread::spawn(move || {
+            match run_downloader(
+                &picked_path,
+                &output_dir,
+                &options,
+                &run_control,
+                Some(&events),
+            ) {
+                Ok(_) => log_message(
+                    Some(&events),
+                    "SnapDown completed successfully.".to_string(),
+                ),
+                Err(e) => log_error(Some(&events), format!("Error running SnapDown: {}", e)),
+            }
+        });
+        self.state = SnapdownState::Downloading;
+    }
+
+    fn show_console(&mut self, ui: &mut egui::Ui) {
+        ui.heading(format!(
+            "Console Log (last 1024 messages only; see {} for full log)",
+            self.log_path.display()
+        ));
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.console_errors_only, false, "All");
+            ui.selectable_value(&mut self.console_errors_only, true, "Errors only");
+            ui.separator();
+            ui.label("Search:");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.console_search)
+                    .hint_text("e.g. 403 or a file name")
+                    .desired_width(200.0),
+            );
+            if !self.console_search.is_empty() && ui.small_button("✖").clicked() {
+                self.console_search.clear();
+            }
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("Save log as...").clicked() {
+                    self.save_console_text();
+                }
+                if ui.button("Copy all").clicked() {
+                    ui.ctx().copy_text(self.console_text());
+                }
+            });
+        });
+        ui.separator();
+
+        // Capture remaining space
+        let available = ui.available_size();
+
+        // A retry can't be started while iterating over the console, so
+        // remember it until afterwards
+        let mut record_to_retry = None;
+
+        // ----- scrollable content -----
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                ui.set_min_size(available);
+
+                let visible = self
+                    .messages_console
+                    .iter()
+                    .filter(|entry| entry.matches(self.console_errors_only, &self.console_search));
+                for entry in visible {
+                    ui.add(
+                        egui::Label::new(egui::RichText::new(&entry.message).monospace())
+                            .sense(egui::Sense::click()),
+                    )
+                    .context_menu(|ui| {
+                        if ui.button("Copy line").clicked() {
+                            ui.ctx().copy_text(entry.message.clone());
+                            ui.close();
+                        }
+                        let Some(record) = &entry.record else {
+                            return;
+                        };
+                        if let Some((_, download_url)) = record_filename_and_url(record)
+                            && ui.button("Copy URL").clicked()
+                        {
+                            ui.ctx().copy_text(download_url.to_string());
+                            ui.close();
+                        }
+                        if ui.button("Open target folder").clicked() {
+                            if let Err(e) =
+                                archive::open_in_file_manager(Path::new(&self.output_dir))
+                            {
+                                error!("Error opening {}: {}", self.output_dir, e);
+                            }
+                            ui.close();
+                        }
+                        if ui.button("Retry this file").clicked() {
+                            record_to_retry = Some(record.clone());
+                            ui.close();
+                        }
+                    });
+                }
+            });
+
+        if let Some(record) = record_to_retry {
+            self.retry_record(record);
+        }
+    }
+
+    // The console's messages as shown, filter and search included, one per
+    // line. Their links are already redacted, so they can go in a bug report.
+    fn console_text(&self) -> String {
+        self.messages_console
+            .iter()
+            .filter(|entry| entry.matches(self.console_errors_only, &self.console_search))
+            .map(|entry| format!("{}\n", entry.message))
+            .collect()
+    }
+
+    fn save_console_text(&self) {
+        let text = self.console_text();
+        let events = self.event_sink();
+        std::thread::spawn(move || {
+            if let Some(path) = rfd::FileDialog::new()
+                .set_file_name("snapdown_console.log")
+                .save_file()
+            {
+                let events: Option<&dyn EventSink> = Some(&events);
+                match fs::write(&path, text) {
+                    Ok(()) => log_message(
+                        events,
+                        format!("Saved the console log to {}", path.display()),
+                    ),
+                    Err(e) => log_error(
+                        events,
+                        format!("Error saving the console log to {}: {}", path.display(), e),
+                    ),
+                }
+            }
+        });
+    }
+
+    fn receive_from_downloader(&mut self) {
+        // The window wasn't drawn for a while, e.g. it was minimized
+        let dropped = self.recv_logs_from_downloader.take_dropped();
+        if dropped > 0 {
+            self.messages_console.push_back(LogEntry {
+                level: log::Level::Warn,
+                message: format!(
+                    "({} earlier messages left out, the full log is in {})",
+                    format::format_count(dropped),
+                    LOG_FILE
+                ),
+                record: None,
+            });
+        }
+        self.messages_console
+            .extend(self.recv_logs_from_downloader.try_iter());
+        for event in self.file_events.take() {
+            // From the files' events, which are all kept, unlike the lines
+            // for the console
+            if let DownloadEvent::Failed { error, .. } = &event {
+                *self
+                    .error_breakdown
+                    .entry(snapshot::error_kind(error))
+                    .or_default() += 1;
+            }
+            self.file_list.apply(event);
+        }
+    }
+
+    // A table of the run's files, sorted by clicking the column headers
+    fn show_file_list(&mut self, ui: &mut egui::Ui) {
+        let (sort_column, ascending) = self.file_list.sort_column();
+        let mut clicked_column = None;
+        // Same as in the console, a retry is started afterwards
+        let mut record_to_retry = None;
+        let row_height = ui.text_style_height(&TextStyle::Monospace) + 4.0;
+        let row_count = self.file_list.row_count();
+        TableBuilder::new(ui)
+            .striped(true)
+            .auto_shrink([false, false])
+            .sense(egui::Sense::click())
+            .column(Column::auto().at_least(100.0))
+            .column(Column::auto().at_least(60.0))
+            .column(Column::initial(360.0).resizable(true).clip(true))
+            .column(Column::auto().at_least(90.0))
+            .column(Column::remainder())
+            .header(row_height + 4.0, |mut header| {
+                for (column, label) in [
+                    (FileColumn::Timestamp, "Taken"),
+                    (FileColumn::MediaType, "Type"),
+                    (FileColumn::Filename, "File"),
+                    (FileColumn::Status, "Status"),
+                    (FileColumn::Size, "Size"),
+                ] {
+                    header.col(|ui| {
+                        let arrow = match (sort_column == column, ascending) {
+                            (false, _) => "",
+                            (true, true) => " ⬆",
+                            (true, false) => " ⬇",
+                        };
+                        if ui
+                            .selectable_label(sort_column == column, format!("{}{}", label, arrow))
+                            .clicked()
+                        {
+                            clicked_column = Some(column);
+                        }
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(row_height, row_count, |mut row| {
+                    let Some(file) = self.file_list.sorted_row(row.index()) else {
+                        return;
+                    };
+                    row.col(|ui| {
+                        ui.monospace(&file.timestamp);
+                    });
+                    row.col(|ui| {
+                        ui.label(&file.media_type);
+                    });
+                    row.col(|ui| {
+                        ui.monospace(&file.filename);
+                    });
+                    row.col(|ui| {
+                        if file.status == FileStatus::Failed {
+                            ui.colored_label(Color32::RED, file.status.label());
+                        } else {
+                            ui.label(file.status.label());
+                        }
+                    });
+                    row.col(|ui| {
+                        if let Some(size) = file.size {
+                            ui.label(format::format_bytes(size));
+                        }
+                    });
+                    row.response().context_menu(|ui| {
+                        if let Some((_, download_url)) = record_filename_and_url(&file.record)
+                            && ui.button("Copy URL").clicked()
+                        {
+                            ui.ctx().copy_text(download_url.to_string());
+                            ui.close();
+                        }
+                        if ui.button("Open target folder").clicked() {
+                            if let Err(e) =
+                                archive::open_in_file_manager(Path::new(&self.output_dir))
+                            {
+                                error!("Error opening {}: {}", self.output_dir, e);
+                            }
+                            ui.close();
+                        }
+                        if file.status == FileStatus::Failed
+                            && ui.button("Retry this file").clicked()
+                        {
+                            record_to_retry = Some(file.record.clone());
+                            ui.close();
+                        }
+                    });
+                });
+            });
+
+        if let Some(column) = clicked_column {
+            self.file_list.sort_by(column);
+        }
+        if let Some(record) = record_to_retry {
+            self.retry_record(record);
+        }
+    }
+
+    // Download a single record again on a background thread. The result
+    // comes back through recv_retry_results.
+    fn retry_record(&self, record: csv::StringRecord) {
+        let resolve_links = self.resolve_links;
+        let write_exif = self.write_exif;
+        let touch = self.touch;
+        let composite_overlays = self.composite_overlays;
+        let auto_rotate = self.auto_rotate;
+        let sidecars = self.sidecars;
+        let dedup_mode = self.dedup;
+        let post_processor = Some(self.post_process_cmd.trim())
+            .filter(|cmd| !cmd.is_empty())
+            .map(|cmd| PostProcessor::new(cmd, 1));
+        let events = self.event_sink();
+        let proxy = match self.parsed_proxy() {
+            Ok(proxy) => proxy,
+            Err(e) => {
+                log_error(Some(&events), format!("Error retrying: {}", e));
+                return;
+            }
+        };
+        let agent = DownloadOptions {
+            proxy,
+            ..Default::default()
+        }
+        .http_agent();
+        let send_retry_results_clone = self.send_retry_results.clone();
+        let output_dir = self.output_dir.clone();
+        // The run may have given it a different name than its own, if it had
+        // the same as another record's
+        let run_filename = self.file_list.filename(&record).map(str::to_string);
+        std::thread::spawn(move || {
+            let events: Option<&dyn EventSink> = Some(&events);
+            let filename_and_url = record_filename_and_url(&record).map(|(filename, url)| {
+                (
+                    run_filename
+                        .filter(|name| !name.is_empty())
+                        .unwrap_or(filename),
+                    url,
+                )
+            });
+            if let Some((filename, _)) = &filename_and_url {
+                log_message(events, format!("Retrying {}...", filename));
+            }
+            let manifest = match Manifest::open_without_compacting(Path::new(&output_dir)) {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    log_error(
+                        events,
+                        format!("Error opening {}: {}", manifest::MANIFEST_FILE, e),
+                    );
+                    return;
+                }
+            };
+            let dedup = dedup_mode.and_then(|mode| {
+                DedupIndex::open(Path::new(&output_dir), mode)
+                    .inspect_err(|e| {
+                        log_error(
+                            events,
+                            format!("Error opening {}: {}", dedup::DEDUP_INDEX_FILE, e),
+                        )
+                    })
+                    .ok()
+            });
+            let download_context = DownloadContext {
+                output_dir: &output_dir,
+                storage: &LocalDir::new(&output_dir),
+                http: &agent,
+                rate_limiter: None,
+                http_debug_log: None,
+                resolve_links,
+                write_exif,
+                touch,
+                composite_overlays,
+                auto_rotate,
+                sidecars,
+                force: false,
+                post_processor: post_processor.as_ref(),
+                uploader: None,
+                dedup: dedup.as_ref(),
+                host_stats: &HostStatsCollector::default(),
+                etag_hosts: &EtagHosts::default(),
+                network: &NetworkMonitor::default(),
+                backoff: &Backoff::new(1),
+                retries: 0,
+                bytes_downloaded: &AtomicU64::new(0),
+                bytes_skipped: &AtomicU64::new(0),
+                manifest: &manifest,
+                control: &RunControl::default(),
+                events,
+            };
+            let outcome =
+                download_record(&record, filename_and_url.as_ref(), None, &download_context);
+            let result = match outcome {
+                DownloadOutcome::Downloaded => {
+                    log_message(events, "Retry succeeded.".to_string());
+                    Ok(())
+                }
+                DownloadOutcome::Skipped => {
+                    log_message(events, "File already exists; nothing to retry.".to_string());
+                    Ok(())
+                }
+                DownloadOutcome::Invalid => Err(INVALID_RECORD_ERROR.to_string()),
+                DownloadOutcome::Failed(_, error) => Err(error),
+                DownloadOutcome::Cancelled => Err("Cancelled".to_string()),
+            };
+            send_retry_results_clone
+                .send((record, result))
+                .unwrap_or_else(|e| {
+                    error!("Error sending retry result to GUI: {}", e);
+                });
+        });
+    }
+
+    fn show_browse_archive_tab(&mut self, ui: &mut egui::Ui) {
+        self.image_sizes.extend(self.recv_image_sizes.try_iter());
+        for (scan, entries) in self.recv_not_downloaded.try_iter() {
+            if scan == self.archive_scan {
+                self.archive_entries.extend(entries);
+                self.listing_not_downloaded = false;
+            }
+        }
+
+        ////////////////////////////////////////////////////////////////////////
+        // Search Section
+        ////////////////////////////////////////////////////////////////////////
+        egui::Grid::new("archive_query_grid")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("From date (YYYY-MM-DD):");
+                ui.text_edit_singleline(&mut self.archive_query.date_from);
+                ui.end_row();
+
+                ui.label("To date (YYYY-MM-DD):");
+                ui.text_edit_singleline(&mut self.archive_query.date_to);
+                ui.end_row();
+
+                ui.label("Media type:");
+                let selected_label = match self.archive_query.media_kind {
+                    Some(kind) => kind.label(),
+                    None => "All",
+                };
+                egui::ComboBox::from_id_salt("archive_media_kind")
+                    .selected_text(selected_label)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.archive_query.media_kind, None, "All");
+                        for kind in [MediaKind::Image, MediaKind::Video, MediaKind::Other] {
+                            ui.selectable_value(
+                                &mut self.archive_query.media_kind,
+                                Some(kind),
+                                kind.label(),
+                            );
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Place contains:");
+                ui.text_edit_singleline(&mut self.archive_query.place);
+                ui.end_row();
+            });
+
+        ui.horizontal(|ui| {
+            if ui.button("Scan output folder").clicked() {
+                match archive::scan_archive(Path::new(&self.output_dir)) {
+                    Ok(entries) => {
+                        info!(
+                            "Found {} files in {} for browsing",
+                            entries.len(),
+                            self.output_dir
+                        );
+                        self.archive_scan += 1;
+                        self.listing_not_downloaded = false;
+                        if self.sample_image_sizes {
+                            self.list_not_downloaded_entries(ui.ctx().clone());
+                        }
+                        self.archive_entries = entries;
+                        self.archive_error = None;
+                        self.image_sizes.clear();
+                    }
+                    Err(e) => {
+                        error!("Error scanning {}: {}", self.output_dir, e);
+                        self.archive_scan += 1;
+                        self.listing_not_downloaded = false;
+                        self.archive_entries.clear();
+                        self.archive_error =
+                            Some(format!("Could not read {}: {}", self.output_dir, e));
+                    }
+                }
+            }
+            if is_local_dir(&self.output_dir) && ui.button("Open output folder").clicked() {
+                self.open_output_folder();
+            }
+        });
+        ui.checkbox(
+            &mut self.sample_image_sizes,
+            "Also list memories not downloaded yet, and get their image sizes (an extra request each)",
+        );
+
+        if let Some(archive_error) = &self.archive_error {
+            ui.colored_label(Color32::RED, archive_error);
+        }
+        if self.listing_not_downloaded {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Reading the memories not downloaded yet...");
+            });
+        }
+
+        ////////////////////////////////////////////////////////////////////////
+        // Results Section
+        ////////////////////////////////////////////////////////////////////////
+        let matching: Vec<&ArchiveEntry> = self
+            .archive_entries
+            .iter()
+            .filter(|entry| self.archive_query.matches(entry))
+            .collect();
+        ui.label(format!(
+            "{} of {} files match",
+            format::format_count(matching.len()),
+            format::format_count(self.archive_entries.len())
+        ));
+        ui.separator();
+
+        let row_height = ui.text_style_height(&TextStyle::Monospace) + 12.0;
+        // Only the rows on screen have their sizes looked up
+        let mut to_sample = Vec::new();
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .show_rows(ui, row_height, matching.len(), |ui, row_range| {
+                for entry in &matching[row_range] {
+                    ui.horizontal(|ui| {
+                        if entry.url.is_some() {
+                            ui.label("Not downloaded");
+                        } else if ui.small_button("Open").clicked()
+                            && let Err(e) = archive::open_in_file_manager(&entry.path)
+                        {
+                            error!("Error opening {:?}: {}", entry.path, e);
+                        }
+                        ui.label(entry.media_kind.label());
+                        ui.monospace(&entry.file_name);
+                        if entry.media_kind != MediaKind::Image {
+                            return;
+                        }
+                        let size =
+                            match &entry.url {
+                                // Reading the start of a local file is quick
+                                // enough to do right away
+                                None => *self.image_sizes.entry(entry.path.clone()).or_insert_with(
+                                    || {
+                                        dimensions::read_local(&entry.path).unwrap_or_else(|e| {
+                                            error!("Error reading {:?}: {}", entry.path, e);
+                                            None
+                                        })
+                                    },
+                                ),
+                                Some(url) => match self.image_sizes.get(&entry.path) {
+                                    Some(size) => *size,
+                                    None => {
+                                        self.image_sizes.insert(entry.path.clone(), None);
+                                        to_sample.push((entry.path.clone(), url.clone()));
+                                        None
+                                    }
+                                },
+                            };
+                        if let Some(size) = size {
+                            ui.label(size.label());
+                        }
+                    });
+                }
+            });
+        if !to_sample.is_empty() {
+            self.sample_remote_image_sizes(to_sample, ui.ctx().clone());
+        }
+    }
+
+    // The memories in the picked input file that aren't in the output
+    // folder, read on a background thread as the input can be a big zip. They
+    // come back through recv_not_downloaded.
+    fn list_not_downloaded_entries(&mut self, ctx: egui::Context) {
+        let Some(picked_path) = self.picked_path.clone() else {
+            return;
+        };
+        self.listing_not_downloaded = true;
+        let scan = self.archive_scan;
+        let output_dir = self.output_dir.clone();
+        let send_not_downloaded_clone = self.send_not_downloaded.clone();
+        std::thread::spawn(move || {
+            let entries = match read_input_records(&picked_path, None) {
+                Ok(records) => archive::not_downloaded_entries(&records, Path::new(&output_dir)),
+                Err(e) => {
+                    error!("Error reading {}: {}", picked_path, e);
+                    Vec::new()
+                }
+            };
+            if send_not_downloaded_clone.send((scan, entries)).is_ok() {
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    // Get the sizes of images that aren't downloaded yet on a background
+    // thread. They come back through recv_image_sizes.
+    fn sample_remote_image_sizes(&mut self, to_sample: Vec<(PathBuf, String)>, ctx: egui::Context) {
+        let resolve_links = self.resolve_links;
+        let proxy = match self.parsed_proxy() {
+            Ok(proxy) => proxy,
+            Err(e) => {
+                self.archive_error = Some(format!("Can't get image sizes. {}", e));
+                return;
+            }
+        };
+        let agent = DownloadOptions {
+            proxy,
+            ..Default::default()
+        }
+        .http_agent();
+        let send_image_sizes_clone = self.send_image_sizes.clone();
+        std::thread::spawn(move || {
+            for (path, download_url) in to_sample {
+                let sample = |download_url: &str| {
+                    if resolve_links {
+                        dimensions::sample_remote(
+                            &agent,
+                            &resolve_download_url(&agent, download_url)?,
+                        )
+                    } else {
+                        dimensions::sample_remote(&agent, download_url)
+                    }
+                };
+                let size = sample(&download_url).unwrap_or_else(|e| {
+                    warn!("Error getting the image size of {:?}: {}", path, e);
+                    None
+                });
+                if send_image_sizes_clone.send((path, size)).is_err() {
+                    return;
+                }
+                ctx.request_repaint();
+            }
+        });
+    }
+}
+
+// The proxy field of the GUI, None if it's empty
+fn parse_proxy(text: &str) -> Result<Option<ureq::Proxy>, String> {
+    match text.trim() {
+        "" => Ok(None),
+        proxy => ureq::Proxy::new(proxy)
+            .map(Some)
+            .map_err(|e| format!("Proxy: {}", e)),
+    }
+}
+
+const DEFAULT_NUM_JOBS: usize = 500;
+// How long an unused connection is kept open for the next download
+const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+const USER_AGENT: &str = concat!("snapdown/", env!("CARGO_PKG_VERSION"));
+const DEFAULT_OUTPUT_DIR: &str = "snapdown_output";
+// SnapDown's own log, see install::data_file() for where it is
+const LOG_FILE: &str = "snapdown.log";
+
+// Settings for a single run_downloader() call
+struct DownloadOptions {
+    jobs: usize,
+    // Record request/response details of failed downloads in http_debug.log
+    debug_http: bool,
+    // Keep the signed parts of download links in the logs and report, see
+    // redact.rs
+    log_full_urls: bool,
+    // Stop the run at the first failed download, showing all there is to
+    // know about it. Needs debug_http for the response's details.
+    fail_fast: bool,
+    // Get a fresh link with the POST the Snapchat web page does, before
+    // downloading
+    resolve_links: bool,
+    // Embed the capture time and location in downloaded JPEGs
+    write_exif: bool,
+    // Set the files' modified times to when the memories were taken
+    touch: bool,
+    // Draw caption/sticker overlays onto photos, instead of saving them as
+    // separate <name>_overlay.png files
+    composite_overlays: bool,
+    // Rotate photos to be upright without their EXIF orientation
+    auto_rotate: bool,
+    // Write a Takeout style <name>.json next to each file, see sidecar.rs
+    sidecars: bool,
+    // What to do with files that have the same content as an earlier one
+    dedup: Option<DedupMode>,
+    // Folders of links to build after the run, see views.rs
+    views: Option<ViewSettings>,
+    // Seal the archive if the run downloads everything, see freeze.rs
+    freeze: bool,
+    // Download every file again, whatever the manifest says
+    force: bool,
+    // Download speed limit for the whole run, in bytes per second
+    limit_rate: Option<u64>,
+    // Hours to download in, paused the rest of the time, see schedule.rs
+    schedule: Option<Schedule>,
+    // HTTP timeouts, ureq's defaults (none) if not set
+    connect_timeout: Option<Duration>,
+    response_timeout: Option<Duration>,
+    // How many times a request that failed with a connection error, a timeout
+    // or a server error is tried again before the record counts as failed,
+    // see retry_request()
+    retries: usize,
+    // Connections kept open to each server for the next downloads, so they
+    // don't each connect and do a TLS handshake again. As many as there are
+    // jobs if not set.
+    pool_size: Option<usize>,
+    pool_idle_timeout: Option<Duration>,
+    // Only report what would be downloaded, see dry_run.rs
+    dry_run: bool,
+    // Command to run on each downloaded file, see post_process.rs
+    post_process_cmd: Option<String>,
+    // Server to upload each downloaded file to, see upload.rs
+    upload: Option<UploadSettings>,
+    // Bucket to store the files in instead of the output directory, see
+    // s3.rs
+    s3: Option<S3Settings>,
+    // The same for a WebDAV folder, see webdav.rs
+    webdav: Option<WebDavSettings>,
+    // How to check there's space for the files first, see space_check.rs
+    space_check: SpaceCheck,
+    // Proxy to download through. If not set, ureq uses the one in
+    // ALL_PROXY/HTTPS_PROXY/HTTP_PROXY, if any.
+    proxy: Option<ureq::Proxy>,
+    // Where to write the run's report instead of the output directory, see
+    // report.rs
+    report_path: Option<PathBuf>,
+    // The file to add the run's downloads to, usage::usage_file() but in
+    // tests, whose runs aren't the user's. None doesn't count them.
+    usage_path: Option<PathBuf>,
+    // Counted for /metrics of --status-port, see metrics.rs
+    metrics: Option<Arc<Metrics>>,
+    // Makes the requests instead of clients with the settings above, a
+    // MockFetch in tests, see fetch.rs
+    http: Option<Arc<dyn Fetch>>,
+    // How long without any progress before logging what the downloads are
+    // doing, see watchdog.rs
+    stall_timeout: Option<Duration>,
+    // Then stop the run, for the CLI to start it again
+    restart_stalled: bool,
+    // Play back this report instead of downloading, see replay.rs
+    replay: Option<PathBuf>,
+    replay_speed: f64,
+    // How the files are named, see naming.rs
+    naming: Naming,
+    // The parts of an export zip to download, see sections.rs
+    sections: Vec<Section>,
+    // The broken files verify --repair moved into quarantine, to keep or put
+    // back once the run is over
+    quarantined: Vec<QuarantinedFile>,
+    // Only download the failed files in the manifest again, instead of the
+    // records of the input
+    retry_failed: bool,
+}
+
+impl DownloadOptions {
+    fn pool_size(&self) -> usize {
+        self.pool_size.unwrap_or(self.jobs)
+    }
+
+    fn pool_idle_timeout(&self) -> Duration {
+        self.pool_idle_timeout.unwrap_or(DEFAULT_POOL_IDLE_TIMEOUT)
+    }
+
+    // Agent with the timeouts, connection pool and proxy, shared by all of a
+    // run's requests
+    fn http_agent(&self) -> ureq::Agent {
+        let mut config = ureq::Agent::config_builder()
+            .timeout_connect(self.connect_timeout)
+            .timeout_recv_response(self.response_timeout)
+            .max_idle_connections(self.pool_size())
+            .max_idle_connections_per_host(self.pool_size())
+            .max_idle_age(self.pool_idle_timeout())
+            .user_agent(USER_AGENT);
+        if let Some(proxy) = &self.proxy {
+            config = config.proxy(Some(proxy.clone()));
+        }
+        config.build().into()
+    }
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions {
+            jobs: DEFAULT_NUM_JOBS,
+            debug_http: false,
+            log_full_urls: false,
+            fail_fast: false,
+            resolve_links: false,
+            write_exif: true,
+            touch: true,
+            composite_overlays: false,
+            auto_rotate: false,
+            sidecars: false,
+            dedup: None,
+            views: None,
+            freeze: false,
+            force: false,
+            limit_rate: None,
+            schedule: None,
+            connect_timeout: None,
+            response_timeout: None,
+            retries: 0,
+            pool_size: None,
+            pool_idle_timeout: None,
+            proxy: None,
+            post_process_cmd: None,
+            upload: None,
+            s3: None,
+            webdav: None,
+            space_check: SpaceCheck::Sample,
+            dry_run: false,
+            report_path: None,
+            usage_path: None,
+            metrics: None,
+            http: None,
+            stall_timeout: None,
+            restart_stalled: false,
+            replay: None,
+            replay_speed: 1.0,
+            naming: Naming::Legacy,
+            sections: vec![Section::Memories],
+            quarantined: Vec::new(),
+            retry_failed: false,
+        }
+    }
+}
+
+// The arguments of a download, checked and with anything missing asked for
+struct Args {
+    input_csv: String,
+    // The parts of an export zip to download, see sections.rs
+    sections: Vec<Section>,
+    output_dir: String,
+    jobs: usize,
+    export_failures: Option<String>,
+    report: Option<String>,
+    status_port: Option<u16>,
+    debug_http: bool,
+    log_full_urls: bool,
+    fail_fast: bool,
+    resolve_links: bool,
+    write_exif: bool,
+    touch: bool,
+    composite_overlays: bool,
+    auto_rotate: bool,
+    sidecars: bool,
+    dedup: Option<DedupMode>,
+    views: Option<ViewSettings>,
+    freeze: bool,
+    force: bool,
+    limit_rate: Option<u64>,
+    schedule: Option<Schedule>,
+    connect_timeout: Option<Duration>,
+    response_timeout: Option<Duration>,
+    retries: usize,
+    pool_size: Option<usize>,
+    pool_idle_timeout: Option<Duration>,
+    proxy: Option<ureq::Proxy>,
+    post_process_cmd: Option<String>,
+    upload: Option<UploadSettings>,
+    s3: Option<S3Settings>,
+    webdav: Option<WebDavSettings>,
+    space_check: SpaceCheck,
+    dry_run: bool,
+    stall_timeout: Option<Duration>,
+    restart_stalled: bool,
+    replay: Option<String>,
+    replay_speed: f64,
+    naming: Naming,
+    email: Option<EmailSettings>,
+    allow_mixed_archives: bool,
+    // Zip to write the logs and report to after the run, see
+    // support_bundle.rs
+    support_bundle: Option<String>,
+    // Moved out of the way by verify --repair, see quarantine.rs
+    quarantined: Vec<QuarantinedFile>,
+    // Without an input, clap makes sure of that
+    retry_failed: bool,
+    // The --notify-error-rate, with --notify
+    notify: Option<f64>,
+    // Only print the settings, see config.rs
+    print_config: bool,
+}
+
+// What to do, going by the command line
+enum Mode {
+    Gui,
+    Download(Box<Args>),
+    Parse(cli::ParseArgs),
+    Verify(cli::VerifyArgs),
+    Diff(cli::DiffArgs),
+}
+
+fn parse_args() -> Result<Mode> {
+    let args = cli::webdav_output_args(cli::upgrade_legacy_args(std::env::args_os().collect()));
+    let cli = cli::Cli::try_parse_from(&args).unwrap_or_else(|e| exit_with(e));
+    if cli.help_all {
+        let program_name = args[0].to_string_lossy();
+        print!("{}", help::long_help(&program_name));
+        std::process::exit(0);
+    }
+    Ok(match cli.command {
+        None | Some(cli::Command::Gui) => Mode::Gui,
+        Some(cli::Command::Download(args)) => Mode::Download(Box::new(download_args(*args)?)),
+        Some(cli::Command::Parse(args)) => Mode::Parse(args),
+        Some(cli::Command::Verify(args)) => Mode::Verify(args),
+        Some(cli::Command::Diff(args)) => Mode::Diff(args),
+    })
+}
+
+// Like clap::Error::exit, but with exit status 1 for bad arguments like
+// before, as 2 means the disk is full
+fn exit_with(error: clap::Error) -> ! {
+    let _ = error.print();
+    std::process::exit(if error.use_stderr() { 1 } else { 0 })
+}
+
+fn download_args(mut args: cli::DownloadArgs) -> Result<Args> {
+    // Errors look the same as clap's own
+    let exit_with_error = |kind, message: String| -> ! {
+        exit_with(
+            cli::Cli::command()
+                .find_subcommand_mut("download")
+                .unwrap()
+                .error(kind, message),
+        )
+    };
+
+    if let Some(name) = &args.preset {
+        let preset = if presets::is_config_file(name) {
+            presets::load_config(Path::new(name))
+        } else {
+            presets::load(&presets::presets_file()).and_then(|presets| {
+                let preset = presets::find(&presets, name)?.clone();
+                Ok(preset)
+            })
+        };
+        let preset = preset.unwrap_or_else(|e| {
+            exit_with_error(
+                clap::error::ErrorKind::ValueValidation,
+                format!("invalid --preset: {:#}", e),
+            )
+        });
+        presets::apply_to_args(&preset, &mut args)?;
+    }
+
+    let email = args.smtp.map(|smtp_url| {
+        let email_to = args.email_to.unwrap_or_default();
+        EmailSettings::new(&smtp_url, &email_to, args.email_from.as_deref()).unwrap_or_else(|e| {
+            exit_with_error(
+                clap::error::ErrorKind::ValueValidation,
+                format!("invalid email settings: {}", e),
+            )
+        })
+    });
+    // clap makes sure --server and --api-key are there
+    let upload = args.upload.map(|target| UploadSettings {
+        target,
+        server: args.server.unwrap_or_default(),
+        api_key: args.api_key.unwrap_or_default(),
+        upload_only: args.upload_only,
+    });
+    let s3 = args.s3_bucket.map(|bucket| S3Settings {
+        bucket,
+        endpoint: args.s3_endpoint,
+        region: args.s3_region,
+        prefix: args.s3_prefix.unwrap_or_default(),
+    });
+    let webdav = args.webdav.map(|address| {
+        WebDavSettings::parse(&address).unwrap_or_else(|e| {
+            exit_with_error(
+                clap::error::ErrorKind::ValueValidation,
+                format!("invalid --webdav address {}: {}", address, e),
+            )
+        })
+    });
+    // Each view once, in the order given
+    let mut view_kinds = Vec::new();
+    for kind in args.views {
+        if !view_kinds.contains(&kind) {
+            view_kinds.push(kind);
+        }
+    }
+    let mut sections = args.sections;
+    sections.sort();
+    sections.dedup();
+    if sections.is_empty() {
+        sections.push(Section::Memories);
+    }
+    let views = (!view_kinds.is_empty()).then(|| ViewSettings {
+        kinds: view_kinds,
+        link: args.view_links.unwrap_or(LinkKind::HardLink),
+    });
+
+    // In a terminal, ask for whichever of -i and -o is missing
+    let interactive = prompt::can_prompt();
+    let input_csv = match args.input {
+        Some(input_csv) => input_csv,
+        None if args.retry_failed => String::new(),
+        None if interactive => prompt::prompt_input_file()?.unwrap_or_else(|| {
+            std::process::exit(1);
+        }),
+        None => exit_with_error(
+            clap::error::ErrorKind::MissingRequiredArgument,
+            "missing -i <INPUT>".to_string(),
+        ),
+    };
+    let output_dir = match args.output_dir {
+        Some(output_dir) => output_dir,
+        // Only for the manifest, report and logs
+        None if webdav.is_some() => DEFAULT_OUTPUT_DIR.to_string(),
+        None if interactive => {
+            prompt::prompt_output_dir(DEFAULT_OUTPUT_DIR)?.unwrap_or_else(|| {
+                std::process::exit(1);
+            })
+        }
+        None => exit_with_error(
+            clap::error::ErrorKind::MissingRequiredArgument,
+            "missing -o <OUTPUT_DIR>".to_string(),
+        ),
+    };
+
+    Ok(Args {
+        input_csv,
+        output_dir,
+        jobs: args.jobs,
+        export_failures: args.export_failures,
+        report: args.report,
+        status_port: args.status_port,
+        debug_http: args.debug_http,
+        log_full_urls: args.log_full_urls,
+        fail_fast: args.fail_fast,
+        resolve_links: args.resolve_links,
+        write_exif: !args.no_exif,
+        touch: !args.no_touch,
+        composite_overlays: args.composite_overlays,
+        auto_rotate: args.auto_rotate,
+        sidecars: args.sidecars,
+        dedup: args.dedup,
+        views,
+        sections,
+        freeze: args.freeze,
+        force: args.force,
+        limit_rate: args.limit_rate,
+        schedule: args.schedule,
+        connect_timeout: args.connect_timeout,
+        response_timeout: args.response_timeout,
+        retries: args.retries,
+        pool_size: args.pool_size,
+        pool_idle_timeout: args.pool_idle_timeout,
+        proxy: args.proxy,
+        post_process_cmd: args.post_process_cmd,
+        upload,
+        s3,
+        webdav,
+        space_check: args.space_check,
+        dry_run: args.dry_run,
+        stall_timeout: args.stall_timeout,
+        restart_stalled: args.restart_stalled,
+        replay: args.replay,
+        replay_speed: args.replay_speed,
+        naming: args
+            .naming
+            .unwrap_or_default()
+            .with_places(args.places.as_deref())?,
+        email,
+        allow_mixed_archives: args.allow_mixed_archives,
+        support_bundle: args.support_bundle,
+        print_config: args.print_config,
+        quarantined: Vec::new(),
+        retry_failed: args.retry_failed,
+        notify: args.notify.then_some(args.notify_error_rate),
+    })
+}
+
+// Unless `full_urls`, the signed parts of download links are left out of
+// the log, see redact.rs
+fn init_logging(full_urls: bool) {
+    let log_path = install::data_file(LOG_FILE);
+    let file = match OpenOptions::new().create(true).append(true).open(&log_path) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("Error opening log file {}: {}", log_path.display(), e);
+            std::process::exit(1);
+        }
+    };
+
+    // Set all dependencies to log at error, and all snapdown logs to info
+    // Pipe the output to the log file
+    Builder::from_env(Env::new().filter_or("SNAPDOWN_LOG", "error,snapdown=info"))
+        .target(env_logger::Target::Pipe(Box::new(file)))
+        .format(move |buf, record| {
+            let message = record.args().to_string();
+            writeln!(
+                buf,
+                "[{}][{}] {}",
+                record.level(),
+                record.target(),
+                if full_urls {
+                    message
+                } else {
+                    redact::redact_tokens(&message)
+                }
+            )
+        })
+        .init();
+}
+
+fn main() -> Result<()> {
+    let mode = parse_args()?;
+
+    init_logging(matches!(&mode, Mode::Download(args) if args.log_full_urls));
+
+    match mode {
+        Mode::Gui => {
+            info!(
+                "[{}] Starting SnapDown (GUI mode)...",
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+            );
+            run_gui()
+        }
+        Mode::Download(args) => run_cli_download(*args),
+        Mode::Parse(args) => run_parse(args),
+        Mode::Verify(args) => run_verify(args),
+        Mode::Diff(args) => run_diff(args),
+    }
+}
+
+fn run_cli_download(mut args: Args) -> Result<()> {
+    if args.print_config {
+        print!("{}", cli_effective_config(&args).to_toml()?);
+        return Ok(());
+    }
+    {
+        if !args.retry_failed
+            && let Err(e) = input::check_input_file(Path::new(&args.input_csv))
+        {
+            eprintln!("Error: {}", e);
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        info!(
+            "[{}] Starting SnapDown (CLI mode)...",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+        );
+        // Keep another account's export out of an existing archive
+        if !args.allow_mixed_archives
+            && !args.retry_failed
+            && let Ok(identity) = ExportIdentity::from_input_file(Path::new(&args.input_csv))
+        {
+            let output_dir = Path::new(&args.output_dir);
+            if let ArchiveCheck::DifferentAccount { existing } =
+                identity::check_archive(output_dir, &identity)
+            {
+                let subfolder = identity::export_subfolder(output_dir, &identity);
+                let message = format!(
+                    "Warning: {} already contains an export from a different account (exported {}). \
+                     Downloading into {} instead to avoid filename clashes. \
+                     Use --allow-mixed-archives to download into {} anyway.",
+                    args.output_dir,
+                    existing.export_date,
+                    subfolder.display(),
+                    args.output_dir
+                );
+                eprintln!("{}", message);
+                warn!("{}", message);
+                args.output_dir = subfolder.display().to_string();
+            }
+        }
+        info!("Input CSV: {}", args.input_csv);
+        info!("Output directory: {}", args.output_dir);
+        info!("Parallel jobs: {}", args.jobs);
+        // Only with --status-port, which serves them
+        let metrics = args.status_port.map(|_| Arc::new(Metrics::default()));
+        // The options go into the DownloadOptions, the counts come after
+        // the run
+        let support_bundle = args.support_bundle.as_ref().map(|path| {
+            (
+                PathBuf::from(path),
+                cli_snapshot_config(&args),
+                support_bundle_files(&args.output_dir, args.report.as_deref()),
+            )
+        });
+        let options = DownloadOptions {
+            metrics: metrics.clone(),
+            ..cli_download_options(&args)
+        };
+        // Ctrl+C cancels gracefully, pressing it again exits immediately
+        let control = Arc::new(RunControl::default());
+        let handler_control = control.clone();
+        if let Err(e) = ctrlc::set_handler(move || {
+            if handler_control.is_cancelled() {
+                std::process::exit(130);
+            }
+            eprintln!("Cancelling... (press Ctrl+C again to exit immediately)");
+            info!("Received Ctrl+C, cancelling run");
+            handler_control.cancel();
+        }) {
+            error!("Error setting Ctrl+C handler: {}", e);
+        }
+
+        let status_server = match args.status_port {
+            Some(port) => {
+                let server = StatusServer::start(port, metrics)?;
+                println!(
+                    "Status page at http://localhost:{}/ (or this computer's address from other devices)",
+                    server.port()
+                );
+                Some(server)
+            }
+            None => None,
+        };
+        let (send_status, recv_status) =
+            gui_channel::bounded::<SnapdownStatus>(gui_channel::STATUS_CAPACITY);
+        let notifier = args.notify.map(Notifier::new);
+        let progress_thread =
+            std::thread::spawn(move || show_cli_progress(recv_status, status_server, notifier));
+        let events = CliSink {
+            statuses: send_status,
+        };
+        let mut restarts = 0;
+        let status = loop {
+            let result = run_downloader(
+                &args.input_csv,
+                &args.output_dir,
+                &options,
+                &control,
+                Some(&events),
+            );
+            let status = match result {
+                Ok(status) => status,
+                Err(e) => {
+                    // Not being able to start is worth asking about too,
+                    // with the error in the bundle's log
+                    log_error(None, format!("Error: {}", e));
+                    if let Some((path, config, files)) = support_bundle {
+                        let snapshot = cli_progress_snapshot(config, None);
+                        write_support_bundle(&path, &snapshot, &files, None);
+                    }
+                    return Err(e);
+                }
+            };
+            if status.stop_reason != Some(StopReason::Stalled)
+                || restarts == watchdog::MAX_STALL_RESTARTS
+            {
+                break status;
+            }
+            // The partial files are continued like after a cancel
+            restarts += 1;
+            log_message(
+                None,
+                format!(
+                    "Starting the run again ({} of {})",
+                    restarts,
+                    watchdog::MAX_STALL_RESTARTS
+                ),
+            );
+            control.restart();
+        };
+        drop(events);
+        progress_thread.join().unwrap_or_else(|_| {
+            error!("Progress bar thread panicked");
+        });
+        if let Some(export_path) = &args.export_failures
+            && !status.failed_records.is_empty()
+        {
+            export_failures(Path::new(export_path), &status.failed_records, None);
+        }
+        // In full, to paste into a file manager or cd to
+        if status.dry_run_report.is_none() {
+            println!(
+                "Output folder: {}",
+                resolve_output_dir(&args.output_dir).display()
+            );
+        }
+        if status.error_count > 0 && status.stop_reason.is_none() {
+            eprintln!(
+                "Failed downloads: {}. To try only those again: snapdown download --retry-failed -o {}",
+                format::format_count(status.error_count),
+                args.output_dir
+            );
+        }
+        if let Some(report) = &status.dry_run_report {
+            for line in report.lines() {
+                println!("{}", line);
+            }
+        }
+        if let Some(email) = &args.email {
+            send_summary_email(email, &args.input_csv, &args.output_dir, &status);
+        }
+        if args.notify.is_some()
+            && let Some(notification) = status.notify_finished()
+        {
+            let _ = notification.join();
+        }
+        if let Some((path, config, files)) = support_bundle {
+            let snapshot = cli_progress_snapshot(config, Some(&status));
+            write_support_bundle(&path, &snapshot, &files, None);
+        }
+        if let Some(reason) = status.stop_reason {
+            eprintln!("Stopped: {}", reason);
+            std::process::exit(reason.exit_code());
+        }
+        Ok(())
+    }
+}
+
+// `snapdown parse`: write the records of any input as a snap_export.csv or
+// JSON, for looking at them or using them in other tools
+fn run_parse(args: cli::ParseArgs) -> Result<()> {
+    if let Err(e) = input::check_input_file(Path::new(&args.input)) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    let records = read_input_records(&args.input, None)?;
+    let (format, output) = args.output();
+    let written = match (format, output) {
+        (cli::ParseFormat::Csv, "-") => {
+            export::write_snap_export_csv_to(std::io::stdout().lock(), &records)?
+        }
+        (cli::ParseFormat::Csv, _) => export::write_snap_export_csv(Path::new(output), &records)?,
+        (cli::ParseFormat::Json, "-") => {
+            export::write_snap_export_json(std::io::stdout().lock(), &records)?
+        }
+        (cli::ParseFormat::Json, _) => {
+            export::write_snap_export_json(File::create(output)?, &records)?
+        }
+    };
+    // Not on the standard output, which may be piped somewhere
+    if output != "-" {
+        eprintln!(
+            "Wrote {} memories to {}",
+            format::format_count(written),
+            output
+        );
+    }
+    if written < records.len() {
+        eprintln!(
+            "Left out {} rows that don't look like a memory",
+            records.len() - written
+        );
+    }
+    Ok(())
+}
+
+// `snapdown diff`: list the memories that are only in one of two exports.
+// Exits with 1 if any are missing from the newer one, so scripts can check.
+fn run_diff(args: cli::DiffArgs) -> Result<()> {
+    for input in [&args.old, &args.new] {
+        if let Err(e) = input::check_input_file(Path::new(input)) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+    let old = read_input_records(&args.old, None)?;
+    let new = read_input_records(&args.new, None)?;
+    let diff = diff::diff_exports(&old, &new);
+    for (title, records) in [
+        (format!("Only in {}:", args.old), &diff.removed),
+        (format!("Only in {}:", args.new), &diff.added),
+    ] {
+        if !records.is_empty() {
+            println!("{}", title);
+            for record in records {
+                println!("  {}", diff::describe(record));
+            }
+        }
+    }
+    println!(
+        "{} memories in both, {} removed, {} added",
+        format::format_count(diff.unchanged),
+        format::format_count(diff.removed.len()),
+        format::format_count(diff.added.len())
+    );
+    if diff.invalid > 0 {
+        println!(
+            "Left out {} rows that don't look like a memory",
+            format::format_count(diff.invalid)
+        );
+    }
+    info!(
+        "Compared {} with {}: {} removed, {} added",
+        args.old,
+        args.new,
+        diff.removed.len(),
+        diff.added.len()
+    );
+    if !diff.removed.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// `snapdown verify`: report files that don't match the manifest. Exits with
+// 1 if there are any (left after --repair), so scripts can check.
+fn run_verify(args: cli::VerifyArgs) -> Result<()> {
+    let output_dir = Path::new(&args.output_dir);
+    let report = verify_with_progress_bar(output_dir);
+    for (filename, problem) in &report.problems {
+        println!("{}: {}", filename, problem);
+    }
+    println!(
+        "Checked {} files: {} OK, {} with problems",
+        format::format_count(report.checked),
+        format::format_count(report.checked - report.problems.len()),
+        format::format_count(report.problems.len())
+    );
+    if report.size_only > 0 {
+        println!(
+            "{} files were downloaded before there were checksums, only their size was checked",
+            format::format_count(report.size_only)
+        );
+    }
+    info!(
+        "Verified {}: {} of {} files with problems",
+        args.output_dir,
+        report.problems.len(),
+        report.checked
+    );
+    if report.problems.is_empty() {
+        return Ok(());
+    }
+    let Some(input_csv) = args.input.filter(|_| args.repair) else {
+        std::process::exit(1);
+    };
+
+    // The broken files are moved into quarantine, after which a normal run
+    // downloads them again and skips everything else
+    let (count, quarantined) = verify::quarantine_broken_files(output_dir, &report)?;
+    if !quarantined.is_empty() {
+        println!(
+            "Moved {} broken files into {}",
+            format::format_count(quarantined.len()),
+            output_dir.join(quarantine::QUARANTINE_DIR).display()
+        );
+    }
+    // From the parts of the export the broken files are from, if the input
+    // is the export zip
+    let mut sections: Vec<Section> = report
+        .problems
+        .iter()
+        .map(|(filename, _)| Section::of_output_file(filename))
+        .collect();
+    sections.sort();
+    sections.dedup();
+    println!("Downloading {} files again...", format::format_count(count));
+    run_cli_download(Args {
+        input_csv,
+        sections,
+        output_dir: args.output_dir.clone(),
+        jobs: DEFAULT_NUM_JOBS,
+        export_failures: None,
+        report: None,
+        status_port: None,
+        debug_http: false,
+        log_full_urls: false,
+        fail_fast: false,
+        resolve_links: args.resolve_links,
+        write_exif: true,
+        touch: true,
+        composite_overlays: false,
+        auto_rotate: false,
+        sidecars: false,
+        dedup: None,
+        views: None,
+        freeze: false,
+        force: false,
+        limit_rate: None,
+        schedule: None,
+        connect_timeout: None,
+        response_timeout: None,
+        retries: 0,
+        pool_size: None,
+        pool_idle_timeout: None,
+        proxy: None,
+        post_process_cmd: None,
+        upload: None,
+        s3: None,
+        webdav: None,
+        // Only a few files, and nothing to do but try
+        space_check: SpaceCheck::Off,
+        dry_run: false,
+        stall_timeout: None,
+        restart_stalled: false,
+        replay: None,
+        replay_speed: 1.0,
+        naming: args
+            .naming
+            .unwrap_or_default()
+            .with_places(args.places.as_deref())?,
+        email: None,
+        // It's the same archive
+        allow_mixed_archives: true,
+        support_bundle: None,
+        print_config: false,
+        quarantined,
+        retry_failed: false,
+        notify: None,
+    })?;
+
+    let report = verify_with_progress_bar(output_dir);
+    if !report.problems.is_empty() {
+        for (filename, problem) in &report.problems {
+            println!("{}: {}", filename, problem);
+        }
+        println!(
+            "{} files are still broken",
+            format::format_count(report.problems.len())
+        );
+        std::process::exit(1);
+    }
+    println!("Repaired {} files", format::format_count(count));
+    Ok(())
+}
+
+// verify::verify() with a progress bar of the hashing, exiting if the
+// output directory can't be verified at all
+fn verify_with_progress_bar(output_dir: &Path) -> verify::VerifyReport {
+    let (send_progress, recv_progress) = mpsc::channel::<hashing::HashProgress>();
+    let progress_thread = std::thread::spawn(move || {
+        let bar = indicatif::ProgressBar::new(0).with_style(
+            indicatif::ProgressStyle::with_template(
+                "{wide_bar} {bytes}/{total_bytes} ({percent}%) {msg}",
+            )
+            .unwrap(),
+        );
+        for progress in recv_progress {
+            bar.set_length(progress.bytes_total);
+            bar.set_position(progress.bytes_done);
+            bar.set_message(format!(
+                "{}/{} files",
+                progress.files_done, progress.files_total
+            ));
+        }
+        bar.finish_and_clear();
+    });
+    let report = verify::verify(output_dir, Some(&send_progress));
+    drop(send_progress);
+    progress_thread.join().unwrap_or_else(|_| {
+        error!("Progress bar thread panicked");
+    });
+    report.unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    })
+}
+
+// Email the summary of a finished CLI run. Failing to send it doesn't change
+// the exit status, the run itself went however it went.
+fn send_summary_email(
+    email: &EmailSettings,
+    input_file: &str,
+    output_dir: &str,
+    status: &SnapdownStatus,
+) {
+    let subject = match status.stop_reason {
+        Some(reason) => format!("SnapDown stopped: {}", reason),
+        None if status.dry_run_report.is_some() => "SnapDown dry run finished".to_string(),
+        None => format!(
+            "SnapDown finished: {} downloaded, {} failed",
+            status.success_count, status.error_count
+        ),
+    };
+    let mut body = vec![
+        format!("Input file: {}", input_file),
+        format!(
+            "Output directory: {}",
+            resolve_output_dir(output_dir).display()
+        ),
+        String::new(),
+    ];
+    if let Some(report) = &status.dry_run_report {
+        body.extend(report.lines());
+    } else {
+        body.extend([
+            format!(
+                "Successful downloads: {}",
+                format::format_count(status.success_count)
+            ),
+            format!("Errors: {}", format::format_count(status.error_count)),
+            match status.skip_savings() {
+                Some(savings) => format!(
+                    "Skipped: {} ({})",
+                    format::format_count(status.skip_count),
+                    savings
+                ),
+                None => format!("Skipped: {}", format::format_count(status.skip_count)),
+            },
+        ]);
+        if status.invalid_count > 0 {
+            body.push(format!(
+                "Not memories: {} rows",
+                format::format_count(status.invalid_count)
+            ));
+        }
+        if status.upload_count > 0 || status.upload_error_count > 0 {
+            body.push(format!(
+                "Uploaded: {} ({} failed)",
+                format::format_count(status.upload_count),
+                format::format_count(status.upload_error_count)
+            ));
+        }
+        body.extend([format!(
+            "Downloaded {} in {}",
+            format::format_bytes(status.bytes_downloaded),
+            format::format_duration(status.elapsed)
+        )]);
+        if let Some(usage) = &status.usage {
+            body.push(format!(
+                "Data used: {}",
+                usage.summary(&usage::current_month())
+            ));
+        }
+        if status.duplicate_count > 0 {
+            body.push(format!(
+                "Duplicates: {} ({} saved)",
+                format::format_count(status.duplicate_count),
+                format::format_bytes(status.dedup_bytes_saved)
+            ));
+        }
+        if !status.host_stats.is_empty() {
+            body.push(String::new());
+            body.push("Per-host statistics:".to_string());
+            for (host, stats) in &status.host_stats {
+                body.push(format!("  - {}", stats::format_host_stats(host, stats)));
+            }
+        }
+    }
+
+    let attachment = if status.failed_records.is_empty() {
+        None
+    } else {
+        body.push(String::new());
+        body.push(
+            "The memories that failed are attached as a snap_export.csv. Run SnapDown on it to retry them."
+                .to_string(),
+        );
+        match export::snap_export_csv_bytes(&status.failed_records) {
+            Ok(csv) => Some(("failed_snap_export.csv", csv)),
+            Err(e) => {
+                error!("Error writing failures for the summary email: {}", e);
+                None
+            }
+        }
+    };
+    match email.send(&subject, body.join("\n"), attachment) {
+        Ok(()) => info!("Sent the summary email"),
+        Err(e) => {
+            error!("Error sending the summary email: {}", e);
+            eprintln!("Error sending the summary email: {}", e);
+        }
+    }
+}
+
+// How much of the last minute's speed the CLI progress bar has room for
+const CLI_SPARKLINE_SECONDS: usize = 20;
+
+// Progress bar on stderr for CLI runs. indicatif hides it when stderr isn't a
+// terminal, so logs and pipes don't fill up with redraws.
+fn show_cli_progress(
+    statuses: gui_channel::Receiver<SnapdownStatus>,
+    status_server: Option<StatusServer>,
+    mut notifier: Option<Notifier>,
+) {
+    // The counts are in the message, as indicatif doesn't know the locale
+    let bar = indicatif::ProgressBar::new(0)
+        .with_style(indicatif::ProgressStyle::with_template("{wide_bar} {msg}").unwrap());
+    for status in statuses {
+        bar.set_length(status.total_count as u64);
+        bar.set_position(status.processed_count() as u64);
+        let fraction = match status.total_count {
+            0 => 0.0,
+            total => status.processed_count() as f64 / total as f64,
+        };
+        bar.set_message(format!(
+            "{}/{} ({}) {} {}",
+            format::format_count(status.processed_count()),
+            format::format_count(status.total_count),
+            format::format_percent(fraction),
+            status.progress_message(),
+            throughput::sparkline(&status.throughput_history, CLI_SPARKLINE_SECONDS)
+        ));
+        if let Some(server) = &status_server {
+            server.update(status.status_page());
+        }
+        if let Some(notifier) = &mut notifier {
+            status.notify_error_rate(notifier);
+        }
+        if status.finished {
+            bar.finish();
+        }
+    }
+}
+
+fn run_gui() -> Result<()> {
+    let (send_from_filepicker, recv_from_filepicker) = mpsc::channel::<String>();
+    let (send_output_dir_from_picker, recv_output_dir_from_picker) = mpsc::channel::<String>();
+    let (send_logs_from_downloader, recv_logs_from_downloader) =
+        gui_channel::bounded::<LogEntry>(gui_channel::LOG_CAPACITY);
+    let (send_retry_results, recv_retry_results) =
+        mpsc::channel::<(csv::StringRecord, Result<(), String>)>();
+    let (send_image_sizes, recv_image_sizes) = mpsc::channel::<(PathBuf, Option<ImageSize>)>();
+    let (send_not_downloaded, recv_not_downloaded) = mpsc::channel::<(usize, Vec<ArchiveEntry>)>();
+    let (send_plans, recv_plans) = mpsc::channel::<PlanResult>();
+    let (send_status_from_downloader, recv_status_from_downloader) =
+        gui_channel::bounded::<SnapdownStatus>(gui_channel::STATUS_CAPACITY);
+    let (presets, preset_error) = match presets::load(&presets::presets_file()) {
+        Ok(presets) => (presets, None),
+        Err(e) => {
+            error!("Error reading the presets: {:#}", e);
+            (Default::default(), Some(format!("{:#}", e)))
+        }
+    };
+    let mut snapdown_app = SnapdownEframeApp {
+        picked_path: None,
+        picked_format: None,
+        state: SnapdownState::Idle,
+        send_from_filepicker,
+        recv_from_filepicker,
+        send_output_dir_from_picker,
+        recv_output_dir_from_picker,
+        output_dir: DEFAULT_OUTPUT_DIR.to_string(),
+        output_dir_free_space: output_dir_free_space(Path::new(DEFAULT_OUTPUT_DIR)),
+        usage: usage::load(&usage::usage_file())
+            .inspect_err(|e| error!("Error reading the data used: {:#}", e))
+            .ok(),
+        log_path: install::data_file(LOG_FILE),
+        send_logs_from_downloader,
+        recv_logs_from_downloader,
+        file_events: Arc::new(FileEvents::default()),
+        send_retry_results,
+        recv_retry_results,
+        send_status_from_downloader,
+        recv_status_from_downloader,
+        success_count: 0,
+        error_count: 0,
+        skip_count: 0,
+        invalid_count: 0,
+        total_count: 0,
+        bytes_downloaded: 0,
+        throughput_history: Vec::new(),
+        skip_savings: None,
+        progress_message: String::new(),
+        run_elapsed: Duration::ZERO,
+        stop_reason: None,
+        failed_records: Vec::new(),
+        duplicate_count: 0,
+        dedup_bytes_saved: 0,
+        space_estimate: None,
+        run_control: Arc::new(RunControl::default()),
+        pending_archive_conflict: None,
+        start_error: None,
+        drop_error: None,
+        error_breakdown: Default::default(),
+        host_stats: Vec::new(),
+        messages_console: CircularBuffer::<1024, LogEntry>::new(),
+        console_errors_only: false,
+        console_search: String::new(),
+        file_list: FileList::default(),
+        download_view: DownloadView::Files,
+        style_applied: false,
+        debug_http: false,
+        resolve_links: false,
+        write_exif: true,
+        touch: true,
+        composite_overlays: false,
+        auto_rotate: false,
+        sidecars: false,
+        dedup: None,
+        notify: false,
+        notify_error_rate: notify::DEFAULT_ERROR_RATE,
+        notifier: None,
+        tray: None,
+        tray_target: None,
+        jobs: DEFAULT_NUM_JOBS,
+        retries: 0,
+        connect_timeout: String::new(),
+        response_timeout: String::new(),
+        show_effective_settings: false,
+        views: Vec::new(),
+        view_links: LinkKind::HardLink,
+        freeze: false,
+        force: false,
+        limit_rate: String::new(),
+        schedule: String::new(),
+        proxy: String::new(),
+        post_process_cmd: String::new(),
+        naming: String::new(),
+        sections: vec![Section::Memories],
+        presets,
+        preset_name: String::new(),
+        preset_error,
+        dry_run: false,
+        dry_run_report: None,
+        plan: None,
+        plan_key: None,
+        planning: false,
+        size_sample: None,
+        estimate_input: None,
+        recv_plans,
+        send_plans,
+        tab: SnapdownTab::Download,
+        archive_query: ArchiveQuery::default(),
+        archive_entries: Vec::new(),
+        archive_scan: 0,
+        listing_not_downloaded: false,
+        recv_not_downloaded,
+        send_not_downloaded,
+        archive_error: None,
+        sample_image_sizes: false,
+        image_sizes: Default::default(),
+        recv_image_sizes,
+        send_image_sizes,
+        status_panel_ratio: DEFAULT_STATUS_PANEL_RATIO,
+    };
+
+    // Have the GUI take care of getting args from the user
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([640.0, 240.0]),
+        persistence_path: install::gui_settings_file(),
+        ..Default::default()
+    };
+    eframe::run_native(
+        "SnapDown GUI",
+        options,
+        Box::new(|cc| {
+            // Restore settings remembered from the previous session
+            if let Some(storage) = cc.storage {
+                snapdown_app.status_panel_ratio =
+                    eframe::get_value::<f32>(storage, STATUS_PANEL_RATIO_KEY)
+                        .unwrap_or(DEFAULT_STATUS_PANEL_RATIO)
+                        .clamp(MIN_STATUS_PANEL_RATIO, MAX_STATUS_PANEL_RATIO);
+                snapdown_app.proxy =
+                    eframe::get_value::<String>(storage, PROXY_KEY).unwrap_or_default();
+                if let Some(output_dir) = eframe::get_value::<String>(storage, OUTPUT_DIR_KEY)
+                    .filter(|output_dir| !output_dir.trim().is_empty())
+                {
+                    snapdown_app.output_dir_free_space =
+                        output_dir_free_space(Path::new(&output_dir));
+                    snapdown_app.output_dir = output_dir;
+                }
+                snapdown_app.post_process_cmd =
+                    eframe::get_value::<String>(storage, POST_PROCESS_CMD_KEY).unwrap_or_default();
+            }
+            let tray_target = TrayTarget::new(&cc.egui_ctx);
+            snapdown_app.tray = Tray::new(&tray_target, cc);
+            if snapdown_app.tray.is_some() {
+                snapdown_app.tray_target = Some(tray_target);
+            }
+            Ok(Box::new(snapdown_app))
+        }),
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to run GUI: {}", e))
+}
+
+// Absolute form of the output directory, even if it doesn't exist yet
+fn resolve_output_dir(output_dir: &str) -> PathBuf {
+    std::path::absolute(output_dir).unwrap_or_else(|_| PathBuf::from(output_dir))
+}
+
+// The speed of each second of the last minute as a line, see throughput.rs.
+// Scaled to the fastest of them, and the same width however much of the
+// minute there is so far, so it fills in from the left.
+fn show_sparkline(ui: &mut egui::Ui, history: &[f64]) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(240.0, 32.0), egui::Sense::hover());
+    let max = history.iter().copied().fold(0.0, f64::max);
+    if history.len() < 2 || max == 0.0 {
+        return;
+    }
+    let step = rect.width() / (throughput::HISTORY_SECONDS - 1) as f32;
+    let points = history
+        .iter()
+        .enumerate()
+        .map(|(i, speed)| {
+            egui::pos2(
+                rect.left() + i as f32 * step,
+                rect.bottom() - rect.height() * (speed / max) as f32,
+            )
+        })
+        .collect();
+    ui.painter().add(egui::Shape::line(
+        points,
+        ui.visuals().widgets.active.fg_stroke,
+    ));
+}
+
+// Free space on the volume the output directory is (or will be) on
+fn output_dir_free_space(output_dir: &Path) -> Option<u64> {
+    // The directory may not exist yet, so check the closest existing parent
+    let resolved = std::path::absolute(output_dir).ok()?;
+    let existing = resolved.ancestors().find(|p| p.exists())?;
+    fs4::available_space(existing).ok()
+}
+
+fn log_message(events: Option<&dyn EventSink>, message: String) {
+    info!("{}", &message);
+    send_log(events, log::Level::Info, message, None);
+}
+
+fn send_log(
+    events: Option<&dyn EventSink>,
+    level: log::Level,
+    message: String,
+    record: Option<&csv::StringRecord>,
+) {
+    // The GUI has no --log-full-urls, the full link of a record is still
+    // there to copy
+    if let Some(events) = events {
+        events.send(DownloadEvent::Log(LogEntry {
+            level,
+            message: redact::redact_tokens(&message),
+            record: record.cloned(),
+        }));
+    }
+}
+
+// SnapDown's own logs and the report of the run, for a support bundle. The
+// report is in the output directory unless `report` says otherwise.
+fn support_bundle_files(output_dir: &str, report: Option<&str>) -> Vec<PathBuf> {
+    vec![
+        install::data_file(LOG_FILE),
+        install::data_file(HTTP_DEBUG_LOG_FILE),
+        report.map_or_else(
+            || Path::new(output_dir).join(report::REPORT_FILE),
+            PathBuf::from,
+        ),
+    ]
+}
+
+fn write_support_bundle(
+    path: &Path,
+    snapshot: &ProgressSnapshot,
+    files: &[PathBuf],
+    events: Option<&dyn EventSink>,
+) {
+    match support_bundle::write_support_bundle(path, snapshot, files) {
+        Ok(included) => {
+            let message = format!(
+                "Wrote a support bundle with {} to {}. The download links in it are redacted.",
+                included.join(", "),
+                path.display()
+            );
+            // The log file is in the bundle, the CLI has to say where it is
+            if events.is_none() {
+                println!("{}", message);
+            }
+            log_message(events, message);
+        }
+        Err(e) => {
+            let message = format!("Error writing the support bundle {}: {}", path.display(), e);
+            if events.is_none() {
+                eprintln!("{}", message);
+            }
+            log_error(events, message);
+        }
+    }
+}
+
+// The settings of a CLI run, as the GUI's snapshots have them
+fn cli_snapshot_config(args: &Args) -> SnapshotConfig {
+    SnapshotConfig {
+        input_file: Path::new(&args.input_csv)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string()),
+        output_dir: args.output_dir.clone(),
+        jobs: args.jobs,
+        retries: args.retries,
+        debug_http: args.debug_http,
+        resolve_links: args.resolve_links,
+        write_exif: args.write_exif,
+        touch: args.touch,
+        composite_overlays: args.composite_overlays,
+        auto_rotate: args.auto_rotate,
+        sidecars: args.sidecars,
+        dedup: args.dedup,
+        views: args
+            .views
+            .iter()
+            .flat_map(|views| views.kinds.iter().map(|kind| kind.dir_name()))
+            .collect(),
+        sections: args.sections.iter().map(Section::name).collect(),
+        freeze: args.freeze,
+        force: args.force,
+        limit_rate: args.limit_rate,
+        schedule: args.schedule.map(|schedule| schedule.to_string()),
+        proxy: args.proxy.is_some(),
+        post_process_cmd: args.post_process_cmd.is_some(),
+        upload: args.upload.as_ref().map(|upload| upload.target.label()),
+    }
+}
+
+// The options of the CLI's run
+fn cli_download_options(args: &Args) -> DownloadOptions {
+    DownloadOptions {
+        jobs: args.jobs,
+        debug_http: args.debug_http || args.fail_fast,
+        log_full_urls: args.log_full_urls,
+        fail_fast: args.fail_fast,
+        resolve_links: args.resolve_links,
+        write_exif: args.write_exif,
+        touch: args.touch,
+        composite_overlays: args.composite_overlays,
+        auto_rotate: args.auto_rotate,
+        sidecars: args.sidecars,
+        dedup: args.dedup,
+        views: args.views.clone(),
+        sections: args.sections.clone(),
+        quarantined: args.quarantined.clone(),
+        retry_failed: args.retry_failed,
+        freeze: args.freeze,
+        force: args.force,
+        limit_rate: args.limit_rate,
+        schedule: args.schedule,
+        connect_timeout: args.connect_timeout,
+        response_timeout: args.response_timeout,
+        retries: args.retries,
+        pool_size: args.pool_size,
+        pool_idle_timeout: args.pool_idle_timeout,
+        proxy: args.proxy.clone(),
+        post_process_cmd: args.post_process_cmd.clone(),
+        upload: args.upload.clone(),
+        s3: args.s3.clone(),
+        webdav: args.webdav.clone(),
+        space_check: args.space_check,
+        dry_run: args.dry_run,
+        report_path: args.report.as_ref().map(PathBuf::from),
+        usage_path: Some(usage::usage_file()),
+        metrics: None,
+        http: None,
+        stall_timeout: args.stall_timeout,
+        restart_stalled: args.restart_stalled,
+        replay: args.replay.as_ref().map(PathBuf::from),
+        replay_speed: args.replay_speed,
+        naming: args.naming.clone(),
+    }
+}
+
+// What --print-config prints for a run with `options`, but for the settings
+// that aren't run options: notifications, the status port and the like. The
+// GUI's "Show effective settings" uses it too, so both print the same for the
+// same run.
+fn options_effective_config(
+    input: &str,
+    output_dir: &str,
+    options: &DownloadOptions,
+) -> EffectiveConfig {
+    let seconds = |timeout: Option<Duration>| timeout.map(|timeout| timeout.as_secs_f64());
+    EffectiveConfig {
+        input: input.to_string(),
+        output_dir: output_dir.to_string(),
+        sections: options.sections.iter().map(Section::name).collect(),
+        jobs: options.jobs,
+        retries: options.retries,
+        naming: options.naming.name(),
+        views: options
+            .views
+            .iter()
+            .flat_map(|views| views.kinds.iter().map(|kind| kind.dir_name()))
+            .collect(),
+        view_links: options
+            .views
+            .as_ref()
+            .map(|views| presets::link_name(views.link)),
+        dedup: presets::dedup_name(options.dedup),
+        no_exif: !options.write_exif,
+        no_touch: !options.touch,
+        composite_overlays: options.composite_overlays,
+        auto_rotate: options.auto_rotate,
+        sidecars: options.sidecars,
+        resolve_links: options.resolve_links,
+        freeze: options.freeze,
+        force: options.force,
+        dry_run: options.dry_run,
+        retry_failed: options.retry_failed,
+        limit_rate: options.limit_rate,
+        schedule: options.schedule.map(|schedule| schedule.to_string()),
+        connect_timeout: seconds(options.connect_timeout),
+        response_timeout: seconds(options.response_timeout),
+        pool_size: options.pool_size(),
+        pool_idle_timeout: options.pool_idle_timeout().as_secs_f64(),
+        stall_timeout: seconds(options.stall_timeout),
+        restart_stalled: options.restart_stalled,
+        proxy: config::proxy_address(options.proxy.as_ref()),
+        post_process_cmd: options.post_process_cmd.clone(),
+        space_check: config::value_name(options.space_check),
+        debug_http: options.debug_http,
+        log_full_urls: options.log_full_urls,
+        fail_fast: options.fail_fast,
+        report: options
+            .report_path
+            .as_ref()
+            .map(|path| path.display().to_string()),
+        replay: options
+            .replay
+            .as_ref()
+            .map(|path| path.display().to_string()),
+        replay_speed: options.replay.as_ref().map(|_| options.replay_speed),
+        upload: options
+            .upload
+            .as_ref()
+            .map(|upload| config::value_name(upload.target)),
+        server: options.upload.as_ref().map(|upload| upload.server.clone()),
+        upload_only: options
+            .upload
+            .as_ref()
+            .is_some_and(|upload| upload.upload_only),
+        s3_bucket: options.s3.as_ref().map(|s3| s3.bucket.clone()),
+        s3_endpoint: options.s3.as_ref().map(|s3| s3.endpoint()),
+        s3_region: options.s3.as_ref().map(|s3| s3.region.clone()),
+        s3_prefix: options.s3.as_ref().map(|s3| s3.prefix.clone()),
+        webdav: options.webdav.as_ref().map(|webdav| webdav.address()),
+        ..Default::default()
+    }
+}
+
+// What --print-config prints
+fn cli_effective_config(args: &Args) -> EffectiveConfig {
+    EffectiveConfig {
+        // --fail-fast turns it on for the run, but it's its own setting
+        debug_http: args.debug_http,
+        notify: args.notify.is_some(),
+        notify_error_rate: args.notify,
+        allow_mixed_archives: args.allow_mixed_archives,
+        export_failures: args.export_failures.clone(),
+        status_port: args.status_port,
+        support_bundle: args.support_bundle.clone(),
+        smtp: args.email.as_ref().map(|email| email.smtp_server()),
+        email_to: args.email.as_ref().map(|email| email.to()),
+        email_from: args.email.as_ref().map(|email| email.from()),
+        ..options_effective_config(
+            &args.input_csv,
+            &args.output_dir,
+            &cli_download_options(args),
+        )
+    }
+}
+
+// None if the run couldn't start
+fn cli_progress_snapshot(
+    config: SnapshotConfig,
+    status: Option<&SnapdownStatus>,
+) -> ProgressSnapshot {
+    let state = match status {
+        None => "failed",
+        Some(status) if status.stop_reason.is_some() => "stopped",
+        Some(_) => "completed",
+    };
+    ProgressSnapshot {
+        created: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        snapdown_version: env!("CARGO_PKG_VERSION"),
+        state: state.to_string(),
+        success_count: status.map_or(0, |status| status.success_count),
+        error_count: status.map_or(0, |status| status.error_count),
+        skip_count: status.map_or(0, |status| status.skip_count),
+        invalid_count: status.map_or(0, |status| status.invalid_count),
+        // The CLI doesn't keep the errors, they're in the log and report
+        error_breakdown: Default::default(),
+        stop_reason: status.and_then(|status| status.stop_reason),
+        config,
+        recent_log: Vec::new(),
+    }
+}
+
+// Write failed records out as a snap_export.csv that can be used as the input
+// for another run, so only the failures get retried
+fn export_failures(
+    path: &Path,
+    failed_records: &[csv::StringRecord],
+    events: Option<&dyn EventSink>,
+) {
+    match export::write_snap_export_csv(path, failed_records) {
+        Ok(written) => {
+            log_message(
+                events,
+                format!("Exported {} failed records to {}", written, path.display()),
+            );
+            if !path.to_string_lossy().ends_with("snap_export.csv") {
+                log_message(
+                    events,
+                    "Note: rename the file to end in snap_export.csv to use it as an input."
+                        .to_string(),
+                );
+            }
+        }
+        Err(e) => log_error(
+            events,
+            format!("Error exporting failures to {}: {}", path.display(), e),
+        ),
+    }
+}
+
+fn log_warning(events: Option<&dyn EventSink>, message: String) {
+    warn!("{}", &message);
+    send_log(events, log::Level::Warn, message, None);
+}
+
+fn log_error(events: Option<&dyn EventSink>, message: String) {
+    error!("{}", &message);
+    send_log(events, log::Level::Error, message, None);
+}
+
+// Log why `record` failed to download, as a bullet under its filename
+fn log_record_error(events: Option<&dyn EventSink>, error: &str, record: &csv::StringRecord) {
+    let message = format!("  * {}", error);
+    error!("{}", &message);
+    send_log(events, log::Level::Error, message, Some(record));
+}
+
+// How many more times a file is downloaded when it doesn't match the MD5 the
+// server gave for it
+const MAX_CHECKSUM_RETRIES: usize = 2;
+
+// Check a downloaded file against the MD5 its response gave, if it gave one,
+// and return that MD5 in hex when it matches. The error says what didn't
+// match. `mismatched` keeps the BLAKE3 hash of the last body that didn't:
+// when the exact same bytes come again and miss an ETag again, the ETag
+// isn't the file's MD5 after all (some servers make theirs another way), the
+// file is taken as it is, and the ETags of its host aren't checked again.
+fn verify_md5(
+    server_md5: Option<ServerMd5>,
+    file_hash: &FileHash,
+    download_url: &str,
+    mismatched: &mut Option<String>,
+    etag_hosts: &EtagHosts,
+) -> Result<Option<String>, String> {
+    let Some(server_md5) = server_md5 else {
+        return Ok(None);
+    };
+    let expected = server_md5.digest();
+    if file_hash.md5 == Some(expected) {
+        return Ok(Some(md5::to_hex(expected)));
+    }
+    if matches!(server_md5, ServerMd5::Etag(_)) && mismatched.as_ref() == Some(&file_hash.hash) {
+        info!(
+            "The ETags from {} aren't MD5s of the files, not checking them",
+            stats::url_host(download_url)
+        );
+        etag_hosts.insert(download_url);
+        return Ok(None);
+    }
+    *mismatched = Some(file_hash.hash.clone());
+    Err(format!(
+        "the checksum doesn't match its {} ({} instead of {})",
+        server_md5.header(),
+        file_hash.md5.map(md5::to_hex).unwrap_or_default(),
+        md5::to_hex(expected)
+    ))
+}
+
+// A request that failed with a connection error, a timeout or a server error
+// like 503 is tried again, up to --retries times. Logs the `retry`th try, and returns
+// how long to wait before it.
+fn retry_request(error: &str, retry: usize, ctx: &DownloadContext) -> Duration {
+    let delay = backoff::retry_delay(retry);
+    log_error(
+        ctx.events,
+        format!(
+            "  * {}, trying again in {} ({} of {})",
+            error,
+            format::format_duration(delay),
+            retry,
+            ctx.retries
+        ),
+    );
+    delay
+}
+
+// After a download that didn't match the server's MD5, whether to download
+// it again. If not, the failure is logged like any other.
+fn retry_checksum_failure(
+    error: &str,
+    retries: &mut usize,
+    row: &csv::StringRecord,
+    ctx: &DownloadContext,
+) -> bool {
+    if *retries < MAX_CHECKSUM_RETRIES && !ctx.control.is_cancelled() {
+        *retries += 1;
+        log_error(ctx.events, format!("  * {}, downloading it again", error));
+        true
+    } else {
+        log_record_error(ctx.events, error, row);
+        false
+    }
+}
+
+// // Helper function to find a pattern in bytes, returns position if found
+// fn find_pattern(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+//     if needle.is_empty() || haystack.len() < needle.len() {
+//         return None;
+//     }
+
+//     for i in 0..=(haystack.len() - needle.len()) {
+//         if &haystack[i..i + needle.len()] == needle {
+//             return Some(i);
+//         }
+//     }
+//     None
+// }
+
+// // Extract latitude and longitude from location string
+// fn extract_coordinates(location: &str) -> (Option<String>, Option<String>) {
+//     // Look for pattern like "Latitude, Longitude: 40.25548, -111.645325"
+//     if let Some(colon_pos) = location.find(':') {
+//         let coords_part = &location[colon_pos + 1..].trim();
+//         let parts: Vec<&str> = coords_part.split(',').collect();
 //         if parts.len() >= 2 {
 //             let lat = parts[0].trim().to_string();
 //             let lng = parts[1].trim().to_string();
@@ -483,559 +4338,2835 @@ fn log_error(gui_console: Option<&mpsc::Sender<String>>, message: String) {
 //     (None, None)
 // }
 
-// // Extract download URL from onclick attribute
-// fn extract_download_url(td_content: &[u8]) -> Option<String> {
-//     let content = String::from_utf8_lossy(td_content);
+// // Extract download URL from onclick attribute
+// fn extract_download_url(td_content: &[u8]) -> Option<String> {
+//     let content = String::from_utf8_lossy(td_content);
+
+//     // Look for downloadMemories('URL' pattern
+//     if let Some(start) = content.find("downloadMemories('") {
+//         let start_pos = start + 18; // Length of "downloadMemories('"
+//         if let Some(end) = content[start_pos..].find("'") {
+//             return Some(content[start_pos..start_pos + end].to_string());
+//         }
+//     }
+//     None
+// }
+
+// Enum to represent the search result
+#[derive(Debug)]
+enum SearchResult {
+    NotFound,
+    Found(usize),                   // Index where found
+    NotFoundWithUnprocessed(usize), // Number of unprocessed bytes at the end
+}
+
+// Linearly look for a pattern of bytes in a buffer, ignoring ASCII case
+// (pages saved by old browsers have <TABLE> and the like). If found, return
+// the index where the tag was found in that buffer.
+// If is_last is true, then it means that this is the end of the data and we
+// don't need to combine the end of this buffer with the beginning of the next
+// buffer.
+fn look_for_item(buffer: &[u8], item: &[u8], is_last: bool) -> SearchResult {
+    let item_size = item.len();
+    let buffer_size = buffer.len();
+
+    if buffer_size == 0 {
+        // Empty buffer
+        return SearchResult::NotFound;
+    }
+    if buffer_size < item_size {
+        // The buffer is too small to possibly contain the item
+        if is_last {
+            return SearchResult::NotFound;
+        } else {
+            return SearchResult::NotFoundWithUnprocessed(buffer_size);
+        }
+    }
+    assert!(item_size > 0, "Item size must be greater than zero");
+
+    for (index, window) in buffer.windows(item_size).enumerate() {
+        // info!(
+        //     "{}: {} vs. {}",
+        //     index,
+        //     String::from_utf8_lossy(window),
+        //     String::from_utf8_lossy(item)
+        // );
+        if window.eq_ignore_ascii_case(item) {
+            return SearchResult::Found(index);
+        }
+    }
+
+    // We did not find the item
+
+    // This is the last buffer, so the windows covered all bytes
+    if is_last {
+        return SearchResult::NotFound;
+    }
+
+    // The end of this buffer needs to be combined with the start of the next
+    // buffer, and windows() can't check the last (item_size - 1) bytes
+    let unprocessed = item_size - 1;
+    SearchResult::NotFoundWithUnprocessed(unprocessed)
+}
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+enum SdParseState {
+    SearchingForTable,
+    SearchingForTbody,
+    SearchingForTr,
+    SearchingForTh,
+    SearchingForThEnd,
+    SearchingForThClosing,
+    SearchingForTd,
+    SearchingForTdEnd,
+    SearchingForTdClosing,
+    SearchingForDownloadLink,
+    SearchingForDownloadLinkEnd,
+    // SearchingForTrClosing,
+    // SearchingForTableClosing,
+    // SearchingForTbodyClosing,
+    // SearchingForHtmlTagEnd,
+    // SearchingForHtmlTagStart,
+    // SearchingForNextNonWhitespace,
+    // SearchingForAttribute,
+    // SearchingForAttributeEnd,
+    // SearchingForAttributeValueStart,
+    // SearchingForAttributeValueEnd,
+    // SearchingForQuote,
+    // SearchingForQuoteEnd,
+    // LookingForDate,
+    // LookingForMediaType,
+    // LookingForLocation,
+    // LookingForDownloadLink,
+}
+
+// fn parse_next(buffer: &[u8], state: &SdParseState) -> usize {
+//     return 0;
+// }
+
+// An entry of the "Saved Media" array in json/memories_history.json
+#[derive(serde::Deserialize)]
+struct SavedMedia {
+    #[serde(rename = "Date")]
+    date: String,
+    #[serde(rename = "Media Type")]
+    media_type: String,
+    // e.g. "Latitude, Longitude: 40.25548, -111.645325". Older exports don't
+    // have locations.
+    #[serde(rename = "Location", default)]
+    location: String,
+    #[serde(rename = "Download Link")]
+    download_link: String,
+    // Direct link to the media, only in newer exports. Download Link is the
+    // older kind that the Snapchat website POSTs to.
+    #[serde(rename = "Media Download Url")]
+    media_download_url: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct MemoriesHistoryJson {
+    #[serde(rename = "Saved Media")]
+    saved_media: Vec<SavedMedia>,
+}
+
+// Returns records of the same shape as parse_memories_history_html(), i.e.
+// (timestamp, format, location, download_url), but without a header row
+fn parse_memories_history_json(
+    input_file: &str,
+    events: Option<&dyn EventSink>,
+) -> Result<Vec<csv::StringRecord>> {
+    parse_memories_history_json_from(File::open(input_file)?, events)
+}
+
+fn parse_memories_history_json_from(
+    json_file: impl Read,
+    events: Option<&dyn EventSink>,
+) -> Result<Vec<csv::StringRecord>> {
+    log_message(
+        events,
+        "Detected JSON file (memories_history.json). Extracting records...".to_string(),
+    );
+
+    let memories: MemoriesHistoryJson =
+        serde_json::from_reader(BufReader::new(Utf8Reader::new(json_file)?))?;
+    Ok(memories
+        .saved_media
+        .into_iter()
+        .map(|media| {
+            let download_url = media.media_download_url.unwrap_or(media.download_link);
+            csv::StringRecord::from(vec![
+                media.date,
+                media.media_type,
+                media.location,
+                download_url,
+            ])
+        })
+        .collect())
+}
+
+fn parse_memories_history_html(
+    input_file: &str,
+    events: Option<&dyn EventSink>,
+) -> Result<Vec<csv::StringRecord>> {
+    let records = parse_memories_history_html_from(File::open(input_file)?, events)?;
+    if found_table(&records) {
+        return Ok(records);
+    }
+    parse_memories_history_html_tolerant(File::open(input_file)?, events)
+}
+
+// Whether parse_memories_history_html_from() found the table of memories, by
+// its row of headings. If it didn't, the file is read again with
+// parse_memories_history_html_tolerant(). A table with nothing after the
+// headings is an export without memories, and isn't read twice.
+fn found_table(records: &[csv::StringRecord]) -> bool {
+    !records.is_empty()
+}
+
+// For a page the state machine below can't make sense of, see
+// tolerant_html.rs. Slower, but it doesn't mind how the table is written.
+fn parse_memories_history_html_tolerant(
+    html_file: impl Read,
+    events: Option<&dyn EventSink>,
+) -> Result<Vec<csv::StringRecord>> {
+    log_error(
+        events,
+        "No memories found in the HTML file, reading it again with a more tolerant (slower) parser..."
+            .to_string(),
+    );
+    let records = tolerant_html::parse_memories_table(html_page_reader(html_file)?)?;
+    log_message(
+        events,
+        format!(
+            "The tolerant parser found {} memories",
+            format::format_count(records.len() - 1)
+        ),
+    );
+    Ok(records)
+}
+
+// The page in an HTML file, as UTF-8. A page saved from a browser as a
+// single file is an MHTML archive, with the page in it encoded. It's only
+// around as big as the page, so that's decoded in memory.
+fn html_page_reader<'a>(html_file: impl Read + 'a) -> Result<Box<dyn BufRead + 'a>> {
+    const BUFFER_SIZE: usize = 1024 * 16;
+    let mut html_reader = BufReader::with_capacity(BUFFER_SIZE, Utf8Reader::new(html_file)?);
+    if saved_page::is_mhtml(html_reader.fill_buf()?) {
+        let mut archive = Vec::new();
+        html_reader.read_to_end(&mut archive)?;
+        Ok(Box::new(std::io::Cursor::new(saved_page::mhtml_page(
+            &archive,
+        )?)))
+    } else {
+        Ok(Box::new(html_reader))
+    }
+}
+
+// Also used to parse the file straight out of an export zip
+fn parse_memories_history_html_from(
+    html_file: impl Read,
+    events: Option<&dyn EventSink>,
+) -> Result<Vec<csv::StringRecord>> {
+    log_message(
+        events,
+        "Detected HTML file (memories_history.html). Converting to CSV format...".to_string(),
+    );
+
+    // Read HTML file and convert to CSV format
+    let mut html_reader = html_page_reader(html_file)?;
+
+    let mut csv_records: Vec<csv::StringRecord> = Vec::new();
+    let mut parse_state = SdParseState::SearchingForTable;
+    let mut header_column_count = 0usize;
+    let mut row_column_count = 0usize;
+    let mut current_record = csv::StringRecord::new();
+    // The text of the field being read, which can go on for any number of
+    // chunks of the file, like a very long link
+    let mut current_value = Vec::new();
+    let mut append_to_current_value = false;
+    // Bytes read from the file and not parsed yet, from `parsed` on. What's
+    // left of a chunk when the next one is read is kept in front of it, so a
+    // tag split between two chunks is found whole.
+    let mut pending: Vec<u8> = Vec::new();
+    let mut parsed = 0usize;
+    // Where in the file pending starts, for the log
+    let mut file_byte_index = 0u64;
+    let mut at_eof = false;
+    const EXPECTED_COLUMNS: usize = 4;
+
+    loop {
+        // Parsing logic
+        // For an example of the HTML data we want to parse, see test_parse_html_snippet()
+
+        // Determine if there is anything we need to grab before looking for the
+        // next tag, and set what tag to look for next. Tags are matched
+        // without their ">", since pages saved from a browser can have more
+        // attributes in them, or a line break before the ">".
+        let tag = match parse_state {
+            SdParseState::SearchingForTable => "<table",
+            SdParseState::SearchingForTbody => "<tbody",
+            SdParseState::SearchingForTr => "<tr",
+            SdParseState::SearchingForTh => "<th",
+            SdParseState::SearchingForThEnd => ">",
+            SdParseState::SearchingForThClosing => "</th",
+            SdParseState::SearchingForTd => "<td",
+            SdParseState::SearchingForTdEnd => ">",
+            SdParseState::SearchingForTdClosing => "</td",
+            SdParseState::SearchingForDownloadLink => "downloadMemories('",
+            SdParseState::SearchingForDownloadLinkEnd => "',",
+            // SdParseState::SearchingForTrClosing => "</tr>",
+            // SdParseState::SearchingForHtmlTagEnd => ">",
+        };
+
+        let buffer = &pending[parsed..];
+        let index = match look_for_item(buffer, tag.as_bytes(), at_eof) {
+            SearchResult::Found(index) => index,
+            result => {
+                // Everything but what the tag may start in is done with
+                let unprocessed = match result {
+                    SearchResult::NotFoundWithUnprocessed(n) => n,
+                    _ => 0,
+                };
+                let processed = buffer.len() - unprocessed;
+                if append_to_current_value {
+                    current_value.extend_from_slice(&buffer[..processed]);
+                }
+                parsed += processed;
+                if at_eof {
+                    break;
+                }
+
+                // Read the next chunk after what's left
+                file_byte_index += parsed as u64;
+                pending.drain(..parsed);
+                parsed = 0;
+                let chunk = html_reader.fill_buf()?;
+                if chunk.is_empty() {
+                    at_eof = true;
+                } else {
+                    pending.extend_from_slice(chunk);
+                    let read = chunk.len();
+                    html_reader.consume(read);
+                }
+                debug!(
+                    "File byte index {}: Parsing {} bytes for tag '{}'... (at_eof={})",
+                    file_byte_index,
+                    pending.len(),
+                    tag,
+                    at_eof
+                );
+                continue;
+            }
+        };
+        info!(
+            "Found '{}' at file byte index {}",
+            tag,
+            file_byte_index + (parsed + index) as u64
+        );
+        // What's between the last tag and this one
+        let text = &buffer[..index];
+        parsed += index + tag.len();
+
+        // Move on to next tag
+        parse_state = match parse_state {
+            SdParseState::SearchingForTable => SdParseState::SearchingForTbody,
+            SdParseState::SearchingForTbody => SdParseState::SearchingForTr,
+            SdParseState::SearchingForTr => {
+                if header_column_count == 0 {
+                    SdParseState::SearchingForTh
+                } else {
+                    SdParseState::SearchingForTd
+                }
+            }
+            SdParseState::SearchingForTh => SdParseState::SearchingForThEnd,
+            SdParseState::SearchingForThEnd => {
+                append_to_current_value = true;
+                current_value.clear();
+                SdParseState::SearchingForThClosing
+            }
+            SdParseState::SearchingForThClosing => {
+                append_to_current_value = false;
+                current_value.extend_from_slice(text);
+                current_record.push_field(String::from_utf8_lossy(&current_value).trim());
+                header_column_count += 1;
+                if header_column_count >= EXPECTED_COLUMNS {
+                    // Finished header row
+                    csv_records.push(current_record.clone());
+                    // Reset for data row
+                    current_record.clear();
+                    SdParseState::SearchingForTr
+                } else {
+                    // Keep looking for header columns
+                    SdParseState::SearchingForTh
+                }
+            }
+            SdParseState::SearchingForTd => SdParseState::SearchingForTdEnd,
+            SdParseState::SearchingForTdEnd => {
+                if row_column_count == 3 {
+                    // Look for the download link inside this td
+                    SdParseState::SearchingForDownloadLink
+                } else {
+                    // Generic td content - save it all
+                    append_to_current_value = true;
+                    current_value.clear();
+                    SdParseState::SearchingForTdClosing
+                }
+            }
+            SdParseState::SearchingForTdClosing => {
+                append_to_current_value = false;
+                current_value.extend_from_slice(text);
+                // Browsers wrap long lines and escape characters
+                // when saving a page
+                current_record.push_field(&export::strip_html(&String::from_utf8_lossy(
+                    current_value.as_slice(),
+                )));
+                row_column_count += 1;
+                if row_column_count == 3 {
+                    // Parse the last column, the download link
+                    SdParseState::SearchingForDownloadLink
+                } else {
+                    // Keep looking for more row data columns
+                    SdParseState::SearchingForTd
+                }
+            }
+            // SdParseState::SearchingForTrClosing => SdParseState::SearchingForTr,
+            SdParseState::SearchingForDownloadLink => {
+                append_to_current_value = true;
+                current_value.clear();
+                SdParseState::SearchingForDownloadLinkEnd
+            }
+            SdParseState::SearchingForDownloadLinkEnd => {
+                append_to_current_value = false;
+                current_value.extend_from_slice(text);
+                // This should be the last column in the row
+                if row_column_count + 1 != EXPECTED_COLUMNS {
+                    log_error(
+                        events,
+                        format!(
+                            "Row {} had an unexpected number of columns",
+                            row_column_count
+                        ),
+                    );
+                }
+                // A saved page has "&amp;" between the parameters
+                let download_link = export::decode_entities(
+                    String::from_utf8_lossy(current_value.as_slice()).trim(),
+                );
+                if download_link.starts_with("https") {
+                    current_record.push_field(&download_link);
+                    csv_records.push(current_record.clone());
+                } else {
+                    log_error(
+                        events,
+                        format!(
+                            "Skipping row, its download link doesn't start with https: {}",
+                            download_link
+                        ),
+                    );
+                }
+                // Reset for next data row
+                current_record.clear();
+                row_column_count = 0;
+                // Skip looking for td end, since we got what we
+                // wanted. Move on to next data row
+                SdParseState::SearchingForTr
+            } // state => unimplemented!("Unhandled parse state: {:?}", state),
+        };
+    }
+
+    info!("Finished reading HTML file.");
+    Ok(csv_records)
+}
 
-//     // Look for downloadMemories('URL' pattern
-//     if let Some(start) = content.find("downloadMemories('") {
-//         let start_pos = start + 18; // Length of "downloadMemories('"
-//         if let Some(end) = content[start_pos..].find("'") {
-//             return Some(content[start_pos..start_pos + end].to_string());
-//         }
-//     }
-//     None
-// }
+// Shown in the GUI for DownloadOutcome::Invalid rows
+const INVALID_RECORD_ERROR: &str = "Not a memory (unexpected number of columns)";
 
-// Enum to represent the search result
 #[derive(Debug)]
-enum SearchResult {
-    NotFound,
-    Found(usize),                   // Index where found
-    NotFoundWithUnprocessed(usize), // Number of unprocessed bytes at the end
+enum DownloadOutcome {
+    Downloaded,
+    // The file was already downloaded completely
+    Skipped,
+    // The row doesn't have the shape of a record, so it can't be retried
+    Invalid,
+    // With what failed and the error, as logged
+    Failed(FailureKind, String),
+    // The run was cancelled before (or while) downloading this record
+    Cancelled,
 }
 
-// Linearly look for a pattern of bytes in a buffer. If found, return the
-// index where the tag was found in that buffer.
-// If is_last is true, then it means that this is the end of the data and we
-// don't need to combine the end of this buffer with the beginning of the next
-// buffer.
-fn look_for_item(buffer: &[u8], item: &[u8], is_last: bool) -> SearchResult {
-    let item_size = item.len();
-    let buffer_size = buffer.len();
+// What a failed download failed at, for the metrics
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FailureKind {
+    // Getting a fresh link, with --resolve-links
+    DownloadLink,
+    // No answer from the server (DNS, connecting, TLS, a timeout...)
+    Connection,
+    // The server answered with an error status
+    HttpStatus,
+    // Creating or writing the file, or reading the body into it
+    File,
+    // The body doesn't match the MD5 the server gave for it, every time
+    Checksum,
+}
 
-    if buffer_size <= 0 {
-        // Empty buffer
-        return SearchResult::NotFound;
+// The legacy filename (see naming.rs) and the download URL of a record, None
+// if the row doesn't have the shape of one. A run's files may be named
+// differently, see naming::unique_filenames_and_urls().
+fn record_filename_and_url(row: &csv::StringRecord) -> Option<(String, &str)> {
+    let download_url = naming::record_url(row)?;
+    Some((LegacyNamer.filename(0, row), download_url))
+}
+
+// Everything download_record() needs besides the record itself, shared by
+// all the workers of a run
+struct DownloadContext<'a> {
+    output_dir: &'a str,
+    // Where the files go, the output directory unless there's --s3-bucket
+    storage: &'a dyn StorageBackend,
+    http_debug_log: Option<&'a HttpDebugLog>,
+    resolve_links: bool,
+    write_exif: bool,
+    touch: bool,
+    composite_overlays: bool,
+    auto_rotate: bool,
+    sidecars: bool,
+    // Download even files that are already there
+    force: bool,
+    post_processor: Option<&'a PostProcessor>,
+    uploader: Option<&'a ImmichUploader>,
+    dedup: Option<&'a DedupIndex>,
+    // For everything but the async engine, which has its own, see fetch.rs
+    http: &'a dyn BlockingFetch,
+    rate_limiter: Option<&'a RateLimiter>,
+    host_stats: &'a HostStatsCollector,
+    // See verify_md5()
+    etag_hosts: &'a EtagHosts,
+    network: &'a NetworkMonitor,
+    backoff: &'a Backoff,
+    // How many more times a request that failed in a way that may not happen
+    // again is tried, see retry_request()
+    retries: usize,
+    // Total of this run's downloads, for the progress display
+    bytes_downloaded: &'a AtomicU64,
+    // Total size of the files skipped because they were already downloaded
+    bytes_skipped: &'a AtomicU64,
+    manifest: &'a Manifest,
+    control: &'a RunControl,
+    events: Option<&'a dyn EventSink>,
+}
+
+// A record that's going to be downloaded, as worked out from the manifest and
+// what's already in the output directory
+struct PlannedDownload<'r> {
+    path: PathBuf,
+    download_url: &'r str,
+    manifest_entry: ManifestEntry,
+    plan: DownloadPlan,
+}
+
+// Work out what to do with a record, before any request is made. Records
+// that are skipped or can't be downloaded end here, with their outcome.
+fn plan_record<'r>(
+    row: &csv::StringRecord,
+    filename_and_url: Option<&(String, &'r str)>,
+    ctx: &DownloadContext,
+) -> Result<PlannedDownload<'r>, DownloadOutcome> {
+    let row_len = row.len();
+    if row_len == 0 {
+        // Skip empty rows
+        log_error(ctx.events, "Row was empty. Skipping download".to_string());
+        return Err(DownloadOutcome::Invalid);
     }
-    if buffer_size < item_size {
-        // The buffer is too small to possibly contain the item
-        if is_last {
-            return SearchResult::NotFound;
-        } else {
-            return SearchResult::NotFoundWithUnprocessed(buffer_size);
+
+    let Some((filename, download_url)) = filename_and_url.cloned() else {
+        // Bad row data
+        log_error(
+            ctx.events,
+            format!(
+                "Row had unexpected number of columns ({}). Skipping download",
+                row_len
+            ),
+        );
+        return Err(DownloadOutcome::Invalid);
+    };
+
+    let path = Path::new(ctx.output_dir).join(&filename);
+    let previous_entry = ctx.manifest.get(&filename);
+    // A file renamed to match its type is looked for under its new name
+    let saved_path = match previous_entry.as_ref() {
+        Some(entry) => Path::new(ctx.output_dir).join(entry.saved_filename()),
+        None => path.clone(),
+    };
+    let saved_filename = previous_entry
+        .as_ref()
+        .map_or(filename.as_str(), |entry| entry.saved_filename());
+    let existing_size = match ctx.storage.stored_size(saved_filename, row) {
+        Ok(size) => size,
+        Err(e) => {
+            let error = format!(
+                "Error looking for {} in {}: {}",
+                saved_filename,
+                ctx.storage.location(),
+                e
+            );
+            log_record_error(ctx.events, &error, row);
+            return Err(DownloadOutcome::Failed(FailureKind::Connection, error));
+        }
+    };
+    let manifest_entry = ManifestEntry {
+        url: download_url.to_string(),
+        filename,
+        status: EntryStatus::Downloading,
+        bytes_written: 0,
+        checksum: None,
+        views: Vec::new(),
+        mime_type: None,
+        saved_as: None,
+        uploaded: None,
+        md5: None,
+        record: None,
+    };
+    let plan = if ctx.force {
+        DownloadPlan::Fresh
+    } else {
+        manifest::plan_download(previous_entry.as_ref(), existing_size)
+    };
+    if plan == DownloadPlan::Skip {
+        debug!(
+            "  * File already exists; skipping download: {:?}",
+            saved_path
+        );
+        let size = previous_entry
+            .as_ref()
+            .map_or(0, |entry| entry.bytes_written);
+        ctx.bytes_skipped
+            .fetch_add(size, std::sync::atomic::Ordering::Relaxed);
+        // Files downloaded before --sidecars was given get theirs too
+        if ctx.sidecars && !sidecar::sidecar_path(&saved_path).exists() {
+            let checksum = previous_entry
+                .as_ref()
+                .and_then(|entry| entry.checksum.as_deref());
+            if let Err(e) = sidecar::write_sidecar(&saved_path, row, download_url, checksum, None) {
+                log_error(
+                    ctx.events,
+                    format!("  * Error writing the sidecar of {:?}: {}", saved_path, e),
+                );
+            }
         }
+        return Err(DownloadOutcome::Skipped);
     }
-    assert!(item_size > 0, "Item size must be greater than zero");
+    Ok(PlannedDownload {
+        path,
+        download_url,
+        manifest_entry,
+        plan,
+    })
+}
 
-    for (index, window) in buffer.windows(item_size).enumerate() {
-        // info!(
-        //     "{}: {} vs. {}",
-        //     index,
-        //     String::from_utf8_lossy(window),
-        //     String::from_utf8_lossy(item)
-        // );
-        if window == item {
-            return SearchResult::Found(index);
+impl PlannedDownload<'_> {
+    // Whether start_offset() needs the size of the file on the server
+    fn needs_remote_size(&self) -> bool {
+        matches!(self.plan, DownloadPlan::AdoptExisting { .. })
+    }
+
+    // How far into the file the download starts, recording it in the
+    // manifest. None if the file that's there is adopted as it is.
+    fn start_offset(&self, remote_size: Option<u64>, ctx: &DownloadContext) -> Option<u64> {
+        let path = &self.path;
+        let offset = match self.plan {
+            // Downloaded before there was a manifest, or by a run that
+            // crashed before recording it, so it may not be complete. Adopted
+            // if it's as big as the file on the server, or if the server
+            // doesn't say.
+            DownloadPlan::AdoptExisting { size } => match remote_size {
+                Some(remote_size) if remote_size > size => {
+                    debug!(
+                        "  * {:?} is smaller than on the server ({} of {} bytes), continuing it",
+                        path, size, remote_size
+                    );
+                    size
+                }
+                Some(remote_size) if remote_size < size => {
+                    debug!(
+                        "  * {:?} is bigger than on the server ({} instead of {} bytes), downloading it again",
+                        path, size, remote_size
+                    );
+                    0
+                }
+                _ => {
+                    debug!("  * File already exists; skipping download: {:?}", path);
+                    ctx.bytes_skipped
+                        .fetch_add(size, std::sync::atomic::Ordering::Relaxed);
+                    ctx.manifest.record(ManifestEntry {
+                        status: EntryStatus::Completed,
+                        bytes_written: size,
+                        ..self.manifest_entry.clone()
+                    });
+                    return None;
+                }
+            },
+            DownloadPlan::Resume { offset } => {
+                debug!("  * Resuming download of {:?} from byte {}", path, offset);
+                offset
+            }
+            // plan_record() already skipped those
+            DownloadPlan::Fresh | DownloadPlan::Skip => 0,
+        };
+        ctx.manifest.record(ManifestEntry {
+            bytes_written: offset,
+            ..self.manifest_entry.clone()
+        });
+        Some(offset)
+    }
+
+    fn record_failure(&self, bytes_written: u64, ctx: &DownloadContext) {
+        ctx.manifest.record(ManifestEntry {
+            status: EntryStatus::Failed,
+            bytes_written,
+            ..self.manifest_entry.clone()
+        });
+    }
+}
+
+// Download a single record into output_dir, logging any problems. The
+// rayon-downloader engine calls this on each of its threads, the async one
+// only uses it to retry a file from the GUI. `filename_and_url` is the
+// record's from naming::unique_filenames_and_urls(), `id` its position in the
+// input for its Progress events (None for a retry, which isn't in a run).
+fn download_record(
+    row: &csv::StringRecord,
+    filename_and_url: Option<&(String, &str)>,
+    id: Option<usize>,
+    ctx: &DownloadContext,
+) -> DownloadOutcome {
+    let mut mismatched = None;
+    let mut checksum_retries = 0;
+    loop {
+        match download_attempt(row, filename_and_url, id, ctx, &mut mismatched) {
+            DownloadOutcome::Failed(FailureKind::Checksum, error)
+                if retry_checksum_failure(&error, &mut checksum_retries, row, ctx) => {}
+            outcome => return outcome,
+        }
+    }
+}
+
+// One try at download_record(). A body that doesn't match the server's MD5
+// is deleted and returned as a Checksum failure without logging it, for
+// download_record() to try again. `mismatched` is for verify_md5().
+fn download_attempt(
+    row: &csv::StringRecord,
+    filename_and_url: Option<&(String, &str)>,
+    id: Option<usize>,
+    ctx: &DownloadContext,
+    mismatched: &mut Option<String>,
+) -> DownloadOutcome {
+    if !ctx.control.wait_while_paused() {
+        return DownloadOutcome::Cancelled;
+    }
+    let mut planned = match plan_record(row, filename_and_url, ctx) {
+        Ok(planned) => planned,
+        Err(outcome) => return outcome,
+    };
+    let remote_size = if planned.needs_remote_size() {
+        remote_size(ctx.http, ctx.resolve_links, planned.download_url)
+    } else {
+        None
+    };
+    let Some(mut resume_offset) = planned.start_offset(remote_size, ctx) else {
+        return DownloadOutcome::Skipped;
+    };
+    // What's stored elsewhere can't be appended to, a partial file is stored
+    // again whole
+    if ctx.storage.local_dir().is_none() {
+        resume_offset = 0;
+    }
+    let path = &planned.path;
+
+    let request_start = std::time::Instant::now();
+    let resolved_url;
+    let download_url = if ctx.resolve_links {
+        match resolve_download_url(ctx.http, planned.download_url) {
+            Ok(url) => {
+                if let Some(debug_log) = ctx.http_debug_log {
+                    debug_log.record_resolved(planned.download_url, &url);
+                }
+                resolved_url = url;
+                resolved_url.as_str()
+            }
+            Err(e) => {
+                let download_url = planned.download_url;
+                ctx.host_stats
+                    .record(download_url, false, request_start.elapsed());
+                if let Some(debug_log) = ctx.http_debug_log {
+                    debug_log.record_transport_error(
+                        download_url,
+                        request_start.elapsed(),
+                        &format!("getting download link: {}", e),
+                    );
+                }
+                let error = format!("Error getting download link from {}: {}", download_url, e);
+                log_record_error(ctx.events, &error, row);
+                planned.record_failure(resume_offset, ctx);
+                return DownloadOutcome::Failed(FailureKind::DownloadLink, error);
+            }
+        }
+    } else {
+        planned.download_url
+    };
+    let debug_http = ctx.http_debug_log.is_some();
+    let mut rate_limited_retries = 0;
+    let mut retries = 0;
+    // The slot is kept until the body is downloaded
+    let (mut result, _slot) = loop {
+        let Some(slot) = ctx.backoff.start(ctx.control) else {
+            return DownloadOutcome::Cancelled;
+        };
+        let result = request_download(ctx.http, download_url, resume_offset, debug_http);
+        match &result {
+            Err(e)
+                if network::is_connection_error(e)
+                    && ctx
+                        .network
+                        .record_connection_error(|m| log_error(ctx.events, m)) =>
+            {
+                // Not this record's fault, try it again once the network is back
+                if !ctx
+                    .network
+                    .wait_until_online(download_url, ctx.control, |m| log_message(ctx.events, m))
+                {
+                    return DownloadOutcome::Cancelled;
+                }
+            }
+            // Try it again once the server's delay is over. Without
+            // --debug-http, ureq turns the 429 into an error without its
+            // Retry-After.
+            Err(ureq::Error::StatusCode(429))
+                if rate_limited_retries < backoff::MAX_RATE_LIMITED_RETRIES =>
+            {
+                rate_limited_retries += 1;
+                ctx.backoff.rate_limited(None, |m| log_error(ctx.events, m));
+            }
+            Ok(resp)
+                if rate_limited_retries < backoff::MAX_RATE_LIMITED_RETRIES
+                    && backoff::is_rate_limited(
+                        resp.status().as_u16(),
+                        backoff::retry_after(resp.headers()),
+                    ) =>
+            {
+                rate_limited_retries += 1;
+                let retry_after =
+                    backoff::retry_after(resp.headers()).and_then(backoff::parse_retry_after);
+                ctx.backoff
+                    .rate_limited(retry_after, |m| log_error(ctx.events, m));
+            }
+            Err(e)
+                if retries < ctx.retries
+                    && (network::is_connection_error(e)
+                        || network::is_timeout(e)
+                        || matches!(e, ureq::Error::StatusCode(status)
+                            if backoff::is_transient_status(*status))) =>
+            {
+                retries += 1;
+                let error = format!("Error downloading from {}: {}", download_url, e);
+                // Others can download in the meantime
+                drop(slot);
+                if !ctx.control.sleep(retry_request(&error, retries, ctx)) {
+                    return DownloadOutcome::Cancelled;
+                }
+            }
+            Ok(resp)
+                if retries < ctx.retries
+                    && backoff::is_transient_status(resp.status().as_u16()) =>
+            {
+                retries += 1;
+                let error = format!(
+                    "Error downloading from {}: http status: {}",
+                    download_url,
+                    resp.status().as_u16()
+                );
+                drop(slot);
+                if !ctx.control.sleep(retry_request(&error, retries, ctx)) {
+                    return DownloadOutcome::Cancelled;
+                }
+            }
+            Err(_) => break (result, slot),
+            Ok(resp) => {
+                ctx.network.record_success();
+                if resp.status().is_success() {
+                    ctx.backoff.succeeded(|m| log_message(ctx.events, m));
+                }
+                break (result, slot);
+            }
+        }
+    };
+    let range_not_satisfiable = match &result {
+        Err(ureq::Error::StatusCode(416)) => true,
+        Ok(resp) => resp.status() == 416,
+        Err(_) => false,
+    };
+    if resume_offset > 0 && range_not_satisfiable {
+        // The partial file doesn't match what the server has, start over
+        debug!("  * Can't resume {:?}, downloading it again", path);
+        resume_offset = 0;
+        result = request_download(ctx.http, download_url, 0, debug_http);
+    }
+    let mut resp = match result {
+        Ok(r) => r,
+        Err(e) => {
+            ctx.host_stats
+                .record(download_url, false, request_start.elapsed());
+            if let Some(debug_log) = ctx.http_debug_log {
+                debug_log.record_transport_error(
+                    download_url,
+                    request_start.elapsed(),
+                    &e.to_string(),
+                );
+            }
+            // Without --debug-http, ureq turns bad statuses into errors
+            let kind = match e {
+                ureq::Error::StatusCode(_) => FailureKind::HttpStatus,
+                _ => FailureKind::Connection,
+            };
+            let error = format!("Error downloading from {}: {}", download_url, e);
+            log_record_error(ctx.events, &error, row);
+            planned.record_failure(resume_offset, ctx);
+            return DownloadOutcome::Failed(kind, error);
+        }
+    };
+
+    if !resp.status().is_success() {
+        // Only reachable in --debug-http mode, otherwise ureq already
+        // turned this into an error above
+        ctx.host_stats
+            .record(download_url, false, request_start.elapsed());
+        let error = format!("http status: {}", resp.status().as_u16());
+        if let Some(debug_log) = ctx.http_debug_log {
+            let body = http_debug::body_snippet(resp.body_mut().as_reader());
+            debug_log.record_response(download_url, &resp, request_start.elapsed(), &error, &body);
+        }
+        let error = format!("Error downloading from {}: {}", download_url, error);
+        log_record_error(ctx.events, &error, row);
+        planned.record_failure(resume_offset, ctx);
+        return DownloadOutcome::Failed(FailureKind::HttpStatus, error);
+    }
+
+    // A server that doesn't support ranges sends the whole file instead
+    if resp.status() != ureq::http::StatusCode::PARTIAL_CONTENT {
+        resume_offset = 0;
+    }
+    let content_type = resp
+        .headers()
+        .get(ureq::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let progress = |bytes| {
+        id.zip(ctx.events)
+            .map(|(id, events)| FileProgress::new(events, id, bytes))
+    };
+    if ctx.storage.local_dir().is_none() {
+        return store_download(
+            row,
+            ctx,
+            planned,
+            resp,
+            download_url,
+            request_start,
+            progress(0),
+        );
+    }
+    // Only a body that's the whole file can be checked
+    let server_md5 = if resume_offset == 0 {
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        let server_md5 = ServerMd5::from_headers(header("content-md5"), header("etag"));
+        ctx.etag_hosts.filter(server_md5, download_url)
+    } else {
+        None
+    };
+
+    // Create the file AFTER the download, so we don't have a ton of open
+    // files and exhaust Linux's default per-process open file limit.
+    let file_result = if resume_offset > 0 {
+        fs::OpenOptions::new().append(true).open(path)
+    } else {
+        File::create(path)
+    };
+    let mut file = match file_result {
+        Ok(f) if server_md5.is_some() => HashingWriter::new(f).with_md5(),
+        Ok(f) => HashingWriter::new(f),
+        Err(e) => {
+            let error = format!("Error creating file {:?}: {}", path, e);
+            log_record_error(ctx.events, &error, row);
+            planned.record_failure(resume_offset, ctx);
+            stop_if_disk_full(&e, ctx.control);
+            return DownloadOutcome::Failed(FailureKind::File, error);
+        }
+    };
+
+    // The checksum covers the whole file, so when continuing a partial file
+    // the part that's already there is hashed first
+    let mut copy_result = if resume_offset > 0 {
+        File::open(path).and_then(|existing| file.update_from(existing.take(resume_offset)))
+    } else {
+        Ok(())
+    };
+    if copy_result.is_ok() {
+        let mut body_reader = CancellableReader::new(
+            CountingReader::new(
+                ThrottledReader::new(resp.body_mut().as_reader(), ctx.rate_limiter),
+                ctx.bytes_downloaded,
+            )
+            .with_progress(progress(resume_offset)),
+            ctx.control,
+        );
+        copy_result = copy(&mut body_reader, &mut file).map(|_| ());
+    }
+    match copy_result.and_then(|_| file.finish()) {
+        Ok(file_hash) => {
+            match verify_md5(
+                server_md5,
+                &file_hash,
+                download_url,
+                mismatched,
+                ctx.etag_hosts,
+            ) {
+                Ok(md5) => planned.manifest_entry.md5 = md5,
+                Err(e) => {
+                    ctx.host_stats
+                        .record(download_url, false, request_start.elapsed());
+                    // Nothing in it is worth continuing from
+                    let _ = fs::remove_file(path);
+                    planned.record_failure(0, ctx);
+                    let error = format!("Downloaded from {}, but {}", download_url, e);
+                    return DownloadOutcome::Failed(FailureKind::Checksum, error);
+                }
+            }
+            ctx.host_stats
+                .record(download_url, true, request_start.elapsed());
+            finish_download(
+                row,
+                ctx,
+                planned,
+                file_hash,
+                content_type.as_deref(),
+                download_url,
+            )
+        }
+        Err(e) => {
+            // The partial file is kept, and continued by the next run
+            let bytes_written = fs::metadata(path)
+                .map(|metadata| metadata.len())
+                .unwrap_or(0);
+            if ctx.control.is_cancelled() {
+                debug!("  * Cancelled download of {}", download_url);
+                ctx.manifest.record(ManifestEntry {
+                    bytes_written,
+                    ..planned.manifest_entry
+                });
+                return DownloadOutcome::Cancelled;
+            }
+
+            ctx.host_stats
+                .record(download_url, false, request_start.elapsed());
+            if let Some(debug_log) = ctx.http_debug_log {
+                debug_log.record_response(
+                    download_url,
+                    &resp,
+                    request_start.elapsed(),
+                    &e.to_string(),
+                    &[],
+                );
+            }
+            let error = format!("Downloaded, but error writing to file {:?}: {}", path, e);
+            log_record_error(ctx.events, &error, row);
+            planned.record_failure(bytes_written, ctx);
+            stop_if_disk_full(&e, ctx.control);
+            DownloadOutcome::Failed(FailureKind::File, error)
+        }
+    }
+}
+
+// Stream the body of a download to storage that isn't the output directory,
+// and record it in the manifest. That's all there is to it: the options
+// that work on the file can't be used with --s3-bucket.
+fn store_download(
+    row: &csv::StringRecord,
+    ctx: &DownloadContext,
+    planned: PlannedDownload,
+    mut resp: ureq::http::Response<ureq::Body>,
+    download_url: &str,
+    request_start: Instant,
+    progress: Option<FileProgress>,
+) -> DownloadOutcome {
+    let content_type = resp
+        .headers()
+        .get(ureq::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let content_type = content_type.as_deref();
+    let size = resp
+        .headers()
+        .get(ureq::http::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+    let filename = &planned.manifest_entry.filename;
+    let mut body = HashingReader::new(CancellableReader::new(
+        CountingReader::new(
+            ThrottledReader::new(resp.body_mut().as_reader(), ctx.rate_limiter),
+            ctx.bytes_downloaded,
+        )
+        .with_progress(progress),
+        ctx.control,
+    ));
+    let result = ctx
+        .storage
+        .put(filename, row, &mut body, size, content_type);
+    let file_hash = body.finish();
+    if let Err(e) = result {
+        if ctx.control.is_cancelled() {
+            debug!("  * Cancelled download of {}", download_url);
+            ctx.manifest.record(planned.manifest_entry);
+            return DownloadOutcome::Cancelled;
+        }
+        ctx.host_stats
+            .record(download_url, false, request_start.elapsed());
+        if let Some(debug_log) = ctx.http_debug_log {
+            debug_log.record_response(
+                download_url,
+                &resp,
+                request_start.elapsed(),
+                &e.to_string(),
+                &[],
+            );
+        }
+        let error = format!(
+            "Downloaded, but error storing {} in {}: {}",
+            filename,
+            ctx.storage.location(),
+            e
+        );
+        log_record_error(ctx.events, &error, row);
+        planned.record_failure(0, ctx);
+        return DownloadOutcome::Failed(FailureKind::File, error);
+    }
+    ctx.host_stats
+        .record(download_url, true, request_start.elapsed());
+    ctx.manifest.record(ManifestEntry {
+        status: EntryStatus::Completed,
+        bytes_written: file_hash.size,
+        checksum: Some(file_hash.hash),
+        mime_type: content_type.map(str::to_string),
+        ..planned.manifest_entry
+    });
+    DownloadOutcome::Downloaded
+}
+
+// What's left of a download once the body is in the file: overlays, the
+// real media type, duplicates, rotation, EXIF tags and file times, the
+// manifest, then --post-process-cmd. All of it blocking work on the file.
+fn finish_download(
+    row: &csv::StringRecord,
+    ctx: &DownloadContext,
+    planned: PlannedDownload,
+    mut file_hash: hashing::FileHash,
+    mut content_type: Option<&str>,
+    download_url: &str,
+) -> DownloadOutcome {
+    let PlannedDownload {
+        mut path,
+        mut manifest_entry,
+        ..
+    } = planned;
+    let mut overlay_sidecar = None;
+    match overlay::unpack_overlay_bundle(&path, ctx.composite_overlays) {
+        Ok(None) => {}
+        Ok(Some(unpacked)) => {
+            debug!("  * Unpacked overlay bundle {:?}: {:?}", path, unpacked);
+            if let overlay::Unpacked::Sidecar(sidecar) = unpacked {
+                overlay_sidecar = Some(sidecar);
+            }
+            // That was the type of the zip
+            content_type = None;
+            match hashing::hash_file(&path) {
+                Ok(unpacked_hash) => file_hash = unpacked_hash,
+                Err(e) => error!("Error hashing {:?}: {}", path, e),
+            }
+        }
+        Err(e) => log_error(
+            ctx.events,
+            format!("  * Error unpacking overlay bundle {:?}: {}", path, e),
+        ),
+    }
+    match media_type::detect_file(&path, content_type) {
+        Ok(Some(detected)) => {
+            manifest_entry.mime_type = Some(detected.mime_type.to_string());
+            let extension = path.extension().unwrap_or_default();
+            if !extension.eq_ignore_ascii_case(detected.extension) {
+                let renamed = path.with_extension(detected.extension);
+                match fs::rename(&path, &renamed) {
+                    Ok(()) => {
+                        debug!("  * {:?} is {}, renamed it", path, detected.mime_type);
+                        // In the same folder as the filename, for chat media
+                        manifest_entry.saved_as = Some(
+                            Path::new(&manifest_entry.filename)
+                                .with_extension(detected.extension)
+                                .to_string_lossy()
+                                .replace('\\', "/"),
+                        );
+                        path = renamed;
+                    }
+                    Err(e) => {
+                        log_error(ctx.events, format!("  * Error renaming {:?}: {}", path, e))
+                    }
+                }
+            }
+        }
+        Ok(None) => debug!("  * Couldn't tell the type of {:?}", path),
+        Err(e) => error!("Error reading {:?}: {}", path, e),
+    }
+    // Before anything changes the file, copies of a photo with
+    // different timestamps only match as downloaded
+    if let Some(dedup) = ctx.dedup {
+        match dedup.dedup(manifest_entry.saved_filename(), &file_hash) {
+            Ok(None) => {}
+            Ok(Some(original)) => {
+                log_message(
+                    ctx.events,
+                    format!(
+                        "  * {} is a duplicate of {} ({})",
+                        manifest_entry.saved_filename(),
+                        original,
+                        dedup.mode().label().to_lowercase()
+                    ),
+                );
+                ctx.manifest.record(ManifestEntry {
+                    status: EntryStatus::Duplicate,
+                    bytes_written: file_hash.size,
+                    checksum: Some(file_hash.hash),
+                    ..manifest_entry
+                });
+                return DownloadOutcome::Downloaded;
+            }
+            Err(e) => log_error(
+                ctx.events,
+                format!("  * Error checking {:?} for duplicates: {}", path, e),
+            ),
+        }
+    }
+    // Before the capture tags are added, which keeps the tags the
+    // rotation left
+    if ctx.auto_rotate {
+        match exif_tags::rotate_upright(&path) {
+            Ok(false) => {}
+            Ok(true) => match hashing::hash_file(&path) {
+                Ok(rotated_hash) => file_hash = rotated_hash,
+                Err(e) => error!("Error hashing {:?}: {}", path, e),
+            },
+            Err(e) => log_error(ctx.events, format!("  * Error rotating {:?}: {}", path, e)),
+        }
+    }
+    if ctx.write_exif {
+        file_hash = add_capture_exif(&path, row, ctx.events).unwrap_or(file_hash);
+    }
+    // After the EXIF tags, since writing those changes the times
+    if ctx.touch
+        && let Some(timestamp) = record::record_timestamp(row)
+    {
+        for touched_path in std::iter::once(&path).chain(overlay_sidecar.as_ref()) {
+            if let Err(e) = set_file_times(touched_path, timestamp) {
+                log_error(
+                    ctx.events,
+                    format!("  * Error setting file times of {:?}: {}", touched_path, e),
+                );
+            }
+        }
+    }
+    // With the final checksum, once nothing changes the file anymore
+    if ctx.sidecars
+        && let Err(e) = sidecar::write_sidecar(
+            &path,
+            row,
+            &manifest_entry.url,
+            Some(&file_hash.hash),
+            Some(chrono::Utc::now()),
+        )
+    {
+        log_error(
+            ctx.events,
+            format!("  * Error writing the sidecar of {:?}: {}", path, e),
+        );
+    }
+    let manifest_entry = ManifestEntry {
+        status: EntryStatus::Completed,
+        bytes_written: file_hash.size,
+        checksum: Some(file_hash.hash),
+        ..manifest_entry
+    };
+    ctx.manifest.record(manifest_entry.clone());
+    // Once the file is done, but before the command moves it
+    if let Some(uploader) = ctx.uploader {
+        upload_download(uploader, &path, row, manifest_entry, ctx);
+    }
+    // Last, the command may well move or change the file
+    if let Some(post_processor) = ctx.post_processor {
+        run_post_process(post_processor, &path, ctx.events);
+    }
+    debug!("  * Downloaded {}", download_url);
+    DownloadOutcome::Downloaded
+}
+
+// Upload a downloaded file with --upload, and with --upload-only delete it.
+// A failed upload is only logged, the file is still downloaded, and the next
+// run uploads it.
+fn upload_download(
+    uploader: &ImmichUploader,
+    path: &Path,
+    row: &csv::StringRecord,
+    manifest_entry: ManifestEntry,
+    ctx: &DownloadContext,
+) {
+    let uploaded = match uploader.upload(path, row) {
+        Ok(uploaded) => uploaded,
+        Err(e) => {
+            log_record_error(
+                ctx.events,
+                &format!("Error uploading {:?}: {}", path, e),
+                row,
+            );
+            return;
         }
+    };
+    match &uploaded {
+        Uploaded::Created(_) => debug!("  * Uploaded {:?}", path),
+        Uploaded::Duplicate(_) => debug!("  * {:?} was already on the server", path),
+    }
+    let mut manifest_entry = ManifestEntry {
+        uploaded: Some(uploaded.asset_id().to_string()),
+        ..manifest_entry
+    };
+    if uploader.upload_only() {
+        match fs::remove_file(path) {
+            Ok(()) => manifest_entry.status = EntryStatus::Uploaded,
+            Err(e) => log_error(
+                ctx.events,
+                format!("  * Error deleting the uploaded {:?}: {}", path, e),
+            ),
+        }
+    }
+    ctx.manifest.record(manifest_entry);
+}
+
+// Embed the record's capture time and location into the downloaded file, if
+// it's a JPEG. Returns the hash of the changed file, for the manifest.
+fn add_capture_exif(
+    path: &Path,
+    row: &csv::StringRecord,
+    events: Option<&dyn EventSink>,
+) -> Option<hashing::FileHash> {
+    let timestamp = record::record_timestamp(row)?;
+    match exif_tags::write_capture_exif(path, timestamp, record::record_location(row)) {
+        Ok(true) => match hashing::hash_file(path) {
+            Ok(file_hash) => Some(file_hash),
+            Err(e) => {
+                error!("Error hashing {:?}: {}", path, e);
+                None
+            }
+        },
+        // Not a JPEG
+        Ok(false) => None,
+        Err(e) => {
+            // The photo itself is fine, so this doesn't fail the download
+            log_error(
+                events,
+                format!("  * Error writing EXIF tags to {:?}: {}", path, e),
+            );
+            None
+        }
+    }
+}
+
+// Run --post-process-cmd on a downloaded file, logging what it printed. A
+// failing command doesn't fail the download, the file itself is fine.
+fn run_post_process(post_processor: &PostProcessor, path: &Path, events: Option<&dyn EventSink>) {
+    let output = match post_processor.run(path) {
+        Ok(output) => output,
+        Err(e) => {
+            log_error(
+                events,
+                format!(
+                    "  * Error running {:?} on {:?}: {}",
+                    post_processor.command(),
+                    path,
+                    e
+                ),
+            );
+            return;
+        }
+    };
+    for line in String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .chain(String::from_utf8_lossy(&output.stderr).lines())
+    {
+        log_message(events, format!("  * [post-process] {}", line));
+    }
+    if !output.status.success() {
+        log_error(
+            events,
+            format!(
+                "  * {:?} failed on {:?} ({})",
+                post_processor.command(),
+                path,
+                output.status
+            ),
+        );
     }
+}
+
+// Every download after this one would fail too, so stop the run instead
+fn stop_if_disk_full(error: &std::io::Error, control: &RunControl) {
+    if error.kind() == std::io::ErrorKind::StorageFull && !control.is_cancelled() {
+        warn!("Output disk is full, stopping run");
+        control.stop(StopReason::DiskFull);
+    }
+}
 
-    // We did not find the item
+// Set the modified (and on Windows, created) time of a file to when the
+// memory was taken, so file managers and photo importers sort it correctly
+fn set_file_times(path: &Path, timestamp: chrono::DateTime<chrono::Utc>) -> std::io::Result<()> {
+    let time = std::time::SystemTime::from(timestamp);
+    let times = fs::FileTimes::new().set_accessed(time).set_modified(time);
+    #[cfg(windows)]
+    let times = std::os::windows::fs::FileTimesExt::set_created(times, time);
+    File::options().write(true).open(path)?.set_times(times)
+}
 
-    // This is the last buffer, so the windows covered all bytes
-    if is_last {
-        return SearchResult::NotFound;
-    }
+// The links in memories_history.html are really the parameters of a POST,
+// which the Snapchat web page sends to get a freshly signed link to the media.
+// A plain GET of the link only works for a few days after the export.
+fn resolve_download_url(http: &dyn BlockingFetch, download_url: &str) -> Result<String> {
+    let (endpoint, params) = split_post_url(download_url)
+        .ok_or_else(|| anyhow::anyhow!("link has no parameters to POST"))?;
+    let response = http
+        .post_form(endpoint, params)?
+        .body_mut()
+        .read_to_string()?;
+    resolved_link(&response)
+}
 
-    // The end of this buffer needs to be combined with the start of the next
-    // buffer, and windows() can't check the last (item_size - 1) bytes
-    let unprocessed = item_size - 1;
-    SearchResult::NotFoundWithUnprocessed(unprocessed)
+// The download link in the response to the POST, which is nothing but the link
+fn resolved_link(response: &str) -> Result<String> {
+    let resolved = response.trim();
+    if !resolved.starts_with("https://") {
+        return Err(anyhow::anyhow!(
+            "unexpected response: {}",
+            resolved.chars().take(100).collect::<String>()
+        ));
+    }
+    Ok(resolved.to_string())
 }
 
-#[derive(Debug)]
-enum SdParseState {
-    SearchingForTable,
-    SearchingForTbody,
-    SearchingForTr,
-    SearchingForTh,
-    SearchingForThEnd,
-    SearchingForThClosing,
-    SearchingForTd,
-    SearchingForTdEnd,
-    SearchingForTdClosing,
-    SearchingForDownloadLink,
-    SearchingForDownloadLinkEnd,
-    // SearchingForTrClosing,
-    // SearchingForTableClosing,
-    // SearchingForTbodyClosing,
-    // SearchingForHtmlTagEnd,
-    // SearchingForHtmlTagStart,
-    // SearchingForNextNonWhitespace,
-    // SearchingForAttribute,
-    // SearchingForAttributeEnd,
-    // SearchingForAttributeValueStart,
-    // SearchingForAttributeValueEnd,
-    // SearchingForQuote,
-    // SearchingForQuoteEnd,
-    // LookingForDate,
-    // LookingForMediaType,
-    // LookingForLocation,
-    // LookingForDownloadLink,
+// Split a link into the endpoint to POST to, and the form encoded body
+fn split_post_url(download_url: &str) -> Option<(&str, &str)> {
+    download_url
+        .split_once('?')
+        .filter(|(_, params)| !params.is_empty())
 }
 
-// fn parse_next(buffer: &[u8], state: &SdParseState) -> usize {
-//     return 0;
-// }
+// Size of a download on the server, to check a file the manifest doesn't
+// know about against. Asked for with a one byte Range GET rather than a HEAD,
+// as signed links are often only valid for GET. None if the request fails or
+// the server doesn't say.
+fn remote_size(http: &dyn BlockingFetch, resolve_links: bool, download_url: &str) -> Option<u64> {
+    let resolved_url;
+    let download_url = if resolve_links {
+        resolved_url = resolve_download_url(http, download_url)
+            .inspect_err(|e| debug!("Error getting download link from {}: {}", download_url, e))
+            .ok()?;
+        resolved_url.as_str()
+    } else {
+        download_url
+    };
+    let response = http
+        .get(download_url, Some("bytes=0-0"), false)
+        .inspect_err(|e| debug!("Error getting the size of {}: {}", download_url, e))
+        .ok()?;
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value: &ureq::http::HeaderValue| value.to_str().ok())
+    };
+    total_size(
+        response.status() == ureq::http::StatusCode::PARTIAL_CONTENT,
+        header("content-range"),
+        header("content-length"),
+    )
+}
 
-fn parse_memories_history_html(
-    input_file: &str,
-    gui_console: Option<&mpsc::Sender<String>>,
-) -> Result<Vec<csv::StringRecord>> {
+// --space-check: estimate the space the files left to download take, from
+// the sizes the server gives, and log it. A --space-check full run that
+// knows every size doesn't start without the space for them.
+fn check_space(
+    filenames: &[Option<(String, &str)>],
+    dir: &Path,
+    check: SpaceCheck,
+    jobs: usize,
+    ctx: &DownloadContext,
+) -> Result<Option<SpaceEstimate>> {
+    let Some(free) = output_dir_free_space(dir) else {
+        return Ok(None);
+    };
+    // Partial files count whole, they may have to start over
+    let to_download: Vec<&str> = filenames
+        .iter()
+        .flatten()
+        .filter(|(filename, _)| {
+            ctx.force
+                || match ctx.manifest.get(filename) {
+                    Some(entry) => !matches!(
+                        entry.status,
+                        EntryStatus::Completed | EntryStatus::Duplicate | EntryStatus::Uploaded
+                    ),
+                    // From before the manifest, if it's there
+                    None => !dir.join(filename).exists(),
+                }
+        })
+        .map(|(_, url)| *url)
+        .collect();
+    if to_download.is_empty() {
+        return Ok(None);
+    }
+    let sample = space_check::sample_indices(to_download.len(), check);
     log_message(
-        gui_console,
-        "Detected HTML file (memories_history.html). Converting to CSV format...".to_string(),
+        ctx.events,
+        format!(
+            "Checking the size of {} files...",
+            format::format_count(sample.len())
+        ),
     );
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let sizes = std::sync::Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, sample.len()) {
+            scope.spawn(|| {
+                while !ctx.control.is_cancelled() {
+                    let next = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    let Some(&index) = sample.get(next) else {
+                        break;
+                    };
+                    if let Some(size) = remote_size(ctx.http, ctx.resolve_links, to_download[index])
+                    {
+                        sizes.lock().unwrap().push(size);
+                    }
+                }
+            });
+        }
+    });
+    let sizes = sizes.into_inner().unwrap();
+    let full = check == SpaceCheck::Full;
+    let Some(estimate) = SpaceEstimate::new(&sizes, to_download.len(), free, full) else {
+        log_error(
+            ctx.events,
+            "Couldn't check the space the files need, the server gave no sizes".to_string(),
+        );
+        return Ok(None);
+    };
+    if estimate.is_enough() {
+        log_message(ctx.events, format!("Space: {}", estimate.message()));
+    } else if estimate.exact {
+        anyhow::bail!(
+            "not enough space in {}: {}",
+            dir.display(),
+            estimate.message()
+        );
+    } else {
+        log_error(
+            ctx.events,
+            format!(
+                "Probably not enough space: {}. The run stops once the disk is full.",
+                estimate.message()
+            ),
+        );
+    }
+    Ok(Some(estimate))
+}
 
-    // Read HTML file and convert to CSV format
-    let html_file = File::open(input_file)?;
-    const BUFFER_SIZE: usize = 1024 * 16;
-    let mut html_reader = BufReader::with_capacity(BUFFER_SIZE, html_file);
+// For the GUI's planner: the sizes the server gives for a sample of the
+// records' files, spread over them as for --space-check sample
+fn sample_sizes(
+    http: &dyn BlockingFetch,
+    resolve_links: bool,
+    records: &[csv::StringRecord],
+) -> SizeSample {
+    let records: Vec<(&str, &str)> = records
+        .iter()
+        .filter_map(|row| Some((row.get(1)?, naming::record_url(row)?)))
+        .collect();
+    let sample = space_check::sample_indices(records.len(), SpaceCheck::Sample);
+    let sizes = std::sync::Mutex::new(SizeSample::default());
+    std::thread::scope(|scope| {
+        for index in sample {
+            let (media_type, download_url) = records[index];
+            let sizes = &sizes;
+            scope.spawn(move || {
+                if let Some(size) = remote_size(http, resolve_links, download_url) {
+                    sizes.lock().unwrap().add(media_type, size);
+                }
+            });
+        }
+    });
+    sizes.into_inner().unwrap()
+}
 
-    let mut csv_records: Vec<csv::StringRecord> = Vec::new();
-    let mut file_byte_index = 0u64;
-    let mut parse_state = SdParseState::SearchingForTable;
-    let mut header_column_count = 0usize;
-    let mut row_column_count = 0usize;
-    let mut current_record = csv::StringRecord::new();
-    let mut current_value = Vec::new();
-    let mut append_to_current_value = false;
-    let mut leftover_bytes: Vec<u8> = Vec::new();
-    let mut leftover_bytes_count = 0usize;
-    const EXPECTED_COLUMNS: usize = 4;
+// The full size of a file, from the headers of a response to a Range
+// request. A partial response has it after the slash in Content-Range
+// ("bytes 0-0/12345"), a server ignoring the range sends the whole file.
+fn total_size(
+    partial: bool,
+    content_range: Option<&str>,
+    content_length: Option<&str>,
+) -> Option<u64> {
+    if partial {
+        content_range?.rsplit_once('/')?.1.trim().parse().ok()
+    } else {
+        content_length?.trim().parse().ok()
+    }
+}
 
-    loop {
-        // Parsing logic
-        // For an example of the HTML data we want to parse, see test_parse_html_snippet()
+// GET a download URL, starting at `offset` bytes in (with a Range request) to
+// continue a partial file
+fn request_download(
+    http: &dyn BlockingFetch,
+    download_url: &str,
+    offset: u64,
+    debug_http: bool,
+) -> Result<ureq::http::Response<ureq::Body>, ureq::Error> {
+    let range = (offset > 0).then(|| format!("bytes={}-", offset));
+    // Keep the response (and its headers) for bad statuses so they can be
+    // written to the debug log
+    http.get(download_url, range.as_deref(), debug_http)
+}
 
-        // Determine if there is anything we need to grab before looking for the
-        // next tag, and set what tag to look for next
-        let tag = match parse_state {
-            SdParseState::SearchingForTable => Some("<table>"),
-            SdParseState::SearchingForTbody => Some("<tbody>"),
-            SdParseState::SearchingForTr => Some("<tr>"),
-            SdParseState::SearchingForTh => Some("<th"),
-            SdParseState::SearchingForThEnd => Some(">"),
-            SdParseState::SearchingForThClosing => Some("</th>"),
-            SdParseState::SearchingForTd => Some("<td"),
-            SdParseState::SearchingForTdEnd => Some(">"),
-            SdParseState::SearchingForTdClosing => Some("</td>"),
-            SdParseState::SearchingForDownloadLink => Some("downloadMemories('"),
-            SdParseState::SearchingForDownloadLinkEnd => Some("',"),
-            // SdParseState::SearchingForTrClosing => Some("</tr>"),
-            // SdParseState::SearchingForHtmlTagEnd => Some(">"),
-            // _ => None,
-        };
+// Parse the input file into records, whatever kind of file it is
+fn read_input_records(
+    input_file: &str,
+    events: Option<&dyn EventSink>,
+) -> Result<Vec<csv::StringRecord>> {
+    read_input_sections(input_file, &[Section::Memories], events)
+}
 
-        match tag {
-            Some(tag) => {
-                // Since we are looking for a tag, read in data and search for it
-                let buffer_raw = html_reader.fill_buf()?;
-                if buffer_raw.is_empty() {
-                    break; // EOF
-                }
-
-                if leftover_bytes_count == 0 && buffer_raw.len() < tag.len() {
-                    leftover_bytes_count = buffer_raw.len();
-                    leftover_bytes.extend_from_slice(buffer_raw);
-                    // Load the next chunk
-                    html_reader.consume(leftover_bytes_count);
-                    continue;
-                }
-
-                let buffer = if leftover_bytes.len() > 0 {
-                    // We have some bytes left over from the previous chunk that
-                    // need to be parsed properly, but we only need to extend it
-                    // as much with the current chunk as is necessary to parse
-                    // the tag (hence the - 1)
-                    leftover_bytes.extend_from_slice(&buffer_raw[..tag.len() - 1]);
-                    &leftover_bytes[..]
-                } else {
-                    buffer_raw
-                };
+// The same, with the `sections` of an export zip. Other input files are one
+// section already.
+fn read_input_sections(
+    input_file: &str,
+    sections: &[Section],
+    events: Option<&dyn EventSink>,
+) -> Result<Vec<csv::StringRecord>> {
+    log_message(events, format!("Reading input file {input_file}..."));
 
-                let is_last = buffer.len() <= tag.len();
+    // Determine if this is memories_history.html/.json, snap_export.csv or
+    // the export zip
+    match InputFormat::detect(input_file) {
+        Some(InputFormat::MemoriesHtml) => {
+            let mut records = parse_memories_history_html(input_file, events)?;
+            skip_header_row(&mut records);
+            Ok(records)
+        }
+        Some(InputFormat::MemoriesJson) => parse_memories_history_json(input_file, events),
+        Some(
+            format @ (InputFormat::ChatHistoryHtml
+            | InputFormat::ChatHistoryJson
+            | InputFormat::SectionHtml(_)
+            | InputFormat::SectionJson(_)),
+        ) => {
+            log_message(
+                events,
+                "Detected chats, stories or Spotlight. Extracting the media in them...".to_string(),
+            );
+            read_section_file(File::open(input_file)?, format)
+        }
+        Some(InputFormat::SnapExportCsv) => {
+            log_message(
+                events,
+                "Detected CSV file (snap_export.html). Extracting records...".to_string(),
+            );
 
-                log_message(
-                    gui_console,
-                    format!(
-                        "File byte index {}: Parsing {} bytes for tag '{}'... (is_last={})",
-                        file_byte_index,
-                        buffer.len(),
-                        tag,
-                        is_last
-                    ),
-                );
-                let mut processed;
-                match look_for_item(&buffer, tag.as_bytes(), is_last) {
-                    SearchResult::Found(index) => {
-                        info!(
-                            "Found '{}' at file byte index {} (buffer byte index {index})",
-                            tag,
-                            file_byte_index + (index as u64) - (leftover_bytes_count as u64)
-                        );
-                        processed = index + tag.len();
-
-                        // Move on to next tag
-                        parse_state = match parse_state {
-                            SdParseState::SearchingForTable => SdParseState::SearchingForTbody,
-                            SdParseState::SearchingForTbody => SdParseState::SearchingForTr,
-                            SdParseState::SearchingForTr => {
-                                if header_column_count == 0 {
-                                    SdParseState::SearchingForTh
-                                } else {
-                                    SdParseState::SearchingForTd
-                                }
-                            }
-                            SdParseState::SearchingForTh => SdParseState::SearchingForThEnd,
-                            SdParseState::SearchingForThEnd => SdParseState::SearchingForThClosing,
-                            SdParseState::SearchingForThClosing => {
-                                current_record
-                                    .push_field(&String::from_utf8_lossy(&buffer[..index]).trim());
-                                header_column_count += 1;
-                                if header_column_count >= EXPECTED_COLUMNS {
-                                    // Finished header row
-                                    csv_records.push(current_record.clone());
-                                    // Reset for data row
-                                    current_record.clear();
-                                    SdParseState::SearchingForTr
-                                } else {
-                                    // Keep looking for header columns
-                                    SdParseState::SearchingForTh
-                                }
-                            }
-                            SdParseState::SearchingForTd => SdParseState::SearchingForTdEnd,
-                            SdParseState::SearchingForTdEnd => {
-                                if row_column_count == 3 {
-                                    // Look for the download link inside this td
-                                    SdParseState::SearchingForDownloadLink
-                                } else {
-                                    // Generic td content - save it all
-                                    append_to_current_value = true;
-                                    current_value.clear();
-                                    SdParseState::SearchingForTdClosing
-                                }
-                            }
-                            SdParseState::SearchingForTdClosing => {
-                                append_to_current_value = false;
-                                current_value.extend_from_slice(&buffer[..index]);
-                                current_record.push_field(
-                                    &String::from_utf8_lossy(current_value.as_slice()).trim(),
-                                );
-                                row_column_count += 1;
-                                if row_column_count == 3 {
-                                    // Parse the last column, the download link
-                                    SdParseState::SearchingForDownloadLink
-                                } else {
-                                    // Keep looking for more row data columns
-                                    SdParseState::SearchingForTd
-                                }
-                            }
-                            // SdParseState::SearchingForTrClosing => SdParseState::SearchingForTr,
-                            SdParseState::SearchingForDownloadLink => {
-                                append_to_current_value = true;
-                                current_value.clear();
-                                SdParseState::SearchingForDownloadLinkEnd
+            // Rows of chats, stories and Spotlight can have a folder after
+            // the other columns, see export.rs
+            let mut rdr = csv::ReaderBuilder::new()
+                .flexible(true)
+                .from_reader(Utf8Reader::new(File::open(input_file)?)?);
+
+            // Collect all records first. The header row was already read by
+            // the csv reader.
+            Ok(rdr.records().collect::<Result<_, _>>()?)
+        }
+        Some(InputFormat::ExportZip) => {
+            let zip_path = Path::new(input_file);
+            let mut archive = input::open_export_zip(zip_path)?;
+            let mut records = Vec::new();
+            let mut found_any = false;
+            for &section in sections {
+                let files = input::find_section_files(&archive, section);
+                if files.is_empty() {
+                    if sections == [Section::Memories] {
+                        let e = input::no_memories_file_error(zip_path);
+                        log_error(events, e.to_string());
+                        return Err(e);
+                    }
+                    log_message(events, format!("No {} in the zip", section.name()));
+                }
+                for (name, format) in files {
+                    found_any = true;
+                    log_message(events, format!("Reading {name} from the zip..."));
+                    let mut section_records = match format {
+                        InputFormat::MemoriesHtml => {
+                            let mut memories =
+                                parse_memories_history_html_from(archive.by_name(&name)?, events)?;
+                            if !found_table(&memories) {
+                                memories = parse_memories_history_html_tolerant(
+                                    archive.by_name(&name)?,
+                                    events,
+                                )?;
                             }
-                            SdParseState::SearchingForDownloadLinkEnd => {
-                                append_to_current_value = false;
-                                current_value.extend_from_slice(&buffer[..index]);
-                                // This should be the last column in the row
-                                if row_column_count + 1 != EXPECTED_COLUMNS {
-                                    log_error(
-                                        gui_console,
-                                        format!(
-                                            "Row {} had an unexpected number of columns",
-                                            row_column_count
-                                        ),
-                                    );
-                                }
-                                let download_link =
-                                    String::from_utf8_lossy(current_value.as_slice())
-                                        .trim()
-                                        .to_string();
-                                if !download_link.starts_with("https") {
-                                    log_error(
-                                        gui_console,
-                                        format!(
-                                            "Extracted download link did not start with https: {}",
-                                            download_link
-                                        ),
-                                    );
-                                    panic!(
-                                        "Invalid download link extracted at buffer index {index}: {}",
-                                        download_link
-                                    );
-                                }
-                                current_record.push_field(&download_link);
-                                csv_records.push(current_record.clone());
-                                // Reset for next data row
-                                current_record.clear();
-                                row_column_count = 0;
-                                // Skip looking for td end, since we got what we
-                                // wanted. Move on to next data row
-                                SdParseState::SearchingForTr
-                            } // state => unimplemented!("Unhandled parse state: {:?}", state),
+                            skip_header_row(&mut memories);
+                            memories
                         }
-                    }
-                    SearchResult::NotFoundWithUnprocessed(n) => {
-                        if append_to_current_value {
-                            current_value.extend_from_slice(&buffer[..buffer.len() - n])
+                        InputFormat::MemoriesJson => {
+                            parse_memories_history_json_from(archive.by_name(&name)?, events)?
                         }
-                        processed = buffer.len() - n
-                    }
-                    SearchResult::NotFound => processed = buffer.len(),
-                }
-
-                if leftover_bytes_count > 0 {
-                    // The leftover bytes from the previous chunk do not count
-                    // as processed bytes in this chunk
-                    processed -= leftover_bytes_count;
-                    leftover_bytes_count = 0;
-                    leftover_bytes.clear();
+                        format => {
+                            let media = read_section_file(archive.by_name(&name)?, format)?;
+                            log_message(
+                                events,
+                                format!(
+                                    "Found {} files to download in {}",
+                                    format::format_count(media.len()),
+                                    name
+                                ),
+                            );
+                            media
+                        }
+                    };
+                    records.append(&mut section_records);
                 }
-                // Parsing progress has been made; advance internal cursor
-                html_reader.consume(processed);
-
-                file_byte_index += processed as u64;
             }
-            None => {}
+            if !found_any {
+                let names: Vec<&str> = sections.iter().map(Section::name).collect();
+                anyhow::bail!(
+                    "Couldn't find any {} in {}",
+                    names.join(", "),
+                    zip_path.display()
+                );
+            }
+            Ok(records)
+        }
+        None => {
+            log_error(
+                events,
+                "Input file is not an export zip, memories_history.html, memories_history.json or snap_export.csv. Exiting."
+                    .to_string(),
+            );
+            Err(anyhow::anyhow!(
+                "Input file is not an export zip, memories_history.html, memories_history.json or snap_export.csv. Exiting."
+            ))
         }
     }
+}
 
-    info!("Finished reading HTML file.");
-    Ok(csv_records)
+// The media of chats, stories or Spotlight, from one of their files
+fn read_section_file(file: impl Read, format: InputFormat) -> Result<Vec<csv::StringRecord>> {
+    match format {
+        InputFormat::ChatHistoryHtml => chat::parse_chat_history_html(html_page_reader(file)?),
+        InputFormat::ChatHistoryJson => {
+            chat::parse_chat_history_json(BufReader::new(Utf8Reader::new(file)?))
+        }
+        InputFormat::SectionHtml(section) => {
+            sections::parse_media_html(html_page_reader(file)?, section)
+        }
+        InputFormat::SectionJson(section) => {
+            sections::parse_media_json(BufReader::new(Utf8Reader::new(file)?), section)
+        }
+        _ => anyhow::bail!("not a file of chats, stories or Spotlight"),
+    }
+}
+
+// The table in memories_history.html starts with its column headings
+fn skip_header_row(records: &mut Vec<csv::StringRecord>) {
+    if !records.is_empty() {
+        records.remove(0);
+    }
+}
+
+// With --s3-bucket or --webdav, at most this many download threads per CPU,
+// however many --jobs there are. A thread each for 1000 jobs is what the
+// async engine is there to avoid.
+#[cfg(not(feature = "rayon-downloader"))]
+const STORAGE_THREADS_PER_CPU: usize = 4;
+
+// The engine for storage other than the output directory, as the async one
+// writes to files: threads that each take the next record and download it
+// with download_record(), which streams the body on
+#[cfg(not(feature = "rayon-downloader"))]
+fn download_on_threads(
+    records: &[csv::StringRecord],
+    filenames: &[Option<(String, &str)>],
+    ctx: &DownloadContext,
+    jobs: usize,
+    on_start: impl Fn(usize) + Sync,
+    on_finish: impl Fn(usize, DownloadOutcome, Duration) + Sync,
+) {
+    let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+    let threads = jobs
+        .min(cpus * STORAGE_THREADS_PER_CPU)
+        .clamp(1, records.len().max(1));
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    let Some(row) = records.get(index) else {
+                        break;
+                    };
+                    on_start(index);
+                    let record_start = Instant::now();
+                    let outcome = download_record(row, filenames[index].as_ref(), Some(index), ctx);
+                    on_finish(index, outcome, record_start.elapsed());
+                }
+            });
+        }
+    });
 }
 
+// How often the GUI and the progress bar get a status while downloading
+const STATUS_INTERVAL: Duration = Duration::from_millis(250);
+
 fn run_downloader(
     input_file: &str,
     output_dir: &str,
-    jobs: usize,
-    gui_console: Option<&mpsc::Sender<String>>,
-    status_sender: Option<&mpsc::Sender<SnapdownStatus>>,
-) -> Result<()> {
-    // Configure Rayon thread pool
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(jobs)
-        .build_global()
-        .unwrap();
+    options: &DownloadOptions,
+    control: &RunControl,
+    events: Option<&dyn EventSink>,
+) -> Result<SnapdownStatus> {
+    let run_start = Instant::now();
+    let started_at = chrono::Utc::now();
+
+    // Nothing is created or written, not even the output directory
+    if options.dry_run {
+        let records = read_input_sections(input_file, &options.sections, events)?;
+        let report = dry_run::plan_run(
+            &records,
+            Path::new(output_dir),
+            options.naming.namer(),
+            options.force,
+        );
+        for line in report.lines() {
+            log_message(events, line);
+        }
+        let finished_status = || SnapdownStatus {
+            finished: true,
+            skip_count: report.to_skip,
+            elapsed: run_start.elapsed(),
+            dry_run_report: Some(report.clone()),
+            ..SnapdownStatus::new(records.len())
+        };
+        if let Some(events) = events {
+            events.send(DownloadEvent::Summary(Box::new(finished_status())));
+        }
+        return Ok(finished_status());
+    }
+
+    // A pool of the run's own rather than the global one, which can only be
+    // set up once: the GUI starts a run after another (Retry failed, for
+    // one) with whatever jobs each has, and the CLI restarts stalled runs
+    #[cfg(feature = "rayon-downloader")]
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.jobs)
+        .build()?;
 
     log_message(
-        gui_console,
+        events,
         "Creating output directory if it doesn't exist...".to_string(),
     );
 
+    // The only directory a run creates, with the folders of chat media once
+    // the records are read. Every other file goes straight into it, so the
+    // workers never create directories and can't race each other doing it.
+    // Anything that adds subdirectories should create them before the
+    // downloads start too, once each.
     fs::create_dir_all(output_dir)?;
-    log_message(gui_console, format!("Reading input file {input_file}..."));
-
-    let records_vec: Vec<_>;
-    let records: &[csv::StringRecord];
-    // Determine if this is memories_history.html or snap_export.csv
-    if input_file.ends_with("memories_history.html") {
-        records_vec = parse_memories_history_html(input_file, gui_console)?;
-        records = &records_vec[1..]; // Skip header row
-    } else if input_file.ends_with("snap_export.csv") {
+    // A retry has no input, and the run that failed wrote the identity
+    if !options.retry_failed {
+        match ExportIdentity::from_input_file(Path::new(input_file)) {
+            Ok(identity) => {
+                if let Err(e) =
+                    identity::write_archive_identity_if_missing(Path::new(output_dir), &identity)
+                {
+                    log_error(
+                        events,
+                        format!("Error writing {}: {}", identity::ARCHIVE_IDENTITY_FILE, e),
+                    );
+                }
+            }
+            Err(e) => log_error(
+                events,
+                format!("Error identifying export {}: {}", input_file, e),
+            ),
+        }
+    }
+    let manifest = Manifest::open(Path::new(output_dir))?;
+    let manifest_entries = manifest.entries();
+    if !manifest_entries.is_empty() {
+        let completed = manifest_entries
+            .iter()
+            .filter(|entry| {
+                matches!(
+                    entry.status,
+                    EntryStatus::Completed | EntryStatus::Duplicate | EntryStatus::Uploaded
+                )
+            })
+            .count();
         log_message(
-            gui_console,
-            "Detected CSV file (snap_export.html). Extracting records...".to_string(),
+            events,
+            format!(
+                "Resuming from {}: {} files completed, {} to retry or continue",
+                manifest::MANIFEST_FILE,
+                completed,
+                manifest_entries.len() - completed
+            ),
         );
-
-        let mut rdr = Reader::from_path(input_file)?;
-
-        // Collect all records first
-        records_vec = rdr.records().collect::<Result<_, _>>()?; // No header row to skip
-        records = &records_vec[..]; // No header row is expected in this CSV
-    } else {
-        log_error(
-            gui_console,
-            "Input file is neither memories_history.html nor snap_export.csv format. Exiting."
-                .to_string(),
+    }
+    // A retry takes the failed records from the manifest, with the names
+    // they got from all the others, so nothing else is read or checked again
+    let retry_entries = match options.retry_failed {
+        true => manifest.failed(),
+        false => Vec::new(),
+    };
+    let records_vec = if options.retry_failed {
+        log_message(
+            events,
+            format!(
+                "Retrying {} failed downloads",
+                format::format_count(retry_entries.len())
+            ),
         );
-        return Err(anyhow::anyhow!(
-            "Input file is neither memories_history.html nor snap_export.csv format. Exiting."
-        ));
+        retry_entries
+            .iter()
+            .map(|entry| csv::StringRecord::from(entry.record.clone().unwrap_or_default()))
+            .collect()
+    } else {
+        read_input_sections(input_file, &options.sections, events)?
+    };
+    let records = &records_vec[..];
+    let filenames = if options.retry_failed {
+        retry_entries
+            .iter()
+            .map(|entry| Some((entry.filename.clone(), entry.url.as_str())))
+            .collect()
+    } else {
+        naming::unique_filenames_and_urls(records, options.naming.namer(), &|filename| {
+            manifest.get(filename).is_some()
+        })
+    };
+    // Chats, stories and Spotlight go in folders of their own (see
+    // sections.rs), and so do records with an implausible date
+    let folders: std::collections::BTreeSet<&Path> = filenames
+        .iter()
+        .flatten()
+        .filter_map(|(filename, _)| Path::new(filename).parent())
+        .filter(|folder| !folder.as_os_str().is_empty())
+        .collect();
+    for folder in &folders {
+        fs::create_dir_all(Path::new(output_dir).join(folder))?;
+    }
+    for (row, filename_and_url) in records.iter().zip(&filenames) {
+        if let Some((filename, _)) = filename_and_url
+            && record::has_implausible_date(row)
+        {
+            log_warning(
+                events,
+                format!(
+                    "Warning: {:?} can't be when this memory was taken, saving it as {}",
+                    &row[0], filename
+                ),
+            );
+        }
+    }
+    if let Some(metrics) = &options.metrics {
+        metrics.set_total(records.len());
     }
+    let replay = options
+        .replay
+        .as_deref()
+        .map(|path| {
+            log_message(
+                events,
+                format!("Replaying {} instead of downloading", path.display()),
+            );
+            Replay::open(path, records)
+        })
+        .transpose()?;
 
-    log_message(gui_console, format!("Downloading {} files:", records.len()));
+    log_message(
+        events,
+        format!("Downloading {} files:", format::format_count(records.len())),
+    );
+    if let Some(events) = events {
+        events.send(DownloadEvent::Parsed {
+            rows: records
+                .iter()
+                .zip(&filenames)
+                .map(|(record, filename_and_url)| FileRow::queued(record, filename_and_url))
+                .collect(),
+        });
+        events.send(DownloadEvent::Status(Box::new(SnapdownStatus::new(
+            records.len(),
+        ))));
+    }
 
     let success_count = std::sync::atomic::AtomicUsize::new(0);
     let error_count = std::sync::atomic::AtomicUsize::new(0);
     let skip_count = std::sync::atomic::AtomicUsize::new(0);
-    // Each row is of the form (timestamp_utc, format, latitude, longitude, download_url)
-    records.par_iter().for_each(|row| {
-        let row_len = row.len();
-        if row_len == 0 {
-            // Skip empty rows
-            log_error(gui_console, format!("Row was empty. Skipping download"));
-            error_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-            return;
-        }
-
-        if row_len < 4 || row_len > 5 {
-            // Bad row data
-            log_error(
-                gui_console,
+    let invalid_count = std::sync::atomic::AtomicUsize::new(0);
+    // Set by the first failure with --fail-fast, later ones were already
+    // under way
+    let failed_fast = AtomicBool::new(false);
+    let failed_records = std::sync::Mutex::new(Vec::new());
+    // With the record's index, they finish in any order
+    let record_reports = std::sync::Mutex::new(Vec::new());
+    let host_stats = HostStatsCollector::default();
+    let bytes_downloaded = AtomicU64::new(0);
+    let bytes_skipped = AtomicU64::new(0);
+    let http_debug_log_path = install::data_file(HTTP_DEBUG_LOG_FILE);
+    let http_debug_log = if options.debug_http {
+        log_message(
+            events,
+            format!(
+                "Recording details of failed requests in {}",
+                http_debug_log_path.display()
+            ),
+        );
+        Some(HttpDebugLog::open(
+            &http_debug_log_path,
+            options.log_full_urls,
+        )?)
+    } else {
+        None
+    };
+    let rate_limiter = options.limit_rate.map(|rate| {
+        log_message(
+            events,
+            format!(
+                "Limiting download speed to {}/s",
+                format::format_bytes(rate)
+            ),
+        );
+        RateLimiter::new(rate)
+    });
+    let post_processor = options.post_process_cmd.as_deref().map(|cmd| {
+        log_message(events, format!("Running {:?} on each downloaded file", cmd));
+        PostProcessor::with_default_limit(cmd)
+    });
+    let dedup = options
+        .dedup
+        .map(|mode| DedupIndex::open(Path::new(output_dir), mode))
+        .transpose()?;
+    // A replay doesn't download anything to upload
+    let uploader = match &options.upload {
+        Some(settings) if options.replay.is_none() => {
+            let uploader = ImmichUploader::new(settings, options.http_agent())?;
+            let user = uploader.check().map_err(|e| {
+                anyhow::anyhow!(
+                    "can't upload to {} at {}: {}",
+                    settings.target.label(),
+                    settings.server,
+                    e
+                )
+            })?;
+            log_message(
+                events,
                 format!(
-                    "Row had unexpected number of columns ({}). Skipping download",
-                    row_len
+                    "Uploading each downloaded file to {} at {} as {}{}",
+                    settings.target.label(),
+                    settings.server,
+                    user,
+                    if settings.upload_only {
+                        ", and deleting it here"
+                    } else {
+                        ""
+                    }
                 ),
             );
-            error_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-            return;
+            Some(uploader)
         }
-
-        assert!((row_len == 4) || (row_len == 5));
-
-        let timestamp_str = row[0].replace(' ', "_").replace(':', "-");
-        let format = &row[1];
-        let ext = match format {
-            "Image" => "jpg",
-            // "Image" => "png",
-            "Video" => "mp4",
-            "PNG" => "png",
-            "SVG" => "svg",
-            _ => "bin",
-        };
-
-        let (filename, download_url) = if row_len == 5 {
-            // Assume timestamp, format, latitude, longitude, download_url
-            let latitude = &row[2];
-            let longitude = &row[3];
-            let download_url = &row[4];
-            (
-                format!("{}_{}_{}.{}", timestamp_str, latitude, longitude, ext),
-                download_url,
-            )
-        } else {
-            // Assume timestamp, format, latitude_longitude, download_url
-            let lat_long = row[2]
-                .replace("Latitude, Longitude: ", "")
-                .replace(", ", "_");
-            let download_url = &row[3];
-            (
-                format!("{}_{}.{}", timestamp_str, lat_long, ext),
-                download_url,
-            )
-        };
-
-        let path = Path::new(output_dir).join(filename);
-
-        if path.exists() {
-            debug!("  * File already exists; skipping download: {:?}", path);
-            skip_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-            return;
+        _ => None,
+    };
+    // A replay doesn't store anything either
+    let remote_storage: Option<Box<dyn StorageBackend>> = match (&options.s3, &options.webdav) {
+        _ if options.replay.is_some() => None,
+        (Some(settings), _) => Some(Box::new(S3Bucket::new(settings, options.http_agent())?)),
+        (None, Some(settings)) => {
+            Some(Box::new(WebDavFolder::new(settings, options.http_agent())?))
         }
-
-        let mut resp = match ureq::get(download_url).call() {
-            Ok(r) => r,
-            Err(e) => {
-                log_error(
-                    gui_console,
-                    format!("  * Error downloading from {}: {}", download_url, e),
-                );
+        (None, None) => None,
+    };
+    if let Some(storage) = &remote_storage {
+        storage
+            .check()
+            .map_err(|e| anyhow::anyhow!("can't store files in {}: {}", storage.location(), e))?;
+        log_message(
+            events,
+            format!(
+                "Storing the files in {}, the output directory only gets the manifest and report",
+                storage.location()
+            ),
+        );
+    }
+    let local_dir = LocalDir::new(output_dir);
+    let storage = remote_storage.as_deref().unwrap_or(&local_dir);
+    let blocking_http: Arc<dyn BlockingFetch> = match &options.http {
+        Some(http) => http.clone(),
+        None => Arc::new(options.http_agent()),
+    };
+    let download_context = DownloadContext {
+        output_dir,
+        storage,
+        http: blocking_http.as_ref(),
+        rate_limiter: rate_limiter.as_ref(),
+        http_debug_log: http_debug_log.as_ref(),
+        resolve_links: options.resolve_links,
+        write_exif: options.write_exif,
+        touch: options.touch,
+        composite_overlays: options.composite_overlays,
+        auto_rotate: options.auto_rotate,
+        sidecars: options.sidecars,
+        force: options.force,
+        post_processor: post_processor.as_ref(),
+        uploader: uploader.as_ref(),
+        dedup: dedup.as_ref(),
+        host_stats: &host_stats,
+        etag_hosts: &EtagHosts::default(),
+        network: &NetworkMonitor::default(),
+        backoff: &Backoff::new(options.jobs),
+        retries: options.retries,
+        bytes_downloaded: &bytes_downloaded,
+        bytes_skipped: &bytes_skipped,
+        manifest: &manifest,
+        control,
+        events,
+    };
+    // What's stored elsewhere takes no space here
+    let space_estimate = match storage.local_dir() {
+        Some(dir) if replay.is_none() && options.space_check != SpaceCheck::Off => check_space(
+            &filenames,
+            dir,
+            options.space_check,
+            options.jobs,
+            &download_context,
+        )?,
+        _ => None,
+    };
+    let watchdog = Watchdog::new(
+        options
+            .stall_timeout
+            .unwrap_or(watchdog::DEFAULT_STALL_TIMEOUT),
+    );
+    let start_record = |index| {
+        if let Some((filename, _)) = &filenames[index] {
+            watchdog.started(index, Path::new(output_dir).join(filename));
+        }
+        if let Some(events) = events {
+            events.send(DownloadEvent::Started { id: index });
+        }
+    };
+    // See schedule.rs
+    let schedule_paused = AtomicBool::new(false);
+    let schedule_changed = |schedule: &Schedule, open: bool| {
+        schedule_paused.store(!open, std::sync::atomic::Ordering::Relaxed);
+        if !open {
+            manifest.sync();
+        }
+        log_message(events, schedule.message(open));
+    };
+    let throughput = std::sync::Mutex::new(Throughput::default());
+    let current_status = || {
+        let elapsed = run_start.elapsed();
+        let total_bytes = bytes_downloaded.load(std::sync::atomic::Ordering::Relaxed);
+        let mut throughput = throughput.lock().unwrap();
+        throughput.sample(elapsed, total_bytes);
+        SnapdownStatus {
+            success_count: success_count.load(std::sync::atomic::Ordering::Relaxed),
+            error_count: error_count.load(std::sync::atomic::Ordering::Relaxed),
+            skip_count: skip_count.load(std::sync::atomic::Ordering::Relaxed),
+            invalid_count: invalid_count.load(std::sync::atomic::Ordering::Relaxed),
+            bytes_downloaded: total_bytes,
+            bytes_skipped: bytes_skipped.load(std::sync::atomic::Ordering::Relaxed),
+            throughput: total_bytes as f64 / elapsed.as_secs_f64(),
+            current_throughput: throughput.current(),
+            throughput_history: throughput.history(),
+            elapsed,
+            throttled: download_context.backoff.throttled(),
+            upload_count: uploader.as_ref().map_or(0, |uploader| uploader.uploaded()),
+            upload_error_count: uploader.as_ref().map_or(0, |uploader| uploader.failed()),
+            space_estimate,
+            paused_until: options
+                .schedule
+                .filter(|_| schedule_paused.load(std::sync::atomic::Ordering::Relaxed))
+                .map(|schedule| schedule.opens_at()),
+            ..SnapdownStatus::new(records.len())
+        }
+    };
+    let finish_record = |index: usize, outcome: DownloadOutcome, duration: Duration| {
+        watchdog.finished(index);
+        let row = &records[index];
+        let failure_kind = match &outcome {
+            DownloadOutcome::Failed(kind, _) => Some(*kind),
+            _ => None,
+        };
+        let (file_status, record_status, error) = match outcome {
+            DownloadOutcome::Downloaded => {
+                success_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                (FileStatus::Done, RecordStatus::Downloaded, None)
+            }
+            DownloadOutcome::Skipped => {
+                skip_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                (FileStatus::Skipped, RecordStatus::Skipped, None)
+            }
+            DownloadOutcome::Cancelled => {
+                // Records not downloaded because of a cancel count as skipped
+                skip_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                (FileStatus::Skipped, RecordStatus::Cancelled, None)
+            }
+            DownloadOutcome::Invalid => {
+                invalid_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                (
+                    FileStatus::Invalid,
+                    RecordStatus::Invalid,
+                    Some(INVALID_RECORD_ERROR.to_string()),
+                )
+            }
+            DownloadOutcome::Failed(_, error) => {
                 error_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                return;
+                failed_records.lock().unwrap().push(row.clone());
+                (FileStatus::Failed, RecordStatus::Failed, Some(error))
             }
         };
-
-        // Create the file AFTER the download, so we don't have a ton of open
-        // files and exhaust Linux's default per-process open file limit.
-        let mut file = match File::create(&path) {
-            Ok(f) => f,
-            Err(e) => {
-                log_error(
-                    gui_console,
-                    format!("  * Error creating file {:?}: {}", path, e),
-                );
-                error_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                return;
+        // With its record, for --retry-failed. Some downloads fail before
+        // they're in the manifest at all. A replay leaves it as it is.
+        if record_status == RecordStatus::Failed
+            && replay.is_none()
+            && let Some((filename, url)) = &filenames[index]
+        {
+            let entry = manifest.get(filename).unwrap_or_else(|| ManifestEntry {
+                url: url.to_string(),
+                filename: filename.clone(),
+                status: EntryStatus::Failed,
+                bytes_written: 0,
+                checksum: None,
+                views: Vec::new(),
+                mime_type: None,
+                saved_as: None,
+                uploaded: None,
+                md5: None,
+                record: None,
+            });
+            manifest.record(ManifestEntry {
+                status: EntryStatus::Failed,
+                record: Some(row.iter().map(str::to_string).collect()),
+                ..entry
+            });
+        }
+        if options.fail_fast
+            && record_status == RecordStatus::Failed
+            && !failed_fast.swap(true, std::sync::atomic::Ordering::Relaxed)
+        {
+            control.stop(StopReason::FailFast);
+            log_error(
+                events,
+                "Stopping at the first failed download (--fail-fast):".to_string(),
+            );
+            let fields: Vec<&str> = row.iter().collect();
+            log_error(
+                events,
+                format!("  Record {}: {}", index + 1, fields.join(",")),
+            );
+            log_error(
+                events,
+                format!("  {}", error.as_deref().unwrap_or_default()),
+            );
+            // What the server answered, if it did
+            let entry = http_debug_log
+                .as_ref()
+                .zip(filenames[index].as_ref())
+                .and_then(|(log, (_, url))| log.entry(url));
+            for line in entry.iter().flat_map(|entry| entry.lines()) {
+                log_error(events, format!("  {}", line));
             }
+        }
+        let filename_and_url = &filenames[index];
+        let saved_filename = filename_and_url
+            .as_ref()
+            .map(|(filename, _)| match manifest.get(filename) {
+                Some(entry) => entry.saved_filename().to_string(),
+                None => filename.clone(),
+            })
+            .unwrap_or_default();
+        // Duplicates deleted by --dedup have no size. A replay has no files,
+        // the sizes are the recorded ones.
+        let size = match &replay {
+            Some(replay) => replay.record(index).bytes(),
+            None => Some(&saved_filename)
+                .filter(|filename| !filename.is_empty() && file_status != FileStatus::Failed)
+                .and_then(|filename| fs::metadata(Path::new(output_dir).join(filename)).ok())
+                .map(|metadata| metadata.len()),
         };
-
-        match copy(&mut resp.body_mut().as_reader(), &mut file) {
-            Ok(_) => {
-                debug!("  * Downloaded {}", download_url);
-                success_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        if let Some(metrics) = &options.metrics {
+            metrics.record(
+                record_status,
+                failure_kind,
+                duration,
+                bytes_downloaded.load(std::sync::atomic::Ordering::Relaxed),
+            );
+        }
+        // The report gets shared like the log
+        let redact = |text: &str| {
+            if options.log_full_urls {
+                text.to_string()
+            } else {
+                redact::redact_tokens(text)
             }
-            Err(e) => {
+        };
+        record_reports.lock().unwrap().push((
+            index,
+            RecordReport {
+                url: filename_and_url
+                    .as_ref()
+                    .map(|(_, url)| redact(url))
+                    .unwrap_or_default(),
+                filename: saved_filename,
+                status: record_status,
+                failure: failure_kind,
+                error: error.as_deref().map(redact),
+                warning: (filename_and_url.is_some()
+                    && record::has_implausible_date(&records[index]))
+                .then(|| format!("implausible capture time {:?}", &records[index][0])),
+                bytes: size,
+                started_ms: report::millis(run_start.elapsed().saturating_sub(duration)),
+                duration_ms: report::millis(duration),
+            },
+        ));
+        if let Some(events) = events {
+            events.send(match error {
+                Some(error) if file_status == FileStatus::Failed => {
+                    DownloadEvent::Failed { id: index, error }
+                }
+                _ => DownloadEvent::Finished {
+                    id: index,
+                    status: file_status,
+                    size,
+                },
+            });
+        }
+    };
+    #[cfg(not(feature = "rayon-downloader"))]
+    let client: Arc<dyn async_download::HttpFetch> = match &options.http {
+        Some(http) => http.clone(),
+        None => Arc::new(async_download::HttpClient::new(options)?),
+    };
+    let schedule = options.schedule.map(|schedule| {
+        let open = schedule.start(control, |open| schedule_changed(&schedule, open));
+        (schedule, open)
+    });
+    let downloads_done = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        if let Some((schedule, open)) = schedule {
+            let downloads_done = &downloads_done;
+            let schedule_changed = &schedule_changed;
+            scope.spawn(move || {
+                schedule.watch(open, downloads_done, control, |open| {
+                    schedule_changed(&schedule, open)
+                });
+            });
+        }
+        // On a timer rather than after each record, so the progress moves
+        // as steadily through a skim of skipped records as through one big
+        // video, and the bytes of downloads in progress show
+        if let Some(events) = events {
+            scope.spawn(|| {
+                loop {
+                    std::thread::sleep(STATUS_INTERVAL);
+                    if downloads_done.load(std::sync::atomic::Ordering::Relaxed) {
+                        break;
+                    }
+                    events.send(DownloadEvent::Status(Box::new(current_status())));
+                }
+            });
+        }
+        scope.spawn(|| {
+            let progress = || {
+                let records_done = success_count.load(std::sync::atomic::Ordering::Relaxed)
+                    + error_count.load(std::sync::atomic::Ordering::Relaxed)
+                    + skip_count.load(std::sync::atomic::Ordering::Relaxed)
+                    + invalid_count.load(std::sync::atomic::Ordering::Relaxed);
+                records_done as u64 + bytes_downloaded.load(std::sync::atomic::Ordering::Relaxed)
+            };
+            watchdog.watch(&downloads_done, control, progress, |downloads| {
                 log_error(
-                    gui_console,
+                    events,
                     format!(
-                        "  * Downloaded, but error writing to file {:?}: {}",
-                        path, e
+                        "Nothing downloaded for {}, with {} downloads in progress:",
+                        format::format_duration(watchdog.timeout()),
+                        format::format_count(downloads.len())
                     ),
                 );
-                error_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-            }
-        }
-
-        // Every 10 items send a status update
-        match &status_sender {
-            Some(sender) => {
-                let total_success = success_count.load(std::sync::atomic::Ordering::Relaxed);
-                let total_error = error_count.load(std::sync::atomic::Ordering::Relaxed);
-                let total_skip = skip_count.load(std::sync::atomic::Ordering::Relaxed);
-                let status = SnapdownStatus {
-                    finished: false,
-                    success_count: total_success,
-                    error_count: total_error,
-                    skip_count: total_skip,
+                for line in downloads {
+                    log_error(events, line);
+                }
+                if download_context.network.is_offline() {
+                    log_error(events, "  The network connection is down".to_string());
+                }
+                if options.restart_stalled {
+                    log_error(events, "Stopping the run, to start it again".to_string());
+                    control.stop(StopReason::Stalled);
+                }
+            });
+        });
+        let result: Result<()> = match &replay {
+            Some(replay) => {
+                // Counted like the downloads would have
+                let replay_finish = |index, outcome: DownloadOutcome, duration| {
+                    let record = replay.record(index);
+                    match &outcome {
+                        DownloadOutcome::Downloaded => {
+                            bytes_downloaded.fetch_add(
+                                record.bytes().unwrap_or(0),
+                                std::sync::atomic::Ordering::Relaxed,
+                            );
+                            host_stats.record(record.url(), true, duration);
+                        }
+                        DownloadOutcome::Failed(..) => {
+                            host_stats.record(record.url(), false, duration)
+                        }
+                        _ => {}
+                    }
+                    finish_record(index, outcome, duration);
                 };
-                sender.send(status).unwrap_or_else(|e| {
-                    error!("Error sending status to GUI: {}", e);
+                replay.run(options.replay_speed, control, start_record, replay_finish);
+                Ok(())
+            }
+            #[cfg(not(feature = "rayon-downloader"))]
+            None if download_context.storage.local_dir().is_none() => {
+                download_on_threads(
+                    records,
+                    &filenames,
+                    &download_context,
+                    options.jobs,
+                    start_record,
+                    finish_record,
+                );
+                Ok(())
+            }
+            #[cfg(not(feature = "rayon-downloader"))]
+            None => async_download::download_all(
+                records,
+                &filenames,
+                &download_context,
+                client.as_ref(),
+                options.jobs,
+                start_record,
+                finish_record,
+            ),
+            #[cfg(feature = "rayon-downloader")]
+            None => {
+                pool.install(|| {
+                    records.par_iter().enumerate().for_each(|(index, row)| {
+                        start_record(index);
+                        let record_start = Instant::now();
+                        let outcome = download_record(
+                            row,
+                            filenames[index].as_ref(),
+                            Some(index),
+                            &download_context,
+                        );
+                        finish_record(index, outcome, record_start.elapsed());
+                    })
                 });
+                Ok(())
+            }
+        };
+        // Also on an error, or the watchdog would keep the scope waiting
+        downloads_done.store(true, std::sync::atomic::Ordering::Relaxed);
+        result
+    })?;
+
+    // The files this run skipped as already downloaded, by a run without
+    // --upload or whose upload failed
+    if let Some(uploader) = &uploader
+        && control.stop_reason().is_none()
+    {
+        let skipped: Vec<usize> = record_reports
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, record)| record.status == RecordStatus::Skipped)
+            .map(|(index, _)| *index)
+            .collect();
+        let not_uploaded: Vec<(&csv::StringRecord, ManifestEntry)> = skipped
+            .into_iter()
+            .filter_map(|index| {
+                let (filename, _) = filenames[index].as_ref()?;
+                let entry = manifest.get(filename)?;
+                (entry.status == EntryStatus::Completed && entry.uploaded.is_none())
+                    .then_some((&records[index], entry))
+            })
+            .collect();
+        if !not_uploaded.is_empty() {
+            log_message(
+                events,
+                format!(
+                    "Uploading {} files downloaded before...",
+                    format::format_count(not_uploaded.len())
+                ),
+            );
+        }
+        for (row, entry) in not_uploaded {
+            if control.is_cancelled() {
+                break;
             }
-            None => {}
+            let path = Path::new(output_dir).join(entry.saved_filename());
+            upload_download(uploader, &path, row, entry, &download_context);
         }
-    });
+    }
+
+    // Also for the files downloaded by earlier runs
+    let view_report = options
+        .views
+        .as_ref()
+        .map(|views| views::build_views(Path::new(output_dir), views, &manifest))
+        .transpose()?;
 
     let success_count = success_count.load(std::sync::atomic::Ordering::Relaxed);
     let error_count = error_count.load(std::sync::atomic::Ordering::Relaxed);
     let skip_count = skip_count.load(std::sync::atomic::Ordering::Relaxed);
+    let invalid_count = invalid_count.load(std::sync::atomic::Ordering::Relaxed);
+    let failed_records = failed_records.into_inner().unwrap();
+    let host_stats = host_stats.into_sorted();
+    let stop_reason = control.stop_reason();
+    let elapsed = run_start.elapsed();
+    let bytes_downloaded = bytes_downloaded.into_inner();
+    let bytes_skipped = bytes_skipped.into_inner();
+    let throughput_history = throughput.into_inner().unwrap().history();
+    let throughput = bytes_downloaded as f64 / elapsed.as_secs_f64();
+    let duplicate_count = dedup.as_ref().map_or(0, |dedup| dedup.duplicates());
+    let dedup_bytes_saved = dedup.as_ref().map_or(0, |dedup| dedup.bytes_saved());
+    let upload_count = uploader.as_ref().map_or(0, |uploader| uploader.uploaded());
+    let upload_error_count = uploader.as_ref().map_or(0, |uploader| uploader.failed());
+    // A replay downloaded nothing
+    let usage = match &options.usage_path {
+        Some(path) if options.replay.is_none() => match usage::record_run(path, bytes_downloaded) {
+            Ok(usage) => Some(usage),
+            Err(e) => {
+                log_error(events, format!("Error recording the data used: {:#}", e));
+                None
+            }
+        },
+        _ => None,
+    };
 
-    match &status_sender {
-        Some(sender) => {
-            let status = SnapdownStatus {
-                finished: true,
-                success_count: success_count,
-                error_count: error_count,
-                skip_count: skip_count,
-            };
-            sender.send(status).unwrap_or_else(|e| {
-                error!("Error sending status to GUI: {}", e);
-            });
+    // Only a complete archive is sealed, a run with failures gets retried
+    if options.freeze {
+        if error_count > 0 || stop_reason.is_some() {
+            log_error(
+                events,
+                "Not freezing the archive, not everything was downloaded".to_string(),
+            );
+        } else {
+            log_message(events, "Freezing the archive...".to_string());
+            match freeze::freeze(Path::new(output_dir), &manifest) {
+                Ok(sealed) => log_message(
+                    events,
+                    format!(
+                        "Made {} files read-only and wrote {} (archive hash {})",
+                        sealed.files.len(),
+                        freeze::SEALED_MANIFEST_FILE,
+                        sealed.archive_hash
+                    ),
+                ),
+                Err(e) => log_error(events, format!("Error freezing the archive: {}", e)),
+            }
         }
-        None => {}
     }
 
+    if let Some(events) = events {
+        let status = SnapdownStatus {
+            finished: true,
+            success_count,
+            error_count,
+            skip_count,
+            invalid_count,
+            stop_reason,
+            total_count: records.len(),
+            bytes_downloaded,
+            bytes_skipped,
+            throughput,
+            current_throughput: 0.0,
+            throughput_history: throughput_history.clone(),
+            elapsed,
+            failed_records: failed_records.clone(),
+            host_stats: host_stats.clone(),
+            dry_run_report: None,
+            duplicate_count,
+            dedup_bytes_saved,
+            throttled: None,
+            upload_count,
+            upload_error_count,
+            space_estimate,
+            paused_until: None,
+            usage: usage.clone(),
+        };
+        events.send(DownloadEvent::Summary(Box::new(status)));
+    }
+
+    if let Some(reason) = stop_reason {
+        log_error(
+            events,
+            format!(
+                "Stopped: {}. Records that weren't downloaded yet are counted as skipped.",
+                reason
+            ),
+        );
+    }
     log_message(
-        gui_console,
-        format!("Finished processing {} links", records.len()),
+        events,
+        format!(
+            "Finished processing {} links",
+            format::format_count(records.len())
+        ),
     );
     if success_count > 0 {
-        log_message(gui_console, format!("  - Success: {} files", records.len()));
+        log_message(
+            events,
+            format!("  - Success: {} files", format::format_count(success_count)),
+        );
     }
     if error_count > 0 {
-        log_error(gui_console, format!("  - Error: {} files", error_count));
+        log_error(
+            events,
+            format!("  - Error: {} files", format::format_count(error_count)),
+        );
     }
     if skip_count > 0 {
+        let savings = skip_savings(bytes_skipped, throughput)
+            .map(|savings| format!(", {}", savings))
+            .unwrap_or_default();
+        log_message(
+            events,
+            format!(
+                "  - Skipped: {} files (already existed{})",
+                format::format_count(skip_count),
+                savings
+            ),
+        );
+    }
+    if invalid_count > 0 {
+        log_message(
+            events,
+            format!(
+                "  - Not memories: {} rows (unexpected number of columns)",
+                format::format_count(invalid_count)
+            ),
+        );
+    }
+    if let Some(estimate) = &space_estimate {
+        log_message(
+            events,
+            format!("  - Space before the run: {}", estimate.message()),
+        );
+    }
+    if let Some(usage) = &usage {
+        log_message(
+            events,
+            format!(
+                "  - Data used: {} this run, {}",
+                format::format_bytes(bytes_downloaded),
+                usage.summary(&usage::current_month())
+            ),
+        );
+    }
+    if let Some(dedup) = &dedup {
+        let what = match dedup.mode() {
+            DedupMode::Delete => "deleted",
+            DedupMode::HardLink => "replaced with hard links",
+        };
+        log_message(
+            events,
+            format!(
+                "  - Duplicates: {} files {}, {} saved",
+                format::format_count(duplicate_count),
+                what,
+                format::format_bytes(dedup_bytes_saved)
+            ),
+        );
+    }
+    if let (Some(uploader), Some(settings)) = (&uploader, &options.upload) {
+        let already_there = match uploader.duplicates() {
+            0 => String::new(),
+            duplicates => format!(" ({} were already there)", format::format_count(duplicates)),
+        };
+        log_message(
+            events,
+            format!(
+                "  - Uploaded to {}: {} files{}",
+                settings.target.label(),
+                format::format_count(upload_count),
+                already_there
+            ),
+        );
+        if upload_error_count > 0 {
+            log_error(
+                events,
+                format!(
+                    "  - Upload errors: {} files, the next run uploads them",
+                    format::format_count(upload_error_count)
+                ),
+            );
+        }
+    }
+    if let (Some(report), Some(views)) = (&view_report, &options.views) {
+        let folders: Vec<String> = views
+            .kinds
+            .iter()
+            .map(|kind| format!("{}/", kind.dir_name()))
+            .collect();
+        log_message(
+            events,
+            format!(
+                "  - Views: {} new links in {}",
+                format::format_count(report.linked),
+                folders.join(", ")
+            ),
+        );
+        if report.failed > 0 {
+            log_error(
+                events,
+                format!(
+                    "  - Views: {} files couldn't be linked, see {}",
+                    format::format_count(report.failed),
+                    install::data_file(LOG_FILE).display()
+                ),
+            );
+        }
+    }
+    if !host_stats.is_empty() {
+        log_message(events, "Per-host statistics:".to_string());
+        for (host, stats) in &host_stats {
+            log_message(
+                events,
+                format!("  - {}", stats::format_host_stats(host, stats)),
+            );
+        }
+    }
+
+    let mut record_reports = record_reports.into_inner().unwrap();
+    record_reports.sort_by_key(|(index, _)| *index);
+    // A repair's broken files stay in quarantine if they were downloaded
+    // again, and go back if not
+    let downloaded: std::collections::HashSet<&str> = record_reports
+        .iter()
+        .filter(|(_, record)| record.status == RecordStatus::Downloaded)
+        .map(|(_, record)| record.filename.as_str())
+        .collect();
+    let quarantined = quarantine::settle(Path::new(output_dir), &options.quarantined, |filename| {
+        downloaded.contains(filename)
+    });
+    if !quarantined.is_empty() {
+        let replaced = quarantined.iter().filter(|file| file.replaced).count();
         log_message(
-            gui_console,
-            format!("  - Skipped: {} files (already existed)", skip_count),
+            events,
+            format!(
+                "  - Quarantine: {} broken files kept in {}, {} put back (not downloaded again)",
+                format::format_count(replaced),
+                quarantine::QUARANTINE_DIR,
+                format::format_count(quarantined.len() - replaced)
+            ),
         );
     }
+    let run_report = RunReport {
+        snapdown_version: env!("CARGO_PKG_VERSION"),
+        input_file: input_file.to_string(),
+        output_dir: output_dir.to_string(),
+        started_at: started_at.to_rfc3339(),
+        finished_at: chrono::Utc::now().to_rfc3339(),
+        duration_secs: elapsed.as_secs_f64(),
+        stop_reason: stop_reason.map(|reason| reason.to_string()),
+        total: records.len(),
+        downloaded: success_count,
+        skipped: skip_count,
+        failed: error_count,
+        invalid: invalid_count,
+        duplicates: duplicate_count,
+        uploaded: upload_count,
+        upload_failed: upload_error_count,
+        bytes_downloaded,
+        bytes_skipped,
+        throughput,
+        hosts: host_stats
+            .iter()
+            .map(|(host, stats)| HostReport::new(host, stats))
+            .collect(),
+        quarantined,
+        records: record_reports
+            .into_iter()
+            .map(|(_, record)| record)
+            .collect(),
+    };
+    let report_path = options
+        .report_path
+        .clone()
+        .unwrap_or_else(|| Path::new(output_dir).join(report::REPORT_FILE));
+    match report::write_report(&report_path, &run_report) {
+        Ok(()) => log_message(
+            events,
+            format!("Wrote the report of this run to {}", report_path.display()),
+        ),
+        Err(e) => log_error(
+            events,
+            format!("Error writing {}: {}", report_path.display(), e),
+        ),
+    }
 
-    Ok(())
+    Ok(SnapdownStatus {
+        finished: true,
+        success_count,
+        error_count,
+        skip_count,
+        invalid_count,
+        stop_reason,
+        total_count: records.len(),
+        bytes_downloaded,
+        bytes_skipped,
+        throughput,
+        current_throughput: 0.0,
+        throughput_history,
+        elapsed,
+        failed_records,
+        host_stats,
+        dry_run_report: None,
+        duplicate_count,
+        dedup_bytes_saved,
+        throttled: None,
+        upload_count,
+        upload_error_count,
+        space_estimate,
+        paused_until: None,
+        usage,
+    })
 }
 
 #[cfg(test)]
@@ -1167,6 +7298,354 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_record_filename_and_url() {
+        let html_record = csv::StringRecord::from(vec![
+            "2026-01-13 01:55:38 UTC",
+            "Image",
+            "Latitude, Longitude: 40.25548, -111.645325",
+            "https://example.com/a",
+        ]);
+        assert_eq!(
+            record_filename_and_url(&html_record),
+            Some((
+                "2026-01-13_01-55-38_UTC_40.25548_-111.645325.jpg".to_string(),
+                "https://example.com/a"
+            ))
+        );
+
+        let csv_record = csv::StringRecord::from(vec![
+            "1800-01-18T00:28:45+00:00",
+            "SVG",
+            "40.0",
+            "40.0",
+            "https://example.com/b",
+        ]);
+        assert_eq!(
+            record_filename_and_url(&csv_record),
+            Some((
+                "1800-01-18T00-28-45+00-00_40.0_40.0.svg".to_string(),
+                "https://example.com/b"
+            ))
+        );
+
+        assert_eq!(
+            record_filename_and_url(&csv::StringRecord::from(vec!["a", "b"])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_status_eta() {
+        let status = SnapdownStatus {
+            success_count: 20,
+            skip_count: 5,
+            elapsed: Duration::from_secs(50),
+            ..SnapdownStatus::new(100)
+        };
+        assert_eq!(status.eta(), Some(Duration::from_secs(150)));
+        assert_eq!(SnapdownStatus::new(100).eta(), None);
+    }
+
+    #[test]
+    fn test_status_invalid_rows() {
+        // Rows that aren't memories are done, but not failed
+        let status = SnapdownStatus {
+            success_count: 7,
+            error_count: 1,
+            invalid_count: 2,
+            ..SnapdownStatus::new(10)
+        };
+        let page = status.status_page();
+        assert_eq!(page.processed, 10);
+        assert_eq!(page.failed, 1);
+        assert_eq!(page.invalid, 2);
+        assert_eq!(page.phase, Phase::Finishing);
+    }
+
+    #[test]
+    fn test_log_entry_matches() {
+        let entry = |level, message: &str| LogEntry {
+            level,
+            message: message.to_string(),
+            record: None,
+        };
+        let error = entry(log::Level::Error, "  * Error downloading: http status: 403");
+        let info = entry(log::Level::Info, "Downloading 12 files:");
+        assert!(error.matches(true, "") && !info.matches(true, ""));
+        assert!(info.matches(false, " DOWNLOADING "));
+        assert!(!error.matches(false, "404") && error.matches(true, "403"));
+    }
+
+    #[test]
+    fn test_total_size() {
+        assert_eq!(
+            total_size(true, Some("bytes 0-0/12345"), Some("1")),
+            Some(12345)
+        );
+        assert_eq!(total_size(true, Some("bytes 0-0/*"), Some("1")), None);
+        assert_eq!(total_size(false, None, Some("12345")), Some(12345));
+        assert_eq!(total_size(false, None, None), None);
+    }
+
+    #[test]
+    fn test_skip_savings() {
+        let status = SnapdownStatus {
+            bytes_skipped: 300_000_000,
+            throughput: 1_000_000.0,
+            ..SnapdownStatus::new(100)
+        };
+        // "300.0 MB" in English, depends on the locale the tests run in
+        let bytes = format::format_bytes(300_000_000);
+        assert_eq!(
+            status.skip_savings().unwrap(),
+            format!("{} already downloaded, about 5m 00s saved", bytes)
+        );
+        // Nothing downloaded this run to estimate the speed from
+        assert_eq!(
+            skip_savings(300_000_000, 0.0).unwrap(),
+            format!("{} already downloaded", bytes)
+        );
+        assert_eq!(SnapdownStatus::new(100).skip_savings(), None);
+    }
+
+    #[test]
+    fn test_set_file_times() {
+        let path = std::env::temp_dir().join("snapdown_test_set_file_times.jpg");
+        fs::write(&path, b"memory").unwrap();
+        let timestamp = chrono::DateTime::parse_from_rfc3339("2026-01-13T01:55:38Z")
+            .unwrap()
+            .to_utc();
+        set_file_times(&path, timestamp).unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(chrono::DateTime::<chrono::Utc>::from(modified), timestamp);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_split_post_url() {
+        assert_eq!(
+            split_post_url("https://us-east1-aws.api.snapchat.com/dmd/mm?uid=bogus-1&sig=bogus-4"),
+            Some((
+                "https://us-east1-aws.api.snapchat.com/dmd/mm",
+                "uid=bogus-1&sig=bogus-4"
+            ))
+        );
+        assert_eq!(split_post_url("https://example.com/a.jpg"), None);
+        assert_eq!(split_post_url("https://example.com/a.jpg?"), None);
+    }
+
+    #[test]
+    fn test_parse_json_snippet() {
+        let test_file_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test")
+            .join("test.json");
+        let records = parse_memories_history_json(test_file_path.to_str().unwrap(), None).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0],
+            csv::StringRecord::from(vec![
+                "2026-01-13 01:55:38 UTC",
+                "Image",
+                "Latitude, Longitude: 40.25548, -111.645325",
+                "https://us-east1-aws.api.snapchat.com/dmd/mm?uid=bogus-1&sid=bogus-2&mid=bogus-3&ts=1768335041137&sig=bogus-4",
+            ])
+        );
+        // No location or direct media URL
+        assert_eq!(&records[1][2], "");
+        assert_eq!(
+            &records[1][3],
+            "https://app.snapchat.com/dmd/memories?uid=bogus-1&sid=bogus-5&mid=bogus-6&ts=1768335041137&sig=bogus-7"
+        );
+        assert_eq!(
+            record_filename_and_url(&records[0]).unwrap().0,
+            "2026-01-13_01-55-38_UTC_40.25548_-111.645325.jpg"
+        );
+    }
+
+    #[test]
+    fn test_read_re_encoded_inputs() {
+        let test_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test");
+        let dir = std::env::temp_dir().join("snapdown_test_re_encoded_inputs");
+        fs::create_dir_all(&dir).unwrap();
+        let utf16_le = |text: &str| -> Vec<u8> {
+            [0xFF, 0xFE]
+                .into_iter()
+                .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                .collect()
+        };
+        for name in ["test.html", "test.json"] {
+            let text = fs::read_to_string(test_dir.join(name)).unwrap();
+            let expected = read_input_records(test_dir.join(name).to_str().unwrap(), None).unwrap();
+            for (variant, data) in [
+                ("bom", [&b"\xEF\xBB\xBF"[..], text.as_bytes()].concat()),
+                ("utf16", utf16_le(&text)),
+            ] {
+                let path = dir.join(format!("{}_{}", variant, name));
+                fs::write(&path, data).unwrap();
+                let records = read_input_records(path.to_str().unwrap(), None).unwrap();
+                assert_eq!(records, expected, "{}", path.display());
+            }
+        }
+
+        // Excel's "CSV UTF-8" and "Unicode Text", the header row has to be
+        // recognized as such
+        let csv = "timestamp_utc,format,latitude,longitude,download_url\n2026-01-13 01:55:38 UTC,Image,40.25548,-111.645325,https://example.com/a\n";
+        for (variant, data) in [
+            ("bom", [&b"\xEF\xBB\xBF"[..], csv.as_bytes()].concat()),
+            ("utf16", utf16_le(csv)),
+        ] {
+            let path = dir.join(format!("{}_snap_export.csv", variant));
+            fs::write(&path, data).unwrap();
+            let records = read_input_records(path.to_str().unwrap(), None).unwrap();
+            assert_eq!(records.len(), 1);
+            assert_eq!(&records[0][0], "2026-01-13 01:55:38 UTC");
+            let mut reader =
+                csv::Reader::from_reader(Utf8Reader::new(File::open(&path).unwrap()).unwrap());
+            assert_eq!(&reader.headers().unwrap()[0], "timestamp_utc");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_verify_md5() {
+        let abc = |md5: Option<[u8; 16]>| FileHash {
+            hash: "abc".to_string(),
+            size: 3,
+            md5,
+        };
+        let url = "https://example.com/abc.jpg";
+        let digest = [0x90; 16];
+        let mut mismatched = None;
+        let etag_hosts = EtagHosts::default();
+        let verify = |server_md5, md5, mismatched: &mut Option<String>| {
+            verify_md5(server_md5, &abc(md5), url, mismatched, &etag_hosts)
+        };
+        assert_eq!(verify(None, None, &mut mismatched), Ok(None));
+        assert_eq!(
+            verify(
+                Some(ServerMd5::ContentMd5(digest)),
+                Some(digest),
+                &mut mismatched
+            ),
+            Ok(Some("90".repeat(16)))
+        );
+
+        // A Content-MD5 that doesn't match never does
+        for _ in 0..2 {
+            let result = verify(
+                Some(ServerMd5::ContentMd5(digest)),
+                Some([0; 16]),
+                &mut mismatched,
+            );
+            assert!(
+                result
+                    .unwrap_err()
+                    .contains("checksum doesn't match its Content-MD5")
+            );
+        }
+        // An ETag the same bytes miss twice isn't an MD5
+        mismatched = None;
+        let etag = Some(ServerMd5::Etag(digest));
+        assert!(verify(etag, Some([0; 16]), &mut mismatched).is_err());
+        assert_eq!(mismatched.as_deref(), Some("abc"));
+        assert_eq!(verify(etag, Some([0; 16]), &mut mismatched), Ok(None));
+        // and then neither are the other ETags from its host
+        assert_eq!(
+            etag_hosts.filter(etag, "https://example.com/other.jpg"),
+            None
+        );
+        assert_eq!(
+            etag_hosts.filter(etag, "https://example.org/other.jpg"),
+            etag
+        );
+        let content_md5 = Some(ServerMd5::ContentMd5(digest));
+        assert_eq!(etag_hosts.filter(content_md5, url), content_md5);
+    }
+
+    #[test]
+    fn test_parse_saved_pages() {
+        let test_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test");
+        let parse = |name: &str| {
+            let mut records =
+                parse_memories_history_html(test_dir.join(name).to_str().unwrap(), None).unwrap();
+            skip_header_row(&mut records);
+            records
+        };
+        // The same memories as test.html, saved from Chrome ("HTML only" and
+        // "Single File") and Firefox ("complete")
+        let expected = parse("test.html");
+        assert_eq!(expected.len(), 3);
+        for name in ["test_chrome.html", "test_chrome.mhtml", "test_firefox.html"] {
+            assert_eq!(parse(name), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_parse_html_fallback() {
+        // Without the <tbody> the state machine looks for, and the cells'
+        // end tags left out
+        let test_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test");
+        let html = std::fs::read_to_string(test_dir.join("test.html"))
+            .unwrap()
+            .replace("<tbody>", "")
+            .replace("</td>", "");
+        assert!(!found_table(
+            &parse_memories_history_html_from(html.as_bytes(), None).unwrap()
+        ));
+        let dir = std::env::temp_dir().join("snapdown_test_html_fallback");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("memories_history.html");
+        std::fs::write(&path, html).unwrap();
+        let mut records = parse_memories_history_html(path.to_str().unwrap(), None).unwrap();
+        skip_header_row(&mut records);
+        let mut expected =
+            parse_memories_history_html(test_dir.join("test.html").to_str().unwrap(), None)
+                .unwrap();
+        skip_header_row(&mut expected);
+        assert_eq!(records, expected);
+
+        // An export without memories has the headings and nothing else
+        let html = std::fs::read_to_string(test_dir.join("test.html")).unwrap();
+        let first_row = html.match_indices("<tr").nth(1).unwrap().0;
+        let html = format!("{}</tbody></table>", &html[..first_row]);
+        let records = parse_memories_history_html_from(html.as_bytes(), None).unwrap();
+        assert!(found_table(&records));
+        assert_eq!(records.len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_long_fields() {
+        // Hands out a few bytes at a time, so tags and fields are split
+        // between reads everywhere
+        struct Trickle<'a>(&'a [u8]);
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let count = buf.len().min(self.0.len()).min(7);
+                buf[..count].copy_from_slice(&self.0[..count]);
+                self.0 = &self.0[count..];
+                Ok(count)
+            }
+        }
+
+        let test_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test");
+        let html = std::fs::read_to_string(test_dir.join("test.html")).unwrap();
+        let expected = parse_memories_history_html_from(html.as_bytes(), None).unwrap();
+        assert_eq!(
+            parse_memories_history_html_from(Trickle(html.as_bytes()), None).unwrap(),
+            expected
+        );
+
+        // A link a lot longer than the parser's buffer
+        let signature = "x".repeat(40_000);
+        let html = html.replacen("sig=bogus-4", &format!("sig={}", signature), 1);
+        let records = parse_memories_history_html_from(Trickle(html.as_bytes()), None).unwrap();
+        assert_eq!(records.len(), expected.len());
+        assert!(records[1][3].ends_with(&format!("&sig={}", signature)));
+        assert_eq!(records[2], expected[2]);
+    }
+
     #[test]
     fn test_parse_html_snippet() {
         let test_file_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
@@ -1236,3 +7715,191 @@ mod tests {
         }
     }
 }
+
+// Whole runs against a MockFetch instead of the network, with either engine,
+// see fetch.rs
+#[cfg(test)]
+mod run_tests {
+    use super::*;
+    use fetch::MockFetch;
+
+    const SVG_URL: &str = "https://upload.wikimedia.org/wikipedia/en/c/c4/Snapchat_logo.svg";
+    const PNG_URL: &str = "https://upload.wikimedia.org/wikipedia/commons/thumb/b/b4/Snapchat%27s_global_reach_in_2014.png/1280px-Snapchat%27s_global_reach_in_2014.png?download";
+    const JPG_URL: &str =
+        "https://upload.wikimedia.org/wikipedia/commons/c/c7/Snapchat_Spectacles.jpg?download";
+
+    fn fixture(name: &str) -> String {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test")
+            .join(name)
+            .display()
+            .to_string()
+    }
+
+    // An empty output directory for a test
+    fn output_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn run(
+        input: &str,
+        output_dir: &Path,
+        http: &Arc<MockFetch>,
+        options: DownloadOptions,
+    ) -> SnapdownStatus {
+        // The rayon engine's pool of DEFAULT_NUM_JOBS threads takes seconds
+        // to start
+        let options = DownloadOptions {
+            http: Some(http.clone()),
+            jobs: 8,
+            ..options
+        };
+        run_downloader(
+            input,
+            output_dir.to_str().unwrap(),
+            &options,
+            &RunControl::default(),
+            None,
+        )
+        .unwrap()
+    }
+
+    // The requests for files, without the space check's for their sizes
+    fn downloads(http: &MockFetch) -> Vec<String> {
+        http.requests()
+            .into_iter()
+            .filter(|request| !request.ends_with(" bytes=0-0"))
+            .collect()
+    }
+
+    #[test]
+    fn test_run_csv_fixture() {
+        let dir = output_dir("snapdown_test_run_csv_fixture");
+        let http = Arc::new(MockFetch::default());
+        http.answer(SVG_URL, 200, b"<svg></svg>");
+        http.answer(PNG_URL, 200, b"not much of a png");
+        http.answer(JPG_URL, 200, b"not much of a jpg");
+        // Inputs are told apart by their names
+        let input = std::env::temp_dir().join("snapdown_test_run_csv_fixture_snap_export.csv");
+        fs::copy(fixture("test.csv"), &input).unwrap();
+        let input = input.to_str().unwrap();
+
+        let usage_path = dir.with_extension("usage.json");
+        let _ = fs::remove_file(&usage_path);
+        let options = || DownloadOptions {
+            usage_path: Some(usage_path.clone()),
+            ..Default::default()
+        };
+        let status = run(input, &dir, &http, options());
+        assert_eq!(status.total_count, 167);
+        assert_eq!(status.success_count, 167);
+        assert_eq!(status.error_count, 0);
+        assert_eq!(status.skip_count, 0);
+        assert_eq!(downloads(&http).len(), 167);
+        assert_eq!(
+            http.requests().len() - downloads(&http).len(),
+            space_check::SAMPLE_SIZE
+        );
+        let downloaded = status.usage.unwrap().total();
+        assert!(downloaded > 0);
+        // Its dates are from the 1800s, which can't be right, so the files
+        // are named by their position
+        assert_eq!(
+            fs::read(dir.join("unknown-date/00001.svg")).unwrap(),
+            b"<svg></svg>"
+        );
+        assert_eq!(
+            fs::read(dir.join("unknown-date/00002.png")).unwrap(),
+            b"not much of a png"
+        );
+
+        // Everything is in the manifest, so nothing is asked for again
+        let status = run(input, &dir, &http, options());
+        assert_eq!(status.success_count, 0);
+        assert_eq!(status.skip_count, 167);
+        assert_eq!(http.requests().len(), 167 + space_check::SAMPLE_SIZE);
+        assert_eq!(status.usage.unwrap().total(), downloaded);
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(usage_path).unwrap();
+        fs::remove_file(input).unwrap();
+    }
+
+    #[test]
+    fn test_run_html_fixture_resolving_links() {
+        let dir = output_dir("snapdown_test_run_html_fixture");
+        let http = Arc::new(MockFetch::default());
+        let endpoint = "https://us-east1-aws.api.snapchat.com/dmd/mm";
+        let resolved = "https://cdn.example.com/memory.jpg";
+        http.answer(endpoint, 200, resolved.as_bytes());
+        http.answer(resolved, 200, b"jpeg");
+        let options = DownloadOptions {
+            resolve_links: true,
+            ..Default::default()
+        };
+
+        let status = run(&fixture("test.html"), &dir, &http, options);
+        assert!(status.total_count > 0);
+        assert_eq!(status.success_count, status.total_count);
+        // Each file's link is resolved with a POST first
+        let requests = downloads(&http);
+        let gets = requests
+            .iter()
+            .filter(|request| request.starts_with("GET "));
+        assert_eq!(gets.count(), status.total_count);
+        assert!(requests.contains(&format!(
+            "POST {} uid=bogus-1&sid=bogus-2&mid=bogus-3&ts=1768335041137&sig=bogus-4",
+            endpoint
+        )));
+        assert_eq!(
+            fs::read(dir.join("2026-01-13_01-55-38_UTC_40.25548_-111.645325.jpg")).unwrap(),
+            b"jpeg"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_retries_and_failures() {
+        let dir = output_dir("snapdown_test_run_retries");
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("snap_export.csv");
+        fs::write(
+            &input,
+            "timestamp_utc,format,latitude,longitude,download_url\n\
+             2026-01-01 10:00:00 UTC,Image,1.0,2.0,https://example.com/unreachable.jpg\n\
+             2026-01-02 10:00:00 UTC,Video,1.0,2.0,https://example.com/busy.mp4\n\
+             2026-01-03 10:00:00 UTC,Image,1.0,2.0,https://example.com/gone.jpg\n",
+        )
+        .unwrap();
+        let http = Arc::new(MockFetch::default());
+        http.fail("https://example.com/unreachable.jpg");
+        http.answer("https://example.com/unreachable.jpg", 200, b"jpeg");
+        http.answer("https://example.com/busy.mp4", 503, b"");
+        http.answer("https://example.com/busy.mp4", 200, b"mp4");
+        // Not answered at all, so a 404
+        let options = DownloadOptions {
+            retries: 1,
+            // Its requests would take the answers meant for the downloads
+            space_check: SpaceCheck::Off,
+            ..Default::default()
+        };
+
+        let status = run(input.to_str().unwrap(), &dir, &http, options);
+        assert_eq!(status.success_count, 2);
+        assert_eq!(status.error_count, 1);
+        assert_eq!(status.failed_records.len(), 1);
+        assert_eq!(&status.failed_records[0][0], "2026-01-03 10:00:00 UTC");
+        let count = |url: &str| {
+            http.requests()
+                .iter()
+                .filter(|request| **request == format!("GET {}", url))
+                .count()
+        };
+        assert_eq!(count("https://example.com/unreachable.jpg"), 2);
+        assert_eq!(count("https://example.com/busy.mp4"), 2);
+        // A 404 isn't worth trying again
+        assert_eq!(count("https://example.com/gone.jpg"), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,359 @@
+// Persistent record of every download in an output directory, so an
+// interrupted run can pick up where it left off: completed files are skipped,
+// failed ones retried, and partial files continued with a Range request.
+//
+// The manifest is a JSON Lines journal. Every update is appended as one line
+// (the last line for a filename wins), so nothing is lost if SnapDown is
+// killed mid-run and a run doesn't rewrite the whole file per download. It's
+// compacted to one line per file whenever a run opens it.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+pub const MANIFEST_FILE: &str = "snapdown_manifest.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryStatus {
+    // Started, but not finished yet (or SnapDown was stopped while downloading)
+    Downloading,
+    Completed,
+    Failed,
+    // Same content as a file downloaded before, and deleted or hard linked
+    // to it (--dedup)
+    Duplicate,
+    // Uploaded with --upload-only, and deleted here
+    Uploaded,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub url: String,
+    // Relative to the output directory
+    pub filename: String,
+    pub status: EntryStatus,
+    pub bytes_written: u64,
+    // BLAKE3 hash of the complete file. Files downloaded before there was a
+    // manifest don't have one.
+    pub checksum: Option<String>,
+    // Links to the file made by --views, relative to the output directory
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub views: Vec<String>,
+    // What the downloaded file turned out to be, if it was recognized
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    // The file's name when it was renamed to match its type (a PNG that the
+    // export called an image gets .png instead of .jpg, for one)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saved_as: Option<String>,
+    // The id the file got on the --upload server, once it's uploaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uploaded: Option<String>,
+    // The MD5 the server gave for the file (its Content-MD5 or ETag), in
+    // hex, which the download was checked against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    // The row of the input a failed download came from, so --retry-failed
+    // can try it again without reading the input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record: Option<Vec<String>>,
+}
+
+impl ManifestEntry {
+    // The name of the file in the output directory
+    pub fn saved_filename(&self) -> &str {
+        self.saved_as.as_deref().unwrap_or(&self.filename)
+    }
+}
+
+// What to do with a record, given its manifest entry and the size of the
+// file already in the output directory (if any)
+#[derive(Debug, PartialEq)]
+pub enum DownloadPlan {
+    // Already downloaded completely
+    Skip,
+    // The file is from before the manifest existed, record it as completed
+    AdoptExisting { size: u64 },
+    // Continue a partial download from this offset
+    Resume { offset: u64 },
+    Fresh,
+}
+
+pub fn plan_download(entry: Option<&ManifestEntry>, existing_size: Option<u64>) -> DownloadPlan {
+    match (entry, existing_size) {
+        // Whether or not the duplicate was kept as a hard link
+        (Some(entry), _)
+            if matches!(entry.status, EntryStatus::Duplicate | EntryStatus::Uploaded) =>
+        {
+            DownloadPlan::Skip
+        }
+        (_, None) => DownloadPlan::Fresh,
+        // Left empty by a crash, most likely
+        (None, Some(0)) => DownloadPlan::Fresh,
+        (None, Some(size)) => DownloadPlan::AdoptExisting { size },
+        (Some(entry), Some(size)) => match entry.status {
+            EntryStatus::Completed if size == entry.bytes_written => DownloadPlan::Skip,
+            // Completed, but the file has changed size since. Most likely
+            // truncated, so start over.
+            EntryStatus::Completed => DownloadPlan::Fresh,
+            EntryStatus::Downloading | EntryStatus::Failed if size > 0 => {
+                DownloadPlan::Resume { offset: size }
+            }
+            EntryStatus::Downloading | EntryStatus::Failed => DownloadPlan::Fresh,
+            EntryStatus::Duplicate | EntryStatus::Uploaded => DownloadPlan::Skip,
+        },
+    }
+}
+
+pub struct Manifest {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, ManifestEntry>>,
+    journal: Mutex<File>,
+}
+
+// The entries of output_dir's manifest by filename, without changing
+// anything (empty if there's no manifest yet)
+pub fn read_entries(output_dir: &Path) -> Result<HashMap<String, ManifestEntry>> {
+    let path = output_dir.join(MANIFEST_FILE);
+    let mut entries = HashMap::new();
+    if path.exists() {
+        for line in BufReader::new(File::open(&path)?).lines() {
+            // A line cut off by a crash is just skipped, the download it
+            // described gets redone
+            match serde_json::from_str::<ManifestEntry>(&line?) {
+                Ok(entry) => {
+                    entries.insert(entry.filename.clone(), entry);
+                }
+                Err(e) => log::warn!("Skipping bad line in {:?}: {}", path, e),
+            }
+        }
+    }
+    Ok(entries)
+}
+
+impl Manifest {
+    // Load the manifest of output_dir (or start an empty one)
+    pub fn open(output_dir: &Path) -> Result<Manifest> {
+        let path = output_dir.join(MANIFEST_FILE);
+        let entries = read_entries(output_dir)?;
+
+        // Compact, so the journal doesn't grow forever across runs
+        let mut sorted: Vec<_> = entries.values().collect();
+        sorted.sort_by(|a, b| a.filename.cmp(&b.filename));
+        let mut compacted = String::new();
+        for entry in sorted {
+            compacted.push_str(&serde_json::to_string(entry)?);
+            compacted.push('\n');
+        }
+        let temp_path = path.with_extension("jsonl.tmp");
+        fs::write(&temp_path, compacted)?;
+        fs::rename(&temp_path, &path)?;
+        Manifest::with_entries(path, entries)
+    }
+
+    // Like open(), for appending to a manifest a run may have open (a retry
+    // from the GUI). Compacting would replace the file under the run, whose
+    // lines would then go to the old one.
+    pub fn open_without_compacting(output_dir: &Path) -> Result<Manifest> {
+        let entries = read_entries(output_dir)?;
+        Manifest::with_entries(output_dir.join(MANIFEST_FILE), entries)
+    }
+
+    fn with_entries(path: PathBuf, entries: HashMap<String, ManifestEntry>) -> Result<Manifest> {
+        let journal = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Manifest {
+            path,
+            entries: Mutex::new(entries),
+            journal: Mutex::new(journal),
+        })
+    }
+
+    pub fn get(&self, filename: &str) -> Option<ManifestEntry> {
+        self.entries.lock().unwrap().get(filename).cloned()
+    }
+
+    pub fn entries(&self) -> Vec<ManifestEntry> {
+        self.entries.lock().unwrap().values().cloned().collect()
+    }
+
+    // The failed downloads that have their record, for --retry-failed, by
+    // filename so a retry goes through them in the same order every time
+    pub fn failed(&self) -> Vec<ManifestEntry> {
+        let mut failed: Vec<ManifestEntry> = self
+            .entries()
+            .into_iter()
+            .filter(|entry| entry.status == EntryStatus::Failed && entry.record.is_some())
+            .collect();
+        failed.sort_by(|a, b| a.filename.cmp(&b.filename));
+        failed
+    }
+
+    // Errors are only logged, a download shouldn't fail because the
+    // manifest couldn't be written
+    pub fn record(&self, entry: ManifestEntry) {
+        match serde_json::to_string(&entry) {
+            Ok(line) => {
+                let mut journal = self.journal.lock().unwrap();
+                if let Err(e) = writeln!(journal, "{}", line) {
+                    log::error!("Error writing to {:?}: {}", self.path, e);
+                }
+            }
+            Err(e) => log::error!("Error serializing manifest entry: {}", e),
+        }
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(entry.filename.clone(), entry);
+    }
+
+    // Make sure everything recorded is on the disk, not only in the OS's
+    // buffers, before a pause the computer may be switched off during
+    pub fn sync(&self) {
+        if let Err(e) = self.journal.lock().unwrap().sync_all() {
+            log::error!("Error syncing {:?}: {}", self.path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(filename: &str, status: EntryStatus, bytes_written: u64) -> ManifestEntry {
+        ManifestEntry {
+            url: format!("https://example.com/{}", filename),
+            filename: filename.to_string(),
+            status,
+            bytes_written,
+            checksum: None,
+            views: Vec::new(),
+            mime_type: None,
+            saved_as: None,
+            uploaded: None,
+            md5: None,
+            record: None,
+        }
+    }
+
+    #[test]
+    fn test_manifest_reopen() {
+        let output_dir = std::env::temp_dir().join("snapdown_test_manifest_reopen");
+        let _ = fs::remove_dir_all(&output_dir);
+        fs::create_dir_all(&output_dir).unwrap();
+
+        let manifest = Manifest::open(&output_dir).unwrap();
+        manifest.record(entry("a.jpg", EntryStatus::Downloading, 0));
+        manifest.record(entry("b.mp4", EntryStatus::Failed, 10));
+        manifest.record(entry("a.jpg", EntryStatus::Completed, 100));
+        drop(manifest);
+
+        // Simulate a crash in the middle of writing a line
+        let mut journal = OpenOptions::new()
+            .append(true)
+            .open(output_dir.join(MANIFEST_FILE))
+            .unwrap();
+        write!(journal, "{{\"url\":\"https://exa").unwrap();
+        drop(journal);
+
+        let manifest = Manifest::open(&output_dir).unwrap();
+        assert_eq!(
+            manifest.get("a.jpg"),
+            Some(entry("a.jpg", EntryStatus::Completed, 100))
+        );
+        assert_eq!(
+            manifest.get("b.mp4"),
+            Some(entry("b.mp4", EntryStatus::Failed, 10))
+        );
+        assert_eq!(manifest.entries().len(), 2);
+
+        // Compacted to one line per file
+        let contents = fs::read_to_string(output_dir.join(MANIFEST_FILE)).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_during_a_run() {
+        let output_dir = std::env::temp_dir().join("snapdown_test_manifest_open_during_a_run");
+        let _ = fs::remove_dir_all(&output_dir);
+        fs::create_dir_all(&output_dir).unwrap();
+
+        let run = Manifest::open(&output_dir).unwrap();
+        run.record(entry("a.jpg", EntryStatus::Completed, 100));
+        let retry = Manifest::open_without_compacting(&output_dir).unwrap();
+        assert_eq!(
+            retry.get("a.jpg"),
+            Some(entry("a.jpg", EntryStatus::Completed, 100))
+        );
+        retry.record(entry("b.mp4", EntryStatus::Completed, 10));
+        run.record(entry("c.jpg", EntryStatus::Completed, 20));
+        drop(retry);
+        drop(run);
+
+        let entries = read_entries(&output_dir).unwrap();
+        assert_eq!(entries.len(), 3);
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed() {
+        let output_dir = std::env::temp_dir().join("snapdown_test_manifest_failed");
+        let _ = fs::remove_dir_all(&output_dir);
+        fs::create_dir_all(&output_dir).unwrap();
+
+        let manifest = Manifest::open(&output_dir).unwrap();
+        let failed = |filename| ManifestEntry {
+            record: Some(vec!["2024-01-01 00:00:00 UTC".to_string()]),
+            ..entry(filename, EntryStatus::Failed, 0)
+        };
+        manifest.record(failed("c.jpg"));
+        manifest.record(failed("a.jpg"));
+        // From before failed downloads kept their record
+        manifest.record(entry("b.mp4", EntryStatus::Failed, 10));
+        // Downloaded since
+        manifest.record(failed("d.jpg"));
+        manifest.record(entry("d.jpg", EntryStatus::Completed, 100));
+        drop(manifest);
+
+        let manifest = Manifest::open(&output_dir).unwrap();
+        assert_eq!(manifest.failed(), [failed("a.jpg"), failed("c.jpg")]);
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    #[test]
+    fn test_plan_download() {
+        let completed = entry("a.jpg", EntryStatus::Completed, 100);
+        let failed = entry("a.jpg", EntryStatus::Failed, 40);
+        assert_eq!(plan_download(None, None), DownloadPlan::Fresh);
+        assert_eq!(plan_download(None, Some(0)), DownloadPlan::Fresh);
+        assert_eq!(
+            plan_download(None, Some(100)),
+            DownloadPlan::AdoptExisting { size: 100 }
+        );
+        assert_eq!(
+            plan_download(Some(&completed), Some(100)),
+            DownloadPlan::Skip
+        );
+        assert_eq!(
+            plan_download(Some(&completed), Some(0)),
+            DownloadPlan::Fresh
+        );
+        assert_eq!(plan_download(Some(&completed), None), DownloadPlan::Fresh);
+        assert_eq!(
+            plan_download(Some(&failed), Some(40)),
+            DownloadPlan::Resume { offset: 40 }
+        );
+        assert_eq!(plan_download(Some(&failed), Some(0)), DownloadPlan::Fresh);
+        let duplicate = entry("a.jpg", EntryStatus::Duplicate, 100);
+        assert_eq!(plan_download(Some(&duplicate), None), DownloadPlan::Skip);
+        let uploaded = entry("a.jpg", EntryStatus::Uploaded, 100);
+        assert_eq!(plan_download(Some(&uploaded), None), DownloadPlan::Skip);
+    }
+}
@@ -0,0 +1,253 @@
+// MD5 (RFC 1321), only for checking downloads against the Content-MD5 or
+// ETag the server sends with them, which is all it's good for anymore. The
+// manifest's checksums are BLAKE3.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use base64::Engine;
+
+use crate::stats::url_host;
+
+// The integer part of abs(sin(i + 1)) * 2^32
+const K: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+const SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9,
+    14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15,
+    21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+pub struct Md5 {
+    state: [u32; 4],
+    // Bytes that don't make a whole block yet
+    buffer: [u8; 64],
+    buffered: usize,
+    length: u64,
+}
+
+impl Md5 {
+    pub fn new() -> Md5 {
+        Md5 {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+            buffer: [0; 64],
+            buffered: 0,
+            length: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length = self.length.wrapping_add(data.len() as u64);
+        if self.buffered > 0 {
+            let take = data.len().min(64 - self.buffered);
+            self.buffer[self.buffered..self.buffered + take].copy_from_slice(&data[..take]);
+            self.buffered += take;
+            data = &data[take..];
+            if self.buffered < 64 {
+                return;
+            }
+            let block = self.buffer;
+            self.process(&block);
+            self.buffered = 0;
+        }
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            self.process(block.try_into().unwrap());
+        }
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    pub fn finalize(mut self) -> [u8; 16] {
+        let bits = self.length.wrapping_mul(8);
+        let mut padding = vec![0x80];
+        padding.resize((55 - self.buffered as isize).rem_euclid(64) as usize + 1, 0);
+        padding.extend_from_slice(&bits.to_le_bytes());
+        // update() would count the padding in the length, which is already
+        // in it
+        let length = self.length;
+        self.update(&padding);
+        self.length = length;
+        let mut digest = [0; 16];
+        for (bytes, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+
+    fn process(&mut self, block: &[u8; 64]) {
+        let mut words = [0; 16];
+        for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+        let [mut a, mut b, mut c, mut d] = self.state;
+        for (i, (shift, k)) in SHIFTS.into_iter().zip(K).enumerate() {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(k)
+                .wrapping_add(words[g])
+                .rotate_left(shift);
+            (a, b, c, d) = (d, b.wrapping_add(rotated), b, c);
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+// The MD5 a response gives for its body
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ServerMd5 {
+    // Base64, as RFC 1864 has it
+    ContentMd5([u8; 16]),
+    // A strong ETag that's an MD5 in hex, as S3, Google Cloud Storage and
+    // the CDNs in front of them give files that were uploaded in one piece.
+    // Some servers make their ETags some other way, see verify_md5() in
+    // main.rs.
+    Etag([u8; 16]),
+}
+
+impl ServerMd5 {
+    pub fn from_headers(content_md5: Option<&str>, etag: Option<&str>) -> Option<ServerMd5> {
+        let content_md5 = content_md5.and_then(|value| {
+            base64::engine::general_purpose::STANDARD
+                .decode(value.trim())
+                .ok()?
+                .try_into()
+                .ok()
+        });
+        if let Some(digest) = content_md5 {
+            return Some(ServerMd5::ContentMd5(digest));
+        }
+        // Weak ones (W/"...") are only meant to be equivalent
+        let etag = etag?.trim().strip_prefix('"')?.strip_suffix('"')?;
+        if etag.len() != 32 {
+            return None;
+        }
+        let mut digest = [0; 16];
+        for (byte, pair) in digest.iter_mut().zip(etag.as_bytes().chunks_exact(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+        }
+        Some(ServerMd5::Etag(digest))
+    }
+
+    pub fn digest(self) -> [u8; 16] {
+        match self {
+            ServerMd5::ContentMd5(digest) | ServerMd5::Etag(digest) => digest,
+        }
+    }
+
+    pub fn header(self) -> &'static str {
+        match self {
+            ServerMd5::ContentMd5(_) => "Content-MD5",
+            ServerMd5::Etag(_) => "ETag",
+        }
+    }
+}
+
+// The hosts whose ETags turned out not to be MD5s, for the rest of a run.
+// Once one of a host's files showed it, checking the others would download
+// each of them twice to find out the same thing.
+#[derive(Default)]
+pub struct EtagHosts {
+    hosts: Mutex<HashSet<String>>,
+}
+
+impl EtagHosts {
+    pub fn insert(&self, url: &str) {
+        self.hosts.lock().unwrap().insert(url_host(url));
+    }
+
+    // `server_md5` without an ETag from one of them, so it isn't checked
+    pub fn filter(&self, server_md5: Option<ServerMd5>, url: &str) -> Option<ServerMd5> {
+        match server_md5 {
+            Some(ServerMd5::Etag(_)) if self.hosts.lock().unwrap().contains(&url_host(url)) => None,
+            server_md5 => server_md5,
+        }
+    }
+}
+
+pub fn to_hex(digest: [u8; 16]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(data: &[u8]) -> String {
+        let mut md5 = Md5::new();
+        md5.update(data);
+        to_hex(md5.finalize())
+    }
+
+    #[test]
+    fn test_md5() {
+        // RFC 1321's test suite
+        assert_eq!(hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            hex(
+                b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+            ),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+
+        // In pieces that don't line up with the blocks
+        let data: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let mut md5 = Md5::new();
+        for piece in data.chunks(37) {
+            md5.update(piece);
+        }
+        assert_eq!(md5.finalize().to_vec(), {
+            let mut whole = Md5::new();
+            whole.update(&data);
+            whole.finalize().to_vec()
+        });
+    }
+
+    #[test]
+    fn test_server_md5() {
+        let abc = [
+            0x90, 0x01, 0x50, 0x98, 0x3c, 0xd2, 0x4f, 0xb0, 0xd6, 0x96, 0x3f, 0x7d, 0x28, 0xe1,
+            0x7f, 0x72,
+        ];
+        assert_eq!(
+            ServerMd5::from_headers(
+                Some("kAFQmDzST7DWlj99KOF/cg=="),
+                Some("\"00000000000000000000000000000000\"")
+            ),
+            Some(ServerMd5::ContentMd5(abc))
+        );
+        assert_eq!(
+            ServerMd5::from_headers(None, Some("\"900150983cd24fb0d6963f7d28e17f72\"")),
+            Some(ServerMd5::Etag(abc))
+        );
+        // Weak, multipart or not hex
+        for etag in [
+            "W/\"900150983cd24fb0d6963f7d28e17f72\"",
+            "\"900150983cd24fb0d6963f7d28e17f72-3\"",
+            "\"zz0150983cd24fb0d6963f7d28e17f72\"",
+            "\"5f1b-5a3c\"",
+        ] {
+            assert_eq!(ServerMd5::from_headers(None, Some(etag)), None, "{}", etag);
+        }
+        assert_eq!(ServerMd5::from_headers(Some("not base64"), None), None);
+    }
+}
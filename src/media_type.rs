@@ -0,0 +1,133 @@
+// The export only says whether a memory is an "Image" or a "Video", so the
+// file name's extension is a guess (.jpg or .mp4). Snapchat also serves PNG,
+// HEIC, WebP and MOV files though, so once a file is downloaded its real type
+// is found from its first bytes, or from the server's Content-Type when those
+// aren't recognized, and the file renamed to match.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MediaType {
+    pub mime_type: &'static str,
+    // Without the dot
+    pub extension: &'static str,
+}
+
+const JPEG: MediaType = MediaType {
+    mime_type: "image/jpeg",
+    extension: "jpg",
+};
+const PNG: MediaType = MediaType {
+    mime_type: "image/png",
+    extension: "png",
+};
+const GIF: MediaType = MediaType {
+    mime_type: "image/gif",
+    extension: "gif",
+};
+const WEBP: MediaType = MediaType {
+    mime_type: "image/webp",
+    extension: "webp",
+};
+const HEIC: MediaType = MediaType {
+    mime_type: "image/heic",
+    extension: "heic",
+};
+const SVG: MediaType = MediaType {
+    mime_type: "image/svg+xml",
+    extension: "svg",
+};
+const MP4: MediaType = MediaType {
+    mime_type: "video/mp4",
+    extension: "mp4",
+};
+const MOV: MediaType = MediaType {
+    mime_type: "video/quicktime",
+    extension: "mov",
+};
+
+// Enough for every signature below
+const HEADER_LEN: usize = 16;
+
+// The type of a file starting with `header`, falling back on the
+// Content-Type the server sent with it
+pub fn detect(header: &[u8], content_type: Option<&str>) -> Option<MediaType> {
+    from_magic_bytes(header).or_else(|| content_type.and_then(from_content_type))
+}
+
+// Like detect(), reading the start of the file at `path`
+pub fn detect_file(path: &Path, content_type: Option<&str>) -> io::Result<Option<MediaType>> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    File::open(path)?
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut header)?;
+    Ok(detect(&header, content_type))
+}
+
+fn from_magic_bytes(header: &[u8]) -> Option<MediaType> {
+    if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some(JPEG);
+    }
+    if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(PNG);
+    }
+    if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        return Some(GIF);
+    }
+    if header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WEBP") {
+        return Some(WEBP);
+    }
+    // ISO base media files (MP4, MOV, HEIC) have an "ftyp" box first, with
+    // the brand of file after it
+    if header.get(4..8) == Some(b"ftyp") {
+        return match header.get(8..12)? {
+            b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"mif1" | b"msf1" => Some(HEIC),
+            b"qt  " => Some(MOV),
+            // isom, mp41, mp42, avc1 and the like
+            _ => Some(MP4),
+        };
+    }
+    None
+}
+
+fn from_content_type(content_type: &str) -> Option<MediaType> {
+    let mime_type = content_type.split(';').next()?.trim().to_ascii_lowercase();
+    match mime_type.as_str() {
+        "image/jpeg" | "image/jpg" => Some(JPEG),
+        "image/png" => Some(PNG),
+        "image/gif" => Some(GIF),
+        "image/webp" => Some(WEBP),
+        "image/heic" | "image/heif" => Some(HEIC),
+        "image/svg+xml" => Some(SVG),
+        "video/mp4" => Some(MP4),
+        "video/quicktime" => Some(MOV),
+        // application/octet-stream and the like say nothing
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10], None), Some(JPEG));
+        assert_eq!(detect(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", None), Some(PNG));
+        assert_eq!(detect(b"RIFF\x24\0\0\0WEBPVP8 ", None), Some(WEBP));
+        assert_eq!(detect(b"\0\0\0\x18ftypheic\0\0\0\0", None), Some(HEIC));
+        assert_eq!(detect(b"\0\0\0\x14ftypqt  \0\0\0\0", None), Some(MOV));
+        assert_eq!(detect(b"\0\0\0\x20ftypisom\0\0\x02\0", None), Some(MP4));
+        // The bytes win over what the server says
+        assert_eq!(detect(b"\x89PNG\r\n\x1a\n", Some("image/jpeg")), Some(PNG));
+        assert_eq!(
+            detect(b"<svg xmlns=", Some("image/svg+xml; charset=utf-8")),
+            Some(SVG)
+        );
+        assert_eq!(detect(b"PK\x03\x04", Some("Video/MP4")), Some(MP4));
+        assert_eq!(detect(b"", Some("application/octet-stream")), None);
+        assert_eq!(detect(b"\0\0\0\x18ftyp", None), None);
+    }
+}
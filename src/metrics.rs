@@ -0,0 +1,217 @@
+// /metrics on the --status-port server: counters and a histogram of the
+// downloads in Prometheus' text format, for graphing long archive runs in
+// Grafana and the like. The counters start at zero with each run.
+
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::FailureKind;
+use crate::report::RecordStatus;
+
+// Upper bounds of the download duration buckets, in seconds. Photos take a
+// fraction of a second, long videos on a slow connection minutes.
+const DURATION_BUCKETS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+#[derive(Default)]
+pub struct Metrics {
+    counts: Mutex<Counts>,
+}
+
+#[derive(Default)]
+struct Counts {
+    total: usize,
+    downloaded: u64,
+    skipped: u64,
+    failed: u64,
+    cancelled: u64,
+    invalid: u64,
+    download_link_errors: u64,
+    connection_errors: u64,
+    http_status_errors: u64,
+    file_errors: u64,
+    checksum_errors: u64,
+    bytes: u64,
+    // Counts per bucket of DURATION_BUCKETS, not cumulative
+    duration_buckets: [u64; DURATION_BUCKETS.len()],
+    // Those over the last bucket
+    duration_over: u64,
+    duration_sum: f64,
+}
+
+impl Metrics {
+    // Once the input is read
+    pub fn set_total(&self, total: usize) {
+        self.counts.lock().unwrap().total = total;
+    }
+
+    // A record is done, with `bytes_downloaded` the run's total so far
+    pub fn record(
+        &self,
+        status: RecordStatus,
+        failure: Option<FailureKind>,
+        duration: Duration,
+        bytes_downloaded: u64,
+    ) {
+        let mut counts = self.counts.lock().unwrap();
+        match status {
+            RecordStatus::Downloaded => counts.downloaded += 1,
+            RecordStatus::Skipped => counts.skipped += 1,
+            RecordStatus::Failed => counts.failed += 1,
+            RecordStatus::Cancelled => counts.cancelled += 1,
+            RecordStatus::Invalid => counts.invalid += 1,
+        }
+        match failure {
+            Some(FailureKind::DownloadLink) => counts.download_link_errors += 1,
+            Some(FailureKind::Connection) => counts.connection_errors += 1,
+            Some(FailureKind::HttpStatus) => counts.http_status_errors += 1,
+            Some(FailureKind::File) => counts.file_errors += 1,
+            Some(FailureKind::Checksum) => counts.checksum_errors += 1,
+            None => {}
+        }
+        counts.bytes = counts.bytes.max(bytes_downloaded);
+        // Skipped records take no time worth graphing
+        if status == RecordStatus::Downloaded {
+            let seconds = duration.as_secs_f64();
+            match DURATION_BUCKETS.iter().position(|bound| seconds <= *bound) {
+                Some(bucket) => counts.duration_buckets[bucket] += 1,
+                None => counts.duration_over += 1,
+            }
+            counts.duration_sum += seconds;
+        }
+    }
+
+    // The text exposition format Prometheus scrapes
+    pub fn render(&self) -> String {
+        let counts = self.counts.lock().unwrap();
+        let mut text = String::new();
+        header(&mut text, "records", "gauge", "Records in the input file.");
+        let _ = writeln!(text, "snapdown_records {}", counts.total);
+
+        header(
+            &mut text,
+            "downloads_total",
+            "counter",
+            "Records done, by outcome.",
+        );
+        for (outcome, count) in [
+            ("downloaded", counts.downloaded),
+            ("skipped", counts.skipped),
+            ("failed", counts.failed),
+            ("invalid", counts.invalid),
+            ("cancelled", counts.cancelled),
+        ] {
+            let _ = writeln!(
+                text,
+                r#"snapdown_downloads_total{{outcome="{}"}} {}"#,
+                outcome, count
+            );
+        }
+
+        header(
+            &mut text,
+            "errors_total",
+            "counter",
+            "Failed records, by what failed.",
+        );
+        for (kind, count) in [
+            ("invalid", counts.invalid),
+            ("download_link", counts.download_link_errors),
+            ("connection", counts.connection_errors),
+            ("http_status", counts.http_status_errors),
+            ("file", counts.file_errors),
+            ("checksum", counts.checksum_errors),
+        ] {
+            let _ = writeln!(
+                text,
+                r#"snapdown_errors_total{{kind="{}"}} {}"#,
+                kind, count
+            );
+        }
+
+        header(&mut text, "bytes_total", "counter", "Bytes downloaded.");
+        let _ = writeln!(text, "snapdown_bytes_total {}", counts.bytes);
+
+        header(
+            &mut text,
+            "download_duration_seconds",
+            "histogram",
+            "How long each downloaded record took.",
+        );
+        let mut cumulative = 0;
+        for (bound, count) in DURATION_BUCKETS.iter().zip(counts.duration_buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                text,
+                r#"snapdown_download_duration_seconds_bucket{{le="{}"}} {}"#,
+                bound, cumulative
+            );
+        }
+        let count = cumulative + counts.duration_over;
+        let _ = writeln!(
+            text,
+            r#"snapdown_download_duration_seconds_bucket{{le="+Inf"}} {}"#,
+            count
+        );
+        let _ = writeln!(
+            text,
+            "snapdown_download_duration_seconds_sum {}",
+            counts.duration_sum
+        );
+        let _ = writeln!(text, "snapdown_download_duration_seconds_count {}", count);
+        text
+    }
+}
+
+fn header(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP snapdown_{} {}", name, help);
+    let _ = writeln!(text, "# TYPE snapdown_{} {}", name, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        let metrics = Metrics::default();
+        metrics.set_total(4);
+        metrics.record(
+            RecordStatus::Downloaded,
+            None,
+            Duration::from_millis(300),
+            1000,
+        );
+        metrics.record(
+            RecordStatus::Downloaded,
+            None,
+            Duration::from_secs(400),
+            5000,
+        );
+        metrics.record(
+            RecordStatus::Failed,
+            Some(FailureKind::HttpStatus),
+            Duration::from_millis(20),
+            5000,
+        );
+        metrics.record(RecordStatus::Skipped, None, Duration::ZERO, 5000);
+        let text = metrics.render();
+        for line in [
+            "snapdown_records 4",
+            r#"snapdown_downloads_total{outcome="downloaded"} 2"#,
+            r#"snapdown_downloads_total{outcome="skipped"} 1"#,
+            r#"snapdown_errors_total{kind="http_status"} 1"#,
+            r#"snapdown_errors_total{kind="connection"} 0"#,
+            "snapdown_bytes_total 5000",
+            r#"snapdown_download_duration_seconds_bucket{le="0.25"} 0"#,
+            r#"snapdown_download_duration_seconds_bucket{le="0.5"} 1"#,
+            r#"snapdown_download_duration_seconds_bucket{le="300"} 1"#,
+            r#"snapdown_download_duration_seconds_bucket{le="+Inf"} 2"#,
+            "snapdown_download_duration_seconds_sum 400.3",
+            "snapdown_download_duration_seconds_count 2",
+            "# TYPE snapdown_download_duration_seconds histogram",
+        ] {
+            assert!(text.lines().any(|l| l == line), "{}\n{}", line, text);
+        }
+    }
+}
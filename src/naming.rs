@@ -0,0 +1,672 @@
+// How downloaded files are named, picked with --naming. Whatever the namer,
+// records that end up with the same name get a suffix to tell them apart
+// (see unique_filenames_and_urls()), so a namer only has to name one record
+// at a time.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use chrono::{DateTime, Utc};
+
+use crate::burst;
+use crate::export::snap_export_row;
+use crate::geocode::Geocoder;
+use crate::record::{has_implausible_date, record_location, record_timestamp};
+
+// Characters that can't be in a filename on some platform, replaced in
+// templates. Only the other sections than memories go in subdirectories (see
+// record_folder()), so that includes the separators.
+const UNSAFE_CHARACTERS: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+// Where the records with an implausible capture time go (see
+// record::has_implausible_date()), named by their place in the input: a name
+// starting with 1970-01-01 would sort them wrong for good
+pub const UNKNOWN_DATE_FOLDER: &str = "unknown-date";
+// Around a {burst_index} that's empty
+const SEPARATORS: [char; 4] = ['_', '-', '.', ' '];
+const PLACEHOLDERS: [&str; 13] = [
+    "timestamp",
+    "date",
+    "time",
+    "year",
+    "month",
+    "day",
+    "latitude",
+    "longitude",
+    "place",
+    "type",
+    "index",
+    "hash",
+    "burst_index",
+];
+
+pub trait Namer: Sync {
+    // The filename of the record at `index` of the input, extension included.
+    // Only called for rows with the shape of a record (see record_url()).
+    fn filename(&self, index: usize, row: &csv::StringRecord) -> String;
+
+    // The filename of every record, None for the rows that aren't one. For
+    // namers that need to see the other records too.
+    fn filenames(&self, records: &[csv::StringRecord]) -> Vec<Option<String>> {
+        records
+            .iter()
+            .enumerate()
+            .map(|(index, row)| record_url(row).map(|_| self.filename(index, row)))
+            .collect()
+    }
+}
+
+// <date>_<time>_UTC_<latitude>_<longitude>.<ext>, what SnapDown always did
+pub struct LegacyNamer;
+
+// A hash of the memory's ID, the same for the memory in every export
+pub struct HashNamer;
+
+// The record's position in the input, 00001.jpg and so on
+pub struct IndexNamer;
+
+// A name made of placeholders like {date} and {type}, see PLACEHOLDERS
+#[derive(Clone)]
+pub struct TemplateNamer {
+    template: String,
+    // For {place}, see geocode.rs
+    places: Option<Arc<Geocoder>>,
+}
+
+// Told apart by their template, a run only has the one geocoder
+impl PartialEq for TemplateNamer {
+    fn eq(&self, other: &Self) -> bool {
+        self.template == other.template
+    }
+}
+
+impl fmt::Debug for TemplateNamer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TemplateNamer({:?})", self.template)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Naming {
+    #[default]
+    Legacy,
+    Hash,
+    Index,
+    Template(TemplateNamer),
+}
+
+impl Naming {
+    // legacy, hash, index, or a template with at least one placeholder
+    pub fn parse(naming: &str) -> Result<Naming> {
+        match naming.trim().to_ascii_lowercase().as_str() {
+            "legacy" => Ok(Naming::Legacy),
+            "hash" => Ok(Naming::Hash),
+            "index" => Ok(Naming::Index),
+            _ if naming.contains('{') => Ok(Naming::Template(TemplateNamer::new(naming)?)),
+            _ => bail!(
+                "unknown naming {:?}, expected legacy, hash, index or a template like {{date}}_{{time}}",
+                naming
+            ),
+        }
+    }
+
+    // With the GeoNames cities file of --places for {place}, instead of the
+    // built-in cities. An error without {place} in the template, rather than
+    // a file that's quietly never read.
+    pub fn with_places(mut self, places: Option<&str>) -> Result<Naming> {
+        let Some(path) = places else {
+            return Ok(self);
+        };
+        match &mut self {
+            Naming::Template(namer) if namer.places.is_some() => {
+                namer.places = Some(Arc::new(Geocoder::open(Path::new(path))?));
+            }
+            _ => bail!(
+                "--places is only used for {{place}} in a --naming template, and {:?} has none",
+                self.name()
+            ),
+        }
+        Ok(self)
+    }
+
+    // As --naming takes it
+    pub fn name(&self) -> String {
+        match self {
+            Naming::Legacy => "legacy".to_string(),
+            Naming::Hash => "hash".to_string(),
+            Naming::Index => "index".to_string(),
+            Naming::Template(namer) => namer.template.clone(),
+        }
+    }
+
+    pub fn namer(&self) -> &dyn Namer {
+        match self {
+            Naming::Legacy => &LegacyNamer,
+            Naming::Hash => &HashNamer,
+            Naming::Index => &IndexNamer,
+            Naming::Template(namer) => namer,
+        }
+    }
+}
+
+// The download URL of a record. None if the row is neither (timestamp_utc,
+// format, latitude, longitude, download_url) from snap_export.csv, the same
+// with a folder after it for chats, stories and Spotlight (see sections.rs), nor
+// (timestamp, format, location, download_url) from memories_history.html.
+pub fn record_url(row: &csv::StringRecord) -> Option<&str> {
+    match row.len() {
+        5 | 6 => Some(&row[4]),
+        4 => Some(&row[3]),
+        _ => None,
+    }
+}
+
+// The folder a record's file goes in, relative to the output directory, e.g.
+// chats/<conversation>. Memories don't have one, or have it empty in a
+// snap_export.csv that has chats too (see export.rs). Every part of it is made
+// safe, so a record can't name a folder outside the output directory. The
+// run creates them before the downloads start.
+pub fn record_folder(row: &csv::StringRecord) -> Option<String> {
+    let folder = row.get(5).filter(|folder| !folder.is_empty())?;
+    Some(
+        folder
+            .split('/')
+            .map(folder_name)
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+// A name as one folder, e.g. a chat's conversation
+pub fn folder_name(name: &str) -> String {
+    let name = name.replace(UNSAFE_CHARACTERS, "-");
+    // Windows doesn't allow a folder name ending in a dot or a space, which
+    // also takes care of . and ..
+    let name = name.trim().trim_end_matches('.');
+    if name.is_empty() {
+        "unknown".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+// The filename and download URL of every record of a run, None for the rows
+// that don't have the shape of one. Memories taken in the same second at the
+// same place get the same legacy name, and would overwrite or skip each
+// other, so all but the first of a name get a _1, _2 and so on before the
+// extension, in input order. The first keeps its name, so the files of
+// archives downloaded before aren't downloaded again.
+//
+// Whatever the namer, records with an implausible date are named like
+// IndexNamer does in UNKNOWN_DATE_FOLDER, by their position among the rows
+// of their folder, so the memories' numbers don't change with --sections.
+// One that `downloaded` (from the manifest) has under the namer's name keeps
+// it, so its file isn't downloaded again.
+pub fn unique_filenames_and_urls<'r>(
+    records: &'r [csv::StringRecord],
+    namer: &dyn Namer,
+    downloaded: &dyn Fn(&str) -> bool,
+) -> Vec<Option<(String, &'r str)>> {
+    let mut positions: HashMap<Option<String>, usize> = HashMap::new();
+    let mut filenames: Vec<_> = records
+        .iter()
+        .zip(namer.filenames(records))
+        .map(|(row, filename)| {
+            let folder = record_folder(row);
+            let position = positions.entry(folder.clone()).or_default();
+            let index = *position;
+            *position += 1;
+            let url = record_url(row)?;
+            let in_folder = |filename: String| match &folder {
+                Some(folder) => format!("{}/{}", folder, filename),
+                None => filename,
+            };
+            let implausible = has_implausible_date(row);
+            let filename = match filename.map(in_folder) {
+                Some(filename) if !implausible || downloaded(&filename) => filename,
+                _ if implausible => in_folder(format!(
+                    "{}/{}",
+                    UNKNOWN_DATE_FOLDER,
+                    IndexNamer.filename(index, row)
+                )),
+                _ => return None,
+            };
+            Some((filename, url))
+        })
+        .collect();
+    // A suffixed name can't take the name another record has without one
+    let mut taken: HashSet<String> = filenames
+        .iter()
+        .flatten()
+        .map(|(filename, _)| filename.clone())
+        .collect();
+    let mut seen = HashSet::new();
+    for (filename, _) in filenames.iter_mut().flatten() {
+        if seen.insert(filename.clone()) {
+            continue;
+        }
+        let (stem, ext) = filename.rsplit_once('.').unwrap_or((filename, ""));
+        let unique = (1..)
+            .map(|n| format!("{}_{}.{}", stem, n, ext))
+            .find(|candidate| !taken.contains(candidate))
+            .unwrap();
+        taken.insert(unique.clone());
+        *filename = unique;
+    }
+    filenames
+}
+
+// From the media type. Files that turn out to be something else are renamed
+// once downloaded.
+fn extension(row: &csv::StringRecord) -> &'static str {
+    match &row[1] {
+        "Image" => "jpg",
+        "Video" => "mp4",
+        "PNG" => "png",
+        "SVG" => "svg",
+        _ => "bin",
+    }
+}
+
+// The capture time as it's in the input, made safe for a filename
+fn timestamp_text(row: &csv::StringRecord) -> String {
+    row[0].replace(' ', "_").replace(':', "-")
+}
+
+impl Namer for LegacyNamer {
+    fn filename(&self, _index: usize, row: &csv::StringRecord) -> String {
+        let location = if row.len() >= 5 {
+            format!("{}_{}", &row[2], &row[3])
+        } else {
+            row[2]
+                .replace("Latitude, Longitude: ", "")
+                .replace(", ", "_")
+        };
+        format!("{}_{}.{}", timestamp_text(row), location, extension(row))
+    }
+}
+
+// Snapchat's links have the memory's ID as their mid parameter, while the
+// rest of the link changes with each export
+pub fn memory_id(row: &csv::StringRecord) -> Option<String> {
+    let url = url::Url::parse(record_url(row)?).ok()?;
+    url.query_pairs()
+        .find(|(name, _)| name == "mid")
+        .map(|(_, value)| value.into_owned())
+}
+
+// Links without a memory ID are hashed whole
+fn memory_hash(row: &csv::StringRecord) -> String {
+    let memory_id =
+        memory_id(row).unwrap_or_else(|| record_url(row).unwrap_or_default().to_string());
+    blake3::hash(memory_id.as_bytes()).to_hex()[..16].to_string()
+}
+
+impl Namer for HashNamer {
+    fn filename(&self, _index: usize, row: &csv::StringRecord) -> String {
+        format!("{}.{}", memory_hash(row), extension(row))
+    }
+}
+
+// Padded so the files sort in input order in most exports, there are seldom
+// 100,000 memories
+fn index_text(index: usize) -> String {
+    format!("{:05}", index + 1)
+}
+
+impl Namer for IndexNamer {
+    fn filename(&self, index: usize, row: &csv::StringRecord) -> String {
+        format!("{}.{}", index_text(index), extension(row))
+    }
+}
+
+impl TemplateNamer {
+    pub fn new(template: &str) -> Result<TemplateNamer> {
+        let mut rest = template;
+        let mut placeholders = 0;
+        while let Some(start) = rest.find('{') {
+            let Some(length) = rest[start..].find('}') else {
+                bail!("{:?} has a {{ without a }}", template);
+            };
+            let placeholder = &rest[start + 1..start + length];
+            if !PLACEHOLDERS.contains(&placeholder) {
+                bail!(
+                    "unknown placeholder {{{}}} in {:?}, expected one of {}",
+                    placeholder,
+                    template,
+                    PLACEHOLDERS.map(|name| format!("{{{}}}", name)).join(", ")
+                );
+            }
+            placeholders += 1;
+            rest = &rest[start + length + 1..];
+        }
+        if placeholders == 0 {
+            bail!("{:?} has no placeholders", template);
+        }
+        Ok(TemplateNamer {
+            template: template.to_string(),
+            places: template
+                .contains("{place}")
+                .then(|| Arc::new(Geocoder::builtin())),
+        })
+    }
+
+    fn value(
+        &self,
+        placeholder: &str,
+        index: usize,
+        row: &csv::StringRecord,
+        burst: Option<DateTime<Utc>>,
+    ) -> String {
+        let timestamp = record_timestamp(row);
+        let formatted = |format: &str| {
+            timestamp.map_or("unknown".to_string(), |timestamp| {
+                timestamp.format(format).to_string()
+            })
+        };
+        let fields = snap_export_row(row).unwrap_or_default();
+        match placeholder {
+            "timestamp" => timestamp_text(row),
+            "date" => formatted("%Y-%m-%d"),
+            "time" => formatted("%H-%M-%S"),
+            "year" => formatted("%Y"),
+            "month" => formatted("%m"),
+            "day" => formatted("%d"),
+            "latitude" => fields[2].clone(),
+            "longitude" => fields[3].clone(),
+            "place" => record_location(row)
+                .zip(self.places.as_ref())
+                .and_then(|((latitude, longitude), places)| places.place(latitude, longitude))
+                .unwrap_or_else(|| "unknown".to_string()),
+            "type" => row[1].to_lowercase(),
+            "index" => index_text(index),
+            "hash" => memory_hash(row),
+            "burst_index" => burst
+                .map(|burst| burst.format("%Y%m%d-%H%M%S").to_string())
+                .unwrap_or_default(),
+            // new() only lets the ones above through
+            _ => String::new(),
+        }
+    }
+
+    // `burst` is the record's from burst::find_bursts()
+    fn name(&self, index: usize, row: &csv::StringRecord, burst: Option<DateTime<Utc>>) -> String {
+        let mut name = String::new();
+        let mut rest = self.template.as_str();
+        while let Some((before, after)) = rest.split_once('{') {
+            let (placeholder, mut after) = after.split_once('}').unwrap_or((after, ""));
+            name.push_str(before);
+            let value = self.value(placeholder, index, row, burst);
+            if value.is_empty() && placeholder == "burst_index" {
+                // Not in a burst, and without a separator left over
+                let trimmed = name.trim_end_matches(SEPARATORS).len();
+                if trimmed < name.len() {
+                    name.truncate(trimmed);
+                } else {
+                    after = after.trim_start_matches(SEPARATORS);
+                }
+            }
+            name.push_str(&value);
+            rest = after;
+        }
+        name.push_str(rest);
+        let name = name.replace(UNSAFE_CHARACTERS, "-");
+        // A template of only missing values, like {latitude} of a memory
+        // without a location
+        let stem = if name.trim().is_empty() {
+            index_text(index)
+        } else {
+            name
+        };
+        format!("{}.{}", stem, extension(row))
+    }
+}
+
+impl Namer for TemplateNamer {
+    fn filename(&self, index: usize, row: &csv::StringRecord) -> String {
+        self.name(index, row, None)
+    }
+
+    fn filenames(&self, records: &[csv::StringRecord]) -> Vec<Option<String>> {
+        let bursts = if self.template.contains("{burst_index}") {
+            burst::find_bursts(records)
+        } else {
+            vec![None; records.len()]
+        };
+        records
+            .iter()
+            .zip(bursts)
+            .enumerate()
+            .map(|(index, (row, burst))| record_url(row).map(|_| self.name(index, row, burst)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn csv_row(timestamp: &str, media_type: &str, url: &str) -> csv::StringRecord {
+        csv::StringRecord::from(vec![timestamp, media_type, "40.0", "-111.0", url])
+    }
+
+    #[test]
+    fn test_legacy_namer() {
+        let html_row = csv::StringRecord::from(vec![
+            "2026-01-13 01:55:38 UTC",
+            "Image",
+            "Latitude, Longitude: 40.25548, -111.645325",
+            "https://example.com/a",
+        ]);
+        assert_eq!(
+            LegacyNamer.filename(0, &html_row),
+            "2026-01-13_01-55-38_UTC_40.25548_-111.645325.jpg"
+        );
+        let row = csv_row("1800-01-18T00:28:45+00:00", "SVG", "https://example.com/b");
+        assert_eq!(
+            LegacyNamer.filename(3, &row),
+            "1800-01-18T00-28-45+00-00_40.0_-111.0.svg"
+        );
+    }
+
+    #[test]
+    fn test_hash_namer() {
+        let row = |url| csv_row("2026-01-13 01:55:38 UTC", "Video", url);
+        let name = HashNamer.filename(0, &row("https://example.com/dmd?mid=m1&sig=a"));
+        assert_eq!(name.len(), "0123456789abcdef.mp4".len());
+        assert!(name.ends_with(".mp4"));
+        // The same memory from another export, a different memory
+        assert_eq!(
+            HashNamer.filename(5, &row("https://example.com/dmd?sig=b&mid=m1")),
+            name
+        );
+        assert_ne!(
+            HashNamer.filename(0, &row("https://example.com/dmd?mid=m2&sig=a")),
+            name
+        );
+    }
+
+    #[test]
+    fn test_index_namer() {
+        let row = csv_row("2026-01-13 01:55:38 UTC", "PNG", "https://example.com/a");
+        assert_eq!(IndexNamer.filename(0, &row), "00001.png");
+        assert_eq!(IndexNamer.filename(123455, &row), "123456.png");
+    }
+
+    #[test]
+    fn test_template_namer() {
+        let row = csv_row("2026-01-13 01:55:38 UTC", "Image", "https://example.com/a");
+        let namer = TemplateNamer::new("{year}/{date} {time}_{type}_{index}").unwrap();
+        assert_eq!(
+            namer.filename(6, &row),
+            "2026-2026-01-13 01-55-38_image_00007.jpg"
+        );
+        let namer = TemplateNamer::new("{latitude},{longitude}").unwrap();
+        assert_eq!(namer.filename(0, &row), "40.0,-111.0.jpg");
+        let paris = csv::StringRecord::from(vec![
+            "2023-06-01 12:00:00 UTC",
+            "Image",
+            "48.8584",
+            "2.2945",
+            "https://example.com/a",
+        ]);
+        let namer = TemplateNamer::new("{date}_{place}").unwrap();
+        assert_eq!(namer.filename(0, &paris), "2023-06-01_Paris_France.jpg");
+        assert_eq!(namer.filename(0, &row), "2026-01-13_unknown.jpg");
+        let no_location = csv::StringRecord::from(vec![
+            "2026-01-13 01:55:38 UTC",
+            "Image",
+            "",
+            "https://example.com/a",
+        ]);
+        assert_eq!(
+            TemplateNamer::new("{latitude}")
+                .unwrap()
+                .filename(1, &no_location),
+            "00002.jpg"
+        );
+        assert!(TemplateNamer::new("{date}_{city}").is_err());
+        assert!(TemplateNamer::new("{date").is_err());
+        assert!(TemplateNamer::new("photo").is_err());
+    }
+
+    #[test]
+    fn test_burst_index() {
+        let photo = |time| csv_row(time, "Image", "https://example.com/a");
+        let records = [
+            photo("2023-06-01 10:00:00 UTC"),
+            photo("2023-06-01 10:00:01 UTC"),
+            photo("2023-06-01 10:00:02 UTC"),
+            photo("2023-06-01 10:05:00 UTC"),
+        ];
+        let namer = TemplateNamer::new("{date}_{burst_index}_{time}").unwrap();
+        assert_eq!(
+            namer.filenames(&records),
+            [
+                Some("2023-06-01_20230601-100000_10-00-00.jpg".to_string()),
+                Some("2023-06-01_20230601-100000_10-00-01.jpg".to_string()),
+                Some("2023-06-01_20230601-100000_10-00-02.jpg".to_string()),
+                Some("2023-06-01_10-05-00.jpg".to_string()),
+            ]
+        );
+        let namer = TemplateNamer::new("{burst_index}-{time}").unwrap();
+        assert_eq!(
+            namer.filenames(&records[3..]),
+            [Some("10-05-00.jpg".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_parse_naming() {
+        assert_eq!(Naming::parse("Legacy").unwrap(), Naming::Legacy);
+        assert_eq!(Naming::parse("index").unwrap(), Naming::Index);
+        assert!(matches!(
+            Naming::parse("{date}_{hash}").unwrap(),
+            Naming::Template(_)
+        ));
+        assert!(Naming::parse("random").is_err());
+        // Nothing to look up
+        let error = Naming::Legacy
+            .with_places(Some("cities1000.txt"))
+            .unwrap_err();
+        assert!(error.to_string().contains("{place}"), "{}", error);
+        let naming = Naming::parse("{date}_{type}").unwrap();
+        assert!(naming.with_places(Some("cities1000.txt")).is_err());
+    }
+
+    #[test]
+    fn test_unique_filenames_and_urls() {
+        let record = |url| csv_row("2026-01-01 00:00:00 UTC", "Image", url);
+        let records = [
+            record("https://example.com/a"),
+            record("https://example.com/b"),
+            csv::StringRecord::from(vec!["bad row"]),
+            record("https://example.com/c"),
+            // Has the name the second one would get otherwise
+            csv::StringRecord::from(vec![
+                "2026-01-01 00:00:00 UTC",
+                "Image",
+                "40.0",
+                "-111.0_1",
+                "https://example.com/d",
+            ]),
+        ];
+        assert_eq!(
+            unique_filenames_and_urls(&records, &LegacyNamer, &|_| false),
+            [
+                Some((
+                    "2026-01-01_00-00-00_UTC_40.0_-111.0.jpg".to_string(),
+                    "https://example.com/a"
+                )),
+                Some((
+                    "2026-01-01_00-00-00_UTC_40.0_-111.0_2.jpg".to_string(),
+                    "https://example.com/b"
+                )),
+                None,
+                Some((
+                    "2026-01-01_00-00-00_UTC_40.0_-111.0_3.jpg".to_string(),
+                    "https://example.com/c"
+                )),
+                Some((
+                    "2026-01-01_00-00-00_UTC_40.0_-111.0_1.jpg".to_string(),
+                    "https://example.com/d"
+                )),
+            ]
+        );
+        let by_date = TemplateNamer::new("{date}").unwrap();
+        let filenames = unique_filenames_and_urls(&records[..2], &by_date, &|_| false);
+        assert_eq!(filenames[1].as_ref().unwrap().0, "2026-01-01_1.jpg");
+
+        // Whatever the naming, a memory from 1970 goes by its place instead
+        let epoch = [
+            records[0].clone(),
+            csv_row("1970-01-01 00:00:00 UTC", "Video", "https://example.com/f"),
+        ];
+        for namer in [&LegacyNamer as &dyn Namer, &by_date, &HashNamer] {
+            let filenames = unique_filenames_and_urls(&epoch, namer, &|_| false);
+            assert_eq!(filenames[1].as_ref().unwrap().0, "unknown-date/00002.mp4");
+        }
+        // unless it was downloaded under its name before
+        let old_name = "1970-01-01_00-00-00_UTC_40.0_-111.0.mp4";
+        let filenames =
+            unique_filenames_and_urls(&epoch, &LegacyNamer, &|filename| filename == old_name);
+        assert_eq!(filenames[1].as_ref().unwrap().0, old_name);
+
+        // Chat media goes in its conversation's folder
+        let chat = |folder| {
+            csv::StringRecord::from(vec![
+                "2026-01-01 00:00:00 UTC",
+                "Media",
+                "",
+                "",
+                "https://example.com/e",
+                folder,
+            ])
+        };
+        let records = [chat("chats/Trip: 2025"), chat("chats/ ... "), chat("../..")];
+        assert_eq!(
+            unique_filenames_and_urls(&records, &by_date, &|_| false),
+            [
+                Some((
+                    "chats/Trip- 2025/2026-01-01.bin".to_string(),
+                    "https://example.com/e"
+                )),
+                Some((
+                    "chats/unknown/2026-01-01.bin".to_string(),
+                    "https://example.com/e"
+                )),
+                Some((
+                    "unknown/unknown/2026-01-01.bin".to_string(),
+                    "https://example.com/e"
+                )),
+            ]
+        );
+
+        // The chats before them don't change the memories' numbers
+        let chats_first = [chat("chats/Trip"), epoch[0].clone(), epoch[1].clone()];
+        let filenames = unique_filenames_and_urls(&chats_first, &by_date, &|_| false);
+        assert_eq!(filenames[2].as_ref().unwrap().0, "unknown-date/00002.mp4");
+    }
+}
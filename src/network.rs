@@ -0,0 +1,222 @@
+// Rides out network outages. When every download fails to even connect,
+// the network itself is gone (Wi-Fi dropped, laptop asleep, ...), so instead
+// of failing every remaining record, the downloads wait and a probe checks
+// for the connection to come back.
+
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::control::RunControl;
+use crate::stats::url_host;
+
+// Connection errors in a row (without any successful request in between)
+// before assuming the network is down
+const OFFLINE_THRESHOLD: usize = 10;
+// How often to check whether the network is back
+const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Shared by all of a run's downloads
+#[derive(Default)]
+pub struct NetworkMonitor {
+    consecutive_errors: AtomicUsize,
+    offline: AtomicBool,
+    // Set while one download probes, so a few hundred waiting downloads don't
+    // all probe at once
+    probing: AtomicBool,
+}
+
+// Held by the download probing
+struct ProbeGuard<'a>(&'a AtomicBool);
+
+impl Drop for ProbeGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+impl NetworkMonitor {
+    pub fn record_success(&self) {
+        self.consecutive_errors.store(0, Ordering::Relaxed);
+    }
+
+    // Returns true if the network is (now) considered down. `log` is only
+    // called by the worker that noticed.
+    pub fn record_connection_error(&self, log: impl Fn(String)) -> bool {
+        let errors = self.consecutive_errors.fetch_add(1, Ordering::Relaxed) + 1;
+        if errors >= OFFLINE_THRESHOLD && !self.offline.swap(true, Ordering::Relaxed) {
+            log(format!(
+                "Network connection lost ({} connection errors in a row), waiting for it to come back...",
+                errors
+            ));
+        }
+        self.is_offline()
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline.load(Ordering::Relaxed)
+    }
+
+    // Block until the network is back, probing the host of `url`. Returns
+    // false if the run was cancelled while waiting.
+    pub fn wait_until_online(&self, url: &str, control: &RunControl, log: impl Fn(String)) -> bool {
+        let host = url_host(url);
+        while self.is_offline() {
+            if control.is_cancelled() {
+                return false;
+            }
+            let Some(_probing) = self.start_probe() else {
+                std::thread::sleep(Duration::from_millis(100));
+                continue;
+            };
+            // Someone else may have found the connection back while this
+            // worker waited to probe
+            if !self.is_offline() {
+                break;
+            }
+            if can_connect(&host) {
+                self.set_online(&log);
+            } else {
+                std::thread::sleep(PROBE_INTERVAL);
+            }
+        }
+        !control.is_cancelled()
+    }
+
+    // Like wait_until_online(), for the async downloader, which waits and
+    // probes without blocking a thread
+    #[cfg(not(feature = "rayon-downloader"))]
+    pub async fn wait_until_online_async(
+        &self,
+        url: &str,
+        control: &RunControl,
+        log: impl Fn(String),
+    ) -> bool {
+        let host = url_host(url);
+        while self.is_offline() {
+            if control.is_cancelled() {
+                return false;
+            }
+            let Some(_probing) = self.start_probe() else {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            };
+            if !self.is_offline() {
+                break;
+            }
+            let connect = tokio::net::TcpStream::connect((host.as_str(), 443));
+            if let Ok(Ok(_)) = tokio::time::timeout(PROBE_TIMEOUT, connect).await {
+                self.set_online(&log);
+            } else {
+                tokio::time::sleep(PROBE_INTERVAL).await;
+            }
+        }
+        !control.is_cancelled()
+    }
+
+    // None if another download is already probing
+    fn start_probe(&self) -> Option<ProbeGuard<'_>> {
+        (!self.probing.swap(true, Ordering::Acquire)).then_some(ProbeGuard(&self.probing))
+    }
+
+    fn set_online(&self, log: impl Fn(String)) {
+        self.consecutive_errors.store(0, Ordering::Relaxed);
+        self.offline.store(false, Ordering::Relaxed);
+        log("Network connection is back, continuing downloads".to_string());
+    }
+}
+
+// Whether the request failed before reaching the server at all, as opposed to
+// the server answering with an error, or too slowly (see is_timeout())
+pub fn is_connection_error(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::HostNotFound
+        | ureq::Error::ConnectionFailed
+        | ureq::Error::Timeout(ureq::Timeout::Resolve | ureq::Timeout::Connect) => true,
+        ureq::Error::Io(e) => matches!(
+            e.kind(),
+            io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::NetworkDown
+                | io::ErrorKind::NetworkUnreachable
+                | io::ErrorKind::HostUnreachable
+        ),
+        _ => false,
+    }
+}
+
+// Whether the server was reached but didn't answer in time, e.g. with
+// --response-timeout. Worth trying again like a connection error, but a slow
+// server says nothing about the network being down.
+pub fn is_timeout(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::Timeout(ureq::Timeout::Resolve | ureq::Timeout::Connect) => false,
+        ureq::Error::Timeout(_) => true,
+        ureq::Error::Io(e) => e.kind() == io::ErrorKind::TimedOut,
+        _ => false,
+    }
+}
+
+// Resolve and open a TCP connection to the host's HTTPS port, without sending
+// anything
+fn can_connect(host: &str) -> bool {
+    let Ok(addresses) = (host, 443).to_socket_addrs() else {
+        return false;
+    };
+    addresses
+        .into_iter()
+        .any(|address| TcpStream::connect_timeout(&address, PROBE_TIMEOUT).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_offline_after_consecutive_errors() {
+        let monitor = NetworkMonitor::default();
+        for _ in 0..OFFLINE_THRESHOLD - 1 {
+            assert!(!monitor.record_connection_error(|_| {}));
+        }
+        monitor.record_success();
+        for _ in 0..OFFLINE_THRESHOLD - 1 {
+            assert!(!monitor.record_connection_error(|_| {}));
+        }
+        let logged = Mutex::new(Vec::new());
+        assert!(monitor.record_connection_error(|m| logged.lock().unwrap().push(m)));
+        assert!(monitor.record_connection_error(|m| logged.lock().unwrap().push(m)));
+        assert_eq!(logged.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_wait_until_online_stops_on_cancel() {
+        let monitor = NetworkMonitor::default();
+        for _ in 0..OFFLINE_THRESHOLD {
+            monitor.record_connection_error(|_| {});
+        }
+        let control = RunControl::default();
+        control.cancel();
+        assert!(!monitor.wait_until_online("https://example.invalid/x", &control, |_| {}));
+    }
+
+    #[test]
+    fn test_is_connection_error() {
+        assert!(is_connection_error(&ureq::Error::HostNotFound));
+        assert!(is_connection_error(&ureq::Error::Io(io::Error::from(
+            io::ErrorKind::ConnectionRefused
+        ))));
+        assert!(!is_connection_error(&ureq::Error::StatusCode(403)));
+        // Not answered in time, the connection itself was fine
+        let timeout = ureq::Error::Timeout(ureq::Timeout::RecvResponse);
+        assert!(!is_connection_error(&timeout));
+        assert!(is_timeout(&timeout));
+        let timeout = ureq::Error::Timeout(ureq::Timeout::Connect);
+        assert!(is_connection_error(&timeout));
+        assert!(!is_timeout(&timeout));
+    }
+}
@@ -0,0 +1,87 @@
+// Desktop notifications (--notify, and "Notify me" in the GUI), for runs of
+// hours that the window is minimized for: one when the run is over, and one
+// the first time the share of failed downloads goes over
+// --notify-error-rate, as a run like that needs looking at (an expired
+// export, a lost connection) rather than more waiting.
+//
+// Sending is best effort. Without a notification service to send to, on a
+// server without a desktop for one, it's only logged.
+
+use crate::format;
+
+// Percent of the downloads so far
+pub const DEFAULT_ERROR_RATE: f64 = 10.0;
+// A few failures among the first files say little about the rest
+const MIN_DOWNLOADS: usize = 20;
+
+#[derive(Debug)]
+pub struct Notifier {
+    error_rate: f64,
+    warned: bool,
+}
+
+impl Notifier {
+    pub fn new(error_rate: f64) -> Self {
+        Notifier {
+            error_rate,
+            warned: false,
+        }
+    }
+
+    // With the downloads of the run so far, successful or failed, the text
+    // of the notification if the failed ones went over the error rate. Only
+    // once a run. Skipped files aren't downloads, or a resumed run with most
+    // of its files already there would never get to the rate.
+    pub fn check_error_rate(&mut self, downloads: usize, failed: usize) -> Option<String> {
+        if self.warned || downloads < MIN_DOWNLOADS {
+            return None;
+        }
+        let fraction = failed as f64 / downloads as f64;
+        if fraction * 100.0 <= self.error_rate {
+            return None;
+        }
+        self.warned = true;
+        Some(format!(
+            "{} of {} files failed so far ({})",
+            format::format_count(failed),
+            format::format_count(downloads),
+            format::format_percent(fraction)
+        ))
+    }
+}
+
+// On a thread of its own, as showing one waits for D-Bus or the system and
+// the GUI shouldn't. Join it if the program is about to exit.
+pub fn send(summary: String, body: String) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        let result = notify_rust::Notification::new()
+            .appname("SnapDown")
+            .summary(&summary)
+            .body(&body)
+            .show();
+        if let Err(e) = result {
+            log::error!("Error showing the notification {:?}: {}", summary, e);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_error_rate() {
+        let mut notifier = Notifier::new(DEFAULT_ERROR_RATE);
+        // Too soon to tell
+        assert_eq!(notifier.check_error_rate(10, 10), None);
+        assert_eq!(notifier.check_error_rate(100, 10), None);
+        let body = notifier.check_error_rate(100, 11).unwrap();
+        assert!(
+            body.starts_with("11 of 100 files failed so far"),
+            "{}",
+            body
+        );
+        // Only once
+        assert_eq!(notifier.check_error_rate(200, 100), None);
+    }
+}
@@ -0,0 +1,188 @@
+// Memories with a caption, drawing or sticker on them. Snapchat keeps the
+// overlay separate from the photo or video, and the download link for such a
+// memory returns a zip with both: <id>-main.jpg (or .mp4) and
+// <id>-overlay.png. This unpacks those, and can draw the overlay onto photos
+// so they look like they did in the app.
+
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use image::{ImageFormat, imageops};
+use zip::ZipArchive;
+
+// What unpack_overlay_bundle() did with a bundle
+#[derive(Debug, PartialEq)]
+pub enum Unpacked {
+    // The overlay was drawn onto the photo
+    Composited,
+    // The overlay was saved next to the main file, at this path
+    Sidecar(PathBuf),
+    // The bundle only had the main file in it
+    MainOnly,
+}
+
+fn is_zip(path: &Path) -> Result<bool> {
+    let mut magic = [0u8; 4];
+    let mut file = File::open(path)?;
+    Ok(file.read(&mut magic)? == 4 && magic == *b"PK\x03\x04")
+}
+
+// <dir>/<stem>_overlay.png for <dir>/<stem>.<ext>
+pub fn overlay_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}_overlay.png", stem))
+}
+
+// If the file at `path` is an overlay bundle, replace it with the main file
+// in it, and composite or save the overlay. Returns None for ordinary files.
+pub fn unpack_overlay_bundle(path: &Path, composite: bool) -> Result<Option<Unpacked>> {
+    if !is_zip(path)? {
+        return Ok(None);
+    }
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+    let mut main = None;
+    let mut overlay = None;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let name = entry.name().to_lowercase();
+        let slot = if name.contains("-main.") {
+            &mut main
+        } else if name.contains("-overlay.") {
+            &mut overlay
+        } else {
+            continue;
+        };
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        *slot = Some((name, data));
+    }
+    let Some((main_name, main_data)) = main else {
+        return Err(anyhow::anyhow!("downloaded zip has no -main file in it"));
+    };
+
+    let is_photo = main_name.ends_with(".jpg") || main_name.ends_with(".jpeg");
+    let unpacked = match overlay {
+        Some((_, overlay_data)) if composite && is_photo => {
+            write_replacing(path, &composite_onto_jpeg(&main_data, &overlay_data)?)?;
+            Unpacked::Composited
+        }
+        Some((_, overlay_data)) => {
+            let sidecar = overlay_path(path);
+            write_replacing(&sidecar, &overlay_data)?;
+            write_replacing(path, &main_data)?;
+            Unpacked::Sidecar(sidecar)
+        }
+        None => {
+            write_replacing(path, &main_data)?;
+            Unpacked::MainOnly
+        }
+    };
+    Ok(Some(unpacked))
+}
+
+// The overlay is made for the photo's size, but scale it if it isn't
+fn composite_onto_jpeg(main: &[u8], overlay: &[u8]) -> Result<Vec<u8>> {
+    let mut photo = image::load_from_memory_with_format(main, ImageFormat::Jpeg)?.to_rgba8();
+    let mut overlay = image::load_from_memory_with_format(overlay, ImageFormat::Png)?.to_rgba8();
+    if overlay.dimensions() != photo.dimensions() {
+        overlay = imageops::resize(
+            &overlay,
+            photo.width(),
+            photo.height(),
+            imageops::FilterType::Triangle,
+        );
+    }
+    imageops::overlay(&mut photo, &overlay, 0, 0);
+    let mut jpeg = Vec::new();
+    image::DynamicImage::ImageRgba8(photo)
+        .to_rgb8()
+        .write_to(&mut std::io::Cursor::new(&mut jpeg), ImageFormat::Jpeg)?;
+    Ok(jpeg)
+}
+
+// Through a temporary file, so a crash never leaves half a file behind
+fn write_replacing(path: &Path, data: &[u8]) -> Result<()> {
+    let temp_path = path.with_extension("snapdown_tmp");
+    fs::write(&temp_path, data)?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    fn encode(image: image::DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut data = Vec::new();
+        image.write_to(&mut Cursor::new(&mut data), format).unwrap();
+        data
+    }
+
+    fn write_bundle(path: &Path, files: &[(&str, &[u8])]) {
+        let mut writer = zip::ZipWriter::new(File::create(path).unwrap());
+        for (name, contents) in files {
+            writer
+                .start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_unpack_overlay_bundle() {
+        let photo = encode(
+            image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+                4,
+                4,
+                image::Rgb([0, 0, 255]),
+            )),
+            ImageFormat::Jpeg,
+        );
+        let overlay = encode(
+            image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                2,
+                2,
+                image::Rgba([255, 255, 255, 255]),
+            )),
+            ImageFormat::Png,
+        );
+        let dir = std::env::temp_dir().join("snapdown_test_overlay");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("2026-01-13_01-55-38_UTC.jpg");
+
+        write_bundle(
+            &path,
+            &[("a-main.jpg", &photo), ("a-overlay.png", &overlay)],
+        );
+        assert_eq!(
+            unpack_overlay_bundle(&path, false).unwrap(),
+            Some(Unpacked::Sidecar(
+                dir.join("2026-01-13_01-55-38_UTC_overlay.png")
+            ))
+        );
+        assert_eq!(fs::read(&path).unwrap(), photo);
+        assert_eq!(fs::read(overlay_path(&path)).unwrap(), overlay);
+
+        // Not a bundle anymore
+        assert_eq!(unpack_overlay_bundle(&path, true).unwrap(), None);
+
+        write_bundle(
+            &path,
+            &[("a-main.jpg", &photo), ("a-overlay.png", &overlay)],
+        );
+        assert_eq!(
+            unpack_overlay_bundle(&path, true).unwrap(),
+            Some(Unpacked::Composited)
+        );
+        // The opaque white overlay covers the whole (blue) photo
+        let composited = image::open(&path).unwrap().to_rgb8();
+        assert_eq!(composited.dimensions(), (4, 4));
+        assert!(composited.get_pixel(1, 1).0.iter().all(|&c| c > 200));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,253 @@
+// The planner the GUI shows before a run: how many files it would download,
+// about how big they are, how long that takes at the speed measured last
+// time, and the space left on the disk afterwards. It's worked out again
+// whenever the input or a setting that changes what gets downloaded does.
+//
+// The size comes from what the server says for a sample of the files, as for
+// --space-check sample (see space_check.rs), averaged by media type since a
+// video is many times the size of a photo. Those requests are only made once
+// the user asks for an estimate, not as soon as a file is picked. The speed is the throughput of the
+// last run into the same output directory, from its snapdown_report.json.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::dry_run::DryRunReport;
+use crate::format::{format_bytes, format_count, format_duration};
+use crate::report::REPORT_FILE;
+use crate::space_check::SpaceEstimate;
+
+// Less than this downloaded is too little to tell the speed from, most of it
+// was waiting for the first responses
+const MIN_MEASURED_BYTES: u64 = 1024 * 1024;
+
+// Sizes the server gave for some of the files, by media type
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SizeSample {
+    // (total bytes, files)
+    by_media_type: BTreeMap<String, (u64, usize)>,
+}
+
+impl SizeSample {
+    pub fn add(&mut self, media_type: &str, size: u64) {
+        let (total, count) = self
+            .by_media_type
+            .entry(media_type.to_string())
+            .or_default();
+        *total += size;
+        *count += 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_media_type.is_empty()
+    }
+
+    // Media types that weren't in the sample count as the average of all the
+    // files that were
+    fn average(&self, media_type: &str) -> Option<f64> {
+        let (total, count) = match self.by_media_type.get(media_type) {
+            Some(sizes) => *sizes,
+            None => self
+                .by_media_type
+                .values()
+                .fold((0, 0), |(total, count), sizes| {
+                    (total + sizes.0, count + sizes.1)
+                }),
+        };
+        if count == 0 {
+            return None;
+        }
+        Some(total as f64 / count as f64)
+    }
+
+    // The size of that many files of each media type. None with no sample.
+    pub fn estimate(&self, by_media_type: &BTreeMap<String, usize>) -> Option<u64> {
+        by_media_type
+            .iter()
+            .map(|(media_type, count)| Some(self.average(media_type)? * *count as f64))
+            .sum::<Option<f64>>()
+            .map(|size| size.round() as u64)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+    // Records in the input, memories or not
+    pub records: usize,
+    // What a --dry-run would say
+    pub report: DryRunReport,
+    // Of the files to download or continue, partial ones counted whole as
+    // they may have to start over. None if there was no sample to go by.
+    pub size: Option<u64>,
+    // Whether the sizes were asked for at all
+    pub sampled: bool,
+    // Bytes per second, None if no run into the output directory measured it
+    pub throughput: Option<f64>,
+    // On the output directory's disk, now
+    pub free: Option<u64>,
+}
+
+impl Plan {
+    pub fn new(
+        records: usize,
+        report: DryRunReport,
+        sample: Option<&SizeSample>,
+        throughput: Option<f64>,
+        free: Option<u64>,
+    ) -> Plan {
+        let size = sample.and_then(|sample| sample.estimate(&report.by_media_type));
+        Plan {
+            records,
+            report,
+            size,
+            sampled: sample.is_some(),
+            throughput,
+            free,
+        }
+    }
+
+    pub fn files(&self) -> usize {
+        self.report.to_download + self.report.to_resume
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        let throughput = self.throughput.filter(|throughput| *throughput >= 1.0)?;
+        Some(Duration::from_secs_f64(self.size? as f64 / throughput))
+    }
+
+    // Always an estimate, only some of the sizes were asked for
+    pub fn space(&self) -> Option<SpaceEstimate> {
+        Some(SpaceEstimate {
+            needed: self.size?,
+            free: self.free?,
+            exact: false,
+        })
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{} records: {} files to download, {} already there",
+            format_count(self.records),
+            format_count(self.files()),
+            format_count(self.report.to_skip)
+        )];
+        if self.files() == 0 {
+            return lines;
+        }
+        if !self.sampled {
+            lines.push("Size and time: not estimated yet".to_string());
+            return lines;
+        }
+        lines.push(match self.size {
+            Some(size) => format!("Size: ~{}", format_bytes(size)),
+            None => "Size: unknown, the server gave no sizes".to_string(),
+        });
+        lines.push(match (self.duration(), self.throughput) {
+            (Some(duration), Some(throughput)) => format!(
+                "Time: ~{} at {}/s (the speed of the last run)",
+                format_duration(duration),
+                format_bytes(throughput.round() as u64)
+            ),
+            _ => "Time: unknown until a run has measured the download speed".to_string(),
+        });
+        if let Some(space) = self.space() {
+            lines.push(if space.is_enough() {
+                format!(
+                    "Space afterwards: ~{} free",
+                    format_bytes(space.free - space.needed)
+                )
+            } else {
+                format!("Probably not enough space: {}", space.message())
+            });
+        }
+        lines
+    }
+}
+
+// The download speed of the last run into `output_dir`, if it downloaded
+// enough to tell
+pub fn last_throughput(output_dir: &Path) -> Option<f64> {
+    let json = std::fs::read_to_string(output_dir.join(REPORT_FILE)).ok()?;
+    let report: serde_json::Value = serde_json::from_str(&json).ok()?;
+    if report["bytes_downloaded"].as_u64()? < MIN_MEASURED_BYTES {
+        return None;
+    }
+    report["throughput"].as_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        let mut sample = SizeSample::default();
+        sample.add("Image", 1_000_000);
+        sample.add("Image", 3_000_000);
+        sample.add("Video", 20_000_000);
+        let report = DryRunReport {
+            to_download: 10,
+            to_resume: 1,
+            to_skip: 5,
+            invalid: 0,
+            // A type that wasn't sampled counts as the average of the others
+            by_media_type: BTreeMap::from([
+                ("Image".to_string(), 9),
+                ("Video".to_string(), 1),
+                ("Media".to_string(), 1),
+            ]),
+        };
+        let plan = Plan::new(
+            16,
+            report.clone(),
+            Some(&sample),
+            Some(1_000_000.0),
+            Some(100_000_000),
+        );
+        assert_eq!(plan.files(), 11);
+        assert_eq!(plan.size, Some(18_000_000 + 20_000_000 + 8_000_000));
+        assert_eq!(plan.duration(), Some(Duration::from_secs(46)));
+        assert!(plan.space().unwrap().is_enough());
+        assert!(plan.lines()[3].starts_with("Space afterwards: ~"));
+
+        let plan = Plan::new(
+            16,
+            report.clone(),
+            Some(&SizeSample::default()),
+            None,
+            Some(1),
+        );
+        assert_eq!(plan.size, None);
+        assert_eq!(plan.duration(), None);
+        assert_eq!(plan.space(), None);
+        assert_eq!(plan.lines().len(), 3);
+
+        // Before the sizes were asked for
+        let plan = Plan::new(16, report, None, Some(1_000_000.0), Some(1));
+        assert_eq!(plan.size, None);
+        assert_eq!(plan.lines()[1], "Size and time: not estimated yet");
+        assert_eq!(plan.lines().len(), 2);
+    }
+
+    #[test]
+    fn test_last_throughput() {
+        let dir = std::env::temp_dir().join("snapdown_test_planner");
+        std::fs::create_dir_all(&dir).unwrap();
+        let _ = std::fs::remove_file(dir.join(REPORT_FILE));
+        assert_eq!(last_throughput(&dir), None);
+        std::fs::write(
+            dir.join(REPORT_FILE),
+            r#"{"bytes_downloaded": 5000000, "throughput": 2500000.0}"#,
+        )
+        .unwrap();
+        assert_eq!(last_throughput(&dir), Some(2_500_000.0));
+        std::fs::write(
+            dir.join(REPORT_FILE),
+            r#"{"bytes_downloaded": 100, "throughput": 50.0}"#,
+        )
+        .unwrap();
+        assert_eq!(last_throughput(&dir), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
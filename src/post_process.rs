@@ -0,0 +1,212 @@
+// --post-process-cmd: run the user's own command on every downloaded file,
+// e.g. exiftool, a renaming script or an upload. The command goes through the
+// shell, with {path} replaced by the file's path (or the path added at the
+// end if there's no {path}).
+
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::{Condvar, Mutex};
+
+use anyhow::Result;
+
+pub const PATH_PLACEHOLDER: &str = "{path}";
+
+// Shared by the download workers. Hundreds of downloads can finish at about
+// the same time, so only a few commands run at once and the rest wait.
+pub struct PostProcessor {
+    command: String,
+    max_running: usize,
+    running: Mutex<usize>,
+    finished: Condvar,
+}
+
+impl PostProcessor {
+    pub fn new(command: &str, max_running: usize) -> Self {
+        PostProcessor {
+            command: command.to_string(),
+            max_running: max_running.max(1),
+            running: Mutex::new(0),
+            finished: Condvar::new(),
+        }
+    }
+
+    // As many commands at once as there are CPUs
+    pub fn with_default_limit(command: &str) -> Self {
+        let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+        PostProcessor::new(command, cpus)
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    // Run the command on `path`, waiting for a free slot first
+    pub fn run(&self, path: &Path) -> Result<Output> {
+        {
+            let mut running = self.running.lock().unwrap();
+            while *running >= self.max_running {
+                running = self.finished.wait(running).unwrap();
+            }
+            *running += 1;
+        }
+        let output = shell_command(&self.command, path).output();
+        *self.running.lock().unwrap() -= 1;
+        self.finished.notify_one();
+        Ok(output?)
+    }
+}
+
+// Replace each {path} in `template` with what `replacement` gives for the
+// quote it's inside of, if any, so a {path} the user already quoted isn't
+// quoted again. `shell` is for sh, where there are single quotes and
+// backslashes too; cmd.exe only has double quotes.
+fn replace_placeholder(
+    template: &str,
+    shell: bool,
+    replacement: impl Fn(Option<char>) -> String,
+) -> String {
+    let mut script = String::new();
+    let mut quote = None;
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with(PATH_PLACEHOLDER) {
+            script.push_str(&replacement(quote));
+            rest = &rest[PATH_PLACEHOLDER.len()..];
+            continue;
+        }
+        script.push(c);
+        rest = &rest[c.len_utf8()..];
+        match (quote, c) {
+            (None, '"') => quote = Some('"'),
+            (None, '\'') if shell => quote = Some('\''),
+            (Some(open), c) if open == c => quote = None,
+            // An escaped character, e.g. \" in a double quoted string
+            (None | Some('"'), '\\') if shell => {
+                if let Some(escaped) = rest.chars().next() {
+                    script.push(escaped);
+                    rest = &rest[escaped.len_utf8()..];
+                }
+            }
+            _ => {}
+        }
+    }
+    script
+}
+
+// The path is passed as an argument rather than pasted into the command where
+// possible, so spaces and quotes in it can't break the command
+#[cfg(not(windows))]
+fn shell_command(template: &str, path: &Path) -> Command {
+    let script = if template.contains(PATH_PLACEHOLDER) {
+        replace_placeholder(template, true, |quote| match quote {
+            None => "\"$1\"".to_string(),
+            Some('"') => "$1".to_string(),
+            // Nothing is expanded in single quotes, so close them around it
+            Some(_) => "'\"$1\"'".to_string(),
+        })
+    } else {
+        format!("{} \"$1\"", template)
+    };
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(script).arg("sh").arg(path);
+    command
+}
+
+// cmd.exe has no arguments to pass the path in, but Windows paths can't have
+// quotes in them
+#[cfg(windows)]
+fn shell_command(template: &str, path: &Path) -> Command {
+    use std::os::windows::process::CommandExt;
+
+    let quoted = format!("\"{}\"", path.display());
+    let script = if template.contains(PATH_PLACEHOLDER) {
+        replace_placeholder(template, false, |quote| match quote {
+            None => quoted.clone(),
+            Some(_) => path.display().to_string(),
+        })
+    } else {
+        format!("{} {}", template, quoted)
+    };
+    let mut command = Command::new("cmd");
+    command.arg("/C").raw_arg(script);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_run_with_path() {
+        let path = Path::new("/tmp/it's a \"memory\".jpg");
+        let processor = PostProcessor::new("printf '%s|' {path} done", 1);
+        let output = processor.run(path).unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "/tmp/it's a \"memory\".jpg|done|"
+        );
+
+        // Without {path}, it goes at the end
+        let output = PostProcessor::new("echo", 1).run(path).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            path.to_string_lossy()
+        );
+
+        let output = PostProcessor::new("echo oops >&2; exit 3", 1)
+            .run(path)
+            .unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(String::from_utf8_lossy(&output.stderr).trim(), "oops");
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_run_with_quoted_path() {
+        let path = Path::new("/tmp/a memory.jpg");
+        for template in [
+            "printf '%s|' \"{path}\"",
+            "printf '%s|' '{path}'",
+            "printf '%s|' \"in {path}\"",
+            "printf '%s|' 'in {path}'",
+        ] {
+            let output = PostProcessor::new(template, 1).run(path).unwrap();
+            let expected = if template.contains("in ") {
+                "in /tmp/a memory.jpg|"
+            } else {
+                "/tmp/a memory.jpg|"
+            };
+            assert_eq!(
+                String::from_utf8_lossy(&output.stdout),
+                expected,
+                "{}",
+                template
+            );
+        }
+
+        // A quote that's escaped doesn't start a quoted string
+        let output = PostProcessor::new("printf '%s|' \\\" {path}", 1)
+            .run(path)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "\"|/tmp/a memory.jpg|"
+        );
+    }
+
+    #[test]
+    fn test_replace_placeholder() {
+        let quotes = |quote: Option<char>| format!("<{:?}>", quote);
+        assert_eq!(
+            replace_placeholder("a {path} \"{path}\" '{path}'", true, quotes),
+            "a <None> \"<Some('\"')>\" '<Some('\\'')>'"
+        );
+        // cmd.exe has no single quotes
+        assert_eq!(
+            replace_placeholder("a '{path}' \"{path}\"", false, quotes),
+            "a '<None>' \"<Some('\"')>\""
+        );
+    }
+}
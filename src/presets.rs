@@ -0,0 +1,365 @@
+// Presets: named bundles of settings, like "Full archive to external drive"
+// with its own output folder, naming and views, kept in snapdown_presets.json
+// (next to the other settings, see install.rs). Picked from the GUI's Preset
+// list or with `download --preset NAME`, where options given on the command
+// line still win over the preset's.
+//
+// Settings are written the way the command line options take them, e.g.
+//
+//   {
+//     "Videos to NAS": {
+//       "output_dir": "/mnt/nas/snapchat",
+//       "naming": "{date}_{time}_{type}",
+//       "views": ["by-year"],
+//       "dedup": "hardlink",
+//       "sections": ["memories", "chats"]
+//     }
+//   }
+//
+// and anything a preset leaves out is left as it is. --preset also takes
+// the TOML that --print-config writes (see config.rs), for the settings of
+// it that a preset has.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::DownloadArgs;
+use crate::dedup::DedupMode;
+use crate::install;
+use crate::naming::Naming;
+use crate::sections::Section;
+use crate::views::{LinkKind, ViewKind};
+
+pub const PRESETS_FILE: &str = "snapdown_presets.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_dir: Option<String>,
+    // As for --naming
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub naming: Option<String>,
+    // e.g. ["by-type", "by-year"], empty for none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub views: Option<Vec<String>>,
+    // hardlink or symlink
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub view_links: Option<String>,
+    // delete, hardlink, or keep to keep every duplicate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dedup: Option<String>,
+    // As for --sections, e.g. ["memories", "stories"]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sections: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sidecars: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub write_exif: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub touch: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub composite_overlays: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_rotate: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub freeze: Option<bool>,
+}
+
+impl Preset {
+    pub fn naming(&self) -> Result<Option<Naming>> {
+        self.naming.as_deref().map(Naming::parse).transpose()
+    }
+
+    pub fn views(&self) -> Result<Option<Vec<ViewKind>>> {
+        self.views
+            .as_ref()
+            .map(|views| views.iter().map(|view| ViewKind::parse(view)).collect())
+            .transpose()
+    }
+
+    pub fn view_links(&self) -> Result<Option<LinkKind>> {
+        self.view_links.as_deref().map(LinkKind::parse).transpose()
+    }
+
+    pub fn sections(&self) -> Result<Option<Vec<Section>>> {
+        self.sections
+            .as_ref()
+            .map(|sections| sections.iter().map(|name| Section::parse(name)).collect())
+            .transpose()
+    }
+
+    // Some(None) to keep every duplicate
+    pub fn dedup(&self) -> Result<Option<Option<DedupMode>>> {
+        self.dedup.as_deref().map(parse_dedup).transpose()
+    }
+
+    // So a mistake in the file is found when it's read, not when the preset
+    // is used
+    fn check(&self) -> Result<()> {
+        self.naming()?;
+        self.views()?;
+        self.view_links()?;
+        self.dedup()?;
+        self.sections()?;
+        Ok(())
+    }
+}
+
+fn parse_dedup(dedup: &str) -> Result<Option<DedupMode>> {
+    if ["keep", "none", "off"].contains(&dedup.trim().to_ascii_lowercase().as_str()) {
+        Ok(None)
+    } else {
+        DedupMode::parse(dedup).map(Some)
+    }
+}
+
+// How a preset writes these, for parse_dedup() and LinkKind::parse() to read
+// back
+pub fn dedup_name(dedup: Option<DedupMode>) -> &'static str {
+    match dedup {
+        None => "keep",
+        Some(DedupMode::Delete) => "delete",
+        Some(DedupMode::HardLink) => "hardlink",
+    }
+}
+
+pub fn link_name(link: LinkKind) -> &'static str {
+    match link {
+        LinkKind::HardLink => "hardlink",
+        LinkKind::Symlink => "symlink",
+    }
+}
+
+pub fn presets_file() -> PathBuf {
+    install::data_file(PRESETS_FILE)
+}
+
+// The presets by name. None saved yet if there's no file.
+pub fn load(path: &Path) -> Result<BTreeMap<String, Preset>> {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+    let presets: BTreeMap<String, Preset> =
+        serde_json::from_str(&json).with_context(|| format!("reading {}", path.display()))?;
+    for (name, preset) in &presets {
+        preset
+            .check()
+            .with_context(|| format!("preset {:?} in {}", name, path.display()))?;
+    }
+    Ok(presets)
+}
+
+pub fn save(path: &Path, presets: &BTreeMap<String, Preset>) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(presets)? + "\n")
+        .with_context(|| format!("writing {}", path.display()))
+}
+
+// The settings of a --print-config file that a preset has, under their
+// names there. The others (jobs, timeouts and so on) are left out.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ConfigFile {
+    output_dir: Option<String>,
+    naming: Option<String>,
+    views: Option<Vec<String>>,
+    view_links: Option<String>,
+    dedup: Option<String>,
+    sections: Option<Vec<String>>,
+    sidecars: Option<bool>,
+    no_exif: Option<bool>,
+    no_touch: Option<bool>,
+    composite_overlays: Option<bool>,
+    auto_rotate: Option<bool>,
+    freeze: Option<bool>,
+}
+
+// Whether --preset was given a file from --print-config rather than a name
+pub fn is_config_file(preset: &str) -> bool {
+    Path::new(preset)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("toml"))
+}
+
+pub fn load_config(path: &Path) -> Result<Preset> {
+    let toml =
+        std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let config: ConfigFile =
+        toml::from_str(&toml).with_context(|| format!("reading {}", path.display()))?;
+    let preset = Preset {
+        output_dir: config.output_dir,
+        naming: config.naming,
+        views: config.views,
+        view_links: config.view_links,
+        dedup: config.dedup,
+        sections: config.sections,
+        sidecars: config.sidecars,
+        write_exif: config.no_exif.map(|no_exif| !no_exif),
+        touch: config.no_touch.map(|no_touch| !no_touch),
+        composite_overlays: config.composite_overlays,
+        auto_rotate: config.auto_rotate,
+        freeze: config.freeze,
+    };
+    preset
+        .check()
+        .with_context(|| format!("reading {}", path.display()))?;
+    Ok(preset)
+}
+
+// Names aren't case sensitive, it's easy to get one wrong on the command line
+pub fn find<'a>(presets: &'a BTreeMap<String, Preset>, name: &str) -> Result<&'a Preset> {
+    if let Some(preset) = presets
+        .iter()
+        .find(|(preset_name, _)| preset_name.eq_ignore_ascii_case(name.trim()))
+        .map(|(_, preset)| preset)
+    {
+        return Ok(preset);
+    }
+    if presets.is_empty() {
+        bail!("no preset {:?}, there are none in {}", name, PRESETS_FILE);
+    }
+    let names: Vec<&str> = presets.keys().map(String::as_str).collect();
+    bail!(
+        "no preset {:?}, expected one of: {}",
+        name,
+        names.join(", ")
+    )
+}
+
+// For `download --preset`: the preset's settings, for the options that
+// weren't given on the command line. Flags that are off can't be told from
+// ones that weren't given, so the preset can turn them on but not off.
+pub fn apply_to_args(preset: &Preset, args: &mut DownloadArgs) -> Result<()> {
+    if args.output_dir.is_none() {
+        args.output_dir = preset.output_dir.clone();
+    }
+    if args.naming.is_none() {
+        args.naming = preset.naming()?;
+    }
+    if args.views.is_empty() {
+        args.views = preset.views()?.unwrap_or_default();
+    }
+    if args.view_links.is_none() && !args.views.is_empty() {
+        args.view_links = preset.view_links()?;
+    }
+    if args.dedup.is_none() {
+        args.dedup = preset.dedup()?.flatten();
+    }
+    if args.sections.is_empty() {
+        args.sections = preset.sections()?.unwrap_or_default();
+    }
+    args.sidecars |= preset.sidecars == Some(true);
+    args.no_exif |= preset.write_exif == Some(false);
+    args.no_touch |= preset.touch == Some(false);
+    args.composite_overlays |= preset.composite_overlays == Some(true);
+    args.auto_rotate |= preset.auto_rotate == Some(true);
+    args.freeze |= preset.freeze == Some(true);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Command};
+    use clap::Parser;
+
+    #[test]
+    fn test_presets() {
+        let presets: BTreeMap<String, Preset> = serde_json::from_str(
+            r#"{
+                "Videos to NAS": {
+                    "output_dir": "/mnt/nas/snapchat",
+                    "naming": "{date}_{type}",
+                    "views": ["by-year"],
+                    "dedup": "keep",
+                    "write_exif": false,
+                    "sidecars": true
+                },
+                "Full archive": {
+                    "views": ["by-type", "by-place"],
+                    "view_links": "symlink",
+                    "sections": ["memories", "chats", "stories", "spotlight"]
+                }
+            }"#,
+        )
+        .unwrap();
+        assert!(find(&presets, "nope").is_err());
+        let preset = find(&presets, "videos to nas").unwrap();
+        assert_eq!(preset.dedup().unwrap(), Some(None));
+        assert_eq!(preset.views().unwrap(), Some(vec![ViewKind::Year]));
+
+        let args = |line: &[&str]| {
+            let Some(Command::Download(args)) = Cli::parse_from(line).command else {
+                panic!("expected a download");
+            };
+            args
+        };
+        // What's on the command line wins
+        let mut download = args(&["snapdown", "download", "-o", "here", "--dedup", "delete"]);
+        apply_to_args(preset, &mut download).unwrap();
+        assert_eq!(download.output_dir.as_deref(), Some("here"));
+        assert_eq!(download.dedup, Some(DedupMode::Delete));
+        assert_eq!(download.naming, Naming::parse("{date}_{type}").ok());
+        assert_eq!(download.views, [ViewKind::Year]);
+        assert_eq!(download.view_links, None);
+        assert!(download.sidecars && download.no_exif && !download.no_touch);
+
+        let mut download = args(&["snapdown", "download"]);
+        apply_to_args(&presets["Full archive"], &mut download).unwrap();
+        assert_eq!(download.views, [ViewKind::Type, ViewKind::Place]);
+        assert_eq!(download.view_links, Some(LinkKind::Symlink));
+        assert_eq!(download.sections, Section::ALL);
+        assert_eq!(download.output_dir, None);
+
+        // Mistakes are found when the file is read
+        let dir = std::env::temp_dir().join("snapdown_test_presets");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(PRESETS_FILE);
+        assert!(load(&path).unwrap().is_empty());
+        save(&path, &presets).unwrap();
+        assert_eq!(load(&path).unwrap(), presets);
+        std::fs::write(&path, r#"{"Bad": {"views": ["by-month"]}}"#).unwrap();
+        assert!(load(&path).is_err());
+        std::fs::write(&path, r#"{"Bad": {"output": "x"}}"#).unwrap();
+        assert!(load(&path).is_err());
+
+        // What --print-config writes
+        let config = crate::config::EffectiveConfig {
+            output_dir: "/mnt/nas/snapchat".to_string(),
+            sections: vec!["memories", "chats"],
+            jobs: 8,
+            naming: "{date}_{type}".to_string(),
+            views: vec!["by-year"],
+            view_links: Some("symlink"),
+            dedup: "keep",
+            no_exif: true,
+            sidecars: true,
+            ..Default::default()
+        };
+        let path = dir.join("config.toml");
+        assert!(is_config_file(path.to_str().unwrap()) && !is_config_file("NAS"));
+        std::fs::write(&path, config.to_toml().unwrap()).unwrap();
+        let preset = load_config(&path).unwrap();
+        assert_eq!(preset.output_dir.as_deref(), Some("/mnt/nas/snapchat"));
+        assert_eq!(preset.naming.as_deref(), Some("{date}_{type}"));
+        assert_eq!(preset.views().unwrap(), Some(vec![ViewKind::Year]));
+        assert_eq!(preset.view_links().unwrap(), Some(LinkKind::Symlink));
+        assert_eq!(preset.dedup().unwrap(), Some(None));
+        assert_eq!(
+            preset.sections().unwrap(),
+            Some(vec![Section::Memories, Section::Chats])
+        );
+        assert_eq!(preset.write_exif, Some(false));
+        assert_eq!(preset.touch, Some(true));
+        assert_eq!(preset.sidecars, Some(true));
+        std::fs::write(&path, "views = [\"by-month\"]\n").unwrap();
+        assert!(load_config(&path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
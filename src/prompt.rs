@@ -0,0 +1,99 @@
+// When --cli is given without -i/-o in a terminal, ask for them instead of
+// just printing the usage. Tab completes file names, like a shell would.
+
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+use anyhow::Result;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use crate::input;
+
+// Only prompt when a person is there to answer, not in scripts or cron jobs
+pub fn can_prompt() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+struct PathHelper {
+    completer: FilenameCompleter,
+}
+
+impl Completer for PathHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        self.completer.complete(line, pos, ctx)
+    }
+}
+
+impl Hinter for PathHelper {
+    type Hint = String;
+}
+
+impl Highlighter for PathHelper {}
+
+impl Validator for PathHelper {}
+
+impl Helper for PathHelper {}
+
+// Ask for a path, starting out with `default` filled in. Returns None if the
+// user pressed Ctrl+C or Ctrl+D.
+fn prompt_path(question: &str, default: &str) -> Result<Option<String>> {
+    let mut editor: Editor<PathHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(PathHelper {
+        completer: FilenameCompleter::new(),
+    }));
+    println!("{}", question);
+    loop {
+        match editor.readline_with_initial("> ", (default, "")) {
+            Ok(line) => match input::expand_path_arg(&line) {
+                Ok(path) => return Ok(Some(path)),
+                Err(e) => println!("{}, try again:", e),
+            },
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+pub fn prompt_input_file() -> Result<Option<String>> {
+    loop {
+        let Some(path) = prompt_path(
+            "Input file (Snapchat's mydata~*.zip export, memories_history.html/.json or snap_export.csv):",
+            "",
+        )?
+        else {
+            return Ok(None);
+        };
+        match input::check_input_file(Path::new(&path)) {
+            Ok(()) => return Ok(Some(path)),
+            Err(e) => println!("{}. Try again.", e),
+        }
+    }
+}
+
+pub fn prompt_output_dir(default: &str) -> Result<Option<String>> {
+    loop {
+        let Some(path) = prompt_path("Output directory:", default)? else {
+            return Ok(None);
+        };
+        if path.is_empty() {
+            println!("Please enter a directory.");
+        } else if Path::new(&path).is_file() {
+            println!("{} is a file, not a directory, try again.", path);
+        } else {
+            return Ok(Some(path));
+        }
+    }
+}
@@ -0,0 +1,133 @@
+// The quarantine: where `verify --repair` puts the broken files it downloads
+// again, .quarantine/ in the output directory, instead of deleting them. A
+// file that's only a bit off (cut short by a full disk, or edited by a photo
+// viewer) can still be worth more than nothing, and whether it is is the
+// user's call, not SnapDown's.
+//
+// Once the run is over, the files that were downloaded again stay in
+// quarantine, and the others go back where they were, broken as they are, so
+// verify still finds them and nothing is lost either way. The report of the
+// run lists them both.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+pub const QUARANTINE_DIR: &str = ".quarantine";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QuarantinedFile {
+    // Where it was, relative to the output directory
+    pub filename: String,
+    // Where it is in quarantine, relative to the output directory
+    pub quarantined_as: String,
+    // What verify found wrong with it
+    pub problem: String,
+    // Downloaded again, so it stays in quarantine. Otherwise it was put back.
+    pub replaced: bool,
+}
+
+// The first free name for `filename` in quarantine: files repaired more than
+// once keep every broken copy, as photo_1.jpg, photo_2.jpg and so on
+fn quarantine_path(output_dir: &Path, filename: &str) -> PathBuf {
+    let path = Path::new(QUARANTINE_DIR).join(filename);
+    if !output_dir.join(&path).exists() {
+        return path;
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{}_{}{}", stem, n, extension)))
+        .find(|path| !output_dir.join(path).exists())
+        .unwrap()
+}
+
+// Move `filename` out of the way into quarantine
+pub fn quarantine(output_dir: &Path, filename: &str, problem: &str) -> Result<QuarantinedFile> {
+    let quarantined_as = quarantine_path(output_dir, filename);
+    let target = output_dir.join(&quarantined_as);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(output_dir.join(filename), &target)
+        .with_context(|| format!("moving {} into {}", filename, QUARANTINE_DIR))?;
+    Ok(QuarantinedFile {
+        filename: filename.to_string(),
+        quarantined_as: quarantined_as.to_string_lossy().replace('\\', "/"),
+        problem: problem.to_string(),
+        replaced: false,
+    })
+}
+
+// After the run: the files that weren't `downloaded` again go back, over
+// whatever part of them the run got
+pub fn settle(
+    output_dir: &Path,
+    files: &[QuarantinedFile],
+    downloaded: impl Fn(&str) -> bool,
+) -> Vec<QuarantinedFile> {
+    let mut settled = Vec::new();
+    for file in files {
+        let mut file = file.clone();
+        if downloaded(&file.filename) {
+            file.replaced = true;
+        } else {
+            let path = output_dir.join(&file.filename);
+            let _ = fs::remove_file(&path);
+            if let Err(e) = fs::rename(output_dir.join(&file.quarantined_as), &path) {
+                log::error!(
+                    "Error putting {} back from {}: {}",
+                    file.filename,
+                    file.quarantined_as,
+                    e
+                );
+            }
+        }
+        settled.push(file);
+    }
+    settled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quarantine() {
+        let dir = std::env::temp_dir().join("snapdown_test_quarantine");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("chats/friend")).unwrap();
+        fs::write(dir.join("a.jpg"), "bad").unwrap();
+        fs::write(dir.join("chats/friend/b.mp4"), "bad").unwrap();
+
+        let a = quarantine(&dir, "a.jpg", "empty").unwrap();
+        assert_eq!(a.quarantined_as, ".quarantine/a.jpg");
+        assert!(!dir.join("a.jpg").exists());
+        let b = quarantine(&dir, "chats/friend/b.mp4", "empty").unwrap();
+        assert_eq!(b.quarantined_as, ".quarantine/chats/friend/b.mp4");
+
+        // a.jpg was downloaded again, b.mp4 only partly
+        fs::write(dir.join("a.jpg"), "good").unwrap();
+        fs::write(dir.join("chats/friend/b.mp4"), "go").unwrap();
+        let settled = settle(&dir, &[a, b], |filename| filename == "a.jpg");
+        assert!(settled[0].replaced && !settled[1].replaced);
+        assert_eq!(
+            fs::read_to_string(dir.join(".quarantine/a.jpg")).unwrap(),
+            "bad"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("chats/friend/b.mp4")).unwrap(),
+            "bad"
+        );
+
+        // Repaired again, the earlier copy is kept
+        let a = quarantine(&dir, "a.jpg", "empty").unwrap();
+        assert_eq!(a.quarantined_as, ".quarantine/a_1.jpg");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,122 @@
+// Typed values out of a record's text fields, for the steps after a download
+// that need the actual capture time and place (EXIF tags, file times...)
+
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+
+use crate::export::snap_export_row;
+
+// memories_history.html/.json have "2026-01-13 01:55:38 UTC", while
+// extract_download_links.js writes whatever the table had, and older
+// versions wrote RFC 3339 ("2026-01-13T01:55:38+00:00")
+fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    let timestamp = timestamp.trim();
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(timestamp) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    let timestamp = timestamp.strip_suffix("UTC").unwrap_or(timestamp).trim();
+    NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|datetime| datetime.and_utc())
+}
+
+// Some very old memories come with a capture time of 1970-01-01 (a zero
+// timestamp) or otherwise before Snapchat existed, and a clock that was off
+// can put one in the future. Neither is when the memory was taken.
+fn is_plausible(timestamp: DateTime<Utc>) -> bool {
+    let snapchat_launch = DateTime::parse_from_rfc3339("2011-07-01T00:00:00Z").unwrap();
+    timestamp >= snapchat_launch && timestamp <= Utc::now() + TimeDelta::days(1)
+}
+
+// The capture time, None if it can't be read or is implausible (see
+// has_implausible_date())
+pub fn record_timestamp(row: &csv::StringRecord) -> Option<DateTime<Utc>> {
+    parse_timestamp(row.get(0)?).filter(|timestamp| is_plausible(*timestamp))
+}
+
+// A record whose capture time is missing, or is one that can't be right.
+// Those are named by their place in the input instead, see
+// naming::unique_filenames_and_urls(). A timestamp in a format that isn't
+// known is left as it is.
+pub fn has_implausible_date(row: &csv::StringRecord) -> bool {
+    match row.get(0).map(str::trim) {
+        None | Some("") => true,
+        Some(timestamp) => {
+            parse_timestamp(timestamp).is_some_and(|timestamp| !is_plausible(timestamp))
+        }
+    }
+}
+
+// Latitude and longitude. Memories saved without a location have 0, 0, which
+// is treated as no location.
+pub fn record_location(row: &csv::StringRecord) -> Option<(f64, f64)> {
+    let row = snap_export_row(row)?;
+    let latitude: f64 = row[2].parse().ok()?;
+    let longitude: f64 = row[3].parse().ok()?;
+    if latitude == 0.0 && longitude == 0.0 {
+        return None;
+    }
+    Some((latitude, longitude))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_record_timestamp() {
+        let expected = Utc.with_ymd_and_hms(2026, 1, 13, 1, 55, 38).unwrap();
+        let html_row = csv::StringRecord::from(vec![
+            "2026-01-13 01:55:38 UTC",
+            "Image",
+            "Latitude, Longitude: 40.25548, -111.645325",
+            "https://example.com/a",
+        ]);
+        assert_eq!(record_timestamp(&html_row), Some(expected));
+        let csv_row = csv::StringRecord::from(vec![
+            "2026-01-13T01:55:38+00:00",
+            "Image",
+            "40.0",
+            "40.0",
+            "https://example.com/a",
+        ]);
+        assert_eq!(record_timestamp(&csv_row), Some(expected));
+        assert_eq!(
+            record_timestamp(&csv::StringRecord::from(vec!["yesterday"])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_has_implausible_date() {
+        let row = |timestamp| csv::StringRecord::from(vec![timestamp, "Image"]);
+        for timestamp in ["1970-01-01 00:00:00 UTC", "2009-05-01 10:00:00 UTC", ""] {
+            assert!(has_implausible_date(&row(timestamp)), "{:?}", timestamp);
+            assert_eq!(record_timestamp(&row(timestamp)), None);
+        }
+        let next_year = (Utc::now() + TimeDelta::days(366)).to_rfc3339();
+        assert!(has_implausible_date(&row(&next_year)));
+        assert!(!has_implausible_date(&row("2014-03-02 10:00:00 UTC")));
+        // Not known to be wrong
+        assert!(!has_implausible_date(&row("yesterday")));
+    }
+
+    #[test]
+    fn test_record_location() {
+        let html_row = csv::StringRecord::from(vec![
+            "2026-01-13 01:55:38 UTC",
+            "Image",
+            "Latitude, Longitude: 40.25548, -111.645325",
+            "https://example.com/a",
+        ]);
+        assert_eq!(record_location(&html_row), Some((40.25548, -111.645325)));
+        let no_location = csv::StringRecord::from(vec![
+            "2026-01-13 01:55:38 UTC",
+            "Image",
+            "0.0",
+            "0.0",
+            "https://example.com/a",
+        ]);
+        assert_eq!(record_location(&no_location), None);
+    }
+}
@@ -0,0 +1,118 @@
+// Download links are signed, and work for anyone who has them: the uid, sid
+// and sig in their query strings are the account, a session and the
+// signature. Logs end up in public issues, so what SnapDown logs and reports
+// has those cut out, unless --log-full-urls says otherwise. The rest of a
+// link is kept, it's what tells downloads apart when debugging.
+
+// Query parameters whose values are left out, compared ignoring case. The
+// first three are Snapchat's, the rest are the usual ones of signed links
+// on other CDNs.
+const SENSITIVE_PARAMS: [&str; 9] = [
+    "uid",
+    "sid",
+    "sig",
+    "signature",
+    "token",
+    "key",
+    "x-amz-signature",
+    "x-amz-credential",
+    "x-amz-security-token",
+];
+const REDACTED: &str = "<redacted>";
+
+// Call `redact` on each URL in `text` with a query string, for what to put
+// in its place. URLs are found anywhere, up to whitespace or a quote, so it
+// works on whole files too, with the links in quotes as in JSON. Punctuation
+// at the end, like the colon of "Error downloading from <url>: ...", isn't
+// part of it.
+fn replace_urls(text: &str, redact: impl Fn(&str, &str) -> String) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("http") {
+        let (before, from_url) = rest.split_at(start);
+        redacted.push_str(before);
+        let end = from_url
+            .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>'))
+            .unwrap_or(from_url.len());
+        let end = from_url[..end]
+            .trim_end_matches([':', ',', '.', ';', ')'])
+            .len();
+        let url = &from_url[..end];
+        match url.split_once('?') {
+            Some((address, query)) if url.contains("://") => {
+                redacted.push_str(&redact(address, query))
+            }
+            _ => redacted.push_str(url),
+        }
+        rest = &from_url[end..];
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+// Drop the query strings of all URLs, for what's meant to be posted as it
+// is, like a support bundle
+pub fn redact_urls(text: &str) -> String {
+    replace_urls(text, |address, _| format!("{}?{}", address, REDACTED))
+}
+
+// Leave out the values of the sensitive query parameters of all URLs, for
+// the log, console and report
+pub fn redact_tokens(text: &str) -> String {
+    replace_urls(text, |address, query| {
+        let params: Vec<String> = query
+            .split('&')
+            .map(|param| match param.split_once('=') {
+                Some((name, _))
+                    if SENSITIVE_PARAMS
+                        .iter()
+                        .any(|sensitive| name.eq_ignore_ascii_case(sensitive)) =>
+                {
+                    format!("{}={}", name, REDACTED)
+                }
+                _ => param.to_string(),
+            })
+            .collect();
+        format!("{}?{}", address, params.join("&"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_urls() {
+        assert_eq!(
+            redact_urls(
+                "  * Error downloading from https://us-east1-aws.api.snapchat.com/dmd/mm?uid=bogus-1&sig=bogus-4: http status: 403"
+            ),
+            "  * Error downloading from https://us-east1-aws.api.snapchat.com/dmd/mm?<redacted>: http status: 403"
+        );
+        assert_eq!(redact_urls("Downloading 3 files:"), "Downloading 3 files:");
+        assert_eq!(
+            redact_urls("{\n  \"url\": \"https://example.com/a?sig=1\",\n  \"http\": 1\n}"),
+            "{\n  \"url\": \"https://example.com/a?<redacted>\",\n  \"http\": 1\n}"
+        );
+    }
+
+    #[test]
+    fn test_redact_tokens() {
+        assert_eq!(
+            redact_tokens(
+                "  * Error downloading from https://us-east1-aws.api.snapchat.com/dmd/mm?uid=bogus-1&sid=bogus-2&mid=bogus-3&ts=1700000000&sig=bogus-4: http status: 403"
+            ),
+            "  * Error downloading from https://us-east1-aws.api.snapchat.com/dmd/mm?uid=<redacted>&sid=<redacted>&mid=bogus-3&ts=1700000000&sig=<redacted>: http status: 403"
+        );
+        assert_eq!(
+            redact_tokens(
+                "\"url\": \"https://cdn.example.com/a.jpg?X-Amz-Signature=abc&X-Amz-Expires=300&flag\""
+            ),
+            "\"url\": \"https://cdn.example.com/a.jpg?X-Amz-Signature=<redacted>&X-Amz-Expires=300&flag\""
+        );
+        assert_eq!(
+            redact_tokens("https://example.com/a.jpg and http without a link"),
+            "https://example.com/a.jpg and http without a link"
+        );
+    }
+}
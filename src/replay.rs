@@ -0,0 +1,258 @@
+// --replay: plays back the snapdown_report.json of an earlier run instead of
+// downloading, each record starting and finishing when it did then. The
+// progress, file list, summary, report and statistics get the same events as
+// in a real run, so they can be worked on and shown without a network
+// connection, or an export whose links still work.
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+use serde::Deserialize;
+
+use crate::control::RunControl;
+use crate::redact;
+use crate::report::RecordStatus;
+use crate::{DownloadOutcome, FailureKind, record_filename_and_url};
+
+// The GUI has no option for it, this replays a report in its runs instead
+pub const REPLAY_ENV: &str = "SNAPDOWN_REPLAY";
+
+// What's used of a report
+#[derive(Deserialize)]
+struct ReplayReport {
+    records: Vec<ReplayRecord>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayRecord {
+    url: String,
+    status: RecordStatus,
+    failure: Option<FailureKind>,
+    error: Option<String>,
+    bytes: Option<u64>,
+    // Reports from before it was recorded start everything at once
+    #[serde(default)]
+    started_ms: u64,
+    duration_ms: u64,
+}
+
+impl ReplayRecord {
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn bytes(&self) -> Option<u64> {
+        self.bytes
+    }
+
+    fn outcome(&self) -> DownloadOutcome {
+        match self.status {
+            RecordStatus::Downloaded => DownloadOutcome::Downloaded,
+            RecordStatus::Skipped => DownloadOutcome::Skipped,
+            RecordStatus::Cancelled => DownloadOutcome::Cancelled,
+            RecordStatus::Invalid => DownloadOutcome::Invalid,
+            RecordStatus::Failed => DownloadOutcome::Failed(
+                self.failure.unwrap_or(FailureKind::Connection),
+                self.error.clone().unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+pub struct Replay {
+    records: Vec<ReplayRecord>,
+}
+
+impl Replay {
+    // The report has to be of a run of the same input file, it's in the
+    // same order. Its links have the signatures left out, unless the run had
+    // --log-full-urls.
+    pub fn open(path: &Path, records: &[csv::StringRecord]) -> Result<Replay> {
+        let report: ReplayReport = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if report.records.len() != records.len() {
+            bail!(
+                "{} has {} records, but the input file has {}",
+                path.display(),
+                report.records.len(),
+                records.len()
+            );
+        }
+        let mismatch = records
+            .iter()
+            .zip(&report.records)
+            .position(|(row, record)| {
+                record_filename_and_url(row).is_some_and(|(_, url)| {
+                    url != record.url && redact::redact_tokens(url) != record.url
+                })
+            });
+        if let Some(index) = mismatch {
+            bail!(
+                "{} isn't a report of this input file, record {} has a different link",
+                path.display(),
+                index + 1
+            );
+        }
+        Ok(Replay {
+            records: report.records,
+        })
+    }
+
+    pub fn record(&self, index: usize) -> &ReplayRecord {
+        &self.records[index]
+    }
+
+    // Call `on_start` and `on_finish` for each record when they happened in
+    // the recorded run, `speed` times as fast, and so taking 1/`speed` of
+    // the time they did then. Pausing holds the clock, and after a cancel the
+    // records that weren't done are cancelled right away.
+    pub fn run(
+        &self,
+        speed: f64,
+        control: &RunControl,
+        on_start: impl Fn(usize),
+        on_finish: impl Fn(usize, DownloadOutcome, Duration),
+    ) {
+        // (when, whether it's the finish, index), a record's start comes
+        // before its finish even when it took no time
+        let mut events: Vec<(u64, bool, usize)> = self
+            .records
+            .iter()
+            .enumerate()
+            .flat_map(|(index, record)| {
+                [
+                    (record.started_ms, false, index),
+                    (record.started_ms + record.duration_ms, true, index),
+                ]
+            })
+            .collect();
+        events.sort();
+
+        let mut started = vec![false; self.records.len()];
+        let mut finished = vec![false; self.records.len()];
+        // How far into the recorded run the replay is, not counting pauses
+        let mut clock = Duration::ZERO;
+        let mut last_tick = Instant::now();
+        'events: for (at_ms, is_finish, index) in events {
+            let at = Duration::from_millis(at_ms).div_f64(speed);
+            loop {
+                if control.is_cancelled() {
+                    break 'events;
+                }
+                let now = Instant::now();
+                if !control.is_paused() {
+                    clock += now - last_tick;
+                }
+                last_tick = now;
+                if clock >= at {
+                    break;
+                }
+                std::thread::sleep((at - clock).min(Duration::from_millis(100)));
+            }
+            let record = &self.records[index];
+            if is_finish {
+                finished[index] = true;
+                on_finish(
+                    index,
+                    record.outcome(),
+                    Duration::from_millis(record.duration_ms).div_f64(speed),
+                );
+            } else {
+                started[index] = true;
+                on_start(index);
+            }
+        }
+
+        for index in (0..self.records.len()).filter(|index| !finished[*index]) {
+            if !started[index] {
+                on_start(index);
+            }
+            on_finish(index, DownloadOutcome::Cancelled, Duration::ZERO);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_replay() {
+        let dir = std::env::temp_dir().join("snapdown_test_replay");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("snapdown_report.json");
+        fs::write(
+            &path,
+            r#"{"snapdown_version": "1.0.0", "total": 3, "records": [
+                {"url": "https://example.com/a", "filename": "a.jpg", "status": "downloaded",
+                 "bytes": 1234, "started_ms": 0, "duration_ms": 300},
+                {"url": "https://example.com/b", "filename": "b.mp4", "status": "failed",
+                 "failure": "http_status", "error": "http status: 404", "bytes": null,
+                 "started_ms": 10, "duration_ms": 20},
+                {"url": "https://example.com/c", "filename": "c.jpg", "status": "skipped",
+                 "bytes": 10, "duration_ms": 0}
+            ]}"#,
+        )
+        .unwrap();
+        let row =
+            |url: &str| csv::StringRecord::from(vec!["2024-01-01 00:00:00 UTC", "Image", "", url]);
+        let records = [
+            row("https://example.com/a"),
+            row("https://example.com/b"),
+            row("https://example.com/c"),
+        ];
+        let replay = Replay::open(&path, &records).unwrap();
+        assert_eq!(replay.record(0).bytes(), Some(1234));
+        assert!(Replay::open(&path, &records[..2]).is_err());
+        let other = [
+            records[0].clone(),
+            row("https://example.com/x"),
+            records[2].clone(),
+        ];
+        assert!(Replay::open(&path, &other).is_err());
+
+        let events = Mutex::new(Vec::new());
+        let start = Instant::now();
+        replay.run(
+            10.0,
+            &RunControl::default(),
+            |index| events.lock().unwrap().push(format!("start {}", index)),
+            |index, outcome, duration| {
+                events.lock().unwrap().push(format!(
+                    "finish {} {:?} {}",
+                    index,
+                    outcome,
+                    duration.as_millis()
+                ))
+            },
+        );
+        // 300ms at ten times the speed
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(
+            events.into_inner().unwrap(),
+            [
+                "start 0",
+                "start 2",
+                "finish 2 Skipped 0",
+                "start 1",
+                r#"finish 1 Failed(HttpStatus, "http status: 404") 2"#,
+                "finish 0 Downloaded 30",
+            ]
+        );
+
+        // Cancelled before anything happened
+        let control = RunControl::default();
+        control.cancel();
+        let finished = Mutex::new(Vec::new());
+        replay.run(
+            1.0,
+            &control,
+            |_| {},
+            |index, outcome, _| finished.lock().unwrap().push((index, outcome)),
+        );
+        assert_eq!(finished.into_inner().unwrap().len(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+// snapdown_report.json: what happened to every record of a run, with the
+// totals and timings, written when the run finishes. Made for scripts that
+// look at the results, and to attach to a support request instead of
+// copying bits of the log.
+
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::FailureKind;
+use crate::quarantine::QuarantinedFile;
+use crate::stats::HostStats;
+
+pub const REPORT_FILE: &str = "snapdown_report.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordStatus {
+    Downloaded,
+    // Already downloaded by an earlier run
+    Skipped,
+    Failed,
+    // Not a memory, the row has the wrong number of columns
+    Invalid,
+    // Not downloaded because the run was stopped
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordReport {
+    pub url: String,
+    // As saved in the output directory, empty for invalid records
+    pub filename: String,
+    pub status: RecordStatus,
+    // What failed, for failed records
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<FailureKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // Something about the record that's worth a look even if it downloaded,
+    // like a capture time that can't be right
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+    // Size of the file on disk, if there is one
+    pub bytes: Option<u64>,
+    // Since the run started, so a run can be replayed, see replay.rs
+    pub started_ms: u64,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HostReport {
+    pub host: String,
+    pub successes: usize,
+    pub errors: usize,
+    pub average_latency_ms: u64,
+    pub max_latency_ms: u64,
+}
+
+impl HostReport {
+    pub fn new(host: &str, stats: &HostStats) -> HostReport {
+        HostReport {
+            host: host.to_string(),
+            successes: stats.success_count,
+            errors: stats.error_count,
+            average_latency_ms: millis(stats.average_latency()),
+            max_latency_ms: millis(stats.max_latency),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunReport {
+    pub snapdown_version: &'static str,
+    pub input_file: String,
+    pub output_dir: String,
+    // RFC 3339
+    pub started_at: String,
+    pub finished_at: String,
+    pub duration_secs: f64,
+    // Why the run stopped early, if it did
+    pub stop_reason: Option<String>,
+    pub total: usize,
+    pub downloaded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    // Rows that aren't memories, not in failed
+    pub invalid: usize,
+    pub duplicates: usize,
+    // With --upload, the files uploaded (or already on the server) and the
+    // ones that couldn't be, of this run's downloads and earlier ones
+    pub uploaded: usize,
+    pub upload_failed: usize,
+    pub bytes_downloaded: u64,
+    pub bytes_skipped: u64,
+    // Bytes per second
+    pub throughput: f64,
+    pub hosts: Vec<HostReport>,
+    // The broken files verify --repair moved into quarantine before the run,
+    // see quarantine.rs. Only there for repairs.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub quarantined: Vec<QuarantinedFile>,
+    // In input order
+    pub records: Vec<RecordReport>,
+}
+
+pub fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+pub fn write_report(path: &Path, report: &RunReport) -> Result<()> {
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, serde_json::to_string_pretty(report)?)?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_report() {
+        let dir = std::env::temp_dir().join("snapdown_test_report");
+        fs::create_dir_all(&dir).unwrap();
+        let report = RunReport {
+            snapdown_version: "1.0.0",
+            input_file: "memories_history.html".to_string(),
+            output_dir: "out".to_string(),
+            started_at: "2026-01-13T01:00:00+00:00".to_string(),
+            finished_at: "2026-01-13T01:00:02+00:00".to_string(),
+            duration_secs: 2.0,
+            stop_reason: None,
+            total: 2,
+            downloaded: 1,
+            skipped: 0,
+            failed: 1,
+            invalid: 0,
+            duplicates: 0,
+            uploaded: 0,
+            upload_failed: 0,
+            bytes_downloaded: 1234,
+            bytes_skipped: 0,
+            throughput: 617.0,
+            hosts: vec![HostReport::new(
+                "example.com",
+                &HostStats {
+                    success_count: 1,
+                    error_count: 1,
+                    total_latency: Duration::from_millis(300),
+                    max_latency: Duration::from_millis(200),
+                },
+            )],
+            quarantined: Vec::new(),
+            records: vec![
+                RecordReport {
+                    url: "https://example.com/a".to_string(),
+                    filename: "a.jpg".to_string(),
+                    status: RecordStatus::Downloaded,
+                    failure: None,
+                    error: None,
+                    warning: None,
+                    bytes: Some(1234),
+                    started_ms: 0,
+                    duration_ms: 1500,
+                },
+                RecordReport {
+                    url: "https://example.com/b".to_string(),
+                    filename: "b.mp4".to_string(),
+                    status: RecordStatus::Failed,
+                    failure: Some(FailureKind::HttpStatus),
+                    error: Some("http status: 404".to_string()),
+                    warning: None,
+                    bytes: None,
+                    started_ms: 10,
+                    duration_ms: 20,
+                },
+            ],
+        };
+        let path = dir.join(REPORT_FILE);
+        write_report(&path, &report).unwrap();
+
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["failed"], 1);
+        assert_eq!(written["invalid"], 0);
+        assert_eq!(written["hosts"][0]["average_latency_ms"], 150);
+        assert_eq!(written["records"][0]["status"], "downloaded");
+        assert!(written["records"][0].get("error").is_none());
+        assert_eq!(written["records"][1]["failure"], "http_status");
+        assert_eq!(written["records"][1]["error"], "http status: 404");
+        assert_eq!(written["records"][1]["bytes"], serde_json::Value::Null);
+        assert!(written.get("quarantined").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
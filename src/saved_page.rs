@@ -0,0 +1,157 @@
+// memories_history.html saved again from a browser with "Save page as".
+// Saving it as "Webpage, complete" or "HTML only" writes the page back out
+// with changes the HTML parser copes with (more attributes, "&amp;" in the
+// links, wrapped lines). Saving it as a single file gives an MHTML archive
+// (.mhtml or .mht) instead: a MIME message with the page in one part,
+// quoted-printable encoded, and its images and styles in the others. This
+// gets the page back out of that.
+
+use anyhow::Result;
+
+// Whether a file starting with `start` is an MHTML archive rather than HTML
+pub fn is_mhtml(start: &[u8]) -> bool {
+    let (headers, _) = split_headers(start);
+    String::from_utf8_lossy(headers)
+        .lines()
+        .any(|line| line.to_ascii_lowercase().starts_with("mime-version:"))
+}
+
+// The HTML page in an MHTML archive, decoded
+pub fn mhtml_page(archive: &[u8]) -> Result<Vec<u8>> {
+    let (headers, body) = split_headers(archive);
+    let boundary = header_param(&String::from_utf8_lossy(headers), "boundary")
+        .ok_or_else(|| anyhow::anyhow!("saved page has no MIME boundary in it"))?;
+    let delimiter = format!("--{}", boundary);
+    for part in split_on(body, delimiter.as_bytes()) {
+        let (part_headers, part_body) = split_headers(trim_line_break(part));
+        let part_headers = String::from_utf8_lossy(part_headers).to_ascii_lowercase();
+        let content_type = header_value(&part_headers, "content-type").unwrap_or_default();
+        if !content_type.starts_with("text/html") {
+            continue;
+        }
+        return match header_value(&part_headers, "content-transfer-encoding").as_deref() {
+            Some("quoted-printable") => Ok(decode_quoted_printable(part_body)),
+            None | Some("7bit" | "8bit" | "binary") => Ok(part_body.to_vec()),
+            Some(encoding) => Err(anyhow::anyhow!(
+                "saved page is encoded as {}, which isn't supported",
+                encoding
+            )),
+        };
+    }
+    Err(anyhow::anyhow!("saved page has no HTML in it"))
+}
+
+// Headers end at the first empty line, with either kind of line break
+fn split_headers(data: &[u8]) -> (&[u8], &[u8]) {
+    for (separator, len) in [(&b"\r\n\r\n"[..], 4), (&b"\n\n"[..], 2)] {
+        if let Some(index) = find(data, separator) {
+            return (&data[..index], &data[index + len..]);
+        }
+    }
+    (data, &[])
+}
+
+fn find(data: &[u8], pattern: &[u8]) -> Option<usize> {
+    data.windows(pattern.len())
+        .position(|window| window == pattern)
+}
+
+fn split_on<'a>(mut data: &'a [u8], delimiter: &[u8]) -> Vec<&'a [u8]> {
+    let mut parts = Vec::new();
+    while let Some(index) = find(data, delimiter) {
+        parts.push(&data[..index]);
+        data = &data[index + delimiter.len()..];
+    }
+    parts.push(data);
+    parts
+}
+
+fn trim_line_break(data: &[u8]) -> &[u8] {
+    let data = data.strip_prefix(b"\r").unwrap_or(data);
+    data.strip_prefix(b"\n").unwrap_or(data)
+}
+
+// The value of header `name` (lowercase), with the lines a long header is
+// folded over joined up again
+fn header_value(headers: &str, name: &str) -> Option<String> {
+    let mut value: Option<String> = None;
+    for line in headers.lines() {
+        match &mut value {
+            Some(value) if line.starts_with([' ', '\t']) => value.push_str(line),
+            Some(_) => break,
+            None => {
+                if let Some((header, rest)) = line.split_once(':')
+                    && header.trim().eq_ignore_ascii_case(name)
+                {
+                    value = Some(rest.trim().to_string());
+                }
+            }
+        }
+    }
+    value.map(|value| value.trim().to_string())
+}
+
+// e.g. the boundary in `multipart/related; boundary="----abc"`
+fn header_param(headers: &str, param: &str) -> Option<String> {
+    let content_type = header_value(headers, "content-type")?;
+    content_type.split(';').find_map(|part| {
+        let (name, value) = part.trim().split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case(param)
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+// "=3D" is "=", and "=" at the end of a line joins it with the next
+fn decode_quoted_printable(data: &[u8]) -> Vec<u8> {
+    let hex_digit = |byte: u8| (byte as char).to_digit(16).map(|digit| digit as u8);
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut index = 0;
+    while index < data.len() {
+        if data[index] != b'=' {
+            decoded.push(data[index]);
+            index += 1;
+            continue;
+        }
+        let rest = &data[index + 1..];
+        if let Some(after) = rest.strip_prefix(b"\r\n") {
+            index = data.len() - after.len();
+        } else if let Some(after) = rest.strip_prefix(b"\n") {
+            index = data.len() - after.len();
+        } else if let [high, low, ..] = rest
+            && let (Some(high), Some(low)) = (hex_digit(*high), hex_digit(*low))
+        {
+            decoded.push(high << 4 | low);
+            index += 3;
+        } else {
+            // Not valid, kept as is
+            decoded.push(b'=');
+            index += 1;
+        }
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_quoted_printable() {
+        assert_eq!(
+            decode_quoted_printable(
+                b"<a href=3D\"#\" onclick=3D\"downloadMem=\r\nories('https://x')\">=C3=A9 = 1"
+            ),
+            "<a href=\"#\" onclick=\"downloadMemories('https://x')\">é = 1".as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_mhtml_page() {
+        let archive = b"From: <Saved by Blink>\r\nMIME-Version: 1.0\r\nContent-Type: multipart/related;\r\n\ttype=\"text/html\";\r\n\tboundary=\"----Boundary--x----\"\r\n\r\n\r\n------Boundary--x----\r\nContent-Type: text/css\r\n\r\ntable {}\r\n------Boundary--x----\r\nContent-Type: text/html\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\n<table class=3D\"a\">\r\n------Boundary--x------\r\n";
+        assert!(is_mhtml(archive));
+        assert!(!is_mhtml(b"<!DOCTYPE html>\n\n<table>"));
+        assert_eq!(mhtml_page(archive).unwrap(), b"<table class=\"a\">\r\n");
+        assert!(mhtml_page(b"MIME-Version: 1.0\r\n\r\n<table>").is_err());
+    }
+}
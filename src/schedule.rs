@@ -0,0 +1,185 @@
+// --schedule: only download during set hours, e.g. 01:00-07:00 for a network
+// with a nighttime allowance. The run pauses when the window closes and goes
+// on when it opens again, the same pause as the GUI's Pause button (see
+// control.rs), so a run can be left going for days. The manifest is synced to
+// disk at each pause, so a computer that sleeps or is switched off in between
+// loses nothing: the next run continues from there.
+//
+// Only the moments the window opens and closes pause or resume the run, so
+// pausing or resuming by hand in between holds until the next one.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use chrono::{NaiveTime, Timelike};
+
+use crate::control::RunControl;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// From `start` to `end` in local time, past midnight if `end` is earlier
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Schedule {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl Schedule {
+    // e.g. "01:00-07:00" or "22:30-6:00"
+    pub fn parse(text: &str) -> Result<Schedule> {
+        let Some((start, end)) = text.split_once('-') else {
+            bail!("expected a start and end time, e.g. 01:00-07:00");
+        };
+        let time = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|_| anyhow::anyhow!("{:?} isn't a time like 07:00", time.trim()))
+        };
+        let schedule = Schedule {
+            start: time(start)?,
+            end: time(end)?,
+        };
+        if schedule.start == schedule.end {
+            bail!("the window starts and ends at the same time");
+        }
+        Ok(schedule)
+    }
+
+    pub fn is_open(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+
+    // e.g. "01:00"
+    pub fn opens_at(&self) -> String {
+        self.start.format("%H:%M").to_string()
+    }
+
+    pub fn message(&self, open: bool) -> String {
+        if open {
+            format!(
+                "Inside the download hours ({}), downloading until {}",
+                self,
+                self.end.format("%H:%M")
+            )
+        } else {
+            format!(
+                "Outside the download hours ({}), pausing until {}",
+                self,
+                self.opens_at()
+            )
+        }
+    }
+
+    // Pause or resume `control` if the window opened or closed since
+    // `was_open` (None for the start of the run, where only a closed window
+    // changes anything). Returns whether it's open at `time`.
+    fn update(
+        &self,
+        was_open: Option<bool>,
+        time: NaiveTime,
+        control: &RunControl,
+        on_change: impl Fn(bool),
+    ) -> bool {
+        let open = self.is_open(time);
+        if was_open == Some(open) || (was_open.is_none() && open) {
+            return open;
+        }
+        if open {
+            control.resume();
+        } else {
+            control.pause();
+        }
+        on_change(open);
+        open
+    }
+
+    // At the start of the run, pause it if the window is closed. Returns
+    // whether it's open.
+    pub fn start(&self, control: &RunControl, on_change: impl Fn(bool)) -> bool {
+        self.update(None, now(), control, on_change)
+    }
+
+    // Check the local time every second until `done`, see update()
+    pub fn watch(
+        &self,
+        mut open: bool,
+        done: &AtomicBool,
+        control: &RunControl,
+        on_change: impl Fn(bool),
+    ) {
+        while !done.load(Ordering::Relaxed) {
+            std::thread::sleep(CHECK_INTERVAL);
+            open = self.update(Some(open), now(), control, &on_change);
+        }
+    }
+}
+
+// Seconds don't matter, and leaving them out makes a window open right at its
+// minute
+fn now() -> NaiveTime {
+    let now = chrono::Local::now().time();
+    now.with_second(0).unwrap_or(now)
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(text: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(text, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_schedule() {
+        let night = Schedule::parse("22:30 - 6:00").unwrap();
+        assert_eq!(night.to_string(), "22:30-06:00");
+        assert!(night.is_open(time("23:00")) && night.is_open(time("05:59")));
+        assert!(!night.is_open(time("06:00")) && !night.is_open(time("12:00")));
+        let day = Schedule::parse("01:00-07:00").unwrap();
+        assert!(day.is_open(time("01:00")) && !day.is_open(time("23:00")));
+        assert!(Schedule::parse("01:00").is_err());
+        assert!(Schedule::parse("1am-7am").is_err());
+        assert!(Schedule::parse("07:00-07:00").is_err());
+    }
+
+    #[test]
+    fn test_update() {
+        let schedule = Schedule::parse("01:00-07:00").unwrap();
+        let control = RunControl::default();
+        let changes = std::cell::RefCell::new(Vec::new());
+        let on_change = |open| changes.borrow_mut().push(open);
+
+        // Starting inside the window changes nothing
+        assert!(schedule.update(None, time("02:00"), &control, on_change));
+        assert!(!control.is_paused() && changes.borrow().is_empty());
+
+        // Closing pauses, and a resume by hand holds until it opens again
+        assert!(!schedule.update(Some(true), time("07:00"), &control, on_change));
+        assert!(control.is_paused());
+        control.resume();
+        assert!(!schedule.update(Some(false), time("08:00"), &control, on_change));
+        assert!(!control.is_paused());
+        assert!(schedule.update(Some(false), time("01:00"), &control, on_change));
+        assert_eq!(*changes.borrow(), [false, true]);
+
+        // Starting outside it pauses right away
+        assert!(!schedule.update(None, time("12:00"), &control, on_change));
+        assert!(control.is_paused());
+    }
+}
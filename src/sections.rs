@@ -0,0 +1,325 @@
+// --sections: which parts of the export a run downloads from a mydata~*.zip.
+// Memories are what SnapDown was made for, and the default, but the export
+// has other media too: the photos and videos sent in chats (see chat.rs), the
+// snaps posted to shared stories, and Spotlight submissions. Each of the
+// others goes in its own folder of the output directory, memories stay at
+// the top as always.
+//
+// The stories and Spotlight files haven't had the same layout in every
+// export, so they're read loosely: any entry with a download link, with its
+// date and media type from whichever of its fields look like them. Entries
+// without a link (many exports only list what was posted) are left out.
+
+use std::cell::RefCell;
+use std::io::{BufRead, Read};
+
+use anyhow::Result;
+use lol_html::{HtmlRewriter, Settings, element, text};
+
+use crate::chat::CHAT_FOLDER;
+use crate::export::{decode_entities, strip_html};
+use crate::tolerant_html::{download_call_link, feed_rewriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Section {
+    Memories,
+    Chats,
+    Stories,
+    Spotlight,
+}
+
+impl Section {
+    pub const ALL: [Section; 4] = [
+        Section::Memories,
+        Section::Chats,
+        Section::Stories,
+        Section::Spotlight,
+    ];
+
+    // e.g. "stories"
+    pub fn parse(name: &str) -> Result<Section> {
+        Section::ALL
+            .into_iter()
+            .find(|section| section.name().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "unknown section {:?}, expected memories, chats, stories or spotlight",
+                    name
+                )
+            })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Section::Memories => "memories",
+            Section::Chats => "chats",
+            Section::Stories => "stories",
+            Section::Spotlight => "spotlight",
+        }
+    }
+
+    // The folder of the output directory its files go in
+    pub fn folder(&self) -> Option<&'static str> {
+        match self {
+            Section::Memories => None,
+            Section::Chats => Some(CHAT_FOLDER),
+            Section::Stories => Some("stories"),
+            Section::Spotlight => Some("spotlight"),
+        }
+    }
+
+    // Whether a file of the export is one of the section's, going by its
+    // name, e.g. json/shared_story.json for stories
+    pub fn has_file(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        let names: &[&str] = match self {
+            Section::Memories => &["memories_history"],
+            Section::Chats => &["chat_history"],
+            Section::Stories => &["shared_story", "story_history"],
+            Section::Spotlight => &["spotlight"],
+        };
+        names.iter().any(|section_name| name.contains(section_name))
+    }
+
+    // The section of a file of the output directory, going by the folder
+    // it's in, e.g. chats/2023-06-01_10-00-00.jpg for chats. Memories can be
+    // in folders of their own naming template, see naming.rs.
+    pub fn of_output_file(filename: &str) -> Section {
+        let Some((folder, _)) = filename.split_once(['/', '\\']) else {
+            return Section::Memories;
+        };
+        Section::ALL
+            .into_iter()
+            .find(|section| section.folder() == Some(folder))
+            .unwrap_or(Section::Memories)
+    }
+
+    // The section of a file that isn't memories or chats, which have their
+    // own parsers
+    pub fn of_other_file(name: &str) -> Option<Section> {
+        [Section::Stories, Section::Spotlight]
+            .into_iter()
+            .find(|section| section.has_file(name))
+    }
+}
+
+// What the files of the export call them, as the memories call them
+fn media_type(name: &str) -> &'static str {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "image" | "photo" | "snap" => "Image",
+        "video" => "Video",
+        _ => "Media",
+    }
+}
+
+// (timestamp, format, latitude, longitude, download_url, folder), like chat
+// media, as there's no location either
+fn media_record(date: &str, media_type: &str, link: &str, section: Section) -> csv::StringRecord {
+    csv::StringRecord::from(vec![
+        date,
+        media_type,
+        "",
+        "",
+        link,
+        section.folder().unwrap_or_default(),
+    ])
+}
+
+fn is_link(value: &str) -> bool {
+    value.trim().starts_with("http")
+}
+
+pub fn parse_media_json(json: impl Read, section: Section) -> Result<Vec<csv::StringRecord>> {
+    let value: serde_json::Value = serde_json::from_reader(json)?;
+    let mut records = Vec::new();
+    collect_media(&value, section, &mut records);
+    Ok(records)
+}
+
+// Entries are objects with a download link in a field named like one, found
+// wherever they are in the file
+fn collect_media(
+    value: &serde_json::Value,
+    section: Section,
+    records: &mut Vec<csv::StringRecord>,
+) {
+    let entry = match value {
+        serde_json::Value::Array(values) => {
+            for value in values {
+                collect_media(value, section, records);
+            }
+            return;
+        }
+        serde_json::Value::Object(entry) => entry,
+        _ => return,
+    };
+    let field = |is_name: fn(&str) -> bool, is_value: fn(&str) -> bool| {
+        entry
+            .iter()
+            .filter(|(name, _)| is_name(&name.to_lowercase()))
+            .filter_map(|(_, value)| value.as_str())
+            .find(|value| is_value(value))
+    };
+    let Some(link) = field(
+        |name| name.contains("url") || name.contains("link"),
+        is_link,
+    ) else {
+        for value in entry.values() {
+            collect_media(value, section, records);
+        }
+        return;
+    };
+    let date = field(
+        |name| name.contains("date") || name.contains("created") || name.contains("timestamp"),
+        |_| true,
+    );
+    let kind = field(|name| name.contains("type"), |_| true);
+    records.push(media_record(
+        date.unwrap_or_default(),
+        media_type(kind.unwrap_or_default()),
+        link.trim(),
+        section,
+    ));
+}
+
+#[derive(Default)]
+struct MediaPage {
+    cells: Vec<String>,
+    link: Option<String>,
+    records: Vec<csv::StringRecord>,
+}
+
+impl MediaPage {
+    // A row with a link is an entry, with its date from the cell that has
+    // one and its type from a cell like "Video"
+    fn finish_row(&mut self, section: Section) {
+        let cells: Vec<String> = self.cells.drain(..).map(|cell| strip_html(&cell)).collect();
+        let Some(link) = self.link.take() else {
+            return;
+        };
+        let date = cells.iter().find(|cell| cell.ends_with("UTC"));
+        let kind = cells
+            .iter()
+            .map(|cell| media_type(cell))
+            .find(|kind| *kind != "Media");
+        self.records.push(media_record(
+            date.map_or("", String::as_str),
+            kind.unwrap_or("Media"),
+            &link,
+            section,
+        ));
+    }
+}
+
+pub fn parse_media_html(html: impl BufRead, section: Section) -> Result<Vec<csv::StringRecord>> {
+    let page = RefCell::new(MediaPage::default());
+    {
+        let rewriter = HtmlRewriter::new(
+            Settings {
+                element_content_handlers: vec![
+                    element!("tr", |_| {
+                        page.borrow_mut().finish_row(section);
+                        Ok(())
+                    }),
+                    element!("td", |_| {
+                        page.borrow_mut().cells.push(String::new());
+                        Ok(())
+                    }),
+                    text!("td", |chunk| {
+                        if let Some(cell) = page.borrow_mut().cells.last_mut() {
+                            cell.push_str(chunk.as_str());
+                        }
+                        Ok(())
+                    }),
+                    element!("tr [onclick]", |element| {
+                        let onclick = element.get_attribute("onclick").unwrap_or_default();
+                        if let Some(link) = download_call_link(&decode_entities(&onclick)) {
+                            page.borrow_mut().link.get_or_insert(link);
+                        }
+                        Ok(())
+                    }),
+                    element!("tr a[href^='http']", |element| {
+                        if let Some(href) = element.get_attribute("href") {
+                            page.borrow_mut().link.get_or_insert(decode_entities(&href));
+                        }
+                        Ok(())
+                    }),
+                ],
+                ..Settings::new()
+            },
+            |_: &[u8]| {},
+        );
+        feed_rewriter(html, rewriter)?;
+    }
+    let mut page = page.into_inner();
+    page.finish_row(section);
+    Ok(page.records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sections() {
+        assert_eq!(Section::parse(" Stories").unwrap(), Section::Stories);
+        assert!(Section::parse("snaps").is_err());
+        assert!(Section::Stories.has_file("json/shared_story.json"));
+        assert!(Section::Memories.has_file("html/Memories_History.html"));
+        assert_eq!(
+            Section::of_other_file("html/story_history.html"),
+            Some(Section::Stories)
+        );
+        assert_eq!(Section::of_other_file("json/chat_history.json"), None);
+        assert_eq!(
+            Section::of_output_file("spotlight\\2023-06-01.mp4"),
+            Section::Spotlight
+        );
+        assert_eq!(Section::of_output_file("2023/06/01.jpg"), Section::Memories);
+        assert_eq!(Section::of_output_file("chats.jpg"), Section::Memories);
+    }
+
+    #[test]
+    fn test_parse_media() {
+        let json = r#"{
+            "Shared Story": [
+                {"Story Date": "2024-03-01 12:00:00 UTC", "Media Type": "VIDEO",
+                 "Download Link": "https://a.example.com/1"},
+                {"Story Date": "2024-03-02 12:00:00 UTC", "Media Type": "IMAGE"}
+            ],
+            "Spotlight History": {"Submissions": [
+                {"Created": "2024-03-03 12:00:00 UTC", "Media Url": " https://a.example.com/2 "}
+            ]}
+        }"#;
+        let expected = [
+            media_record(
+                "2024-03-01 12:00:00 UTC",
+                "Video",
+                "https://a.example.com/1",
+                Section::Stories,
+            ),
+            media_record(
+                "2024-03-03 12:00:00 UTC",
+                "Media",
+                "https://a.example.com/2",
+                Section::Stories,
+            ),
+        ];
+        assert_eq!(
+            parse_media_json(json.as_bytes(), Section::Stories).unwrap(),
+            expected
+        );
+        assert_eq!(expected[0].get(5), Some("stories"));
+
+        let html = "<table><tr><th>Date<th>Media Type<th>\
+            <tr><td>2024-03-01 12:00:00 UTC<td><b>Video</b>\
+            <td><a href=# onclick=\"downloadMemories('https://a.example.com/1', this)\">Download</a>\
+            <tr><td>2024-03-02 12:00:00 UTC<td>Image<td>Not available\
+            <tr><td>2024-03-03 12:00:00 UTC<td>Spotlight<td><a href='https://a.example.com/2'>x</a>\
+            </table>";
+        assert_eq!(
+            parse_media_html(html.as_bytes(), Section::Stories).unwrap(),
+            expected
+        );
+    }
+}
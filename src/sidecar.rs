@@ -0,0 +1,201 @@
+// --sidecars: a <filename>.json next to each downloaded file, with what the
+// export says about the memory. It's laid out like the sidecars of Google
+// Takeout, which Immich (with immich-go), PhotoPrism and other importers
+// already read the date and location from, so they don't have to go by the
+// file times or EXIF tags, which videos don't have. What Takeout has no field
+// for is under "snapdown". The download link is there without its signature,
+// as sidecars end up in photo libraries and backups (see redact.rs).
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{record, redact};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Sidecar<'a> {
+    title: String,
+    description: &'static str,
+    // When the memory was saved, Takeout's is when the photo was uploaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    creation_time: Option<TakeoutTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    photo_taken_time: Option<TakeoutTime>,
+    // All 0 for no location, like in Takeout
+    geo_data: GeoData,
+    geo_data_exif: GeoData,
+    url: String,
+    snapdown: SnapdownData<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct TakeoutTime {
+    // Seconds since 1970, as a string
+    timestamp: String,
+    // e.g. "Jun 1, 2023, 10:00:00 AM UTC"
+    formatted: String,
+}
+
+impl TakeoutTime {
+    fn new(time: DateTime<Utc>) -> TakeoutTime {
+        TakeoutTime {
+            timestamp: time.timestamp().to_string(),
+            formatted: time.format("%b %-d, %Y, %-I:%M:%S %p UTC").to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeoData {
+    latitude: f64,
+    longitude: f64,
+    altitude: f64,
+    latitude_span: f64,
+    longitude_span: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SnapdownData<'a> {
+    // Image or Video, as in the export
+    media_type: &'a str,
+    // As in the export, in case it couldn't be read
+    timestamp: &'a str,
+    // RFC 3339, unknown for files downloaded before there were sidecars
+    #[serde(skip_serializing_if = "Option::is_none")]
+    downloaded_at: Option<String>,
+    // BLAKE3 of the file, as in the manifest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blake3: Option<&'a str>,
+}
+
+// Where the sidecar of a file goes, e.g. photo.jpg.json
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".json");
+    PathBuf::from(name)
+}
+
+// Whether a file in the output directory is the sidecar of another one there
+pub fn is_sidecar(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+        && path
+            .file_stem()
+            .is_some_and(|stem| path.with_file_name(stem).is_file())
+}
+
+// Write the sidecar of the downloaded file at `path`, for `row` of the input
+pub fn write_sidecar(
+    path: &Path,
+    row: &csv::StringRecord,
+    download_url: &str,
+    checksum: Option<&str>,
+    downloaded_at: Option<DateTime<Utc>>,
+) -> Result<()> {
+    let taken = record::record_timestamp(row);
+    let (latitude, longitude) = record::record_location(row).unwrap_or_default();
+    let geo_data = GeoData {
+        latitude,
+        longitude,
+        altitude: 0.0,
+        latitude_span: 0.0,
+        longitude_span: 0.0,
+    };
+    let sidecar = Sidecar {
+        title: path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        description: "",
+        creation_time: taken.map(TakeoutTime::new),
+        photo_taken_time: taken.map(TakeoutTime::new),
+        geo_data,
+        geo_data_exif: geo_data,
+        url: redact::redact_tokens(download_url),
+        snapdown: SnapdownData {
+            media_type: row.get(1).unwrap_or_default(),
+            timestamp: row.get(0).unwrap_or_default(),
+            downloaded_at: downloaded_at
+                .map(|time| time.to_rfc3339_opts(chrono::SecondsFormat::Secs, false)),
+            blake3: checksum,
+        },
+    };
+    fs::write(sidecar_path(path), serde_json::to_string_pretty(&sidecar)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_write_sidecar() {
+        let dir = std::env::temp_dir().join("snapdown_test_sidecar");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("2023-06-01_10-00-00_48.8584_2.2945.jpg");
+        fs::write(&path, "jpeg").unwrap();
+        let row = csv::StringRecord::from(vec![
+            "2023-06-01 10:00:00 UTC",
+            "Image",
+            "48.8584",
+            "2.2945",
+            "https://example.com/a?mid=1&sig=secret",
+        ]);
+        let downloaded_at = Utc.with_ymd_and_hms(2026, 1, 13, 1, 55, 38).unwrap();
+        write_sidecar(
+            &path,
+            &row,
+            "https://example.com/a?mid=1&sig=secret",
+            Some("abc123"),
+            Some(downloaded_at),
+        )
+        .unwrap();
+
+        let sidecar_path = sidecar_path(&path);
+        assert_eq!(
+            sidecar_path,
+            dir.join("2023-06-01_10-00-00_48.8584_2.2945.jpg.json")
+        );
+        assert!(is_sidecar(&sidecar_path));
+        assert!(!is_sidecar(&path));
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&sidecar_path).unwrap()).unwrap();
+        assert_eq!(written["title"], "2023-06-01_10-00-00_48.8584_2.2945.jpg");
+        assert_eq!(written["photoTakenTime"]["timestamp"], "1685613600");
+        assert_eq!(
+            written["photoTakenTime"]["formatted"],
+            "Jun 1, 2023, 10:00:00 AM UTC"
+        );
+        assert_eq!(written["geoData"]["latitude"], 48.8584);
+        assert_eq!(written["geoDataExif"]["longitude"], 2.2945);
+        assert_eq!(written["url"], "https://example.com/a?mid=1&sig=<redacted>");
+        assert_eq!(written["snapdown"]["mediaType"], "Image");
+        assert_eq!(
+            written["snapdown"]["downloadedAt"],
+            "2026-01-13T01:55:38+00:00"
+        );
+        assert_eq!(written["snapdown"]["blake3"], "abc123");
+
+        // Without a location or a download time
+        let row = csv::StringRecord::from(vec![
+            "2023-06-01 10:00:00 UTC",
+            "Video",
+            "0.0",
+            "0.0",
+            "https://example.com/b",
+        ]);
+        write_sidecar(&path, &row, "https://example.com/b", None, None).unwrap();
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&sidecar_path).unwrap()).unwrap();
+        assert_eq!(written["geoData"]["latitude"], 0.0);
+        assert!(written["snapdown"].get("downloadedAt").is_none());
+        assert!(written["snapdown"].get("blake3").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
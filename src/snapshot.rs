@@ -0,0 +1,122 @@
+// A compact JSON summary of a run (counts, what kind of errors, settings and
+// the last few log lines), for users asking for help mid-run. Much easier to
+// paste into an issue than the full snapdown.log.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::control::StopReason;
+
+// How many of the most recent console lines to include
+pub const SNAPSHOT_LOG_LINES: usize = 50;
+
+#[derive(Debug, Serialize)]
+pub struct ProgressSnapshot {
+    pub created: String,
+    pub snapdown_version: &'static str,
+    // downloading, paused, stopping, stopped or completed, or for the CLI,
+    // failed when the run couldn't start
+    pub state: String,
+    pub success_count: usize,
+    pub error_count: usize,
+    pub skip_count: usize,
+    // Rows that aren't memories, not in error_count
+    pub invalid_count: usize,
+    // Number of errors of each kind, see error_kind()
+    pub error_breakdown: BTreeMap<String, usize>,
+    pub stop_reason: Option<StopReason>,
+    pub config: SnapshotConfig,
+    pub recent_log: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotConfig {
+    // Just the file name, the rest of the path is usually the user's home
+    pub input_file: Option<String>,
+    pub output_dir: String,
+    pub jobs: usize,
+    pub retries: usize,
+    pub debug_http: bool,
+    pub resolve_links: bool,
+    pub write_exif: bool,
+    pub touch: bool,
+    pub composite_overlays: bool,
+    pub auto_rotate: bool,
+    pub sidecars: bool,
+    pub dedup: Option<crate::dedup::DedupMode>,
+    // e.g. ["by-year", "by-type"]
+    pub views: Vec<&'static str>,
+    // e.g. ["memories", "chats"]
+    pub sections: Vec<&'static str>,
+    pub freeze: bool,
+    pub force: bool,
+    pub limit_rate: Option<u64>,
+    // e.g. "01:00-07:00"
+    pub schedule: Option<String>,
+    // Not the proxy URL itself, it can have a password in it
+    pub proxy: bool,
+    // Same for the command, it can have a password or token in it
+    pub post_process_cmd: bool,
+    // Just where to, e.g. "Immich", not the server or its API key
+    pub upload: Option<&'static str>,
+}
+
+impl ProgressSnapshot {
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+// Rough category of a download error message, e.g. "http status 403"
+pub fn error_kind(message: &str) -> String {
+    if let Some(index) = message.find("http status: ") {
+        let status: String = message[index + "http status: ".len()..]
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        format!("http status {}", status)
+    } else if message.contains("checksum doesn't match") {
+        "checksum".to_string()
+    } else if message.contains("error writing to file") {
+        "writing file".to_string()
+    } else if message.contains("Error creating file") {
+        "creating file".to_string()
+    } else if message.contains("Error getting download link") {
+        "getting download link".to_string()
+    } else if message.contains("Error downloading from") {
+        "connection".to_string()
+    } else {
+        "other".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_kind() {
+        assert_eq!(
+            error_kind("  * Error downloading from https://a.example.com/x: http status: 403"),
+            "http status 403"
+        );
+        assert_eq!(
+            error_kind("  * Error downloading from https://a.example.com/x: io: Connection reset"),
+            "connection"
+        );
+        assert_eq!(
+            error_kind("  * Downloaded, but error writing to file \"a.jpg\": No space left"),
+            "writing file"
+        );
+        assert_eq!(
+            error_kind(
+                "  * Downloaded from https://a.example.com/x, but the checksum doesn't match its ETag (0a instead of 0b)"
+            ),
+            "checksum"
+        );
+    }
+}
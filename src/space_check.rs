@@ -0,0 +1,109 @@
+// --space-check: before downloading, how much space the files will take,
+// going by the sizes the server gives for them, against the free space on the
+// output volume. A run otherwise only finds out the disk is too small when
+// it's full, hours in (see stop_if_disk_full() in main.rs).
+
+use clap::ValueEnum;
+
+use crate::format::format_bytes;
+
+// How many files --space-check sample asks the size of
+pub const SAMPLE_SIZE: usize = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum SpaceCheck {
+    Off,
+    // The sizes of a few files spread over the run, for a quick estimate
+    // that only warns
+    Sample,
+    // The size of every file, and no run without the space for them
+    Full,
+}
+
+// Which of `count` files to ask the size of, spread evenly over them so a
+// run of videos somewhere in the middle isn't missed
+pub fn sample_indices(count: usize, check: SpaceCheck) -> Vec<usize> {
+    match check {
+        SpaceCheck::Off => Vec::new(),
+        SpaceCheck::Full => (0..count).collect(),
+        SpaceCheck::Sample if count <= SAMPLE_SIZE => (0..count).collect(),
+        SpaceCheck::Sample => (0..SAMPLE_SIZE).map(|i| i * count / SAMPLE_SIZE).collect(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpaceEstimate {
+    pub needed: u64,
+    pub free: u64,
+    // Whether every file's size was asked for, rather than some of them
+    pub exact: bool,
+}
+
+impl SpaceEstimate {
+    // From the `sizes` the server gave of the files asked about, for `count`
+    // files to download. Files it gave no size for count as the average of
+    // the others. None if it gave none at all.
+    pub fn new(sizes: &[u64], count: usize, free: u64, exact: bool) -> Option<SpaceEstimate> {
+        if sizes.is_empty() {
+            return None;
+        }
+        let total: u64 = sizes.iter().sum();
+        let needed = if sizes.len() == count {
+            total
+        } else {
+            (total as f64 / sizes.len() as f64 * count as f64).round() as u64
+        };
+        Some(SpaceEstimate {
+            needed,
+            free,
+            exact: exact && sizes.len() == count,
+        })
+    }
+
+    pub fn is_enough(&self) -> bool {
+        self.needed <= self.free
+    }
+
+    // e.g. "~48 GB needed, 20 GB free"
+    pub fn message(&self) -> String {
+        format!(
+            "{}{} needed, {} free",
+            if self.exact { "" } else { "~" },
+            format_bytes(self.needed),
+            format_bytes(self.free)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_indices() {
+        assert_eq!(sample_indices(5, SpaceCheck::Off), Vec::<usize>::new());
+        assert_eq!(sample_indices(3, SpaceCheck::Sample), [0, 1, 2]);
+        assert_eq!(sample_indices(3, SpaceCheck::Full), [0, 1, 2]);
+        let sample = sample_indices(3000, SpaceCheck::Sample);
+        assert_eq!(sample.len(), SAMPLE_SIZE);
+        assert_eq!(sample[..3], [0, 100, 200]);
+        assert_eq!(sample.last(), Some(&2900));
+    }
+
+    #[test]
+    fn test_space_estimate() {
+        assert_eq!(SpaceEstimate::new(&[], 10, 100, true), None);
+
+        // Half the sizes, the rest like them
+        let estimate = SpaceEstimate::new(&[10, 30], 4, 100, true).unwrap();
+        assert_eq!(estimate.needed, 80);
+        assert!(!estimate.exact);
+        assert!(estimate.is_enough());
+
+        let estimate = SpaceEstimate::new(&[60, 60], 2, 100, true).unwrap();
+        assert_eq!(estimate.needed, 120);
+        assert!(estimate.exact);
+        assert!(!estimate.is_enough());
+        assert!(!estimate.message().starts_with('~'));
+    }
+}
@@ -0,0 +1,104 @@
+// Per-host download statistics, so it's easy to see when one particular CDN
+// endpoint (e.g. a single region) is the one failing or slow.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::format::format_count;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HostStats {
+    pub success_count: usize,
+    pub error_count: usize,
+    pub total_latency: Duration,
+    pub max_latency: Duration,
+}
+
+impl HostStats {
+    pub fn average_latency(&self) -> Duration {
+        let requests = self.success_count + self.error_count;
+        if requests == 0 {
+            Duration::ZERO
+        } else {
+            self.total_latency / requests as u32
+        }
+    }
+}
+
+// Shared across the rayon workers
+#[derive(Default)]
+pub struct HostStatsCollector {
+    hosts: Mutex<HashMap<String, HostStats>>,
+}
+
+impl HostStatsCollector {
+    pub fn record(&self, url: &str, success: bool, latency: Duration) {
+        let host = url_host(url);
+        let mut hosts = self.hosts.lock().unwrap();
+        let stats = hosts.entry(host).or_default();
+        if success {
+            stats.success_count += 1;
+        } else {
+            stats.error_count += 1;
+        }
+        stats.total_latency += latency;
+        stats.max_latency = stats.max_latency.max(latency);
+    }
+
+    // Sorted by host name so the summary is stable between runs
+    pub fn into_sorted(self) -> Vec<(String, HostStats)> {
+        let mut hosts: Vec<_> = self.hosts.into_inner().unwrap().into_iter().collect();
+        hosts.sort_by(|a, b| a.0.cmp(&b.0));
+        hosts
+    }
+}
+
+pub fn url_host(url: &str) -> String {
+    match url.parse::<ureq::http::Uri>() {
+        Ok(uri) => uri.host().unwrap_or("<no host>").to_string(),
+        Err(_) => "<invalid url>".to_string(),
+    }
+}
+
+pub fn format_host_stats(host: &str, stats: &HostStats) -> String {
+    format!(
+        "{}: {} ok, {} errors, avg {} ms, max {} ms",
+        host,
+        format_count(stats.success_count),
+        format_count(stats.error_count),
+        format_count(stats.average_latency().as_millis() as usize),
+        format_count(stats.max_latency.as_millis() as usize)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_host() {
+        assert_eq!(
+            url_host("https://us-east1-aws.api.snapchat.com/dmd/mm?uid=bogus-1&sig=bogus-4"),
+            "us-east1-aws.api.snapchat.com"
+        );
+        assert_eq!(url_host("not a url"), "<invalid url>");
+    }
+
+    #[test]
+    fn test_collector_aggregates_per_host() {
+        let collector = HostStatsCollector::default();
+        collector.record("https://a.example.com/1", true, Duration::from_millis(100));
+        collector.record("https://a.example.com/2", false, Duration::from_millis(300));
+        collector.record("https://b.example.com/1", true, Duration::from_millis(50));
+
+        let hosts = collector.into_sorted();
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts[0].0, "a.example.com");
+        assert_eq!(hosts[0].1.success_count, 1);
+        assert_eq!(hosts[0].1.error_count, 1);
+        assert_eq!(hosts[0].1.average_latency(), Duration::from_millis(200));
+        assert_eq!(hosts[0].1.max_latency, Duration::from_millis(300));
+        assert_eq!(hosts[1].0, "b.example.com");
+    }
+}
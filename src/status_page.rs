@@ -0,0 +1,211 @@
+// --status-port: the progress of a CLI run as a small JSON page, for
+// checking on a long run on a NAS or another headless machine from a
+// browser or phone. /metrics has the same for Prometheus (see metrics.rs),
+// every other path the JSON, with the counts and speed only, none of the
+// records or their download links.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use log::debug;
+use serde::Serialize;
+
+use crate::control::StopReason;
+use crate::metrics::Metrics;
+
+// How long a client gets to send its request, so one that never does
+// doesn't keep the page from everyone else
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    // Reading and parsing the input file, the total isn't known yet
+    ReadingInput,
+    Downloading,
+    // Every record is done, the views, --freeze and the report are left
+    Finishing,
+    Finished,
+    // Stopped before the end, see stop_reason
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusPage {
+    pub phase: Phase,
+    pub total: usize,
+    // Downloaded, skipped or failed so far
+    pub processed: usize,
+    pub downloaded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    // Rows that aren't memories
+    pub invalid: usize,
+    // With --upload, apart from the downloads
+    pub uploaded: usize,
+    pub upload_failed: usize,
+    pub bytes_downloaded: u64,
+    // Bytes per second, since the run started
+    pub rate: f64,
+    pub elapsed_secs: u64,
+    // Going by the rate so far, none before anything is downloaded
+    pub eta_secs: Option<u64>,
+    pub stop_reason: Option<StopReason>,
+}
+
+impl StatusPage {
+    fn reading_input() -> StatusPage {
+        StatusPage {
+            phase: Phase::ReadingInput,
+            total: 0,
+            processed: 0,
+            downloaded: 0,
+            skipped: 0,
+            failed: 0,
+            invalid: 0,
+            uploaded: 0,
+            upload_failed: 0,
+            bytes_downloaded: 0,
+            rate: 0.0,
+            elapsed_secs: 0,
+            eta_secs: None,
+            stop_reason: None,
+        }
+    }
+}
+
+// Serves the page from its own thread until the program exits
+pub struct StatusServer {
+    page: Arc<Mutex<StatusPage>>,
+    address: SocketAddr,
+}
+
+impl StatusServer {
+    // Listens on every interface, so other devices on the network can check
+    // too. Port 0 picks a free one.
+    pub fn start(port: u16, metrics: Option<Arc<Metrics>>) -> Result<StatusServer> {
+        let listener = TcpListener::bind(("0.0.0.0", port))
+            .map_err(|e| anyhow::anyhow!("can't serve the status page on port {}: {}", port, e))?;
+        let address = listener.local_addr()?;
+        let page = Arc::new(Mutex::new(StatusPage::reading_input()));
+        let served_page = page.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let result =
+                    stream.and_then(|stream| respond(stream, &served_page, metrics.as_deref()));
+                if let Err(e) = result {
+                    debug!("Error serving the status page: {}", e);
+                }
+            }
+        });
+        Ok(StatusServer { page, address })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.address.port()
+    }
+
+    pub fn update(&self, page: StatusPage) {
+        *self.page.lock().unwrap() = page;
+    }
+}
+
+// Answer one request
+fn respond(
+    stream: TcpStream,
+    page: &Mutex<StatusPage>,
+    metrics: Option<&Metrics>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers, which aren't used
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && !line.trim().is_empty() {
+        line.clear();
+    }
+    let mut parts = request_line.split(' ');
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let (status, content_type, body) = match (method, path, metrics) {
+        ("GET" | "HEAD", "/metrics", Some(metrics)) => {
+            ("200 OK", "text/plain; version=0.0.4", metrics.render())
+        }
+        ("GET" | "HEAD", _, _) => {
+            let page = page.lock().unwrap().clone();
+            (
+                "200 OK",
+                "application/json",
+                serde_json::to_string_pretty(&page)?,
+            )
+        }
+        _ => (
+            "405 Method Not Allowed",
+            "application/json",
+            r#"{"error": "only GET is supported"}"#.to_string(),
+        ),
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    if method != "HEAD" {
+        stream.write_all(body.as_bytes())?;
+    }
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn request(port: u16, request: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_status_server() {
+        let metrics = Arc::new(Metrics::default());
+        let server = StatusServer::start(0, Some(metrics.clone())).unwrap();
+        let response = request(server.port(), "GET / HTTP/1.1\r\nHost: nas\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("\"phase\": \"reading_input\""));
+
+        server.update(StatusPage {
+            phase: Phase::Downloading,
+            total: 10,
+            processed: 4,
+            downloaded: 3,
+            eta_secs: Some(90),
+            ..StatusPage::reading_input()
+        });
+        let response = request(server.port(), "GET /status HTTP/1.0\r\n\r\n");
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        let page: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(page["phase"], "downloading");
+        assert_eq!(page["processed"], 4);
+        assert_eq!(page["eta_secs"], 90);
+        assert_eq!(page["stop_reason"], serde_json::Value::Null);
+
+        metrics.set_total(10);
+        let response = request(server.port(), "GET /metrics HTTP/1.1\r\n\r\n");
+        assert!(response.contains("text/plain"), "{}", response);
+        assert!(response.contains("\nsnapdown_records 10\n"), "{}", response);
+
+        let response = request(server.port(), "POST / HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405 "), "{}", response);
+    }
+}
@@ -0,0 +1,118 @@
+// Where downloaded files end up. Normally that's the output directory, with
+// --s3-bucket or --webdav they're streamed to an S3-compatible bucket or a
+// WebDAV server instead (see s3.rs and webdav.rs) and never touch the disk.
+// The output directory then only has the manifest, the report and the logs.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+pub trait StorageBackend: Sync {
+    // For the log, e.g. the directory or s3://bucket/prefix
+    fn location(&self) -> String;
+
+    // The directory the files are written in, None if they're stored
+    // elsewhere. Continuing partial downloads and everything done to a file
+    // once it's downloaded (overlays, renaming it by type, EXIF tags, file
+    // times, sidecars, dedup, views) need the file there.
+    fn local_dir(&self) -> Option<&Path>;
+
+    // That the files can be stored, before anything is downloaded, rather
+    // than failing every one of them
+    fn check(&self) -> Result<()> {
+        Ok(())
+    }
+
+    // The size of the stored file of `row`, None if there isn't one
+    fn stored_size(&self, filename: &str, row: &csv::StringRecord) -> Result<Option<u64>>;
+
+    // Store `body` as the file of `row`, `size` bytes long if that's known
+    fn put(
+        &self,
+        filename: &str,
+        row: &csv::StringRecord,
+        body: &mut dyn Read,
+        size: Option<u64>,
+        content_type: Option<&str>,
+    ) -> Result<()>;
+}
+
+// Whether an output folder the user typed is one on this computer, and not
+// the address of a bucket or server (s3://bucket, https://dav.example.com),
+// which there's no folder to open for
+pub fn is_local_dir(output_dir: &str) -> bool {
+    !output_dir.contains("://")
+}
+
+// The output directory, with every file straight in it
+pub struct LocalDir {
+    dir: PathBuf,
+}
+
+impl LocalDir {
+    pub fn new(dir: impl Into<PathBuf>) -> LocalDir {
+        LocalDir { dir: dir.into() }
+    }
+}
+
+impl StorageBackend for LocalDir {
+    fn location(&self) -> String {
+        self.dir.display().to_string()
+    }
+
+    fn local_dir(&self) -> Option<&Path> {
+        Some(&self.dir)
+    }
+
+    fn stored_size(&self, filename: &str, _row: &csv::StringRecord) -> Result<Option<u64>> {
+        Ok(fs::metadata(self.dir.join(filename))
+            .map(|metadata| metadata.len())
+            .ok())
+    }
+
+    fn put(
+        &self,
+        filename: &str,
+        _row: &csv::StringRecord,
+        body: &mut dyn Read,
+        _size: Option<u64>,
+        _content_type: Option<&str>,
+    ) -> Result<()> {
+        let mut file = File::create(self.dir.join(filename))?;
+        io::copy(body, &mut file)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_dir() {
+        let dir = std::env::temp_dir().join("snapdown_test_local_dir");
+        fs::create_dir_all(&dir).unwrap();
+        let storage = LocalDir::new(&dir);
+        let row = csv::StringRecord::from(vec!["2023-06-01 10:00:00 UTC", "Image"]);
+        assert_eq!(storage.local_dir(), Some(dir.as_path()));
+        assert!(is_local_dir(dir.to_str().unwrap()));
+        assert!(!is_local_dir("s3://bucket/memories"));
+        assert!(!is_local_dir("webdav://nas.local/memories"));
+        assert_eq!(storage.stored_size("a.jpg", &row).unwrap(), None);
+
+        storage
+            .put(
+                "a.jpg",
+                &row,
+                &mut &b"jpeg"[..],
+                Some(4),
+                Some("image/jpeg"),
+            )
+            .unwrap();
+        assert_eq!(fs::read(dir.join("a.jpg")).unwrap(), b"jpeg");
+        assert_eq!(storage.stored_size("a.jpg", &row).unwrap(), Some(4));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
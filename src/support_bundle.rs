@@ -0,0 +1,187 @@
+// A zip to attach to a bug report, with everything that's usually asked for
+// next: SnapDown's log, the HTTP debug log, the report of the run, a progress
+// snapshot with the settings, and the version and OS. The download links in
+// all of it have their signatures cut off (see redact::redact_urls()), so
+// it can be posted in a public issue.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+use crate::install::InstallMode;
+use crate::redact;
+use crate::snapshot::ProgressSnapshot;
+
+pub const SUPPORT_BUNDLE_FILE: &str = "snapdown_support.zip";
+// A log of many runs can be huge, only the end of it goes in, which has the
+// last run
+const MAX_FILE_SIZE: usize = 10 * 1024 * 1024;
+
+// Write the bundle, with `files` in it as far as they exist. Returns the
+// names of the files in it.
+pub fn write_support_bundle(
+    path: &Path,
+    snapshot: &ProgressSnapshot,
+    files: &[PathBuf],
+) -> Result<Vec<String>> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = SimpleFileOptions::default();
+    let mut contents = vec![
+        ("system.txt".to_string(), system_info()),
+        (
+            "snapdown_snapshot.json".to_string(),
+            serde_json::to_string_pretty(snapshot)?,
+        ),
+    ];
+    for file in files {
+        // Nothing to say about a log that was never written
+        let Ok(bytes) = fs::read(file) else {
+            continue;
+        };
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        contents.push((name, String::from_utf8_lossy(tail(&bytes)).to_string()));
+    }
+    for (name, text) in &contents {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(redact::redact_urls(text).as_bytes())?;
+    }
+    zip.finish()?;
+    Ok(contents.into_iter().map(|(name, _)| name).collect())
+}
+
+// The last MAX_FILE_SIZE bytes, from the start of a line
+fn tail(bytes: &[u8]) -> &[u8] {
+    if bytes.len() <= MAX_FILE_SIZE {
+        return bytes;
+    }
+    let tail = &bytes[bytes.len() - MAX_FILE_SIZE..];
+    match tail.iter().position(|&byte| byte == b'\n') {
+        Some(index) => &tail[index + 1..],
+        None => tail,
+    }
+}
+
+fn system_info() -> String {
+    let install_mode = match InstallMode::current() {
+        InstallMode::Portable => "portable",
+        InstallMode::Installed => "installed",
+    };
+    format!(
+        "SnapDown {} ({})\nOS: {} ({}, {})\nCreated: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        install_mode,
+        std::env::consts::OS,
+        std::env::consts::FAMILY,
+        std::env::consts::ARCH,
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S %:z")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::io::Read;
+
+    use crate::snapshot::SnapshotConfig;
+
+    #[test]
+    fn test_write_support_bundle() {
+        let dir = std::env::temp_dir().join("snapdown_test_support_bundle");
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("snapdown.log");
+        fs::write(
+            &log,
+            "[ERROR][snapdown]   * Error downloading from https://example.com/dmd/mm?uid=1&sig=2: http status: 403\n",
+        )
+        .unwrap();
+        let report = dir.join("snapdown_report.json");
+        fs::write(
+            &report,
+            r#"{"records": [{"url": "https://example.com/a?sig=3"}]}"#,
+        )
+        .unwrap();
+        let snapshot = ProgressSnapshot {
+            created: "2026-01-13 01:55:38".to_string(),
+            snapdown_version: "1.0.0",
+            state: "completed".to_string(),
+            success_count: 1,
+            error_count: 1,
+            skip_count: 0,
+            invalid_count: 0,
+            error_breakdown: BTreeMap::new(),
+            stop_reason: None,
+            config: SnapshotConfig {
+                input_file: None,
+                output_dir: "out".to_string(),
+                jobs: 1,
+                retries: 0,
+                debug_http: false,
+                resolve_links: false,
+                write_exif: true,
+                touch: true,
+                composite_overlays: false,
+                auto_rotate: false,
+                sidecars: false,
+                dedup: None,
+                views: Vec::new(),
+                sections: vec!["memories"],
+                freeze: false,
+                force: false,
+                limit_rate: None,
+                schedule: None,
+                proxy: false,
+                post_process_cmd: false,
+                upload: None,
+            },
+            recent_log: Vec::new(),
+        };
+        let path = dir.join(SUPPORT_BUNDLE_FILE);
+        let included =
+            write_support_bundle(&path, &snapshot, &[log, dir.join("http_debug.log"), report])
+                .unwrap();
+        assert_eq!(
+            included,
+            [
+                "system.txt",
+                "snapdown_snapshot.json",
+                "snapdown.log",
+                "snapdown_report.json"
+            ]
+        );
+
+        let mut zip = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut read = |name: &str| {
+            let mut text = String::new();
+            zip.by_name(name)
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            text
+        };
+        assert!(read("system.txt").starts_with("SnapDown "));
+        assert!(
+            read("snapdown.log").contains("https://example.com/dmd/mm?<redacted>: http status")
+        );
+        assert_eq!(
+            read("snapdown_report.json"),
+            r#"{"records": [{"url": "https://example.com/a?<redacted>"}]}"#
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_tail() {
+        let mut bytes = vec![b'a'; MAX_FILE_SIZE];
+        bytes.extend_from_slice(b"\nlast line\n");
+        assert_eq!(tail(&bytes), b"last line\n");
+        assert_eq!(tail(b"short\n"), b"short\n");
+    }
+}
@@ -0,0 +1,63 @@
+// A local HTTP server for the tests of the clients of the user's own servers
+// (upload.rs, webdav.rs), which make their requests with a ureq agent of
+// their own rather than through a BlockingFetch that a MockFetch could stand
+// in for (see fetch.rs).
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+// The server's address, e.g. http://127.0.0.1:40000, and the requests it
+// got. It answers them with `responses` in order, a status and a JSON body
+// each, and the last one for any after that. Each request is kept as its
+// request line, the headers named in `headers` (lowercase, one "name: value"
+// line each) and its body.
+pub fn serve(
+    responses: Vec<(&'static str, &'static str)>,
+    headers: &'static [&'static str],
+) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let received = requests.clone();
+    std::thread::spawn(move || {
+        for (count, stream) in listener.incoming().enumerate() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                let Some((name, value)) = line.split_once(':') else {
+                    continue;
+                };
+                let name = name.to_ascii_lowercase();
+                if name == "content-length" {
+                    content_length = value.trim().parse().unwrap();
+                }
+                if headers.contains(&name.as_str()) {
+                    request.push_str(&format!("{}: {}\n", name, value.trim()));
+                }
+            }
+            let mut request_body = vec![0; content_length];
+            reader.read_exact(&mut request_body).unwrap();
+            request.push_str(&String::from_utf8_lossy(&request_body));
+            received.lock().unwrap().push(request);
+            let (status, body) = responses[count.min(responses.len() - 1)];
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+        }
+    });
+    (address, requests)
+}
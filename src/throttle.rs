@@ -0,0 +1,146 @@
+// Global download speed limit (--limit-rate). One limiter is shared by all the
+// downloads, so the limit holds for the whole run however many downloads
+// are in progress, and SnapDown doesn't take over a home connection.
+
+use std::io::{self, Read};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+// Read at most this much at a time, so a single read can't use up a whole
+// second's worth of the limit at once
+const MAX_READ_SIZE: usize = 16 * 1024;
+
+pub struct RateLimiter {
+    bytes_per_second: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    // Can go negative, that's bytes read that still need to be waited for
+    available: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_second: u64) -> Self {
+        RateLimiter {
+            bytes_per_second: bytes_per_second as f64,
+            bucket: Mutex::new(Bucket {
+                available: bytes_per_second as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    // Account for `bytes` just read, sleeping until they fit in the limit.
+    // Bursts of up to a second's worth of bytes go through right away.
+    pub fn consume(&self, bytes: usize) {
+        let wait = self.reserve(bytes);
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+
+    // Like consume(), but returns how long to wait instead of sleeping, for
+    // the async downloader to wait without blocking a thread
+    pub fn reserve(&self, bytes: usize) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.last_refill).as_secs_f64() * self.bytes_per_second;
+        bucket.available = (bucket.available + refill).min(self.bytes_per_second);
+        bucket.last_refill = now;
+        bucket.available -= bytes as f64;
+        if bucket.available < 0.0 {
+            Duration::from_secs_f64(-bucket.available / self.bytes_per_second)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+// Reader wrapper that keeps to the limiter, if there is one
+pub struct ThrottledReader<'a, R> {
+    inner: R,
+    limiter: Option<&'a RateLimiter>,
+}
+
+impl<'a, R> ThrottledReader<'a, R> {
+    pub fn new(inner: R, limiter: Option<&'a RateLimiter>) -> Self {
+        ThrottledReader { inner, limiter }
+    }
+}
+
+impl<R: Read> Read for ThrottledReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(limiter) = self.limiter else {
+            return self.inner.read(buf);
+        };
+        let len = buf.len().min(MAX_READ_SIZE);
+        let read = self.inner.read(&mut buf[..len])?;
+        limiter.consume(read);
+        Ok(read)
+    }
+}
+
+// Parse a rate like curl's --limit-rate: a number of bytes per second,
+// optionally followed by K, M or G (powers of 1024)
+pub fn parse_rate(rate: &str) -> Result<u64> {
+    let rate = rate.trim();
+    let (number, multiplier) = match rate.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&rate[..rate.len() - 1], 1024),
+        Some('M') => (&rate[..rate.len() - 1], 1024 * 1024),
+        Some('G') => (&rate[..rate.len() - 1], 1024 * 1024 * 1024),
+        _ => (rate, 1),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid rate {:?}, expected e.g. 500K or 5M", rate))?;
+    let bytes_per_second = (number * multiplier as f64) as u64;
+    if bytes_per_second == 0 {
+        return Err(anyhow::anyhow!("rate must be more than 0"));
+    }
+    Ok(bytes_per_second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("5M").unwrap(), 5 * 1024 * 1024);
+        assert_eq!(parse_rate("500k").unwrap(), 500 * 1024);
+        assert_eq!(parse_rate("1.5G").unwrap(), 1536 * 1024 * 1024);
+        assert_eq!(parse_rate("2000").unwrap(), 2000);
+        assert!(parse_rate("fast").is_err());
+        assert!(parse_rate("0M").is_err());
+    }
+
+    #[test]
+    fn test_throttled_reader_keeps_to_limit() {
+        // The first second's worth goes through at once, the rest is limited
+        let limiter = RateLimiter::new(100 * 1024);
+        let data = vec![0u8; 150 * 1024];
+        let start = Instant::now();
+        let mut reader = ThrottledReader::new(&data[..], Some(&limiter));
+        let copied = io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(copied, data.len() as u64);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(450), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn test_reserve_returns_wait() {
+        let limiter = RateLimiter::new(1000);
+        assert_eq!(limiter.reserve(1000), Duration::ZERO);
+        let wait = limiter.reserve(500);
+        assert!(
+            wait > Duration::from_millis(400) && wait <= Duration::from_millis(500),
+            "{:?}",
+            wait
+        );
+    }
+}
@@ -0,0 +1,164 @@
+// The download speed as it is now, rather than the average since the start of
+// the run: a run that skimmed through thousands of skipped records and is now
+// stuck on a slow CDN shows a good average long after the downloads slowed
+// down. The status thread samples the bytes downloaded, once a second at
+// most, and the speed is over the last few seconds of them. The samples also
+// make the sparkline of the last minute in the GUI and the CLI's progress
+// bar.
+
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::events::FileProgress;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+// How far back the current speed goes, enough to smooth over a file that
+// finished between samples
+const CURRENT_SECONDS: usize = 3;
+// How many seconds the sparkline shows
+pub const HISTORY_SECONDS: usize = 60;
+
+#[derive(Debug, Default)]
+pub struct Throughput {
+    // (time since the run started, bytes downloaded by then)
+    samples: VecDeque<(Duration, u64)>,
+}
+
+impl Throughput {
+    pub fn sample(&mut self, elapsed: Duration, total_bytes: u64) {
+        if let Some((last, _)) = self.samples.back()
+            && elapsed.saturating_sub(*last) < SAMPLE_INTERVAL
+        {
+            return;
+        }
+        self.samples.push_back((elapsed, total_bytes));
+        while self.samples.len() > HISTORY_SECONDS + 1 {
+            self.samples.pop_front();
+        }
+    }
+
+    // In bytes per second, 0 until there are two samples
+    pub fn current(&self) -> f64 {
+        let first = self.samples.len().saturating_sub(CURRENT_SECONDS + 1);
+        match (self.samples.get(first), self.samples.back()) {
+            (Some(first), Some(last)) => rate(*first, *last),
+            _ => 0.0,
+        }
+    }
+
+    // The speed between each sample and the next, oldest first
+    pub fn history(&self) -> Vec<f64> {
+        self.samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .map(|(earlier, later)| rate(*earlier, *later))
+            .collect()
+    }
+}
+
+// The last `width` seconds of `history` as block characters, for the CLI's
+// progress bar, scaled to the fastest of them
+pub fn sparkline(history: &[f64], width: usize) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let history = &history[history.len().saturating_sub(width)..];
+    let max = history.iter().copied().fold(0.0, f64::max);
+    history
+        .iter()
+        .map(|speed| {
+            if max == 0.0 {
+                BARS[0]
+            } else {
+                BARS[((speed / max) * (BARS.len() - 1) as f64).round() as usize]
+            }
+        })
+        .collect()
+}
+
+fn rate((start, start_bytes): (Duration, u64), (end, end_bytes): (Duration, u64)) -> f64 {
+    let seconds = end.saturating_sub(start).as_secs_f64();
+    if seconds == 0.0 {
+        return 0.0;
+    }
+    end_bytes.saturating_sub(start_bytes) as f64 / seconds
+}
+
+// Reader wrapper that adds what it reads to `counter` as it goes, so the
+// bytes of a big video count while it downloads and not only once it's done,
+// and to the file's Progress events if it has them
+pub struct CountingReader<'a, R> {
+    inner: R,
+    counter: &'a AtomicU64,
+    progress: Option<FileProgress<'a>>,
+}
+
+impl<'a, R> CountingReader<'a, R> {
+    pub fn new(inner: R, counter: &'a AtomicU64) -> Self {
+        CountingReader {
+            inner,
+            counter,
+            progress: None,
+        }
+    }
+
+    pub fn with_progress(mut self, progress: Option<FileProgress<'a>>) -> Self {
+        self.progress = progress;
+        self
+    }
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.counter.fetch_add(read as u64, Ordering::Relaxed);
+        if let Some(progress) = &mut self.progress {
+            progress.add(read);
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throughput() {
+        let mut throughput = Throughput::default();
+        assert_eq!(throughput.current(), 0.0);
+        throughput.sample(Duration::ZERO, 0);
+        // Too soon after the last one
+        throughput.sample(Duration::from_millis(250), 500);
+        assert_eq!(throughput.current(), 0.0);
+        throughput.sample(Duration::from_secs(1), 1000);
+        throughput.sample(Duration::from_secs(2), 3000);
+        assert_eq!(throughput.history(), [1000.0, 2000.0]);
+        assert_eq!(throughput.current(), 1500.0);
+        // Only the last few seconds count
+        for second in 3..10 {
+            throughput.sample(Duration::from_secs(second), 3000);
+        }
+        assert_eq!(throughput.current(), 0.0);
+
+        for second in 10..100 {
+            throughput.sample(Duration::from_secs(second), 3000);
+        }
+        assert_eq!(throughput.history().len(), HISTORY_SECONDS);
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[], 10), "");
+        assert_eq!(sparkline(&[0.0, 0.0], 10), "▁▁");
+        assert_eq!(sparkline(&[5.0, 0.0, 700.0, 350.0, 100.0], 4), "▁█▅▂");
+    }
+
+    #[test]
+    fn test_counting_reader() {
+        let counter = AtomicU64::new(10);
+        let mut reader = CountingReader::new(&b"hello"[..], &counter);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(counter.load(Ordering::Relaxed), 15);
+    }
+}
@@ -0,0 +1,227 @@
+// The slow way to read memories_history.html, for when the state machine in
+// parse_memories_history_html_from() (main.rs) finds no memories in it. That
+// one looks for the exact tags Snapchat writes, one after the other, so a
+// change to the markup (attributes on <table>, tags nested in the cells,
+// end tags left out of minified output) can leave it with nothing.
+//
+// This one runs the page through a real HTML parser (lol_html, which streams
+// it like the state machine does) and takes every table row it finds: the
+// text of its first three cells, and the link from a downloadMemories('...')
+// call or a plain https link anywhere in the row. Rows without a link are
+// left out, except the first row of headings. chat.rs and sections.rs read
+// their pages the same way, with the helpers at the end.
+
+use std::cell::RefCell;
+use std::io::BufRead;
+
+use anyhow::Result;
+use lol_html::{HtmlRewriter, OutputSink, Settings, element, text};
+
+use crate::export::{decode_entities, strip_html};
+
+const DOWNLOAD_CALL: &str = "downloadMemories('";
+
+#[derive(Default)]
+struct Row {
+    headings: Vec<String>,
+    cells: Vec<String>,
+    // The cell text goes to whichever was started last
+    in_heading: bool,
+    link: Option<String>,
+}
+
+#[derive(Default)]
+struct Table {
+    header: Option<csv::StringRecord>,
+    records: Vec<csv::StringRecord>,
+    row: Option<Row>,
+}
+
+impl Table {
+    fn start_row(&mut self) {
+        self.finish_row();
+        self.row = Some(Row::default());
+    }
+
+    // Rows end where the next one starts (or the page does), which works
+    // whether or not their end tags are there
+    fn finish_row(&mut self) {
+        let Some(row) = self.row.take() else {
+            return;
+        };
+        if self.header.is_none() && !row.headings.is_empty() {
+            self.header = Some(csv::StringRecord::from(
+                row.headings
+                    .iter()
+                    .map(|heading| strip_html(heading))
+                    .collect::<Vec<_>>(),
+            ));
+        }
+        if let Some(link) = row.link
+            && row.cells.len() >= 3
+        {
+            let mut record: csv::StringRecord =
+                row.cells[..3].iter().map(|cell| strip_html(cell)).collect();
+            record.push_field(&link);
+            self.records.push(record);
+        }
+    }
+
+    fn start_cell(&mut self, heading: bool) {
+        let row = self.row.get_or_insert_default();
+        row.in_heading = heading;
+        if heading {
+            row.headings.push(String::new());
+        } else {
+            row.cells.push(String::new());
+        }
+    }
+
+    fn add_text(&mut self, text: &str) {
+        let Some(row) = &mut self.row else {
+            return;
+        };
+        let cell = if row.in_heading {
+            row.headings.last_mut()
+        } else {
+            row.cells.last_mut()
+        };
+        if let Some(cell) = cell {
+            cell.push_str(text);
+        }
+    }
+
+    // The first link of the row, Snapchat's download button comes before
+    // anything else that could look like one
+    fn add_link(&mut self, link: String) {
+        if let Some(row) = &mut self.row
+            && row.link.is_none()
+        {
+            row.link = Some(link);
+        }
+    }
+}
+
+// The link in an onclick="downloadMemories('https://...', this, true)", with
+// its entities decoded
+pub fn download_call_link(onclick: &str) -> Option<String> {
+    let start = onclick.find(DOWNLOAD_CALL)? + DOWNLOAD_CALL.len();
+    let end = onclick[start..].find('\'')?;
+    Some(onclick[start..start + end].to_string())
+}
+
+// Records in the same shape as parse_memories_history_html_from() gives,
+// the row of headings first. It's empty if there was none.
+pub fn parse_memories_table(html: impl BufRead) -> Result<Vec<csv::StringRecord>> {
+    let table = RefCell::new(Table::default());
+    {
+        let rewriter = HtmlRewriter::new(
+            Settings {
+                element_content_handlers: vec![
+                    element!("tr", |_| {
+                        table.borrow_mut().start_row();
+                        Ok(())
+                    }),
+                    element!("th", |_| {
+                        table.borrow_mut().start_cell(true);
+                        Ok(())
+                    }),
+                    element!("td", |_| {
+                        table.borrow_mut().start_cell(false);
+                        Ok(())
+                    }),
+                    text!("tr", |chunk| {
+                        table.borrow_mut().add_text(chunk.as_str());
+                        Ok(())
+                    }),
+                    element!("tr [onclick]", |element| {
+                        let onclick = element.get_attribute("onclick").unwrap_or_default();
+                        if let Some(link) = download_call_link(&decode_entities(&onclick)) {
+                            table.borrow_mut().add_link(link);
+                        }
+                        Ok(())
+                    }),
+                    element!("tr a[href^='https']", |element| {
+                        if let Some(href) = element.get_attribute("href") {
+                            table.borrow_mut().add_link(decode_entities(&href));
+                        }
+                        Ok(())
+                    }),
+                ],
+                ..Settings::new()
+            },
+            // Only what the handlers find is kept, not the page itself
+            |_: &[u8]| {},
+        );
+        feed_rewriter(html, rewriter)?;
+    }
+
+    let mut table = table.into_inner();
+    table.finish_row();
+    let mut records = vec![table.header.unwrap_or_default()];
+    records.append(&mut table.records);
+    Ok(records)
+}
+
+// Run all of `html` through `rewriter`, a piece at a time so a page of
+// hundreds of megabytes isn't read into memory
+pub fn feed_rewriter<O: OutputSink>(
+    mut html: impl BufRead,
+    mut rewriter: HtmlRewriter<'_, O>,
+) -> Result<()> {
+    loop {
+        let chunk = html.fill_buf()?;
+        if chunk.is_empty() {
+            break;
+        }
+        rewriter.write(chunk)?;
+        let read = chunk.len();
+        html.consume(read);
+    }
+    rewriter.end()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_memories_table() {
+        // Attributes everywhere, tags in the cells, no end tags, and an
+        // onclick in single quotes with its quotes as entities
+        let html = "<html><body><table class=memories data-x='1'>\n\
+            <tr><th class=h><b>Date</b><th>Media Type<th>Location<th>\n\
+            <tr><td><span>2026-01-13 01:55:38 UTC</span><td><i>Image</i>\
+            <td>Latitude, Longitude: 40.25548, -111.645325\
+            <td><a href=# onclick='downloadMemories(&#39;https://a.example.com/dmd?mid=1&amp;sig=2&#39;, this, true)'>Download</a>\n\
+            <tr><td>2026-01-11 03:34:07 UTC<td>Video<td>Latitude, Longitude: 0.0, 0.0\
+            <td><a href=\"https://b.example.com/file.mp4\">Download</a>\n\
+            <tr><td>No link<td>Image<td>x<td>\n\
+            </table></body></html>";
+        let records = parse_memories_table(html.as_bytes()).unwrap();
+        assert_eq!(
+            records,
+            [
+                csv::StringRecord::from(vec!["Date", "Media Type", "Location", ""]),
+                csv::StringRecord::from(vec![
+                    "2026-01-13 01:55:38 UTC",
+                    "Image",
+                    "Latitude, Longitude: 40.25548, -111.645325",
+                    "https://a.example.com/dmd?mid=1&sig=2",
+                ]),
+                csv::StringRecord::from(vec![
+                    "2026-01-11 03:34:07 UTC",
+                    "Video",
+                    "Latitude, Longitude: 0.0, 0.0",
+                    "https://b.example.com/file.mp4",
+                ]),
+            ]
+        );
+
+        assert_eq!(
+            parse_memories_table("<p>Not a table</p>".as_bytes()).unwrap(),
+            [csv::StringRecord::new()]
+        );
+    }
+}
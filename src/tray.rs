@@ -0,0 +1,527 @@
+// The icon in the system tray, so a run of hours can go on with the window
+// hidden. The icon's colour shows how the run is going, its tooltip the
+// counts, and its menu has Pause/Resume, Open output folder and Quit. Clicking
+// the icon (or Show SnapDown in the menu) brings the window back.
+//
+// A hidden window doesn't get to update (see gui_channel.rs), so the tray
+// can't be kept up to date from the GUI's update(). Instead each run's
+// statuses go through a thread that sets them here first, see
+// SnapdownEframeApp::start_run(), and the tray's actions work on the run
+// control and output directory shared here rather than on the app. For the
+// same reason, Windows and macOS show the window again themselves: a
+// ViewportCommand would wait for a frame that a hidden window never has. On
+// X11 winit still runs a hidden window's frames, and Wayland can't hide one.
+//
+// Windows and macOS have tray-icon, and the other systems the
+// StatusNotifierItem D-Bus protocol (ksni). Without a tray to put the icon
+// in, e.g. a desktop without the protocol, there's only a log line and no
+// Hide to tray.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use eframe::egui;
+
+use crate::archive;
+use crate::control::RunControl;
+
+const ICON_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrayState {
+    Idle,
+    Downloading,
+    Paused,
+    Completed,
+    // Finished, but with failed downloads or stopped before the end
+    Failed,
+}
+
+impl TrayState {
+    fn color(self) -> [u8; 3] {
+        match self {
+            TrayState::Idle => [0x80, 0x80, 0x80],
+            TrayState::Downloading => [0x2f, 0x80, 0xed],
+            TrayState::Paused => [0xf2, 0xa9, 0x00],
+            TrayState::Completed => [0x2e, 0xa0, 0x43],
+            TrayState::Failed => [0xd7, 0x3a, 0x49],
+        }
+    }
+}
+
+// A disc in the state's colour, as RGBA rows from the top
+fn icon_rgba(state: TrayState) -> Vec<u8> {
+    let [r, g, b] = state.color();
+    let center = (ICON_SIZE as f32 - 1.0) / 2.0;
+    let radius = ICON_SIZE as f32 / 2.0 - 1.0;
+    let mut rgba = Vec::with_capacity(ICON_SIZE * ICON_SIZE * 4);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let distance = (x as f32 - center).hypot(y as f32 - center);
+            // Half a pixel of antialiasing at the edge
+            let alpha = (radius - distance + 0.5).clamp(0.0, 1.0);
+            rgba.extend_from_slice(&[r, g, b, (alpha * 255.0).round() as u8]);
+        }
+    }
+    rgba
+}
+
+// What the tray's actions act on, and the last status it was told about
+pub struct TrayTarget {
+    ctx: egui::Context,
+    run_control: Mutex<Arc<RunControl>>,
+    output_dir: Mutex<String>,
+    status: Mutex<(TrayState, String)>,
+    // Tells the backend to show a new status, set once it's running
+    on_change: OnceLock<Box<dyn Fn() + Send + Sync>>,
+    // Shows the hidden window without waiting for a frame, set by the
+    // backends that need it
+    show_window: OnceLock<Box<dyn Fn() + Send + Sync>>,
+}
+
+impl TrayTarget {
+    pub fn new(ctx: &egui::Context) -> Arc<Self> {
+        Arc::new(TrayTarget {
+            ctx: ctx.clone(),
+            run_control: Mutex::new(Arc::new(RunControl::default())),
+            output_dir: Mutex::new(String::new()),
+            status: Mutex::new((TrayState::Idle, "SnapDown".to_string())),
+            on_change: OnceLock::new(),
+            show_window: OnceLock::new(),
+        })
+    }
+
+    pub fn set_run_control(&self, run_control: Arc<RunControl>) {
+        *self.run_control.lock().unwrap() = run_control;
+    }
+
+    pub fn set_output_dir(&self, output_dir: &str) {
+        output_dir.clone_into(&mut self.output_dir.lock().unwrap());
+    }
+
+    pub fn set_status(&self, state: TrayState, tooltip: String) {
+        let mut status = self.status.lock().unwrap();
+        if *status == (state, tooltip.clone()) {
+            return;
+        }
+        *status = (state, tooltip);
+        drop(status);
+        self.changed();
+    }
+
+    // The last status, but Paused while the run is
+    fn status(&self) -> (TrayState, String) {
+        let (state, tooltip) = self.status.lock().unwrap().clone();
+        if state == TrayState::Downloading && self.is_paused() {
+            return (TrayState::Paused, tooltip);
+        }
+        (state, tooltip)
+    }
+
+    fn is_paused(&self) -> bool {
+        self.run_control.lock().unwrap().is_paused()
+    }
+
+    fn changed(&self) {
+        if let Some(on_change) = self.on_change.get() {
+            on_change();
+        }
+    }
+
+    // Show the window, and have its next frame know it's visible
+    fn show(&self) {
+        if let Some(show_window) = self.show_window.get() {
+            show_window();
+        }
+        self.ctx
+            .send_viewport_cmd(egui::ViewportCommand::Visible(true));
+    }
+
+    fn act(&self, action: Action) {
+        match action {
+            Action::Show => {
+                self.show();
+                self.ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+            }
+            Action::PauseOrResume => {
+                let run_control = self.run_control.lock().unwrap().clone();
+                if run_control.is_paused() {
+                    run_control.resume();
+                } else {
+                    run_control.pause();
+                }
+                self.changed();
+            }
+            Action::OpenOutputFolder => {
+                let output_dir = self.output_dir.lock().unwrap().clone();
+                if !crate::storage::is_local_dir(&output_dir) {
+                    log::error!("{} is not a folder on this computer", output_dir);
+                } else if let Err(e) = archive::open_in_file_manager(Path::new(&output_dir)) {
+                    log::error!("Error opening {}: {:#}", output_dir, e);
+                }
+            }
+            Action::Quit => {
+                // The same as closing the window, once it's shown again to
+                // get the command
+                self.show();
+                self.ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+        }
+        self.ctx.request_repaint();
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    Show,
+    PauseOrResume,
+    OpenOutputFolder,
+    Quit,
+}
+
+fn pause_label(paused: bool) -> &'static str {
+    if paused { "Resume" } else { "Pause" }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+pub use sni::Tray;
+
+#[cfg(not(any(windows, target_os = "macos")))]
+mod sni {
+    use std::sync::Arc;
+
+    use ksni::blocking::TrayMethods;
+
+    use super::{Action, ICON_SIZE, TrayTarget, icon_rgba, pause_label};
+
+    struct SniTray {
+        target: Arc<TrayTarget>,
+    }
+
+    impl ksni::Tray for SniTray {
+        fn id(&self) -> String {
+            "snapdown".to_string()
+        }
+
+        fn title(&self) -> String {
+            "SnapDown".to_string()
+        }
+
+        fn icon_pixmap(&self) -> Vec<ksni::Icon> {
+            let (state, _) = self.target.status();
+            // ARGB rather than RGBA
+            let mut data = icon_rgba(state);
+            for pixel in data.chunks_exact_mut(4) {
+                pixel.rotate_right(1);
+            }
+            vec![ksni::Icon {
+                width: ICON_SIZE as i32,
+                height: ICON_SIZE as i32,
+                data,
+            }]
+        }
+
+        fn tool_tip(&self) -> ksni::ToolTip {
+            let (_, tooltip) = self.target.status();
+            ksni::ToolTip {
+                title: "SnapDown".to_string(),
+                description: tooltip,
+                ..Default::default()
+            }
+        }
+
+        fn activate(&mut self, _x: i32, _y: i32) {
+            self.target.act(Action::Show);
+        }
+
+        fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
+            let item = |label: &str, action: Action| {
+                ksni::menu::StandardItem {
+                    label: label.to_string(),
+                    activate: Box::new(move |tray: &mut Self| tray.target.act(action)),
+                    ..Default::default()
+                }
+                .into()
+            };
+            vec![
+                item("Show SnapDown", Action::Show),
+                item(pause_label(self.target.is_paused()), Action::PauseOrResume),
+                item("Open output folder", Action::OpenOutputFolder),
+                ksni::MenuItem::Separator,
+                item("Quit", Action::Quit),
+            ]
+        }
+    }
+
+    pub struct Tray {
+        _handle: ksni::blocking::Handle<SniTray>,
+    }
+
+    impl Tray {
+        // Showing the window is left to the viewport commands, see
+        // TrayTarget::act()
+        pub fn new(target: &Arc<TrayTarget>, _window: &eframe::CreationContext) -> Option<Tray> {
+            let tray = SniTray {
+                target: target.clone(),
+            };
+            let handle = match tray.spawn() {
+                Ok(handle) => handle,
+                Err(e) => {
+                    log::warn!("No system tray icon: {}", e);
+                    return None;
+                }
+            };
+            // An update, even one that changes nothing, has ksni ask for the
+            // icon, tooltip and menu again
+            let on_change = handle.clone();
+            let _ = target.on_change.set(Box::new(move || {
+                on_change.update(|_| {});
+            }));
+            Some(Tray { _handle: handle })
+        }
+    }
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+pub use native::Tray;
+
+#[cfg(any(windows, target_os = "macos"))]
+mod native {
+    use std::sync::Arc;
+
+    use raw_window_handle::HasWindowHandle;
+    use tray_icon::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+    use tray_icon::{Icon, TrayIcon, TrayIconBuilder, TrayIconEvent};
+
+    use super::{Action, ICON_SIZE, TrayState, TrayTarget, icon_rgba, pause_label};
+
+    // The tray icon can only be changed from the thread it was made on, see
+    // platform::spawn()
+    struct NativeTray {
+        target: Arc<TrayTarget>,
+        icon: TrayIcon,
+        pause: MenuItem,
+        shown: (TrayState, String),
+    }
+
+    fn icon(state: TrayState) -> Option<Icon> {
+        Icon::from_rgba(icon_rgba(state), ICON_SIZE as u32, ICON_SIZE as u32).ok()
+    }
+
+    impl NativeTray {
+        fn build(target: &Arc<TrayTarget>) -> Option<NativeTray> {
+            let pause = MenuItem::with_id("pause", pause_label(false), true, None);
+            let menu = Menu::new();
+            let built = menu
+                .append_items(&[
+                    &MenuItem::with_id("show", "Show SnapDown", true, None),
+                    &pause,
+                    &MenuItem::with_id("open", "Open output folder", true, None),
+                    &PredefinedMenuItem::separator(),
+                    &MenuItem::with_id("quit", "Quit", true, None),
+                ])
+                .map_err(anyhow::Error::from)
+                .and_then(|()| {
+                    let mut builder = TrayIconBuilder::new()
+                        .with_menu(Box::new(menu))
+                        .with_tooltip("SnapDown");
+                    if let Some(icon) = icon(TrayState::Idle) {
+                        builder = builder.with_icon(icon);
+                    }
+                    Ok(builder.build()?)
+                });
+            let icon = match built {
+                Ok(icon) => icon,
+                Err(e) => {
+                    log::warn!("No system tray icon: {:#}", e);
+                    return None;
+                }
+            };
+
+            let menu_target = target.clone();
+            MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+                let action = match event.id.as_ref() {
+                    "show" => Action::Show,
+                    "pause" => Action::PauseOrResume,
+                    "open" => Action::OpenOutputFolder,
+                    "quit" => Action::Quit,
+                    _ => return,
+                };
+                menu_target.act(action);
+            }));
+            let icon_target = target.clone();
+            TrayIconEvent::set_event_handler(Some(move |event: TrayIconEvent| {
+                if let TrayIconEvent::DoubleClick { .. } = event {
+                    icon_target.act(Action::Show);
+                }
+            }));
+
+            Some(NativeTray {
+                target: target.clone(),
+                icon,
+                pause,
+                shown: (TrayState::Idle, "SnapDown".to_string()),
+            })
+        }
+
+        fn update(&mut self) {
+            let status = self.target.status();
+            if status == self.shown {
+                return;
+            }
+            let (state, tooltip) = &status;
+            if state != &self.shown.0 {
+                if let Err(e) = self.icon.set_icon(icon(*state)) {
+                    log::error!("Error changing the tray icon: {}", e);
+                }
+                self.pause
+                    .set_text(pause_label(*state == TrayState::Paused));
+            }
+            if let Err(e) = self.icon.set_tooltip(Some(tooltip)) {
+                log::error!("Error changing the tray tooltip: {}", e);
+            }
+            self.shown = status;
+        }
+    }
+
+    // The icon lives on the thread that's given it, see platform::spawn()
+    pub struct Tray;
+
+    impl Tray {
+        pub fn new(target: &Arc<TrayTarget>, window: &eframe::CreationContext) -> Option<Tray> {
+            let window = window.window_handle().ok().map(|handle| handle.as_raw());
+            platform::spawn(target, window).then_some(Tray)
+        }
+    }
+
+    // Windows: the icon works from any thread with a message loop, so it
+    // gets a thread of its own, woken up to show each new status
+    #[cfg(windows)]
+    mod platform {
+        use std::sync::{Arc, mpsc};
+
+        use raw_window_handle::RawWindowHandle;
+        use windows_sys::Win32::System::Threading::GetCurrentThreadId;
+        use windows_sys::Win32::UI::WindowsAndMessaging::{
+            DispatchMessageW, GetMessageW, MSG, PostThreadMessageW, SW_SHOW, SetForegroundWindow,
+            ShowWindowAsync, TranslateMessage, WM_NULL,
+        };
+
+        use super::{NativeTray, TrayTarget};
+
+        pub fn spawn(target: &Arc<TrayTarget>, window: Option<RawWindowHandle>) -> bool {
+            if let Some(RawWindowHandle::Win32(handle)) = window {
+                let hwnd = handle.hwnd.get();
+                // The window is the app's, it's there for as long as this is
+                let _ = target.show_window.set(Box::new(move || unsafe {
+                    ShowWindowAsync(hwnd as _, SW_SHOW);
+                    SetForegroundWindow(hwnd as _);
+                }));
+            }
+            let target = target.clone();
+            let (send_built, recv_built) = mpsc::channel();
+            let spawned = std::thread::Builder::new()
+                .name("tray".to_string())
+                .spawn(move || {
+                    let Some(mut tray) = NativeTray::build(&target) else {
+                        let _ = send_built.send(false);
+                        return;
+                    };
+                    let _ = send_built.send(true);
+                    // An empty message wakes GetMessageW() up. The thread
+                    // has a message queue since the icon's window was made.
+                    let thread_id = unsafe { GetCurrentThreadId() };
+                    let _ = target.on_change.set(Box::new(move || unsafe {
+                        PostThreadMessageW(thread_id, WM_NULL, 0, 0);
+                    }));
+                    tray.update();
+                    let mut msg = MSG::default();
+                    while unsafe { GetMessageW(&mut msg, std::ptr::null_mut(), 0, 0) } > 0 {
+                        unsafe {
+                            TranslateMessage(&msg);
+                            DispatchMessageW(&msg);
+                        }
+                        tray.update();
+                    }
+                });
+            spawned.is_ok() && recv_built.recv() == Ok(true)
+        }
+    }
+
+    // macOS: the icon has to be on the main thread, where the GUI's event
+    // loop is, so a new status is sent there through Grand Central Dispatch.
+    // Menu clicks come on the main thread too.
+    #[cfg(target_os = "macos")]
+    mod platform {
+        use std::cell::RefCell;
+        use std::sync::Arc;
+
+        use objc2::MainThreadMarker;
+        use objc2_app_kit::{NSApplication, NSView};
+        use raw_window_handle::RawWindowHandle;
+
+        use super::{NativeTray, TrayTarget};
+
+        thread_local! {
+            static TRAY: RefCell<Option<NativeTray>> = const { RefCell::new(None) };
+        }
+
+        // activateIgnoringOtherApps() is deprecated from macOS 14, but its
+        // replacement isn't there before
+        #[allow(deprecated)]
+        pub fn spawn(target: &Arc<TrayTarget>, window: Option<RawWindowHandle>) -> bool {
+            let Some(tray) = NativeTray::build(target) else {
+                return false;
+            };
+            TRAY.set(Some(tray));
+            let _ = target.on_change.set(Box::new(|| {
+                dispatch2::DispatchQueue::main().exec_async(|| {
+                    TRAY.with_borrow_mut(|tray| {
+                        if let Some(tray) = tray {
+                            tray.update();
+                        }
+                    });
+                });
+            }));
+            if let Some(RawWindowHandle::AppKit(handle)) = window {
+                let view = handle.ns_view.as_ptr() as usize;
+                let _ = target.show_window.set(Box::new(move || {
+                    let Some(main_thread) = MainThreadMarker::new() else {
+                        return;
+                    };
+                    // The view is the app's window's, it's there for as long
+                    // as this is
+                    unsafe {
+                        let view = &*(view as *const NSView);
+                        if let Some(window) = view.window() {
+                            window.makeKeyAndOrderFront(None);
+                        }
+                        NSApplication::sharedApplication(main_thread)
+                            .activateIgnoringOtherApps(true);
+                    }
+                }));
+            }
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_icon_rgba() {
+        let rgba = icon_rgba(TrayState::Failed);
+        assert_eq!(rgba.len(), ICON_SIZE * ICON_SIZE * 4);
+        let pixel = |x: usize, y: usize| &rgba[(y * ICON_SIZE + x) * 4..][..4];
+        // Transparent corners around a disc of the state's colour
+        assert_eq!(pixel(0, 0)[3], 0);
+        assert_eq!(pixel(ICON_SIZE - 1, ICON_SIZE - 1)[3], 0);
+        assert_eq!(
+            pixel(ICON_SIZE / 2, ICON_SIZE / 2),
+            [0xd7, 0x3a, 0x49, 0xff]
+        );
+        assert_ne!(icon_rgba(TrayState::Completed), rgba);
+    }
+}
@@ -0,0 +1,425 @@
+// --upload immich: send each downloaded memory to an Immich server through
+// its REST API. Immich goes by the EXIF tags of a photo for its date and
+// place, which videos don't have, so once a file is uploaded the date and
+// place of the memory are set on it too. With --upload-only the file is
+// deleted once it's uploaded, and the manifest remembers that so it isn't
+// downloaded again.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{Result, bail};
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde::Deserialize;
+use url::Url;
+
+use crate::{media_type, record};
+
+// Sent as the deviceId of every upload, Immich keeps track of assets by the
+// device they came from
+const DEVICE_ID: &str = "snapdown";
+// Between the parts of the upload form. Never in the form's fields, and a
+// file only has to not contain it right after a line break.
+const BOUNDARY: &str = "snapdown-upload-4f1c9e27b3d8a605";
+// For the error of a response that isn't Immich's JSON
+const ERROR_SNIPPET_LEN: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum UploadTarget {
+    Immich,
+}
+
+impl UploadTarget {
+    pub fn label(self) -> &'static str {
+        match self {
+            UploadTarget::Immich => "Immich",
+        }
+    }
+}
+
+// Not Debug, so the API key doesn't end up in a log
+#[derive(Clone)]
+pub struct UploadSettings {
+    pub target: UploadTarget,
+    // e.g. https://photos.example.com, with or without the /api
+    pub server: String,
+    pub api_key: String,
+    // Delete each file once it's uploaded, instead of keeping it in the
+    // output directory too
+    pub upload_only: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Uploaded {
+    // A new asset, with its id
+    Created(String),
+    // The server already had the file, as this asset
+    Duplicate(String),
+}
+
+impl Uploaded {
+    pub fn asset_id(&self) -> &str {
+        match self {
+            Uploaded::Created(id) | Uploaded::Duplicate(id) => id,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AssetResponse {
+    id: String,
+    // "created" or "duplicate"
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct UserResponse {
+    email: String,
+}
+
+// Shared by the download workers, which upload the files they finish
+pub struct ImmichUploader {
+    // The server's address with /api, without a slash at the end
+    api_url: String,
+    api_key: String,
+    agent: ureq::Agent,
+    upload_only: bool,
+    uploaded: AtomicUsize,
+    // Of the uploaded, the ones the server already had
+    duplicates: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+impl ImmichUploader {
+    pub fn new(settings: &UploadSettings, agent: ureq::Agent) -> Result<ImmichUploader> {
+        Ok(ImmichUploader {
+            api_url: api_url(&settings.server)?,
+            api_key: settings.api_key.clone(),
+            agent,
+            upload_only: settings.upload_only,
+            uploaded: AtomicUsize::new(0),
+            duplicates: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn upload_only(&self) -> bool {
+        self.upload_only
+    }
+
+    pub fn uploaded(&self) -> usize {
+        self.uploaded.load(Ordering::Relaxed)
+    }
+
+    pub fn duplicates(&self) -> usize {
+        self.duplicates.load(Ordering::Relaxed)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    // Check the server and API key before anything is downloaded, rather
+    // than failing every upload. Returns who the key belongs to.
+    pub fn check(&self) -> Result<String> {
+        let mut response = self
+            .agent
+            .get(format!("{}/users/me", self.api_url))
+            .config()
+            .http_status_as_error(false)
+            .build()
+            .header("x-api-key", &self.api_key)
+            .header("Accept", "application/json")
+            .call()?;
+        if !response.status().is_success() {
+            return Err(response_error(&mut response));
+        }
+        let user: UserResponse = serde_json::from_str(&response.body_mut().read_to_string()?)
+            .map_err(|e| anyhow::anyhow!("not an Immich server: {}", e))?;
+        Ok(user.email)
+    }
+
+    // Upload the file at `path`, downloaded for `row`
+    pub fn upload(&self, path: &Path, row: &csv::StringRecord) -> Result<Uploaded> {
+        let result = self.upload_file(path, row);
+        match &result {
+            Ok(Uploaded::Created(_)) => {
+                self.uploaded.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(Uploaded::Duplicate(_)) => {
+                self.uploaded.fetch_add(1, Ordering::Relaxed);
+                self.duplicates.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.failed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+
+    fn upload_file(&self, path: &Path, row: &csv::StringRecord) -> Result<Uploaded> {
+        let metadata = fs::metadata(path)?;
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let taken = record::record_timestamp(row);
+        // Immich wants both, the file's times are only the download's
+        let file_time = taken
+            .or_else(|| metadata.modified().ok().map(DateTime::<Utc>::from))
+            .unwrap_or_else(Utc::now);
+        let file_time = file_time.to_rfc3339_opts(SecondsFormat::Millis, true);
+        let content_type = media_type::detect_file(path, None)
+            .ok()
+            .flatten()
+            .map_or("application/octet-stream", |detected| detected.mime_type);
+        let fields = [
+            // What the Immich CLI sends, so the same file isn't taken for
+            // another one
+            ("deviceAssetId", format!("{}-{}", filename, metadata.len())),
+            ("deviceId", DEVICE_ID.to_string()),
+            ("fileCreatedAt", file_time.clone()),
+            ("fileModifiedAt", file_time),
+            ("isFavorite", "false".to_string()),
+        ];
+        let (head, tail) = multipart_form(&fields, &filename, content_type);
+        let content_length = head.len() as u64 + metadata.len() + tail.len() as u64;
+        let mut body = head
+            .as_bytes()
+            .chain(File::open(path)?)
+            .chain(tail.as_bytes());
+        let mut response = self
+            .agent
+            .post(format!("{}/assets", self.api_url))
+            .config()
+            .http_status_as_error(false)
+            .build()
+            .header("x-api-key", &self.api_key)
+            .header("Accept", "application/json")
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .header("Content-Length", content_length)
+            .send(ureq::SendBody::from_reader(&mut body))?;
+        if !response.status().is_success() {
+            return Err(response_error(&mut response));
+        }
+        let asset: AssetResponse = serde_json::from_str(&response.body_mut().read_to_string()?)?;
+        // Left as it is, it may have been changed in Immich since
+        if asset.status == "duplicate" {
+            return Ok(Uploaded::Duplicate(asset.id));
+        }
+
+        if let Some(update) = asset_update(taken, record::record_location(row)) {
+            let mut response = self
+                .agent
+                .put(format!("{}/assets/{}", self.api_url, asset.id))
+                .config()
+                .http_status_as_error(false)
+                .build()
+                .header("x-api-key", &self.api_key)
+                .header("Accept", "application/json")
+                .header("Content-Type", "application/json")
+                .send(update.to_string())?;
+            if !response.status().is_success() {
+                let error = response_error(&mut response);
+                bail!("uploaded, but setting its date and place failed: {}", error);
+            }
+        }
+        Ok(Uploaded::Created(asset.id))
+    }
+}
+
+// The API's address, from the server's as it's shown in the browser
+fn api_url(server: &str) -> Result<String> {
+    let url = Url::parse(server.trim())
+        .map_err(|e| anyhow::anyhow!("invalid server address {:?}: {}", server, e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        bail!(
+            "invalid server address {:?}: not an http(s) address",
+            server
+        );
+    }
+    let url = url.as_str().trim_end_matches('/');
+    let url = url.strip_suffix("/api").unwrap_or(url);
+    Ok(format!("{}/api", url))
+}
+
+// The parts of a multipart/form-data body before and after the file's bytes
+fn multipart_form(
+    fields: &[(&str, String)],
+    filename: &str,
+    content_type: &str,
+) -> (String, String) {
+    let mut head = String::new();
+    for (name, value) in fields {
+        head.push_str(&format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+            BOUNDARY, name, value
+        ));
+    }
+    head.push_str(&format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"assetData\"; filename=\"{}\"\r\n\
+         Content-Type: {}\r\n\r\n",
+        BOUNDARY,
+        filename.replace(['"', '\r', '\n'], "_"),
+        content_type
+    ));
+    (head, format!("\r\n--{}--\r\n", BOUNDARY))
+}
+
+// What to set on a new asset, None if the export has neither
+fn asset_update(
+    taken: Option<DateTime<Utc>>,
+    location: Option<(f64, f64)>,
+) -> Option<serde_json::Value> {
+    let mut update = serde_json::Map::new();
+    if let Some(taken) = taken {
+        update.insert(
+            "dateTimeOriginal".to_string(),
+            taken.to_rfc3339_opts(SecondsFormat::Millis, true).into(),
+        );
+    }
+    if let Some((latitude, longitude)) = location {
+        update.insert("latitude".to_string(), latitude.into());
+        update.insert("longitude".to_string(), longitude.into());
+    }
+    (!update.is_empty()).then_some(serde_json::Value::Object(update))
+}
+
+// Immich's errors are JSON with a message, anything else (from a proxy in
+// front of it, say) is shown as it is
+fn response_error(response: &mut ureq::http::Response<ureq::Body>) -> anyhow::Error {
+    let status = response.status();
+    let body = response.body_mut().read_to_string().unwrap_or_default();
+    let message = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|error| match error.get("message")? {
+            serde_json::Value::String(message) => Some(message.clone()),
+            // A list, for a request that didn't validate
+            message => Some(message.to_string()),
+        })
+        .unwrap_or_else(|| body.trim().chars().take(ERROR_SNIPPET_LEN).collect());
+    anyhow::anyhow!("http status {}: {}", status.as_u16(), message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+    use std::sync::{Arc, Mutex};
+
+    // An Immich server that answers `responses` in order, keeping each
+    // request's line, API key and body
+    fn fake_immich(
+        responses: Vec<(&'static str, &'static str)>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        test_server::serve(responses, &["x-api-key"])
+    }
+
+    fn uploader(server: &str) -> ImmichUploader {
+        let settings = UploadSettings {
+            target: UploadTarget::Immich,
+            server: server.to_string(),
+            api_key: "secret".to_string(),
+            upload_only: false,
+        };
+        ImmichUploader::new(&settings, ureq::Agent::new_with_defaults()).unwrap()
+    }
+
+    #[test]
+    fn test_api_url() {
+        assert_eq!(
+            api_url("https://photos.example.com").unwrap(),
+            "https://photos.example.com/api"
+        );
+        assert_eq!(
+            api_url("http://nas.local:2283/api/").unwrap(),
+            "http://nas.local:2283/api"
+        );
+        assert!(api_url("photos.example.com").is_err());
+        assert!(api_url("ftp://photos.example.com").is_err());
+    }
+
+    #[test]
+    fn test_upload() {
+        let dir = std::env::temp_dir().join("snapdown_test_upload");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("2023-06-01_10-00-00_UTC_48.8584_2.2945.mp4");
+        fs::write(&path, "video bytes").unwrap();
+        let row = csv::StringRecord::from(vec![
+            "2023-06-01 10:00:00 UTC",
+            "Video",
+            "48.8584",
+            "2.2945",
+            "https://example.com/a",
+        ]);
+        let (server, requests) = fake_immich(vec![
+            ("200 OK", r#"{"email": "me@example.com"}"#),
+            ("201 Created", r#"{"id": "asset-1", "status": "created"}"#),
+            ("200 OK", r#"{"id": "asset-1"}"#),
+            ("200 OK", r#"{"id": "asset-1", "status": "duplicate"}"#),
+            (
+                "400 Bad Request",
+                r#"{"message": "Unsupported file type", "statusCode": 400}"#,
+            ),
+        ]);
+        let uploader = uploader(&server);
+        assert_eq!(uploader.check().unwrap(), "me@example.com");
+        assert_eq!(
+            uploader.upload(&path, &row).unwrap(),
+            Uploaded::Created("asset-1".to_string())
+        );
+        assert_eq!(
+            uploader.upload(&path, &row).unwrap(),
+            Uploaded::Duplicate("asset-1".to_string())
+        );
+        let error = uploader.upload(&path, &row).unwrap_err();
+        assert_eq!(error.to_string(), "http status 400: Unsupported file type");
+        assert_eq!(
+            (
+                uploader.uploaded(),
+                uploader.duplicates(),
+                uploader.failed()
+            ),
+            (2, 1, 1)
+        );
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].starts_with("GET /api/users/me "));
+        assert!(requests[0].contains("x-api-key: secret"));
+        let upload = &requests[1];
+        assert!(upload.starts_with("POST /api/assets "));
+        assert!(upload.contains("name=\"fileCreatedAt\"\r\n\r\n2023-06-01T10:00:00.000Z\r\n"));
+        assert!(upload.contains(
+            "name=\"deviceAssetId\"\r\n\r\n2023-06-01_10-00-00_UTC_48.8584_2.2945.mp4-11\r\n"
+        ));
+        assert!(upload.contains("Content-Type: application/octet-stream\r\n\r\nvideo bytes\r\n"));
+        assert!(upload.ends_with(&format!("--{}--\r\n", BOUNDARY)));
+        let update = &requests[2];
+        assert!(update.starts_with("PUT /api/assets/asset-1 "));
+        let update: serde_json::Value =
+            serde_json::from_str(update.lines().last().unwrap()).unwrap();
+        assert_eq!(update["dateTimeOriginal"], "2023-06-01T10:00:00.000Z");
+        assert_eq!(update["latitude"], 48.8584);
+        assert_eq!(update["longitude"], 2.2945);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_error() {
+        let (server, _) = fake_immich(vec![(
+            "401 Unauthorized",
+            r#"{"message": "Invalid API key", "statusCode": 401}"#,
+        )]);
+        assert_eq!(
+            uploader(&server).check().unwrap_err().to_string(),
+            "http status 401: Invalid API key"
+        );
+        assert!(asset_update(None, None).is_none());
+    }
+}
@@ -0,0 +1,137 @@
+// Bandwidth accounting: how much SnapDown downloaded on this computer, by
+// calendar month, for users on metered connections who need to know what it
+// used this month. Every run adds its bytes at the end, to
+// snapdown_usage.json next to the presets (see install.rs). Dry runs and
+// replays don't download anything, so they don't count.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::format::format_bytes;
+use crate::install;
+
+pub const USAGE_FILE: &str = "snapdown_usage.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    // Bytes downloaded by month, e.g. "2026-10"
+    months: BTreeMap<String, u64>,
+}
+
+impl Usage {
+    pub fn add(&mut self, month: &str, bytes: u64) {
+        *self.months.entry(month.to_string()).or_default() += bytes;
+    }
+
+    pub fn month(&self, month: &str) -> u64 {
+        self.months.get(month).copied().unwrap_or(0)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.months.values().sum()
+    }
+
+    // e.g. "1.2 GB this month, 5.6 GB since March 2026"
+    pub fn summary(&self, month: &str) -> String {
+        let this_month = format!("{} this month", format_bytes(self.month(month)));
+        match self.months.keys().next() {
+            Some(first) if first != month => {
+                let since = chrono::NaiveDate::parse_from_str(&format!("{}-01", first), "%Y-%m-%d")
+                    .map_or(first.clone(), |date| date.format("%B %Y").to_string());
+                format!(
+                    "{}, {} since {}",
+                    this_month,
+                    format_bytes(self.total()),
+                    since
+                )
+            }
+            _ => this_month,
+        }
+    }
+}
+
+// In local time, e.g. "2026-10"
+pub fn current_month() -> String {
+    chrono::Local::now().format("%Y-%m").to_string()
+}
+
+pub fn usage_file() -> PathBuf {
+    install::data_file(USAGE_FILE)
+}
+
+// Nothing downloaded yet if there's no file
+pub fn load(path: &Path) -> Result<Usage> {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Usage::default()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+    serde_json::from_str(&json).with_context(|| format!("reading {}", path.display()))
+}
+
+// Add what a run downloaded this month, returning the usage with it. The
+// file is replaced rather than written over, so a run that's killed while
+// writing it can't leave half of it. One that can't be read all the same
+// (edited by hand, say) is started over rather than never counted again.
+pub fn record_run(path: &Path, bytes: u64) -> Result<Usage> {
+    let mut usage = match load(path) {
+        Ok(usage) => usage,
+        Err(e) if e.downcast_ref::<serde_json::Error>().is_some() => {
+            log::warn!("{:#}, counting the data used from now on", e);
+            Usage::default()
+        }
+        Err(e) => return Err(e),
+    };
+    if bytes == 0 {
+        return Ok(usage);
+    }
+    usage.add(&current_month(), bytes);
+    let temp_path = path.with_extension("json.tmp");
+    std::fs::write(&temp_path, serde_json::to_string_pretty(&usage)? + "\n")
+        .and_then(|_| std::fs::rename(&temp_path, path))
+        .with_context(|| format!("writing {}", path.display()))?;
+    Ok(usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage() {
+        let mut usage = Usage::default();
+        usage.add("2026-09", 1000);
+        usage.add("2026-10", 200);
+        usage.add("2026-10", 300);
+        assert_eq!(usage.month("2026-10"), 500);
+        assert_eq!(usage.month("2026-11"), 0);
+        assert_eq!(usage.total(), 1500);
+        assert!(usage.summary("2026-10").ends_with(" since September 2026"));
+        assert!(!Usage::default().summary("2026-10").contains("since"));
+    }
+
+    #[test]
+    fn test_record_run() {
+        let path = std::env::temp_dir().join("snapdown_test_usage.json");
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(load(&path).unwrap(), Usage::default());
+        record_run(&path, 100).unwrap();
+        let usage = record_run(&path, 50).unwrap();
+        assert_eq!(usage.month(&current_month()), 150);
+        assert_eq!(load(&path).unwrap(), usage);
+        // Nothing downloaded, nothing written
+        std::fs::remove_file(&path).unwrap();
+        record_run(&path, 0).unwrap();
+        assert!(!path.exists());
+        // A file that isn't usage is started over
+        std::fs::write(&path, "{\"months\":").unwrap();
+        assert!(load(&path).is_err());
+        let usage = record_run(&path, 10).unwrap();
+        assert_eq!(usage.total(), 10);
+        assert_eq!(load(&path).unwrap(), usage);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
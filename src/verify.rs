@@ -0,0 +1,260 @@
+// `snapdown verify`: check the files in an output directory against its
+// manifest, to find files that went missing, got truncated or were changed
+// since they were downloaded. Nothing is changed, unless --repair asks for
+// the broken files to be downloaded again, which moves them into quarantine
+// first (see quarantine.rs).
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use anyhow::Result;
+
+use crate::archive::ArchiveEntry;
+use crate::hashing::{self, HashProgress};
+use crate::manifest::{self, EntryStatus, ManifestEntry};
+use crate::quarantine::{self, QuarantinedFile};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    Missing,
+    // 0 bytes, usually left by a crash. Also found for files that aren't in
+    // the manifest.
+    Empty,
+    WrongSize { expected: u64, actual: u64 },
+    WrongChecksum,
+    // Couldn't be read, with the error
+    Unreadable(String),
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Missing => write!(f, "missing"),
+            Problem::Empty => write!(f, "empty"),
+            Problem::WrongSize { expected, actual } => {
+                write!(f, "{} bytes, expected {}", actual, expected)
+            }
+            Problem::WrongChecksum => write!(f, "contents changed (checksum doesn't match)"),
+            Problem::Unreadable(e) => write!(f, "can't be read: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    // Completed downloads in the manifest
+    pub checked: usize,
+    // Of those, how many have no checksum to compare against (downloaded
+    // before there was a manifest), so only their size was checked
+    pub size_only: usize,
+    // By filename
+    pub problems: Vec<(String, Problem)>,
+}
+
+pub fn verify(
+    output_dir: &Path,
+    progress: Option<&mpsc::Sender<HashProgress>>,
+) -> Result<VerifyReport> {
+    let path = output_dir.join(manifest::MANIFEST_FILE);
+    if !path.exists() {
+        return Err(anyhow::anyhow!("{} not found", path.display()));
+    }
+    let all_entries = manifest::read_entries(output_dir)?;
+    let saved_filenames: HashSet<&str> = all_entries
+        .values()
+        .map(ManifestEntry::saved_filename)
+        .collect();
+    let mut report = VerifyReport::default();
+
+    // Files from before the manifest, or from a run that crashed before
+    // recording them, can only be checked for being empty
+    for dir_entry in fs::read_dir(output_dir)? {
+        let dir_entry = dir_entry?;
+        let filename = dir_entry.file_name().to_string_lossy().to_string();
+        let is_download =
+            ArchiveEntry::from_path(&dir_entry.path()).is_some_and(|entry| !entry.date.is_empty());
+        if is_download
+            && !saved_filenames.contains(filename.as_str())
+            && dir_entry
+                .metadata()
+                .is_ok_and(|m| m.is_file() && m.len() == 0)
+        {
+            report.checked += 1;
+            report.problems.push((filename, Problem::Empty));
+        }
+    }
+
+    let mut entries: Vec<ManifestEntry> = all_entries
+        .values()
+        .filter(|entry| entry.status == EntryStatus::Completed)
+        .cloned()
+        .collect();
+    // From here on, files go by the name they were saved under
+    for entry in &mut entries {
+        entry.filename = entry.saved_filename().to_string();
+    }
+    entries.sort_by(|a, b| a.filename.cmp(&b.filename));
+    report.checked += entries.len();
+    // Only files of the right size are worth hashing
+    let mut to_hash = Vec::new();
+    for entry in entries {
+        let problem = match output_dir.join(&entry.filename).metadata() {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Problem::Missing,
+            Err(e) => Problem::Unreadable(e.to_string()),
+            Ok(metadata) if metadata.len() == entry.bytes_written => {
+                if entry.checksum.is_none() {
+                    report.size_only += 1;
+                } else {
+                    to_hash.push(entry);
+                }
+                continue;
+            }
+            Ok(metadata) if metadata.len() == 0 => Problem::Empty,
+            Ok(metadata) => Problem::WrongSize {
+                expected: entry.bytes_written,
+                actual: metadata.len(),
+            },
+        };
+        report.problems.push((entry.filename, problem));
+    }
+
+    let paths: Vec<PathBuf> = to_hash
+        .iter()
+        .map(|entry| output_dir.join(&entry.filename))
+        .collect();
+    for (entry, result) in to_hash
+        .into_iter()
+        .zip(hashing::hash_files(&paths, progress))
+    {
+        match result {
+            Ok(file_hash) if Some(&file_hash.hash) == entry.checksum.as_ref() => {}
+            Ok(_) => report
+                .problems
+                .push((entry.filename, Problem::WrongChecksum)),
+            Err(e) => report
+                .problems
+                .push((entry.filename, Problem::Unreadable(e.to_string()))),
+        }
+    }
+    report.problems.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(report)
+}
+
+// --repair: move the broken files found by verify() into quarantine, so the
+// download run that follows gets them again like any other missing file.
+// Returns how many there are to download again, and the files moved.
+pub fn quarantine_broken_files(
+    output_dir: &Path,
+    report: &VerifyReport,
+) -> Result<(usize, Vec<QuarantinedFile>)> {
+    let mut count = 0;
+    let mut quarantined = Vec::new();
+    for (filename, problem) in &report.problems {
+        match problem {
+            Problem::Missing => {}
+            Problem::Empty | Problem::WrongSize { .. } | Problem::WrongChecksum => {
+                quarantined.push(quarantine::quarantine(
+                    output_dir,
+                    filename,
+                    &problem.to_string(),
+                )?);
+            }
+            // Might be fine, just locked by another program
+            Problem::Unreadable(_) => continue,
+        }
+        count += 1;
+    }
+    Ok((count, quarantined))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Manifest;
+    use std::fs;
+
+    #[test]
+    fn test_verify() {
+        let dir = std::env::temp_dir().join("snapdown_test_verify");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let manifest = Manifest::open(&dir).unwrap();
+        let checksum = Some(blake3::hash(b"data").to_hex().to_string());
+        for (filename, contents, checksum) in [
+            ("fine.jpg", Some("data"), checksum.clone()),
+            ("old.jpg", Some("data"), None),
+            ("gone.jpg", None, checksum.clone()),
+            ("short.jpg", Some("da"), checksum.clone()),
+            ("changed.jpg", Some("DATA"), checksum.clone()),
+        ] {
+            if let Some(contents) = contents {
+                fs::write(dir.join(filename), contents).unwrap();
+            }
+            manifest.record(ManifestEntry {
+                url: "https://example.com/a".to_string(),
+                filename: filename.to_string(),
+                status: EntryStatus::Completed,
+                bytes_written: 4,
+                checksum,
+                views: Vec::new(),
+                mime_type: None,
+                saved_as: None,
+                uploaded: None,
+                md5: None,
+                record: None,
+            });
+        }
+        // Found under the name it was renamed to
+        fs::write(dir.join("renamed.png"), "data").unwrap();
+        manifest.record(ManifestEntry {
+            url: "https://example.com/a".to_string(),
+            filename: "renamed.jpg".to_string(),
+            status: EntryStatus::Completed,
+            bytes_written: 4,
+            checksum: checksum.clone(),
+            views: Vec::new(),
+            mime_type: Some("image/png".to_string()),
+            saved_as: Some("renamed.png".to_string()),
+            uploaded: None,
+            md5: None,
+            record: None,
+        });
+        drop(manifest);
+        // Not in the manifest
+        fs::write(dir.join("2024-01-01_00-00-00_UTC_0.0_0.0.mp4"), "").unwrap();
+        fs::write(dir.join("2024-01-02_00-00-00_UTC_0.0_0.0.mp4"), "data").unwrap();
+
+        let report = verify(&dir, None).unwrap();
+        assert_eq!((report.checked, report.size_only), (7, 1));
+        let problems: Vec<(&str, Problem)> = report
+            .problems
+            .iter()
+            .map(|(filename, problem)| (filename.as_str(), problem.clone()))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                ("2024-01-01_00-00-00_UTC_0.0_0.0.mp4", Problem::Empty),
+                ("changed.jpg", Problem::WrongChecksum),
+                ("gone.jpg", Problem::Missing),
+                (
+                    "short.jpg",
+                    Problem::WrongSize {
+                        expected: 4,
+                        actual: 2
+                    }
+                ),
+            ]
+        );
+        assert!(verify(&dir.join("nothing here"), None).is_err());
+
+        let (count, quarantined) = quarantine_broken_files(&dir, &report).unwrap();
+        assert_eq!((count, quarantined.len()), (4, 3));
+        assert!(!dir.join("short.jpg").exists());
+        assert!(dir.join(".quarantine/short.jpg").exists());
+        assert!(dir.join("fine.jpg").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,298 @@
+// Alternate views of the archive (--views): folders like by-year/2024/ or
+// by-type/Video/ filled with links to the downloaded files, so the archive
+// can be browsed by year, type or place without storing anything twice. The
+// files themselves stay at the top of the output directory.
+//
+// The links each file got are recorded in its manifest entry, so later runs
+// only link the files that are new (or were downloaded again).
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::archive::ArchiveEntry;
+use crate::manifest::{EntryStatus, Manifest, ManifestEntry};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewKind {
+    Type,
+    Year,
+    Place,
+}
+
+impl ViewKind {
+    pub const ALL: [ViewKind; 3] = [ViewKind::Type, ViewKind::Year, ViewKind::Place];
+
+    // e.g. "by-year"
+    pub fn parse(name: &str) -> Result<ViewKind> {
+        ViewKind::ALL
+            .into_iter()
+            .find(|kind| kind.dir_name().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "unknown view {:?}, expected by-type, by-year or by-place",
+                    name
+                )
+            })
+    }
+
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            ViewKind::Type => "by-type",
+            ViewKind::Year => "by-year",
+            ViewKind::Place => "by-place",
+        }
+    }
+
+    // The folder within the view that `entry` goes in
+    fn folder(&self, entry: &ArchiveEntry) -> String {
+        match self {
+            ViewKind::Type => entry.media_kind.label().to_string(),
+            ViewKind::Year => entry.date.get(..4).unwrap_or("unknown").to_string(),
+            ViewKind::Place => place_folder(&entry.location),
+        }
+    }
+}
+
+// Coordinates rounded to 0.1 degree (about 10 km), so memories from the same
+// town end up together. Snapchat uses 0, 0 for memories without a location.
+fn place_folder(location: &str) -> String {
+    let coordinates = location.split_once('_').and_then(|(latitude, longitude)| {
+        Some((
+            latitude.parse::<f64>().ok()?,
+            longitude.parse::<f64>().ok()?,
+        ))
+    });
+    match coordinates {
+        Some((latitude, longitude)) if (latitude, longitude) != (0.0, 0.0) => {
+            format!("{:.1}_{:.1}", latitude, longitude)
+        }
+        _ => "unknown".to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkKind {
+    // Works without special permissions everywhere, but only within one
+    // file system, and a link no longer follows a file that's replaced
+    HardLink,
+    // Relative, so the output directory can still be moved. Needs developer
+    // mode or admin rights on Windows.
+    Symlink,
+}
+
+impl LinkKind {
+    pub fn parse(kind: &str) -> Result<LinkKind> {
+        match kind.trim().to_ascii_lowercase().as_str() {
+            "hardlink" | "hard-link" | "hard" => Ok(LinkKind::HardLink),
+            "symlink" | "symbolic" | "soft" => Ok(LinkKind::Symlink),
+            _ => Err(anyhow::anyhow!(
+                "unknown link type {:?}, expected hardlink or symlink",
+                kind
+            )),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LinkKind::HardLink => "Hard links",
+            LinkKind::Symlink => "Symbolic links",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewSettings {
+    pub kinds: Vec<ViewKind>,
+    pub link: LinkKind,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ViewReport {
+    // Links made in this run
+    pub linked: usize,
+    // Files that couldn't be linked, the errors are in the log
+    pub failed: usize,
+}
+
+// Link every completed download in the manifest into the views
+pub fn build_views(
+    output_dir: &Path,
+    settings: &ViewSettings,
+    manifest: &Manifest,
+) -> Result<ViewReport> {
+    let mut report = ViewReport::default();
+    // Hundreds of files share each folder, so each is only created once
+    let mut created_dirs = HashSet::new();
+    let mut entries = manifest.entries();
+    entries.sort_by(|a, b| a.filename.cmp(&b.filename));
+    for entry in entries {
+        if entry.status != EntryStatus::Completed {
+            continue;
+        }
+        let filename = entry.saved_filename().to_string();
+        let Some(archive_entry) = ArchiveEntry::from_path(&output_dir.join(&filename)) else {
+            continue;
+        };
+        let views: Vec<String> = settings
+            .kinds
+            .iter()
+            .map(|kind| {
+                format!(
+                    "{}/{}/{}",
+                    kind.dir_name(),
+                    kind.folder(&archive_entry),
+                    filename
+                )
+            })
+            .collect();
+        let done = |view: &String| {
+            entry.views.contains(view) && output_dir.join(view).symlink_metadata().is_ok()
+        };
+        if views.iter().all(done) {
+            continue;
+        }
+
+        let mut failed = false;
+        for view in views.iter().filter(|view| !done(view)) {
+            let link_path = output_dir.join(view);
+            let result = create_parent(&link_path, &mut created_dirs)
+                .and_then(|()| link(&filename, &link_path, output_dir, settings.link));
+            match result {
+                Ok(()) => report.linked += 1,
+                Err(e) => {
+                    log::error!("Error linking {:?}: {}", link_path, e);
+                    failed = true;
+                }
+            }
+        }
+        if failed {
+            report.failed += 1;
+        } else {
+            manifest.record(ManifestEntry { views, ..entry });
+        }
+    }
+    Ok(report)
+}
+
+fn create_parent(path: &Path, created_dirs: &mut HashSet<PathBuf>) -> io::Result<()> {
+    let Some(parent) = path.parent() else {
+        return Ok(());
+    };
+    if !created_dirs.contains(parent) {
+        fs::create_dir_all(parent)?;
+        created_dirs.insert(parent.to_path_buf());
+    }
+    Ok(())
+}
+
+// Link <output_dir>/<filename> at link_path, replacing whatever is there (a
+// hard link to an earlier download of the file, most likely)
+fn link(filename: &str, link_path: &Path, output_dir: &Path, kind: LinkKind) -> io::Result<()> {
+    if link_path.symlink_metadata().is_ok() {
+        fs::remove_file(link_path)?;
+    }
+    match kind {
+        LinkKind::HardLink => fs::hard_link(output_dir.join(filename), link_path),
+        LinkKind::Symlink => {
+            // The links are two folders down, e.g. by-year/2024/
+            let target = Path::new("..").join("..").join(filename);
+            #[cfg(windows)]
+            return std::os::windows::fs::symlink_file(target, link_path);
+            #[cfg(not(windows))]
+            return std::os::unix::fs::symlink(target, link_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_views() {
+        assert_eq!(ViewKind::parse(" BY-YEAR").unwrap(), ViewKind::Year);
+        assert!(ViewKind::parse("by-month").is_err());
+        assert_eq!(place_folder("40.25548_-111.645325"), "40.3_-111.6");
+        assert_eq!(place_folder("0.0_0.0"), "unknown");
+        assert_eq!(place_folder(""), "unknown");
+    }
+
+    #[test]
+    fn test_build_views() {
+        let dir = std::env::temp_dir().join("snapdown_test_views");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let manifest = Manifest::open(&dir).unwrap();
+        for (filename, status) in [
+            (
+                "2024-05-01_10-00-00_UTC_40.25_-111.64.jpg",
+                EntryStatus::Completed,
+            ),
+            (
+                "2025-01-02_10-00-00_UTC_0.0_0.0.mp4",
+                EntryStatus::Completed,
+            ),
+            ("2025-01-03_10-00-00_UTC_0.0_0.0.mp4", EntryStatus::Failed),
+        ] {
+            fs::write(dir.join(filename), filename).unwrap();
+            manifest.record(ManifestEntry {
+                url: "https://example.com/a".to_string(),
+                filename: filename.to_string(),
+                status,
+                bytes_written: filename.len() as u64,
+                checksum: None,
+                views: Vec::new(),
+                mime_type: None,
+                saved_as: None,
+                uploaded: None,
+                md5: None,
+                record: None,
+            });
+        }
+        let settings = ViewSettings {
+            kinds: vec![ViewKind::Year, ViewKind::Place],
+            link: LinkKind::HardLink,
+        };
+
+        let report = build_views(&dir, &settings, &manifest).unwrap();
+        assert_eq!(
+            report,
+            ViewReport {
+                linked: 4,
+                failed: 0
+            }
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("by-year/2024/2024-05-01_10-00-00_UTC_40.25_-111.64.jpg"))
+                .unwrap(),
+            "2024-05-01_10-00-00_UTC_40.25_-111.64.jpg"
+        );
+        assert!(
+            dir.join("by-place/unknown/2025-01-02_10-00-00_UTC_0.0_0.0.mp4")
+                .exists()
+        );
+        assert!(
+            !dir.join("by-year/2025/2025-01-03_10-00-00_UTC_0.0_0.0.mp4")
+                .exists()
+        );
+        assert_eq!(
+            manifest
+                .get("2025-01-02_10-00-00_UTC_0.0_0.0.mp4")
+                .unwrap()
+                .views,
+            [
+                "by-year/2025/2025-01-02_10-00-00_UTC_0.0_0.0.mp4",
+                "by-place/unknown/2025-01-02_10-00-00_UTC_0.0_0.0.mp4"
+            ]
+        );
+
+        // Nothing left to do the second time
+        let report = build_views(&dir, &settings, &manifest).unwrap();
+        assert_eq!(report, ViewReport::default());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+// Notices a run that stopped making progress, like every download hanging on
+// a server that stopped sending without closing the connection. An unattended
+// overnight run would otherwise sit there until someone looks. What each
+// download is doing goes to the log, and with --restart-stalled the run is
+// stopped to be started again, continuing the partial files.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::control::RunControl;
+use crate::format;
+
+// Longer than the longest wait for a server limiting requests (see
+// backoff.rs), which isn't being stuck
+pub const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+// How often a stalled run is started again before giving up
+pub const MAX_STALL_RESTARTS: usize = 3;
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct Watchdog {
+    timeout: Duration,
+    // The records being downloaded, by index
+    active: Mutex<HashMap<usize, Activity>>,
+}
+
+struct Activity {
+    // Where the file is being written
+    path: PathBuf,
+    started: Instant,
+}
+
+impl Watchdog {
+    pub fn new(timeout: Duration) -> Watchdog {
+        Watchdog {
+            timeout,
+            active: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn started(&self, index: usize, path: PathBuf) {
+        let activity = Activity {
+            path,
+            started: Instant::now(),
+        };
+        self.active.lock().unwrap().insert(index, activity);
+    }
+
+    pub fn finished(&self, index: usize) {
+        self.active.lock().unwrap().remove(&index);
+    }
+
+    // Check every second (more often for short timeouts) until `done`,
+    // calling `on_stall` with what each download is doing once `progress`
+    // and the files being written haven't changed for the timeout. Once per
+    // stall, and never while paused. `progress` is anything that goes up as
+    // the run goes, like the records done and bytes downloaded.
+    pub fn watch(
+        &self,
+        done: &AtomicBool,
+        control: &RunControl,
+        progress: impl Fn() -> u64,
+        on_stall: impl Fn(Vec<String>),
+    ) {
+        let mut last_progress = None;
+        let mut last_change = Instant::now();
+        let mut reported = false;
+        while !done.load(Ordering::Relaxed) {
+            std::thread::sleep(CHECK_INTERVAL.min(self.timeout / 4));
+            // The sizes are read for each check, so the rayon engine's
+            // downloads, which only count their bytes once done, show too
+            let current = progress() + self.bytes_written();
+            if last_progress != Some(current) || control.is_paused() {
+                last_progress = Some(current);
+                last_change = Instant::now();
+                reported = false;
+            } else if !reported && last_change.elapsed() >= self.timeout {
+                reported = true;
+                on_stall(self.diagnostics());
+            }
+        }
+    }
+
+    // Size of the files being written
+    fn bytes_written(&self) -> u64 {
+        let active = self.active.lock().unwrap();
+        active
+            .values()
+            .filter_map(|activity| fs::metadata(&activity.path).ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
+
+    // A line per download, the longest running first
+    fn diagnostics(&self) -> Vec<String> {
+        let active = self.active.lock().unwrap();
+        let mut activities: Vec<&Activity> = active.values().collect();
+        activities.sort_by_key(|activity| activity.started);
+        activities
+            .iter()
+            .map(|activity| {
+                let filename = activity
+                    .path
+                    .file_name()
+                    .map(|name| name.to_string_lossy())
+                    .unwrap_or_default();
+                let written = match fs::metadata(&activity.path) {
+                    Ok(metadata) => format!("{} written", format::format_bytes(metadata.len())),
+                    Err(_) => "waiting for the server".to_string(),
+                };
+                format!(
+                    "  {}: started {} ago, {}",
+                    filename,
+                    format::format_duration(activity.started.elapsed()),
+                    written
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU64;
+
+    #[test]
+    fn test_watchdog() {
+        let dir = std::env::temp_dir().join("snapdown_test_watchdog");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.mp4"), [0; 1000]).unwrap();
+        let watchdog = Watchdog::new(Duration::from_millis(400));
+        watchdog.started(0, dir.join("a.mp4"));
+        watchdog.started(1, dir.join("b.jpg"));
+        watchdog.started(2, dir.join("c.jpg"));
+        watchdog.finished(2);
+
+        let done = AtomicBool::new(false);
+        let progress = AtomicU64::new(0);
+        let stalls = Mutex::new(Vec::new());
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                watchdog.watch(
+                    &done,
+                    &RunControl::default(),
+                    || progress.load(Ordering::Relaxed),
+                    |diagnostics| stalls.lock().unwrap().push(diagnostics),
+                )
+            });
+            // Progress keeps it from reporting anything
+            for _ in 0..10 {
+                std::thread::sleep(Duration::from_millis(50));
+                progress.fetch_add(1, Ordering::Relaxed);
+            }
+            assert!(stalls.lock().unwrap().is_empty());
+            // Then it stalls, and is reported once
+            std::thread::sleep(Duration::from_millis(1000));
+            done.store(true, Ordering::Relaxed);
+        });
+
+        let stalls = stalls.into_inner().unwrap();
+        assert_eq!(stalls.len(), 1);
+        assert_eq!(stalls[0].len(), 2);
+        assert!(
+            stalls[0][0].starts_with("  a.mp4: started "),
+            "{:?}",
+            stalls
+        );
+        assert!(stalls[0][1].ends_with(", waiting for the server"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}